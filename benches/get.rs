@@ -0,0 +1,60 @@
+// Benchmarking the raw string lookups the `strict-keys` feature deprecates.
+#![cfg_attr(feature = "strict-keys", allow(deprecated))]
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use singleton_manager::{key, Key, SingletonManager};
+
+static ROUTES: Key<Vec<String>> = Key::new("routes");
+
+fn get(c: &mut Criterion) {
+    let mut manager = SingletonManager::new();
+    manager
+        .set_factory("config", || Box::new(vec!["/".to_string()]))
+        .unwrap();
+    manager
+        .set_factory("routes", || Box::new(vec!["/".to_string()]))
+        .unwrap();
+    manager.freeze("routes").unwrap();
+    manager.set_inline("dark_mode", true).unwrap();
+
+    let mut group = c.benchmark_group("get");
+    group.bench_function("get_ref", |b| {
+        b.iter(|| {
+            black_box(
+                manager
+                    .get_ref::<Vec<String>>(black_box("config"))
+                    .unwrap()
+                    .len(),
+            )
+        })
+    });
+    group.bench_function("get_ref_by_key", |b| {
+        b.iter(|| {
+            black_box(
+                manager
+                    .get_ref_by_key::<Vec<String>>(black_box(key!("config")))
+                    .unwrap()
+                    .len(),
+            )
+        })
+    });
+    group.bench_function("get_key", |b| {
+        b.iter(|| black_box(manager.get_key(black_box(&ROUTES)).unwrap().len()))
+    });
+    group.bench_function("get_copy_inline", |b| {
+        b.iter(|| black_box(manager.get_copy::<bool>(black_box("dark_mode")).unwrap()))
+    });
+    group.bench_function("get", |b| {
+        b.iter(|| {
+            black_box(
+                manager
+                    .get::<Vec<String>>(black_box("config"))
+                    .unwrap()
+                    .len(),
+            )
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, get);
+criterion_main!(benches);
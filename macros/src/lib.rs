@@ -0,0 +1,128 @@
+//! # Singleton manager macros
+//! The attribute macros of `singleton-manager`, used through its re-exports.
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, Error, FnArg, ItemFn, Pat, ReturnType, Type};
+
+/// Generating variants of a function with its shared reference parameters resolved from the
+/// singleton manager, see `singleton_manager::inject`.
+#[proc_macro_attribute]
+pub fn inject(attr: TokenStream, item: TokenStream) -> TokenStream {
+    if !attr.is_empty() {
+        return Error::new(Span::call_site(), "`#[inject]` takes no arguments")
+            .into_compile_error()
+            .into();
+    }
+    let function = parse_macro_input!(item as ItemFn);
+    expand(function)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+/// Whether the tokens name `Self`, which only functions of an impl block can.
+fn mentions_self(tokens: proc_macro2::TokenStream) -> bool {
+    tokens.into_iter().any(|token| match token {
+        proc_macro2::TokenTree::Ident(ident) => ident == "Self",
+        proc_macro2::TokenTree::Group(group) => mentions_self(group.stream()),
+        _ => false,
+    })
+}
+
+fn expand(function: ItemFn) -> syn::Result<proc_macro2::TokenStream> {
+    let sig = &function.sig;
+    if let Some(receiver) = sig.receiver() {
+        return Err(Error::new_spanned(
+            receiver,
+            "`#[inject]` does not apply to methods, only to free functions and constructors",
+        ));
+    }
+    if let Some(asyncness) = sig.asyncness {
+        return Err(Error::new_spanned(
+            asyncness,
+            "`#[inject]` does not apply to async functions",
+        ));
+    }
+
+    // The parameters taken by shared reference are resolved, the others are kept.
+    let mut resolved = Vec::new();
+    let mut kept = Vec::new();
+    let mut kept_names = Vec::new();
+    let mut arguments = Vec::new();
+    for (i, input) in sig.inputs.iter().enumerate() {
+        let input = match input {
+            FnArg::Typed(input) => input,
+            FnArg::Receiver(_) => unreachable!("methods are rejected above"),
+        };
+        match &*input.ty {
+            Type::Reference(reference) if reference.mutability.is_none() => {
+                let service = format_ident!("__service_{}", i);
+                let ty = &reference.elem;
+                resolved.push(quote! {
+                    let #service = manager.get_bound::<#ty>()?;
+                });
+                arguments.push(quote! { &**#service });
+            }
+            ty => {
+                let name = match &*input.pat {
+                    Pat::Ident(pat) => pat.ident.clone(),
+                    _ => format_ident!("arg_{}", i),
+                };
+                kept.push(quote! { #name: #ty });
+                arguments.push(quote! { #name });
+                kept_names.push(name);
+            }
+        }
+    }
+
+    let vis = &function.vis;
+    let name = &sig.ident;
+    let injected = format_ident!("{}_injected", name);
+    let injected_from = format_ident!("{}_injected_from", name);
+    let (generics, where_clause) = (&sig.generics, &sig.generics.where_clause);
+    let output = match &sig.output {
+        ReturnType::Default => quote! { () },
+        ReturnType::Type(_, ty) => quote! { #ty },
+    };
+    // In an impl block, e.g. of a constructor returning `Self`, the function is associated.
+    let (callee, callee_from) = if mentions_self(quote! { #sig }) {
+        (quote! { Self::#name }, quote! { Self::#injected_from })
+    } else {
+        (quote! { #name }, quote! { #injected_from })
+    };
+    let doc = format!(
+        "Calling `{}` with the services bound in the global singleton manager.",
+        name
+    );
+    let doc_from = format!(
+        "Calling `{}` with the services bound in the singleton manager.",
+        name
+    );
+
+    Ok(quote! {
+        #function
+
+        #[doc = #doc]
+        #[allow(dead_code)]
+        #[track_caller]
+        #vis fn #injected #generics (#(#kept),*)
+            -> ::std::result::Result<#output, ::singleton_manager::GetError>
+            #where_clause
+        {
+            #callee_from(::singleton_manager::sm(), #(#kept_names),*)
+        }
+
+        #[doc = #doc_from]
+        #[allow(dead_code)]
+        #[track_caller]
+        #vis fn #injected_from #generics (
+            manager: &::singleton_manager::SingletonManager,
+            #(#kept),*
+        ) -> ::std::result::Result<#output, ::singleton_manager::GetError>
+            #where_clause
+        {
+            #(#resolved)*
+            Ok(#callee(#(#arguments),*))
+        }
+    })
+}
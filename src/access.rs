@@ -0,0 +1,110 @@
+//! # Scoped access
+//! Restricting the services a component may access.
+//!
+//! Plugins, and other components that are not trusted to grab arbitrary singletons, can be
+//! given a `ScopedAccess` instead of the singleton manager. It only resolves the aliases it was
+//! created for, and fails with `Error::AccessDenied` for any other alias, whether it is
+//! registered or not.
+//! ```
+//! use singleton_manager::sm;
+//!
+//! sm().set("access_db", "postgres".to_string()).unwrap();
+//! sm().set("access_secrets", "hunter2".to_string()).unwrap();
+//!
+//! let plugin = sm().scoped_access(&["access_db"]);
+//! assert_eq!("postgres", *plugin.get_ref::<String>("access_db").unwrap());
+//! assert!(plugin.get_ref::<String>("access_secrets").unwrap_err().is_access_denied());
+//! ```
+use crate::{Error, GetError, ServiceRef, ServiceRefMut, SingletonManager};
+use std::collections::HashSet;
+
+/// An accessor of the singleton manager restricted to a set of aliases, see
+/// `SingletonManager::scoped_access`.
+#[derive(Clone)]
+pub struct ScopedAccess<'a> {
+    manager: &'a SingletonManager,
+    allowed: HashSet<String>,
+}
+
+impl<'a> ScopedAccess<'a> {
+    /// True if the accessor may access the alias.
+    pub fn is_allowed(&self, service_name: &str) -> bool {
+        self.allowed.contains(service_name)
+    }
+
+    fn check(&self, service_name: &str) -> std::result::Result<(), GetError> {
+        if self.is_allowed(service_name) {
+            Ok(())
+        } else {
+            Err(GetError::from_error(
+                Error::AccessDenied(service_name.into()),
+                service_name,
+            ))
+        }
+    }
+
+    /// Getting a shared reference to the service, as `SingletonManager::get_ref` does, if the
+    /// accessor may access it.
+    #[track_caller]
+    pub fn get_ref<T: 'static>(
+        &self,
+        service_name: &str,
+    ) -> std::result::Result<ServiceRef<'a, T>, GetError> {
+        self.check(service_name)?;
+        self.manager.get_ref_by_alias(service_name)
+    }
+
+    /// Getting an exclusive reference to the service, as `SingletonManager::get_mut` does, if
+    /// the accessor may access it.
+    #[track_caller]
+    pub fn get_mut<T: 'static>(
+        &self,
+        service_name: &str,
+    ) -> std::result::Result<ServiceRefMut<'a, T>, GetError> {
+        self.check(service_name)?;
+        self.manager.get_mut_by_alias(service_name)
+    }
+
+    /// True if the accessor may access the alias, and a service is registered under it.
+    pub fn has(&self, service_name: &str) -> bool {
+        self.is_allowed(service_name) && self.manager.has(service_name)
+    }
+}
+
+impl SingletonManager {
+    /// An accessor restricted to the aliases, failing with `Error::AccessDenied` for any other
+    /// alias.
+    pub fn scoped_access(&self, service_names: &[&str]) -> ScopedAccess<'_> {
+        ScopedAccess {
+            manager: self,
+            allowed: service_names
+                .iter()
+                .map(|alias| alias.to_string())
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{GetError, SingletonManager};
+
+    #[test]
+    fn test_scoped_access_denies_other_aliases() {
+        let mut manager = SingletonManager::new();
+        manager.set_factory("db", || Box::new(5432_u16)).unwrap();
+        manager.set_factory("cfg", || Box::new(1_u8)).unwrap();
+        let access = manager.scoped_access(&["db", "missing"]);
+
+        *access.get_mut::<u16>("db").unwrap() += 1;
+        assert_eq!(5433, *access.get_ref::<u16>("db").unwrap());
+        assert!(matches!(
+            access.get_ref::<u8>("cfg"),
+            Err(GetError::AccessDenied(alias)) if alias == "cfg"
+        ));
+        assert!(access.get_ref::<u8>("missing").unwrap_err().is_not_found());
+        assert!(access.has("db"));
+        assert!(!access.has("cfg"));
+        assert!(!access.has("missing"));
+    }
+}
@@ -0,0 +1,174 @@
+//! # Serialized access
+//! Running closures on a service, one at a time, instead of handing out mutable references.
+//!
+//! `with` runs a closure with exclusive access to the service, waiting for the other borrows of
+//! the service to be released first, so all mutations of the service are serialized as if it
+//! was an actor processing one message at a time. The mutable reference never outlives the
+//! closure, which makes this a safer default than `get`, whose reference is not tracked.
+//!
+//! `with_async` does the same from async code, e.g. tokio handlers, with an async closure. The
+//! returned future waits for the other borrows without blocking the executor thread, and keeps
+//! the service borrowed until the future of the closure completed, across its awaits.
+//! `get_mut_async` waits for the exclusive borrow the same way.
+//! ```
+//! use singleton_manager::SingletonManager;
+//!
+//! let mut manager = SingletonManager::new();
+//! manager.set_factory("counter", || Box::new(0_u64)).unwrap();
+//!
+//! std::thread::scope(|s| {
+//!     for _ in 0..4 {
+//!         s.spawn(|| manager.with("counter", |counter: &mut u64| *counter += 1).unwrap());
+//!     }
+//! });
+//! assert_eq!(4, manager.with("counter", |counter: &mut u64| *counter).unwrap());
+//! ```
+use crate::{Error, GetError, ServiceRefMut, SingletonManager};
+use std::future::Future;
+use std::marker::PhantomData;
+use std::panic::Location;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+impl SingletonManager {
+    /// Running the closure with exclusive access to the service, blocking until all other
+    /// borrows of the service are released, as `get_mut` does.
+    #[track_caller]
+    pub fn with<T: 'static, R>(
+        &self,
+        service_name: &str,
+        f: impl FnOnce(&mut T) -> R,
+    ) -> std::result::Result<R, GetError> {
+        let mut service = self.get_mut_by_alias::<T>(service_name)?;
+        Ok(f(&mut service))
+    }
+
+    /// Running the async closure with exclusive access to the service, once all other borrows
+    /// of the service are released, without blocking the thread while waiting.
+    /// The service stays borrowed until the future of the closure completed, or the borrow
+    /// passed to the closure is dropped.
+    /// ```
+    /// use singleton_manager::SingletonManager;
+    ///
+    /// async fn handle(manager: &SingletonManager) -> usize {
+    ///     manager
+    ///         .with_async::<Vec<String>, _, _>("sessions", |mut sessions| async move {
+    ///             sessions.push("alice".to_string());
+    ///             sessions.len()
+    ///         })
+    ///         .await
+    ///         .unwrap()
+    /// }
+    /// ```
+    #[track_caller]
+    pub fn with_async<'a, T, F, Fut>(
+        &'a self,
+        service_name: &'a str,
+        f: F,
+    ) -> impl Future<Output = std::result::Result<Fut::Output, GetError>> + 'a
+    where
+        T: 'static,
+        F: FnOnce(ServiceRefMut<'a, T>) -> Fut + 'a,
+        Fut: Future + 'a,
+    {
+        let borrow = self.get_mut_async::<T>(service_name);
+        async move { Ok(f(borrow.await?).await) }
+    }
+
+    /// Getting an exclusive reference to the service, as `get_mut` does, but waiting for the
+    /// other borrows of the service without blocking the thread.
+    /// A service created from its factory is created while polling.
+    #[track_caller]
+    pub fn get_mut_async<'a, T: 'static>(&'a self, service_name: &'a str) -> GetMutAsync<'a, T> {
+        GetMutAsync {
+            manager: self,
+            service_name,
+            location: Location::caller(),
+            _service: PhantomData,
+        }
+    }
+}
+
+/// The future borrowing a service exclusively, see `SingletonManager::get_mut_async`.
+#[must_use = "futures do nothing unless polled"]
+pub struct GetMutAsync<'a, T> {
+    manager: &'a SingletonManager,
+    service_name: &'a str,
+    location: &'static Location<'static>,
+    _service: PhantomData<fn() -> T>,
+}
+
+impl<'a, T: 'static> Future for GetMutAsync<'a, T> {
+    type Output = std::result::Result<ServiceRefMut<'a, T>, GetError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let (service_name, location) = (self.service_name, self.location);
+        let borrowed = self
+            .manager
+            .exclusive_borrow::<T>(service_name, location, |state| {
+                state.try_borrow_mut_or_wake(service_name, location, cx.waker())
+            });
+        match borrowed {
+            Ok(service) => Poll::Ready(Ok(service)),
+            Err(Error::AlreadyBorrowed(_, _)) => Poll::Pending,
+            Err(e) => Poll::Ready(Err(GetError::from_error(e, service_name))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::SingletonManager;
+    use std::future::Future;
+    use std::pin::pin;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::task::{Context, Poll, Wake, Waker};
+
+    #[derive(Default)]
+    struct Flag(AtomicBool);
+
+    impl Wake for Flag {
+        fn wake(self: Arc<Self>) {
+            self.0.store(true, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn test_with_async_waits_for_borrows() {
+        let mut manager = SingletonManager::new();
+        manager
+            .set_factory("log", || Box::new(Vec::<u32>::new()))
+            .unwrap();
+        let flag = Arc::new(Flag::default());
+        let waker = Waker::from(flag.clone());
+        let mut cx = Context::from_waker(&waker);
+
+        let borrowed = manager.get_ref::<Vec<u32>>("log").unwrap();
+        let mut push = pin!(
+            manager.with_async::<Vec<u32>, _, _>("log", |mut log| async move {
+                log.push(1);
+                log.len()
+            })
+        );
+        fn assert_send<F: Send>(_: &F) {}
+        assert_send(&push);
+        assert!(push.as_mut().poll(&mut cx).is_pending());
+        assert!(!flag.0.load(Ordering::SeqCst));
+        drop(borrowed);
+        assert!(flag.0.load(Ordering::SeqCst));
+        assert!(matches!(push.as_mut().poll(&mut cx), Poll::Ready(Ok(1))));
+
+        let missing = pin!(manager.get_mut_async::<u32>("missing"));
+        assert!(matches!(
+            missing.poll(&mut cx),
+            Poll::Ready(Err(e)) if e.is_not_found()
+        ));
+        assert_eq!(
+            vec![1],
+            manager
+                .with("log", |log: &mut Vec<u32>| log.clone())
+                .unwrap()
+        );
+    }
+}
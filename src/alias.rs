@@ -0,0 +1,410 @@
+//! # Aliases
+//! The aliases the singletons are registered under, and their migration.
+//!
+//! Aliases are stored interned, every distinct alias in use is only allocated once, and shared by
+//! everything stored for it. The static aliases of providers and static singletons are stored
+//! without any allocation at all.
+//!
+//! A registration can be renamed atomically, optionally leaving a deprecated forwarding alias
+//! behind, which keeps resolving to the renamed registration but logs a warning the first time it
+//! is used to get the service from each call site.
+//! This allows renaming a service across a workspace in stages instead of in one go.
+//! ```
+//! use singleton_manager::SingletonManager;
+//!
+//! let mut manager = SingletonManager::new();
+//! manager.set("db", 1).unwrap();
+//! manager.rename_forwarding("db", "database").unwrap();
+//!
+//! assert_eq!(1, *manager.get::<i32>("database").unwrap());
+//! // Still resolves, but logs a warning about the deprecated alias.
+//! assert_eq!(1, *manager.get::<i32>("db").unwrap());
+//! ```
+use crate::{versioned_alias, Error, Operation, Registry, Result, SingletonManager, Uuid};
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::fmt::{Debug, Display, Formatter};
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+use std::panic::Location;
+use std::sync::Arc;
+
+/// An alias as stored in the registry, either a static string or an interned string.
+/// Aliases compare and hash as the string they contain.
+///
+/// Errors carry the alias of the service they are about as an `Alias`, so an error about a
+/// registered service shares the interned alias instead of allocating a copy of it.
+#[derive(Clone)]
+pub enum Alias {
+    Static(&'static str),
+    Interned(Arc<str>),
+}
+
+impl Alias {
+    /// The alias as a string.
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Static(alias) => alias,
+            Self::Interned(alias) => alias,
+        }
+    }
+}
+
+impl Debug for Alias {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl Default for Alias {
+    fn default() -> Self {
+        Self::Static("")
+    }
+}
+
+impl From<&str> for Alias {
+    fn from(alias: &str) -> Self {
+        Self::Interned(Arc::from(alias))
+    }
+}
+
+impl From<String> for Alias {
+    fn from(alias: String) -> Self {
+        Self::Interned(Arc::from(alias))
+    }
+}
+
+impl From<Alias> for String {
+    fn from(alias: Alias) -> Self {
+        alias.as_str().to_string()
+    }
+}
+
+impl Deref for Alias {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl Borrow<str> for Alias {
+    fn borrow(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl PartialEq for Alias {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl Eq for Alias {}
+
+impl PartialEq<str> for Alias {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for Alias {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl PartialEq<String> for Alias {
+    fn eq(&self, other: &String) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<Alias> for str {
+    fn eq(&self, other: &Alias) -> bool {
+        self == other.as_str()
+    }
+}
+
+impl PartialEq<Alias> for &str {
+    fn eq(&self, other: &Alias) -> bool {
+        *self == other.as_str()
+    }
+}
+
+impl PartialEq<Alias> for String {
+    fn eq(&self, other: &Alias) -> bool {
+        self == other.as_str()
+    }
+}
+
+impl PartialOrd for Alias {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Alias {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.as_str().cmp(other.as_str())
+    }
+}
+
+impl Hash for Alias {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_str().hash(state)
+    }
+}
+
+impl Display for Alias {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// An alias given to register a service under, a static alias is stored as it is.
+#[derive(Clone, Copy)]
+pub(crate) enum NewAlias<'a> {
+    Borrowed(&'a str),
+    Static(&'static str),
+}
+
+impl NewAlias<'_> {
+    /// The alias as a string.
+    pub(crate) fn as_str(&self) -> &str {
+        match self {
+            Self::Borrowed(alias) => alias,
+            Self::Static(alias) => alias,
+        }
+    }
+}
+
+impl<'a> From<&'a str> for NewAlias<'a> {
+    fn from(alias: &'a str) -> Self {
+        Self::Borrowed(alias)
+    }
+}
+
+impl<'a> From<&'a String> for NewAlias<'a> {
+    fn from(alias: &'a String) -> Self {
+        Self::Borrowed(alias)
+    }
+}
+
+impl Registry {
+    /// Getting the alias to store, sharing the alias stored by a registration or forwarding alias
+    /// under the same name, so an alias is only allocated once while it is in use.
+    pub(crate) fn intern<'a>(&self, alias: impl Into<NewAlias<'a>>) -> Alias {
+        let alias = alias.into();
+        let stored = self
+            .alias
+            .get_key_value(alias.as_str())
+            .map(|(stored, _)| stored)
+            .or_else(|| {
+                self.forwarding
+                    .get_key_value(alias.as_str())
+                    .map(|(stored, _)| stored)
+            });
+        match (stored, alias) {
+            (Some(stored), _) => stored.clone(),
+            (None, NewAlias::Static(alias)) => Alias::Static(alias),
+            (None, NewAlias::Borrowed(alias)) => Alias::Interned(Arc::from(alias)),
+        }
+    }
+
+    /// Resolving an alias to its id, following default versions and deprecated forwarding
+    /// aliases.
+    pub(crate) fn resolve(&self, alias: &str) -> Option<Uuid> {
+        if let Some(version) = self.default_versions.get(alias) {
+            return self
+                .alias
+                .get(versioned_alias(alias, version).as_str())
+                .copied();
+        }
+        self.alias
+            .get(alias)
+            .or_else(|| self.alias.get(self.forwarding.get(alias)?))
+            .copied()
+    }
+
+    /// Resolving an alias to use the service, as `resolve` does, warning about a deprecated
+    /// forwarding alias the first time it is used from the call site.
+    pub(crate) fn resolve_for_use(
+        &self,
+        alias: &str,
+        location: &'static Location<'static>,
+    ) -> Option<Uuid> {
+        if !self.alias.contains_key(alias) && !self.default_versions.contains_key(alias) {
+            if let Some((old, target)) = self.forwarding.get_key_value(alias) {
+                let first_use = self
+                    .forwarding_warned
+                    .lock()
+                    .map(|mut warned| warned.insert((old.clone(), location)))
+                    .unwrap_or(true);
+                if first_use {
+                    log::warn!(
+                        "Singleton alias `{}` used at {} is deprecated, use `{}` instead",
+                        alias,
+                        location,
+                        target
+                    );
+                }
+            }
+        }
+        self.resolve(alias)
+    }
+
+    /// Forgetting the call sites warned about forwarding aliases that were removed.
+    pub(crate) fn forget_forwarding_warnings(&self) {
+        if let Ok(mut warned) = self.forwarding_warned.lock() {
+            warned.retain(|(alias, _)| self.forwarding.contains_key(alias));
+        }
+    }
+
+    pub(crate) fn rename(&mut self, old: &str, new: &str) -> Result<Uuid> {
+        if self.alias.contains_key(new) {
+            return Err(Error::ServiceAlreadyExists(new.into()));
+        }
+        let id = self
+            .alias
+            .remove(old)
+            .ok_or_else(|| Error::ServiceDoesNotExist(old.into(), Default::default()))?;
+        self.unindex_alias(old);
+        let new = self.intern(new);
+        self.alias.insert(new.clone(), id);
+        self.index_alias(new.clone());
+        self.forwarding.remove(&*new);
+        self.invalidate_facades();
+        for target in self.forwarding.values_mut() {
+            if &**target == old {
+                *target = new.clone();
+            }
+        }
+        Ok(id)
+    }
+}
+
+impl SingletonManager {
+    /// Renaming a registration, moving the service, or factory, to the new alias.
+    /// Fails with `Error::ServiceAlreadyExists` if the new alias is already in use.
+    #[track_caller]
+    pub fn rename(&self, old: &str, new: &str) -> Result<()> {
+        let location = Location::caller();
+        let mut registry = self.registry_mut()?;
+        registry.rename(old, new)?;
+        registry.record(Operation::Rename(new.to_string()), old, Some(location));
+        Ok(())
+    }
+
+    /// Renaming a registration, leaving the old alias as a deprecated forwarding alias.
+    /// The old alias keeps resolving to the registration, but getting the service through it is
+    /// logged as a warning, once per call site.
+    /// Registering a new service under the old alias removes the forwarding.
+    #[track_caller]
+    pub fn rename_forwarding(&self, old: &str, new: &str) -> Result<()> {
+        let location = Location::caller();
+        let mut registry = self.registry_mut()?;
+        registry.rename(old, new)?;
+        let (old_alias, new_alias) = (registry.intern(old), registry.intern(new));
+        registry.forwarding.insert(old_alias, new_alias);
+        registry.record(Operation::Rename(new.to_string()), old, Some(location));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Alias, Error, Operation, SingletonManager};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_rename_moves_registration() {
+        let mut manager = SingletonManager::new();
+        manager.set_factory("db", || Box::new(1_u32)).unwrap();
+        let id = manager.service_id("db").unwrap();
+
+        manager.rename("db", "database").unwrap();
+        assert_eq!(
+            Operation::Rename("database".to_string()),
+            manager.history().last().unwrap().operation
+        );
+
+        assert!(!manager.has("db"));
+        assert_eq!(id, manager.service_id("database").unwrap());
+        assert_eq!(1, *manager.get::<u32>("database").unwrap());
+    }
+
+    #[test]
+    fn test_rename_conflicts() {
+        let mut manager = SingletonManager::new();
+        manager.set("a", 1_u32).unwrap();
+        manager.set("b", 2_u32).unwrap();
+
+        assert!(matches!(
+            manager.rename("a", "b"),
+            Err(Error::ServiceAlreadyExists(_))
+        ));
+        assert!(matches!(
+            manager.rename("c", "d"),
+            Err(Error::ServiceDoesNotExist(_, _))
+        ));
+        assert_eq!(1, *manager.get::<u32>("a").unwrap());
+    }
+
+    #[test]
+    fn test_forwarding_alias() {
+        let mut manager = SingletonManager::new();
+        manager.set("v1", 1_u32).unwrap();
+        manager.rename_forwarding("v1", "v2").unwrap();
+        manager.rename_forwarding("v2", "v3").unwrap();
+
+        assert!(manager.has("v1"));
+        assert_eq!("v3", manager.info("v1").unwrap().alias());
+        let warned = |manager: &SingletonManager| {
+            let registry = manager.registry().unwrap();
+            let warned = registry.forwarding_warned.lock().unwrap().len();
+            warned
+        };
+        assert_eq!(0, warned(&manager));
+
+        for _ in 0..2 {
+            assert_eq!(1, *manager.get::<u32>("v1").unwrap());
+        }
+        assert_eq!(1, warned(&manager));
+        assert_eq!(1, *manager.get::<u32>("v2").unwrap());
+        assert_eq!(2, warned(&manager));
+
+        manager.set("v1", 2_u32).unwrap();
+        assert_eq!(2, *manager.get::<u32>("v1").unwrap());
+        assert_eq!(1, *manager.get::<u32>("v2").unwrap());
+    }
+
+    #[test]
+    fn test_aliases_are_interned() {
+        let mut manager = SingletonManager::new();
+        manager.provide(crate::provider_fn("db", || 1_u32)).unwrap();
+        manager.set("cache", 2_u32).unwrap();
+        let cache = {
+            let registry = manager.registry().unwrap();
+            assert!(matches!(
+                registry.alias.get_key_value("db").unwrap().0,
+                Alias::Static(_)
+            ));
+            let index = registry.prefix_index.read().unwrap();
+            let cache = match (
+                registry.alias.get_key_value("cache").unwrap().0,
+                index.get("cache").unwrap(),
+            ) {
+                (Alias::Interned(first), Alias::Interned(second)) => {
+                    assert!(Arc::ptr_eq(first, second));
+                    first.clone()
+                }
+                _ => panic!("Expected interned aliases"),
+            };
+            cache
+        };
+
+        manager.take::<u32>("cache").unwrap();
+        assert_eq!(1, Arc::strong_count(&cache));
+    }
+}
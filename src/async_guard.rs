@@ -0,0 +1,238 @@
+//! # Async guards
+//! Borrowing services from async code without blocking the executor, behind the `tokio` feature.
+//!
+//! `get_ref` and `get_mut` block the thread until the conflicting borrows are released, which on
+//! a tokio worker thread stalls every task scheduled on it. `get_read_async` and
+//! `get_write_async` instead wait on a `tokio::sync::RwLock` of the service, yielding to the
+//! executor, and queueing fairly behind the other async readers and writers, before taking the
+//! tracked borrow, so they still exclude the guards taken by synchronous code.
+//!
+//! Taking a synchronous guard from within a tokio runtime is usually a mistake, so the first
+//! `get_ref` or `get_mut` from each call site running within a runtime logs a warning, naming
+//! the call site. Code deliberately blocking in `spawn_blocking` is warned about as well.
+//! ```
+//! use singleton_manager::SingletonManager;
+//!
+//! let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+//! let mut manager = SingletonManager::new();
+//! manager.set_factory("sessions", || Box::new(Vec::<String>::new())).unwrap();
+//!
+//! runtime.block_on(async {
+//!     manager
+//!         .get_write_async::<Vec<String>>("sessions")
+//!         .await
+//!         .unwrap()
+//!         .push("3f2a".to_string());
+//!     let sessions = manager.get_read_async::<Vec<String>>("sessions").await.unwrap();
+//!     assert_eq!(vec!["3f2a".to_string()], *sessions);
+//! });
+//! ```
+use crate::{Error, GetError, Result, ServiceRef, ServiceRefMut, SingletonManager};
+use std::fmt::{Debug, Formatter};
+use std::future::{poll_fn, Future};
+use std::ops::{Deref, DerefMut};
+use std::panic::Location;
+use std::sync::Arc;
+use std::task::Poll;
+use tokio::sync::{OwnedRwLockReadGuard, OwnedRwLockWriteGuard, RwLock};
+
+/// A shared borrow of a service taken by `SingletonManager::get_read_async`, released when
+/// dropped.
+pub struct AsyncServiceRef<'a, T> {
+    service: ServiceRef<'a, T>,
+    _lock: OwnedRwLockReadGuard<()>,
+}
+
+impl<T> Deref for AsyncServiceRef<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.service
+    }
+}
+
+impl<T: Debug> Debug for AsyncServiceRef<'_, T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.service.fmt(f)
+    }
+}
+
+/// A mutable borrow of a service taken by `SingletonManager::get_write_async`, released when
+/// dropped.
+pub struct AsyncServiceRefMut<'a, T> {
+    service: ServiceRefMut<'a, T>,
+    _lock: OwnedRwLockWriteGuard<()>,
+}
+
+impl<T> Deref for AsyncServiceRefMut<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.service
+    }
+}
+
+impl<T> DerefMut for AsyncServiceRefMut<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.service
+    }
+}
+
+impl<T: Debug> Debug for AsyncServiceRefMut<'_, T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.service.fmt(f)
+    }
+}
+
+impl SingletonManager {
+    /// Getting a shared reference to the service, as `get_ref` does, but waiting for the
+    /// conflicting borrows without blocking the thread.
+    #[track_caller]
+    pub fn get_read_async<'a, T: 'static>(
+        &'a self,
+        service_name: &'a str,
+    ) -> impl Future<Output = std::result::Result<AsyncServiceRef<'a, T>, GetError>> + 'a {
+        let location = Location::caller();
+        async move {
+            let lock = self.async_lock(service_name, location)?.read_owned().await;
+            let service = poll_fn(|cx| {
+                pending_if_borrowed(self.shared_borrow::<T>(service_name, location, |state| {
+                    state.try_borrow_or_wake(service_name, location, cx.waker())
+                }))
+            })
+            .await
+            .map_err(|e| GetError::from_error(e, service_name))?;
+            Ok(AsyncServiceRef {
+                service,
+                _lock: lock,
+            })
+        }
+    }
+
+    /// Getting an exclusive reference to the service, as `get_mut` does, but waiting for the
+    /// other borrows without blocking the thread.
+    #[track_caller]
+    pub fn get_write_async<'a, T: 'static>(
+        &'a self,
+        service_name: &'a str,
+    ) -> impl Future<Output = std::result::Result<AsyncServiceRefMut<'a, T>, GetError>> + 'a {
+        let location = Location::caller();
+        async move {
+            let lock = self.async_lock(service_name, location)?.write_owned().await;
+            let service = poll_fn(|cx| {
+                pending_if_borrowed(self.exclusive_borrow::<T>(service_name, location, |state| {
+                    state.try_borrow_mut_or_wake(service_name, location, cx.waker())
+                }))
+            })
+            .await
+            .map_err(|e| GetError::from_error(e, service_name))?;
+            Ok(AsyncServiceRefMut {
+                service,
+                _lock: lock,
+            })
+        }
+    }
+
+    /// The lock the async borrows of the service queue on.
+    fn async_lock(
+        &self,
+        service_name: &str,
+        location: &'static Location<'static>,
+    ) -> std::result::Result<Arc<RwLock<()>>, GetError> {
+        let id = self
+            .serving_id(service_name, location)
+            .map_err(|e| GetError::from_error(e, service_name))?;
+        self.borrow_state(&id, service_name)
+            .map(|state| state.async_lock())
+            .map_err(|e| GetError::from_error(e, service_name))
+    }
+
+    /// Warning once per call site about a synchronous guard taken within a tokio runtime.
+    pub(crate) fn lint_sync_guard(&self, service_name: &str, location: &'static Location<'static>) {
+        if tokio::runtime::Handle::try_current().is_err() {
+            return;
+        }
+        let first = self.registry().is_ok_and(|registry| {
+            registry
+                .sync_guard_warnings
+                .lock()
+                .is_ok_and(|mut warned| warned.insert(location))
+        });
+        if first {
+            log::warn!(
+                "Service `{}` is borrowed by a blocking guard within an async runtime at {}, \
+                 use `get_read_async` or `get_write_async` instead",
+                service_name,
+                location
+            );
+        }
+    }
+}
+
+/// Waiting for the borrow to be released if the service is borrowed.
+fn pending_if_borrowed<T>(borrowed: Result<T>) -> Poll<Result<T>> {
+    match borrowed {
+        Err(Error::AlreadyBorrowed(_, _)) => Poll::Pending,
+        borrowed => Poll::Ready(borrowed),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::SingletonManager;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_async_guards_yield_to_other_borrows() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let manager = Arc::new(SingletonManager::new());
+        manager.set_send_factory("log", Vec::<u32>::new).unwrap();
+
+        runtime.block_on(async {
+            let read = manager.get_read_async::<Vec<u32>>("log").await.unwrap();
+            let writer = tokio::spawn({
+                let manager = manager.clone();
+                async move {
+                    manager
+                        .get_write_async::<Vec<u32>>("log")
+                        .await
+                        .unwrap()
+                        .push(1);
+                }
+            });
+            tokio::task::yield_now().await;
+            assert!(!writer.is_finished());
+            drop(read);
+            writer.await.unwrap();
+
+            let held = manager.borrow_mut::<Vec<u32>>("log").unwrap();
+            let reader = tokio::spawn({
+                let manager = manager.clone();
+                async move {
+                    manager
+                        .get_read_async::<Vec<u32>>("log")
+                        .await
+                        .unwrap()
+                        .len()
+                }
+            });
+            tokio::task::yield_now().await;
+            assert!(!reader.is_finished());
+            drop(held);
+            assert_eq!(1, reader.await.unwrap());
+
+            assert!(manager
+                .get_read_async::<Vec<u32>>("missing")
+                .await
+                .unwrap_err()
+                .is_not_found());
+            for _ in 0..2 {
+                drop(manager.get_ref::<Vec<u32>>("log").unwrap());
+            }
+            let registry = manager.registry().unwrap();
+            assert_eq!(1, registry.sync_guard_warnings.lock().unwrap().len());
+        });
+    }
+}
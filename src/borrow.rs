@@ -0,0 +1,626 @@
+//! # Borrow tracking
+//! Runtime tracking of the borrows handed out by `SingletonManager::borrow` and
+//! `SingletonManager::borrow_mut`, similar to what a `RefCell` does for a single value.
+//! A conflicting borrow will fail with `GetError::AlreadyBorrowed`, naming the location of the code
+//! holding the other borrow, instead of silently aliasing the service.
+//!
+//! The same counters are used by `SingletonManager::get_ref` and `SingletonManager::get_mut`, which
+//! instead of failing will block until the conflicting borrows are released, like a `RwLock`.
+use crate::{Alias, Error, HoldHistogram, Result};
+use std::fmt::{Debug, Formatter};
+use std::ops::{Deref, DerefMut};
+use std::panic::Location;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::task::Waker;
+use std::time::{Duration, Instant};
+
+/// The borrow counters of a single service.
+#[derive(Debug, Default)]
+pub(crate) struct BorrowState {
+    /// The alias the service was registered under, shared by the errors about it.
+    alias: Alias,
+    borrows: Mutex<Borrows>,
+    /// Notified every time a borrow is released.
+    released: Condvar,
+    /// The references to the instance handed out without tracking, counted in debug builds.
+    raw_references: Mutex<RawReferences>,
+    /// Counting the instances stored for the service, so the guards can check in debug builds
+    /// that the instance they borrow is not replaced.
+    instance_generation: AtomicU64,
+    /// Recording the hold times of the guards, see `ManagerConfig::metrics`.
+    metrics: bool,
+    /// The lock the async borrows queue on, see `SingletonManager::get_read_async`.
+    #[cfg(feature = "tokio")]
+    async_lock: Arc<tokio::sync::RwLock<()>>,
+}
+
+/// The references handed out without tracking since an exclusive access of the manager, which
+/// ends the references handed out before.
+#[derive(Debug, Default)]
+struct RawReferences {
+    epoch: u64,
+    count: usize,
+}
+
+impl RawReferences {
+    fn at(&self, epoch: u64) -> usize {
+        if self.epoch == epoch {
+            self.count
+        } else {
+            0
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Borrows {
+    /// Location of the holder of the mutable borrow, if any.
+    exclusive: Option<&'static Location<'static>>,
+    /// Locations of the holders of the shared borrows.
+    shared: Vec<&'static Location<'static>>,
+    /// The tasks waiting for a borrow to be released.
+    waiting: Vec<Waker>,
+    /// How long the guards of the service were held.
+    hold_times: HoldHistogram,
+    /// The deadline of the mutable borrow, see `SingletonManager::get_guard_with_deadline`.
+    deadline: Option<Deadline>,
+}
+
+/// The deadline of a mutable borrow, reported once to the borrows contending with it.
+#[derive(Debug, Clone, Copy)]
+struct Deadline {
+    due: Instant,
+    deadline: Duration,
+    reported: bool,
+}
+
+impl Borrows {
+    /// Waking the task once a borrow is released, registering each task once however often it
+    /// is polled.
+    fn wake_on_release(&mut self, waker: &Waker) {
+        if !self.waiting.iter().any(|waiting| waiting.will_wake(waker)) {
+            self.waiting.push(waker.clone());
+        }
+    }
+
+    /// Warning once if the mutable borrow is held beyond its deadline, as a borrow taken at
+    /// the location contends with it.
+    fn report_overdue(&mut self, alias: &str, location: &'static Location<'static>) {
+        let (deadline, holder) = match (&mut self.deadline, self.exclusive) {
+            (Some(deadline), Some(holder)) => (deadline, holder),
+            _ => return,
+        };
+        if deadline.reported || deadline.due > Instant::now() {
+            return;
+        }
+        deadline.reported = true;
+        log::warn!(
+            "Service `{}` is held by {} beyond its deadline of {:?}, blocking {}",
+            alias,
+            holder,
+            deadline.deadline,
+            location
+        );
+    }
+}
+
+impl BorrowState {
+    pub(crate) fn new(alias: Alias, metrics: bool) -> Self {
+        Self {
+            alias,
+            metrics,
+            ..Self::default()
+        }
+    }
+
+    /// The alias for an error about the service, the registered alias if it is the one used.
+    pub(crate) fn alias_for(&self, name: &str) -> Alias {
+        match self.alias == *name {
+            true => self.alias.clone(),
+            false => name.into(),
+        }
+    }
+
+    fn borrows(&self) -> MutexGuard<'_, Borrows> {
+        self.borrows.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub(crate) fn try_borrow(
+        &self,
+        name: &str,
+        location: &'static Location<'static>,
+    ) -> Result<()> {
+        let mut borrows = self.borrows();
+        match borrows.exclusive {
+            Some(holder) => {
+                borrows.report_overdue(&self.alias, location);
+                Err(Error::AlreadyBorrowed(self.alias_for(name), holder))
+            }
+            None => {
+                borrows.shared.push(location);
+                Ok(())
+            }
+        }
+    }
+
+    pub(crate) fn try_borrow_mut(
+        &self,
+        name: &str,
+        location: &'static Location<'static>,
+    ) -> Result<()> {
+        let mut borrows = self.borrows();
+        match borrows.exclusive.or_else(|| borrows.shared.last().copied()) {
+            Some(holder) => {
+                borrows.report_overdue(&self.alias, location);
+                Err(Error::AlreadyBorrowed(self.alias_for(name), holder))
+            }
+            None => {
+                borrows.exclusive = Some(location);
+                Ok(())
+            }
+        }
+    }
+
+    /// Blocks until a borrow is released, waking up at the deadline of the mutable borrow to
+    /// report it if it is still held then.
+    fn wait_released<'a>(
+        &self,
+        mut borrows: MutexGuard<'a, Borrows>,
+        location: &'static Location<'static>,
+    ) -> MutexGuard<'a, Borrows> {
+        borrows.report_overdue(&self.alias, location);
+        match borrows.deadline.filter(|deadline| !deadline.reported) {
+            Some(deadline) => {
+                let remaining = deadline.due.saturating_duration_since(Instant::now());
+                self.released
+                    .wait_timeout(borrows, remaining)
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .0
+            }
+            None => self
+                .released
+                .wait(borrows)
+                .unwrap_or_else(PoisonError::into_inner),
+        }
+    }
+
+    /// Blocks until the service is no longer mutably borrowed, then borrows it.
+    pub(crate) fn wait_borrow(&self, location: &'static Location<'static>) {
+        let mut borrows = self.borrows();
+        while borrows.exclusive.is_some() {
+            borrows = self.wait_released(borrows, location);
+        }
+        borrows.shared.push(location);
+    }
+
+    /// Borrows the service, or wakes the task once a borrow is released.
+    #[cfg(feature = "tokio")]
+    pub(crate) fn try_borrow_or_wake(
+        &self,
+        name: &str,
+        location: &'static Location<'static>,
+        waker: &Waker,
+    ) -> Result<()> {
+        let mut borrows = self.borrows();
+        match borrows.exclusive {
+            Some(holder) => {
+                borrows.report_overdue(&self.alias, location);
+                borrows.wake_on_release(waker);
+                Err(Error::AlreadyBorrowed(self.alias_for(name), holder))
+            }
+            None => {
+                borrows.shared.push(location);
+                Ok(())
+            }
+        }
+    }
+
+    /// Mutably borrows the service, or wakes the task once a borrow is released.
+    pub(crate) fn try_borrow_mut_or_wake(
+        &self,
+        name: &str,
+        location: &'static Location<'static>,
+        waker: &Waker,
+    ) -> Result<()> {
+        let mut borrows = self.borrows();
+        match borrows.exclusive.or_else(|| borrows.shared.last().copied()) {
+            Some(holder) => {
+                borrows.report_overdue(&self.alias, location);
+                borrows.wake_on_release(waker);
+                Err(Error::AlreadyBorrowed(self.alias_for(name), holder))
+            }
+            None => {
+                borrows.exclusive = Some(location);
+                Ok(())
+            }
+        }
+    }
+
+    /// Blocks until the service is no longer borrowed, then mutably borrows it.
+    pub(crate) fn wait_borrow_mut(&self, location: &'static Location<'static>) {
+        let mut borrows = self.borrows();
+        while borrows.exclusive.is_some() || !borrows.shared.is_empty() {
+            borrows = self.wait_released(borrows, location);
+        }
+        borrows.exclusive = Some(location);
+    }
+
+    /// Fails if there is any outstanding borrow of the service.
+    pub(crate) fn check_unborrowed(&self, name: &str) -> Result<()> {
+        let borrows = self.borrows();
+        match borrows.exclusive.or_else(|| borrows.shared.last().copied()) {
+            Some(holder) => Err(Error::AlreadyBorrowed(self.alias_for(name), holder)),
+            None => Ok(()),
+        }
+    }
+
+    /// True if there is any outstanding borrow of the service.
+    pub(crate) fn is_borrowed(&self) -> bool {
+        let borrows = self.borrows();
+        borrows.exclusive.is_some() || !borrows.shared.is_empty()
+    }
+
+    fn raw(&self) -> MutexGuard<'_, RawReferences> {
+        self.raw_references
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Counting a reference to the instance handed out without tracking.
+    /// The references counted before the exclusive access `epoch` of the manager have ended.
+    pub(crate) fn note_raw_reference(&self, epoch: u64) {
+        let mut raw = self.raw();
+        if raw.epoch != epoch {
+            *raw = RawReferences { epoch, count: 0 };
+        }
+        raw.count += 1;
+    }
+
+    /// The number of references to the instance handed out without tracking since the
+    /// exclusive access `epoch` of the manager.
+    pub(crate) fn raw_references(&self, epoch: u64) -> usize {
+        self.raw().at(epoch)
+    }
+
+    /// Resetting the number of references handed out without tracking, when the instance is
+    /// dropped, returning the number of references since the exclusive access `epoch`.
+    pub(crate) fn take_raw_references(&self, epoch: u64) -> usize {
+        std::mem::take(&mut *self.raw()).at(epoch)
+    }
+
+    /// Starting a new generation of the instance, when it is stored or removed.
+    pub(crate) fn replace_instance(&self) {
+        self.instance_generation.fetch_add(1, Ordering::AcqRel);
+    }
+
+    fn instance_generation(&self) -> u64 {
+        self.instance_generation.load(Ordering::Acquire)
+    }
+
+    /// Checking, in debug builds, that the instance borrowed at the generation is still stored.
+    fn check_generation(&self, generation: u64) {
+        debug_assert_eq!(
+            generation,
+            self.instance_generation(),
+            "Service `{}` was replaced while borrowed",
+            self.alias
+        );
+    }
+
+    /// The number of outstanding borrows of the service.
+    pub(crate) fn handles(&self) -> usize {
+        let borrows = self.borrows();
+        borrows.shared.len() + usize::from(borrows.exclusive.is_some())
+    }
+
+    #[cfg(feature = "tokio")]
+    pub(crate) fn async_lock(&self) -> Arc<tokio::sync::RwLock<()>> {
+        self.async_lock.clone()
+    }
+
+    /// Setting the deadline of the mutable borrow, from now.
+    fn set_deadline(&self, deadline: Duration) {
+        self.borrows().deadline = Some(Deadline {
+            due: Instant::now() + deadline,
+            deadline,
+            reported: false,
+        });
+    }
+
+    /// The histogram of how long the guards of the service were held.
+    pub(crate) fn hold_histogram(&self) -> HoldHistogram {
+        self.borrows().hold_times.clone()
+    }
+
+    /// Releasing the borrow of a guard, recording how long it was held.
+    fn release_guard(&self, location: Option<&'static Location<'static>>, acquired: Instant) {
+        let mut borrows = self.borrows();
+        if self.metrics {
+            borrows.hold_times.record(acquired.elapsed());
+        }
+        match location {
+            Some(location) => Self::remove_shared(&mut borrows, location),
+            None => {
+                borrows.exclusive = None;
+                borrows.deadline = None;
+            }
+        }
+        self.notify_released(borrows);
+    }
+
+    fn remove_shared(borrows: &mut Borrows, location: &'static Location<'static>) {
+        if let Some(index) = borrows.shared.iter().rposition(|l| *l == location) {
+            borrows.shared.remove(index);
+        }
+    }
+
+    pub(crate) fn release(&self, location: &'static Location<'static>) {
+        let mut borrows = self.borrows();
+        Self::remove_shared(&mut borrows, location);
+        self.notify_released(borrows);
+    }
+
+    pub(crate) fn release_mut(&self) {
+        let mut borrows = self.borrows();
+        borrows.exclusive = None;
+        borrows.deadline = None;
+        self.notify_released(borrows);
+    }
+
+    /// Waking the threads and tasks waiting for a borrow to be released, after unlocking.
+    fn notify_released(&self, mut borrows: MutexGuard<'_, Borrows>) {
+        let waiting = std::mem::take(&mut borrows.waiting);
+        drop(borrows);
+        self.released.notify_all();
+        waiting.into_iter().for_each(Waker::wake);
+    }
+}
+
+/// A shared borrow of a service, released when dropped.
+pub struct ServiceRef<'a, T: ?Sized> {
+    service: &'a T,
+    state: Arc<BorrowState>,
+    location: &'static Location<'static>,
+    acquired: Instant,
+    generation: u64,
+}
+
+impl<'a, T: ?Sized> ServiceRef<'a, T> {
+    pub(crate) fn new(
+        service: &'a T,
+        state: Arc<BorrowState>,
+        location: &'static Location<'static>,
+    ) -> Self {
+        Self {
+            service,
+            generation: state.instance_generation(),
+            state,
+            location,
+            acquired: Instant::now(),
+        }
+    }
+}
+
+impl<T: ?Sized> Deref for ServiceRef<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.state.check_generation(self.generation);
+        self.service
+    }
+}
+
+impl<T: ?Sized> Drop for ServiceRef<'_, T> {
+    fn drop(&mut self) {
+        self.state.release_guard(Some(self.location), self.acquired)
+    }
+}
+
+impl<T: Debug + ?Sized> Debug for ServiceRef<'_, T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.service.fmt(f)
+    }
+}
+
+/// A mutable borrow of a service, released when dropped.
+pub struct ServiceRefMut<'a, T: ?Sized> {
+    service: &'a mut T,
+    state: Arc<BorrowState>,
+    acquired: Instant,
+    generation: u64,
+}
+
+impl<'a, T: ?Sized> ServiceRefMut<'a, T> {
+    pub(crate) fn new(service: &'a mut T, state: Arc<BorrowState>) -> Self {
+        Self {
+            service,
+            generation: state.instance_generation(),
+            state,
+            acquired: Instant::now(),
+        }
+    }
+
+    /// Expecting the guard to be dropped before the deadline, reporting it to the borrows
+    /// contending with it otherwise.
+    pub(crate) fn set_deadline(&self, deadline: Duration) {
+        self.state.set_deadline(deadline);
+    }
+}
+
+impl<T: ?Sized> Deref for ServiceRefMut<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.state.check_generation(self.generation);
+        self.service
+    }
+}
+
+impl<T: ?Sized> DerefMut for ServiceRefMut<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.state.check_generation(self.generation);
+        self.service
+    }
+}
+
+impl<T: ?Sized> Drop for ServiceRefMut<'_, T> {
+    fn drop(&mut self) {
+        self.state.release_guard(None, self.acquired)
+    }
+}
+
+impl<T: Debug + ?Sized> Debug for ServiceRefMut<'_, T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.service.fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::BorrowState;
+    use crate::{GetError, SingletonManager};
+    use std::panic::Location;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Barrier};
+    use std::task::{Wake, Waker};
+    use std::thread;
+    use std::time::Duration;
+
+    /// A task counting how often it is woken.
+    #[derive(Default)]
+    struct Task(AtomicUsize);
+
+    impl Wake for Task {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn test_polling_registers_the_waker_once() {
+        let task = Arc::new(Task::default());
+        let waker = Waker::from(task.clone());
+        let state = BorrowState::new("counter".into(), false);
+        let location = Location::caller();
+        state.try_borrow_mut("counter", location).unwrap();
+        for _ in 0..3 {
+            assert!(state
+                .try_borrow_mut_or_wake("counter", location, &waker)
+                .is_err());
+        }
+        assert_eq!(1, state.borrows().waiting.len());
+        state.release_mut();
+        assert_eq!(1, task.0.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn test_contenders_report_borrows_held_beyond_their_deadline() {
+        let state = BorrowState::new("counter".into(), false);
+        let location = Location::caller();
+        state.try_borrow_mut("counter", location).unwrap();
+        state.set_deadline(Duration::from_millis(1));
+
+        thread::scope(|s| {
+            let waiting = s.spawn(|| state.wait_borrow(location));
+            while !state.borrows().deadline.is_some_and(|d| d.reported) {
+                thread::sleep(Duration::from_millis(1));
+            }
+            state.release_mut();
+            waiting.join().unwrap();
+        });
+        assert!(state.borrows().deadline.is_none());
+        assert!(state.try_borrow_mut("counter", location).is_err());
+    }
+
+    #[test]
+    fn test_shared_borrows() {
+        let mut manager = SingletonManager::new();
+        manager.set_factory("counter", || Box::new(1_u32)).unwrap();
+
+        let first = manager.borrow::<u32>("counter").unwrap();
+        let second = manager.borrow::<u32>("counter").unwrap();
+        assert_eq!(2, *first + *second);
+    }
+
+    #[test]
+    fn test_conflicting_borrows_report_holder() {
+        let mut manager = SingletonManager::new();
+        manager.set_factory("counter", || Box::new(1_u32)).unwrap();
+
+        let mut service = manager.borrow_mut::<u32>("counter").unwrap();
+        *service += 1;
+        let holder_line = line!() - 2;
+
+        match manager.borrow::<u32>("counter") {
+            Err(GetError::AlreadyBorrowed(name, holder)) => {
+                assert_eq!("counter", name);
+                assert_eq!(holder_line, holder.line());
+                assert_eq!(file!(), holder.file());
+            }
+            _ => panic!("Expected the service to be borrowed"),
+        }
+        assert!(manager.borrow_mut::<u32>("counter").is_err());
+
+        drop(service);
+        assert_eq!(2, *manager.borrow::<u32>("counter").unwrap());
+        assert!(manager.borrow_mut::<u32>("counter").is_ok());
+    }
+
+    #[test]
+    fn test_mutable_borrow_conflicts_with_shared_borrow() {
+        let mut manager = SingletonManager::new();
+        manager.set_factory("counter", || Box::new(1_u32)).unwrap();
+
+        let shared = manager.borrow::<u32>("counter").unwrap();
+        assert!(matches!(
+            manager.borrow_mut::<u32>("counter"),
+            Err(GetError::AlreadyBorrowed(_, _))
+        ));
+        drop(shared);
+        assert!(manager.borrow_mut::<u32>("counter").is_ok());
+    }
+
+    #[test]
+    fn test_concurrent_readers_and_exclusive_writer() {
+        let alias = "borrow_test_concurrent_readers";
+        crate::sm().set_factory(alias, || Box::new(0_u32)).unwrap();
+
+        let readers = 4;
+        let barrier = Arc::new(Barrier::new(readers));
+        let handles = (0..readers)
+            .map(|_| {
+                let barrier = barrier.clone();
+                thread::spawn(move || {
+                    let service = crate::sm().get_ref::<u32>(alias).unwrap();
+                    // All readers must be holding their borrow at the same time to pass.
+                    barrier.wait();
+                    *service
+                })
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            assert_eq!(0, handle.join().unwrap());
+        }
+
+        let mut service = crate::sm().get_mut::<u32>(alias).unwrap();
+        let writer = thread::spawn(move || {
+            *crate::sm().get_mut::<u32>(alias).unwrap() += 1;
+        });
+        thread::sleep(Duration::from_millis(20));
+        *service += 1;
+        drop(service);
+        writer.join().unwrap();
+
+        assert_eq!(2, *crate::sm().get_ref::<u32>(alias).unwrap());
+    }
+
+    #[test]
+    fn test_failed_downcast_does_not_keep_borrow() {
+        let mut manager = SingletonManager::new();
+        manager.set_factory("counter", || Box::new(1_u32)).unwrap();
+
+        assert!(manager.borrow_mut::<String>("counter").is_err());
+        assert!(manager.borrow_mut::<u32>("counter").is_ok());
+    }
+}
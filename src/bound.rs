@@ -0,0 +1,54 @@
+//! # Trait bindings
+//! Binding services as trait objects, resolved by the trait instead of an alias.
+//!
+//! A service bound as `dyn Trait` is stored as a `Box<dyn Trait>` under the alias of the trait,
+//! so the code using it only depends on the trait, and the implementation can be swapped, e.g.
+//! for a test double.
+//! ```
+//! use singleton_manager::SingletonManager;
+//!
+//! trait Greeter: Send + Sync {
+//!     fn greet(&self) -> String;
+//! }
+//!
+//! struct English;
+//!
+//! impl Greeter for English {
+//!     fn greet(&self) -> String {
+//!         "hello".to_string()
+//!     }
+//! }
+//!
+//! let manager = SingletonManager::new();
+//! manager.bind::<dyn Greeter>(Box::new(English)).unwrap();
+//! assert_eq!("hello", manager.get_bound::<dyn Greeter>().unwrap().greet());
+//! ```
+use crate::{GetError, ServiceRef, SetError, SingletonManager};
+use std::panic::Location;
+
+/// Getting the alias a service bound as a `T` is registered under.
+pub fn bound_alias<T: ?Sized + 'static>() -> &'static str {
+    std::any::type_name::<T>()
+}
+
+impl SingletonManager {
+    /// Binding a service as a `T`, usually a `dyn Trait`.
+    #[track_caller]
+    pub fn bind<T: ?Sized + Send + Sync + 'static>(
+        &self,
+        service: Box<T>,
+    ) -> std::result::Result<(), SetError> {
+        let alias = bound_alias::<T>();
+        self.store(alias, service, &[], Location::caller())
+            .map(|_| ())
+            .map_err(|e| SetError::from_error(e, alias))
+    }
+
+    /// Getting a shared reference to the service bound as a `T`, as `get_ref` does.
+    #[track_caller]
+    pub fn get_bound<T: ?Sized + 'static>(
+        &self,
+    ) -> std::result::Result<ServiceRef<'_, Box<T>>, GetError> {
+        self.get_ref_by_alias(bound_alias::<T>())
+    }
+}
@@ -0,0 +1,264 @@
+//! # Builder
+//! Assembling a fully populated singleton manager before it is used.
+//!
+//! Setting up the global instance service by service leaves a window during startup where the
+//! manager is reachable but only partially populated. The builder collects the services and
+//! factories, and only hands out the manager, or publishes it as the global instance, once all
+//! of them are registered.
+//!
+//! Services can be registered for a profile only, e.g. `prod` or `dev`, where they are only
+//! registered if the profile is the one selected with `with_profile`.
+//! ```
+//! use singleton_manager::SingletonManager;
+//!
+//! let manager = SingletonManager::builder()
+//!     .with_service("cfg", "production".to_string())
+//!     .with_factory("db", || Box::new("postgres://prod".to_string()))
+//!     .with_factory_in("dev", "db", || Box::new("sqlite::memory:".to_string()))
+//!     .with_profile("prod")
+//!     .build()
+//!     .unwrap();
+//!
+//! assert_eq!(Some("prod".to_string()), manager.profile());
+//! assert_eq!("postgres://prod", *manager.get_ref::<String>("db").unwrap());
+//! ```
+use crate::clock::SharedClock;
+use crate::{
+    ClockAndIds, Error, IdGenerator, ManagerConfig, Result, SingletonManager, SystemClock,
+    INSTANCE, ONCE,
+};
+use std::any::Any;
+use std::collections::HashSet;
+use std::panic::Location;
+use std::ptr::addr_of_mut;
+
+/// Registering a service or factory on the manager being built.
+type Register = Box<dyn FnOnce(&SingletonManager) -> Result<()>>;
+
+/// A registration collected by the builder.
+struct Entry {
+    /// The alias registered.
+    alias: String,
+    /// The profile the registration is restricted to.
+    profile: Option<String>,
+    register: Register,
+}
+
+/// Builder of a `SingletonManager`, see `SingletonManager::builder`.
+#[derive(Default)]
+pub struct SingletonManagerBuilder {
+    clock_and_ids: SharedClock,
+    profile: Option<String>,
+    config: ManagerConfig,
+    entries: Vec<Entry>,
+}
+
+impl SingletonManagerBuilder {
+    /// Using the given `IdGenerator` for the ids linking the aliases to the singleton storage.
+    pub fn with_id_generator(self, id_generator: impl IdGenerator + 'static) -> Self {
+        self.with_clock_and_ids(SystemClock::new(id_generator))
+    }
+
+    /// Using the given `ClockAndIds` for the timestamps, expiry checks and ids of the manager.
+    pub fn with_clock_and_ids(mut self, clock_and_ids: impl ClockAndIds + 'static) -> Self {
+        self.clock_and_ids = SharedClock::new(clock_and_ids);
+        self
+    }
+
+    /// Selecting the profile, only the registrations without a profile or for this profile are
+    /// registered.
+    pub fn with_profile(mut self, profile: &str) -> Self {
+        self.profile = Some(profile.to_string());
+        self
+    }
+
+    /// Configuring the behavior of the manager, applying to the registrations of the builder as
+    /// well.
+    pub fn with_config(mut self, config: ManagerConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Registering a service, as `SingletonManager::set` does.
+    #[track_caller]
+    pub fn with_service<T: Send + Sync + 'static>(self, service_name: &str, service: T) -> Self {
+        self.entry(None, service_name, Self::service(service_name, service))
+    }
+
+    /// Registering a factory, as `SingletonManager::set_factory` does.
+    #[track_caller]
+    pub fn with_factory<F: 'static + Fn() -> Box<dyn Any + Send + Sync> + Send + Sync>(
+        self,
+        service_name: &str,
+        factory: F,
+    ) -> Self {
+        self.entry(None, service_name, Self::factory(service_name, factory))
+    }
+
+    /// Registering a service only if the profile is selected.
+    /// Takes precedence over a registration of the same alias without a profile.
+    #[track_caller]
+    pub fn with_service_in<T: Send + Sync + 'static>(
+        self,
+        profile: &str,
+        service_name: &str,
+        service: T,
+    ) -> Self {
+        self.entry(
+            Some(profile),
+            service_name,
+            Self::service(service_name, service),
+        )
+    }
+
+    /// Registering a factory only if the profile is selected.
+    /// Takes precedence over a registration of the same alias without a profile.
+    #[track_caller]
+    pub fn with_factory_in<F: 'static + Fn() -> Box<dyn Any + Send + Sync> + Send + Sync>(
+        self,
+        profile: &str,
+        service_name: &str,
+        factory: F,
+    ) -> Self {
+        self.entry(
+            Some(profile),
+            service_name,
+            Self::factory(service_name, factory),
+        )
+    }
+
+    /// Building the manager with all registrations of the selected profile.
+    /// Fails if any registration fails, e.g. with `Error::ServiceAlreadyExists` if an alias is
+    /// registered twice for the same profile.
+    pub fn build(self) -> Result<SingletonManager> {
+        let manager = SingletonManager::with_shared_clock(self.clock_and_ids);
+        {
+            let mut registry = manager.registry_mut()?;
+            registry.collision_policy = self.config.collision_policy;
+            registry.config = self.config;
+        }
+        let profile = self.profile;
+        let (profiled, common): (Vec<_>, Vec<_>) = self
+            .entries
+            .into_iter()
+            .filter(|entry| entry.profile.is_none() || entry.profile == profile)
+            .partition(|entry| entry.profile.is_some());
+        let overridden: HashSet<String> =
+            profiled.iter().map(|entry| entry.alias.clone()).collect();
+        for entry in profiled {
+            (entry.register)(&manager)?;
+        }
+        for entry in common {
+            if !overridden.contains(&entry.alias) {
+                (entry.register)(&manager)?;
+            }
+        }
+        manager.registry_mut()?.profile = profile;
+        Ok(manager)
+    }
+
+    /// Building the manager and publishing it as the global instance, returned by
+    /// `SingletonManager::instance` and `sm()`.
+    /// Fails with `Error::InstanceAlreadyInitialized` if the global instance is already in use.
+    pub fn build_global(self) -> Result<&'static mut SingletonManager> {
+        let mut manager = Some(self.build()?);
+        unsafe { ONCE.call_once(|| *addr_of_mut!(INSTANCE) = manager.take()) };
+        if manager.is_some() {
+            return Err(Error::InstanceAlreadyInitialized);
+        }
+        Ok(SingletonManager::instance())
+    }
+
+    fn entry(mut self, profile: Option<&str>, service_name: &str, register: Register) -> Self {
+        self.entries.push(Entry {
+            alias: service_name.to_string(),
+            profile: profile.map(str::to_string),
+            register,
+        });
+        self
+    }
+
+    #[track_caller]
+    fn service<T: Send + Sync + 'static>(service_name: &str, service: T) -> Register {
+        let location = Location::caller();
+        let service_name = service_name.to_string();
+        Box::new(move |manager| {
+            manager
+                .store(&service_name, service, &[], location)
+                .map(|_| ())
+        })
+    }
+
+    #[track_caller]
+    fn factory<F: 'static + Fn() -> Box<dyn Any + Send + Sync> + Send + Sync>(
+        service_name: &str,
+        factory: F,
+    ) -> Register {
+        let location = Location::caller();
+        let service_name = service_name.to_string();
+        Box::new(move |manager| {
+            manager
+                .store_factory_with_default_timeout(&service_name, factory, location)
+                .map(|_| ())
+        })
+    }
+}
+
+impl SingletonManager {
+    /// Creating a builder assembling a manager before it is used.
+    pub fn builder() -> SingletonManagerBuilder {
+        SingletonManagerBuilder::default()
+    }
+
+    /// The profile selected when building the manager.
+    pub fn profile(&self) -> Option<String> {
+        self.registry()
+            .ok()
+            .and_then(|registry| registry.profile.clone())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Error, SequentialIdGenerator, SingletonManager, Uuid};
+
+    #[test]
+    fn test_builder_selects_profile() {
+        let manager = SingletonManager::builder()
+            .with_id_generator(SequentialIdGenerator::default())
+            .with_service("db", "postgres".to_string())
+            .with_service_in("dev", "db", "sqlite".to_string())
+            .with_service_in("prod", "replica", "postgres".to_string())
+            .with_profile("dev")
+            .build()
+            .unwrap();
+
+        assert_eq!("sqlite", *manager.get_ref::<String>("db").unwrap());
+        assert_eq!(Uuid::from_u128(1), manager.service_id("db").unwrap());
+        assert!(!manager.has("replica"));
+
+        let manager = SingletonManager::builder()
+            .with_service("db", "postgres".to_string())
+            .with_service_in("dev", "db", "sqlite".to_string())
+            .build()
+            .unwrap();
+        assert_eq!(None, manager.profile());
+        assert_eq!("postgres", *manager.get_ref::<String>("db").unwrap());
+
+        assert!(matches!(
+            SingletonManager::builder()
+                .with_service("db", 1_u32)
+                .with_factory("db", || Box::new(2_u32))
+                .build(),
+            Err(Error::ServiceAlreadyExists(_))
+        ));
+        assert!(matches!(
+            SingletonManager::builder()
+                .with_service_in("dev", "db", 1_u32)
+                .with_service_in("dev", "db", 2_u32)
+                .with_profile("dev")
+                .build(),
+            Err(Error::ServiceAlreadyExists(_))
+        ));
+    }
+}
@@ -0,0 +1,151 @@
+//! # Caller restrictions
+//! Restricting the code that may resolve a service.
+//!
+//! Any code holding the singleton manager can resolve any service, which erodes the boundaries
+//! between the parts of an application. A service can be restricted to callers in source files
+//! with one of a list of path prefixes, as reported by `std::panic::Location::file`, e.g.
+//! `crates/billing/` or `src/billing/`. Resolving it from anywhere else fails with
+//! `Error::AccessDenied`.
+//!
+//! The caller is the location of the call to `get`, `get_ref`, `borrow` and their variants,
+//! passed down from the public functions of the singleton manager. Resolutions by the singleton
+//! manager itself, e.g. running the health check of a service, pass the location of the manager
+//! instead, and are not restricted.
+//! ```
+//! use singleton_manager::SingletonManager;
+//!
+//! let mut manager = SingletonManager::new();
+//! manager.set_factory("ledger", || Box::new(0_u64)).unwrap();
+//! manager.restrict_callers("ledger", &["crates/billing/"]).unwrap();
+//! assert_eq!(vec!["crates/billing/"], manager.allowed_callers("ledger"));
+//! ```
+use crate::{Error, Registry, Result, SingletonManager, Uuid};
+use std::panic::Location;
+
+/// The location of the singleton manager itself as caller, never restricted.
+pub(crate) fn manager_caller() -> &'static Location<'static> {
+    Location::caller()
+}
+
+impl Registry {
+    /// Failing with `Error::AccessDenied` if the service is restricted to other callers than the
+    /// caller, unless the caller is the singleton manager itself.
+    pub(crate) fn check_caller(
+        &self,
+        id: &Uuid,
+        alias: &str,
+        location: &'static Location<'static>,
+    ) -> Result<()> {
+        match *location == *manager_caller() {
+            true => Ok(()),
+            false => self.check_caller_file(id, alias, location.file()),
+        }
+    }
+
+    /// Failing with `Error::AccessDenied` if the service is restricted to other callers than the
+    /// source file.
+    fn check_caller_file(&self, id: &Uuid, alias: &str, file: &str) -> Result<()> {
+        match self.allowed_callers.get(id) {
+            Some(prefixes)
+                if !prefixes
+                    .iter()
+                    .any(|prefix| file.starts_with(prefix.as_str())) =>
+            {
+                Err(Error::AccessDenied(alias.into()))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl SingletonManager {
+    /// Restricting the callers resolving the service to the source files starting with one of
+    /// the prefixes, replacing the restriction set before.
+    pub fn restrict_callers(&self, service_name: &str, prefixes: &[&str]) -> Result<()> {
+        let id = self.service_id(service_name)?;
+        self.registry_mut()?.allowed_callers.insert(
+            id,
+            prefixes.iter().map(|prefix| prefix.to_string()).collect(),
+        );
+        Ok(())
+    }
+
+    /// The prefixes of the source files allowed to resolve the service, empty if it is not
+    /// restricted.
+    pub fn allowed_callers(&self, service_name: &str) -> Vec<String> {
+        self.service_id(service_name)
+            .and_then(|id| {
+                Ok(self
+                    .registry()?
+                    .allowed_callers
+                    .get(&id)
+                    .cloned()
+                    .unwrap_or_default())
+            })
+            .unwrap_or_default()
+    }
+
+    /// Failing with `Error::AccessDenied` if the caller may not resolve the service.
+    pub(crate) fn check_caller(
+        &self,
+        id: &Uuid,
+        service_name: &str,
+        location: &'static Location<'static>,
+    ) -> Result<()> {
+        self.registry()?.check_caller(id, service_name, location)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Error, Health, SingletonManager};
+
+    #[test]
+    fn test_callers_outside_the_prefixes_are_denied() {
+        let mut manager = SingletonManager::new();
+        manager.set("ledger", 0_u64).unwrap();
+        manager
+            .restrict_callers("ledger", &["crates/billing/", "crates/admin/src/audit.rs"])
+            .unwrap();
+        let id = manager.service_id("ledger").unwrap();
+        let registry = manager.registry().unwrap();
+
+        let denied = registry.check_caller_file(&id, "ledger", "crates/web/src/routes.rs");
+        assert!(matches!(denied, Err(Error::AccessDenied(alias)) if alias == "ledger"));
+        assert!(registry
+            .check_caller_file(&id, "ledger", "crates/billing/src/invoice.rs")
+            .is_ok());
+        assert!(registry
+            .check_caller_file(&id, "ledger", "crates/admin/src/audit.rs")
+            .is_ok());
+        drop(registry);
+
+        // The sources of this crate are restricted as any other caller.
+        assert!(manager
+            .get_ref::<u64>("ledger")
+            .unwrap_err()
+            .is_access_denied());
+        assert!(manager.get::<u64>("ledger").unwrap_err().is_access_denied());
+        manager.restrict_callers("ledger", &["src/"]).unwrap();
+        assert_eq!(0, *manager.get_ref::<u64>("ledger").unwrap());
+    }
+
+    #[test]
+    fn test_manager_resolutions_are_not_restricted() {
+        let manager = SingletonManager::new();
+        manager.set_send_factory("ledger", || 0_u64).unwrap();
+        manager
+            .set_health_check::<u64, _>("ledger", |_| Err("empty".to_string()))
+            .unwrap();
+        manager.add_to_group("ledger", "books").unwrap();
+        manager.get_ref::<u64>("ledger").unwrap();
+        manager
+            .restrict_callers("ledger", &["crates/billing/"])
+            .unwrap();
+
+        assert_eq!(
+            vec![("ledger".to_string(), Health::Unhealthy("empty".to_string()))],
+            manager.health_of_group("books")
+        );
+    }
+}
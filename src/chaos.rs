@@ -0,0 +1,178 @@
+//! # Chaos testing
+//! Injecting faults into the resolution of services, behind the `chaos` feature.
+//!
+//! Resilience tests need the error paths of the code using the singleton manager to be taken,
+//! without changing that code. Faults injected for an alias make getting the service fail, every
+//! n-th time or at random, or make its factory slow. Random faults are drawn from a seeded
+//! generator, so a failing test can be reproduced with the same seed.
+//! ```
+//! use singleton_manager::{Fault, SingletonManager};
+//!
+//! let mut manager = SingletonManager::new();
+//! manager.set_factory("db", || Box::new("postgres".to_string())).unwrap();
+//! manager.inject_fault("db", Fault::FailEvery(3)).unwrap();
+//!
+//! assert!(manager.get_ref::<String>("db").is_ok());
+//! assert!(manager.get_ref::<String>("db").is_ok());
+//! assert!(manager.get_ref::<String>("db").unwrap_err().is_fault_injected());
+//! ```
+use crate::{Error, Result, SingletonManager};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// The seed of the random faults, unless set with `SingletonManager::set_chaos_seed`.
+const DEFAULT_SEED: u64 = 0x2545_f491_4f6c_dd1d;
+
+/// A fault injected into the resolution of a service.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Fault {
+    /// Failing every n-th get of the service with `Error::FaultInjected`.
+    FailEvery(u64),
+    /// Failing gets of the service at random with the probability, between 0 and 1.
+    FailWithProbability(f64),
+    /// Delaying the factory of the service.
+    DelayFactory(Duration),
+}
+
+/// A fault with the number of gets it has seen.
+struct InjectedFault {
+    fault: Fault,
+    calls: AtomicU64,
+}
+
+/// The faults injected into the resolution of services.
+pub(crate) struct Chaos {
+    faults: HashMap<String, Vec<InjectedFault>>,
+    /// The state of the xorshift generator drawing the random faults.
+    random: AtomicU64,
+}
+
+impl Default for Chaos {
+    fn default() -> Self {
+        Chaos {
+            faults: HashMap::new(),
+            random: AtomicU64::new(DEFAULT_SEED),
+        }
+    }
+}
+
+impl Chaos {
+    /// Drawing a number between 0 and 1.
+    fn next_random(&self) -> f64 {
+        let mut next = 0;
+        let _ = self
+            .random
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |mut x| {
+                x ^= x << 13;
+                x ^= x >> 7;
+                x ^= x << 17;
+                next = x;
+                Some(x)
+            });
+        (next >> 11) as f64 / (1_u64 << 53) as f64
+    }
+
+    /// Failing with `Error::FaultInjected` if a fault of the service is due.
+    pub(crate) fn check_get(&self, service_name: &str) -> Result<()> {
+        let faults = match self.faults.get(service_name) {
+            Some(faults) => faults,
+            None => return Ok(()),
+        };
+        for injected in faults {
+            let fail = match injected.fault {
+                Fault::FailEvery(n) => {
+                    let calls = injected.calls.fetch_add(1, Ordering::Relaxed) + 1;
+                    n > 0 && calls % n == 0
+                }
+                Fault::FailWithProbability(probability) => self.next_random() < probability,
+                Fault::DelayFactory(_) => false,
+            };
+            if fail {
+                return Err(Error::FaultInjected(service_name.into()));
+            }
+        }
+        Ok(())
+    }
+
+    /// The delay of the factory of the service.
+    pub(crate) fn factory_delay(&self, service_name: &str) -> Duration {
+        self.faults
+            .get(service_name)
+            .into_iter()
+            .flatten()
+            .filter_map(|injected| match injected.fault {
+                Fault::DelayFactory(delay) => Some(delay),
+                _ => None,
+            })
+            .sum()
+    }
+}
+
+impl SingletonManager {
+    /// Injecting a fault into the resolution of the service, in addition to the faults already
+    /// injected. The service does not have to be registered yet.
+    pub fn inject_fault(&self, service_name: &str, fault: Fault) -> Result<()> {
+        self.registry_mut()?
+            .chaos
+            .faults
+            .entry(service_name.to_string())
+            .or_default()
+            .push(InjectedFault {
+                fault,
+                calls: AtomicU64::new(0),
+            });
+        Ok(())
+    }
+
+    /// Removing all injected faults.
+    pub fn clear_faults(&self) -> Result<()> {
+        self.registry_mut()?.chaos.faults.clear();
+        Ok(())
+    }
+
+    /// Seeding the generator drawing the random faults, to reproduce a run.
+    pub fn set_chaos_seed(&self, seed: u64) -> Result<()> {
+        // Xorshift never leaves a zero state.
+        let seed = if seed == 0 { DEFAULT_SEED } else { seed };
+        self.registry_mut()?
+            .chaos
+            .random
+            .store(seed, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Fault, SingletonManager};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_injected_faults() {
+        let mut manager = SingletonManager::new();
+        manager.set_factory("cache", || Box::new(1_u32)).unwrap();
+        manager
+            .inject_fault("cache", Fault::DelayFactory(Duration::from_millis(50)))
+            .unwrap();
+        manager
+            .inject_fault("cache", Fault::FailWithProbability(0.5))
+            .unwrap();
+
+        let failures = |manager: &SingletonManager| {
+            (0..100)
+                .filter(|_| manager.get_ref::<u32>("cache").is_err())
+                .count()
+        };
+        manager.set_chaos_seed(7).unwrap();
+        let started = Instant::now();
+        let first = failures(&manager);
+        assert!(started.elapsed() >= Duration::from_millis(50));
+        assert!(first > 20 && first < 80);
+        manager.set_chaos_seed(7).unwrap();
+        assert_eq!(first, failures(&manager));
+
+        manager.clear_faults().unwrap();
+        assert_eq!(0, failures(&manager));
+    }
+}
@@ -0,0 +1,222 @@
+//! # Circuit breakers
+//! Failing fast while the factory of a service keeps failing.
+//!
+//! A factory depending on a broken downstream, e.g. a database that is down, fails on every
+//! `get` of its service, and every `get` hammers the downstream again. With a circuit breaker,
+//! the factory failing a number of times in a row trips the circuit open, and for a cool-down
+//! period getting the service fails with `Error::CircuitOpen`, holding the time until the next
+//! attempt, without running the factory.
+//!
+//! Once the cool-down passed, the next `get` runs the factory again. Succeeding closes the
+//! circuit, failing opens it for another cool-down period. A factory fails when it panics, does
+//! not finish within its budget, or its service is rejected by a validator.
+//! ```
+//! use singleton_manager::{Error, SingletonManager, TestClockAndIds};
+//! use std::time::Duration;
+//!
+//! let clock = TestClockAndIds::default();
+//! let mut manager = SingletonManager::with_clock_and_ids(clock.clone());
+//! manager
+//!     .add_validator(|info, service| match service.downcast_ref::<String>() {
+//!         Some(url) if url.is_empty() => Err(Error::ValidationFailed(
+//!             info.alias().into(),
+//!             "no database".to_string(),
+//!         )),
+//!         _ => Ok(()),
+//!     })
+//!     .unwrap();
+//! manager.set_factory("db", || Box::new(String::new())).unwrap();
+//! manager
+//!     .set_circuit_breaker("db", 2, Duration::from_secs(30))
+//!     .unwrap();
+//!
+//! assert!(manager.get_ref::<String>("db").unwrap_err().is_validation_failed());
+//! assert!(manager.get_ref::<String>("db").unwrap_err().is_validation_failed());
+//! assert!(manager.get_ref::<String>("db").unwrap_err().is_circuit_open());
+//!
+//! clock.advance(Duration::from_secs(30));
+//! assert!(manager.get_ref::<String>("db").unwrap_err().is_validation_failed());
+//! ```
+use crate::{Error, Registry, Result, SingletonManager, Uuid};
+use std::time::{Duration, SystemTime};
+
+/// The circuit breaker of a service, see `SingletonManager::set_circuit_breaker`.
+#[derive(Debug, Clone)]
+pub(crate) struct Breaker {
+    /// The number of failures in a row tripping the circuit open.
+    threshold: u32,
+    cool_down: Duration,
+    /// The number of failures in a row of the factory.
+    failures: u32,
+    /// When the circuit was opened, if it is open.
+    opened_at: Option<SystemTime>,
+}
+
+impl Registry {
+    /// Failing with `Error::CircuitOpen` if the circuit of the service is open, and its
+    /// cool-down did not pass yet.
+    fn check_circuit(&self, id: &Uuid, now: SystemTime) -> Result<()> {
+        let breaker = match self.breakers.get(id) {
+            Some(breaker) => breaker,
+            None => return Ok(()),
+        };
+        let elapsed = breaker
+            .opened_at
+            .map(|opened_at| now.duration_since(opened_at).unwrap_or_default());
+        match elapsed {
+            Some(elapsed) if elapsed < breaker.cool_down => Err(Error::CircuitOpen(
+                self.alias_of(id).unwrap_or_default().into(),
+                breaker.cool_down - elapsed,
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Counting the outcome of running the factory of the service, opening the circuit once the
+    /// factory failed too often in a row.
+    fn note_factory_outcome(&mut self, id: &Uuid, succeeded: bool, now: SystemTime) {
+        if let Some(breaker) = self.breakers.get_mut(id) {
+            if succeeded {
+                breaker.failures = 0;
+                breaker.opened_at = None;
+            } else {
+                breaker.failures = breaker.failures.saturating_add(1);
+                if breaker.failures >= breaker.threshold {
+                    breaker.opened_at = Some(now);
+                }
+            }
+        }
+    }
+}
+
+/// Counting the run of a factory as failed when dropped, unless it was closed.
+pub(crate) struct CircuitGuard<'a> {
+    manager: &'a SingletonManager,
+    id: Uuid,
+    succeeded: bool,
+}
+
+impl CircuitGuard<'_> {
+    /// Counting the run of the factory as succeeded, closing the circuit.
+    pub(crate) fn close(mut self, registry: &mut Registry) {
+        self.succeeded = true;
+        registry.note_factory_outcome(&self.id, true, self.manager.clock_and_ids.now());
+    }
+}
+
+impl Drop for CircuitGuard<'_> {
+    fn drop(&mut self) {
+        if self.succeeded {
+            return;
+        }
+        if let Ok(mut registry) = self.manager.registry_mut() {
+            registry.note_factory_outcome(&self.id, false, self.manager.clock_and_ids.now());
+        }
+    }
+}
+
+impl SingletonManager {
+    /// Setting the circuit breaker of a service, tripping open once its factory failed
+    /// `threshold` times in a row, and making `get` fail with `Error::CircuitOpen` for the
+    /// cool-down period.
+    pub fn set_circuit_breaker(
+        &self,
+        service_name: &str,
+        threshold: u32,
+        cool_down: Duration,
+    ) -> Result<()> {
+        let id = self.service_id(service_name)?;
+        self.registry_mut()?.breakers.insert(
+            id,
+            Breaker {
+                threshold: threshold.max(1),
+                cool_down,
+                failures: 0,
+                opened_at: None,
+            },
+        );
+        Ok(())
+    }
+
+    /// True if the circuit of the service is open, and getting it fails fast.
+    pub fn is_circuit_open(&self, service_name: &str) -> bool {
+        self.registry().is_ok_and(|registry| {
+            registry.resolve(service_name).is_some_and(|id| {
+                registry
+                    .check_circuit(&id, self.clock_and_ids.now())
+                    .is_err()
+            })
+        })
+    }
+
+    /// Failing if the circuit of the service is open, and otherwise watching the run of its
+    /// factory, if the service has a circuit breaker.
+    pub(crate) fn enter_circuit(&self, id: &Uuid) -> Result<Option<CircuitGuard<'_>>> {
+        let registry = self.registry()?;
+        if !registry.breakers.contains_key(id) {
+            return Ok(None);
+        }
+        registry.check_circuit(id, self.clock_and_ids.now())?;
+        Ok(Some(CircuitGuard {
+            manager: self,
+            id: *id,
+            succeeded: false,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Error, GetError, SingletonManager, TestClockAndIds};
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[test]
+    fn test_circuit_opens_after_failures_and_closes_on_success() {
+        let clock = TestClockAndIds::default();
+        let mut manager = SingletonManager::with_clock_and_ids(clock.clone());
+        let down = Arc::new(AtomicBool::new(true));
+        let runs = Arc::new(AtomicUsize::new(0));
+        let (is_down, counted) = (down.clone(), runs.clone());
+        manager
+            .set_factory("db", move || {
+                counted.fetch_add(1, Ordering::SeqCst);
+                if is_down.load(Ordering::SeqCst) {
+                    panic!("connection refused");
+                }
+                Box::new(5432_u16)
+            })
+            .unwrap();
+        manager
+            .set_circuit_breaker("db", 3, Duration::from_secs(10))
+            .unwrap();
+
+        for _ in 0..3 {
+            let get = catch_unwind(AssertUnwindSafe(|| {
+                manager.get_ref::<u16>("db").map(|_| ())
+            }));
+            assert!(get.is_err());
+        }
+        assert!(manager.is_circuit_open("db"));
+        clock.advance(Duration::from_secs(4));
+        match manager.get_ref::<u16>("db") {
+            Err(GetError::Factory(e)) => {
+                assert!(matches!(
+                    Error::from(e),
+                    Error::CircuitOpen(alias, retry_after)
+                        if alias == "db" && retry_after == Duration::from_secs(6)
+                ));
+            }
+            _ => panic!("Expected the circuit to be open"),
+        }
+        assert_eq!(3, runs.load(Ordering::SeqCst));
+
+        clock.advance(Duration::from_secs(6));
+        down.store(false, Ordering::SeqCst);
+        assert_eq!(5432, *manager.get_ref::<u16>("db").unwrap());
+        assert!(!manager.is_circuit_open("db"));
+        assert_eq!(4, runs.load(Ordering::SeqCst));
+    }
+}
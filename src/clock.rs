@@ -0,0 +1,202 @@
+//! # Clock and ids
+//! The source of the timestamps, the expiry checks and the ids of a singleton manager.
+//!
+//! By default a manager uses the system clock and random ids. Tests that assert on timestamps or
+//! ids, or on logic expiring services after a time to live, can construct the manager with a
+//! `TestClockAndIds` instead, which hands out sequential ids and only moves its virtual clock when
+//! told to, so every run is the same.
+//!
+//! Blocking waits, e.g. `wait_for_timeout` or `wait_ready`, keep using the real time, as a
+//! virtual clock would never wake them up.
+//! ```
+//! use singleton_manager::{SingletonManager, TestClockAndIds, Uuid};
+//! use std::time::{Duration, UNIX_EPOCH};
+//!
+//! let clock = TestClockAndIds::default();
+//! let mut manager = SingletonManager::with_clock_and_ids(clock.clone());
+//! manager.set_factory("db", || Box::new("postgres".to_string())).unwrap();
+//!
+//! assert_eq!(Uuid::from_u128(1), manager.service_id("db").unwrap());
+//! assert_eq!(UNIX_EPOCH, manager.history()[0].at);
+//!
+//! clock.advance(Duration::from_secs(60));
+//! assert!(manager.is_expired(UNIX_EPOCH, Duration::from_secs(30)));
+//! ```
+use crate::{IdGenerator, RandomIdGenerator, SequentialIdGenerator, SingletonManager, Uuid};
+use std::ops::Deref;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The source of the timestamps, the expiry checks and the ids of a singleton manager.
+pub trait ClockAndIds: IdGenerator {
+    /// The current time.
+    fn now(&self) -> SystemTime;
+
+    /// The time elapsed since the earlier time, zero if it is in the future.
+    fn elapsed_since(&self, earlier: SystemTime) -> Duration {
+        self.now().duration_since(earlier).unwrap_or_default()
+    }
+
+    /// True if the time to live, starting at the time, has passed.
+    fn is_expired(&self, since: SystemTime, ttl: Duration) -> bool {
+        self.elapsed_since(since) >= ttl
+    }
+}
+
+/// The system clock, with the ids of an `IdGenerator`, random by default.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock<G = RandomIdGenerator> {
+    id_generator: G,
+}
+
+impl<G: IdGenerator> SystemClock<G> {
+    /// Using the system clock with the ids of the generator.
+    pub fn new(id_generator: G) -> Self {
+        Self { id_generator }
+    }
+}
+
+impl<G: IdGenerator> IdGenerator for SystemClock<G> {
+    fn generate(&self, alias: &str) -> Uuid {
+        self.id_generator.generate(alias)
+    }
+}
+
+impl<G: IdGenerator> ClockAndIds for SystemClock<G> {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// A virtual clock, only moving when told to, with sequential ids starting from `1`.
+/// Clones share the clock and the ids, so a test can keep a clone to control the clock of a
+/// manager.
+#[derive(Debug, Clone)]
+pub struct TestClockAndIds {
+    state: Arc<TestState>,
+}
+
+#[derive(Debug)]
+struct TestState {
+    now: Mutex<SystemTime>,
+    ids: SequentialIdGenerator,
+}
+
+impl Default for TestClockAndIds {
+    /// A clock at the Unix epoch.
+    fn default() -> Self {
+        Self::starting_at(UNIX_EPOCH)
+    }
+}
+
+impl TestClockAndIds {
+    /// A clock at the time.
+    pub fn starting_at(now: SystemTime) -> Self {
+        Self {
+            state: Arc::new(TestState {
+                now: Mutex::new(now),
+                ids: SequentialIdGenerator::default(),
+            }),
+        }
+    }
+
+    /// Moving the clock forward.
+    pub fn advance(&self, duration: Duration) {
+        *self.lock() += duration;
+    }
+
+    /// Moving the clock to the time, which may be in its past.
+    pub fn set_now(&self, now: SystemTime) {
+        *self.lock() = now;
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, SystemTime> {
+        self.state
+            .now
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl IdGenerator for TestClockAndIds {
+    fn generate(&self, alias: &str) -> Uuid {
+        self.state.ids.generate(alias)
+    }
+}
+
+impl ClockAndIds for TestClockAndIds {
+    fn now(&self) -> SystemTime {
+        *self.lock()
+    }
+}
+
+/// The clock of a manager, shared by the manager and its registry.
+#[derive(Clone)]
+pub(crate) struct SharedClock(Arc<dyn ClockAndIds>);
+
+impl SharedClock {
+    pub(crate) fn new(clock_and_ids: impl ClockAndIds + 'static) -> Self {
+        Self(Arc::new(clock_and_ids))
+    }
+}
+
+impl Default for SharedClock {
+    fn default() -> Self {
+        Self::new(SystemClock::new(RandomIdGenerator))
+    }
+}
+
+impl Deref for SharedClock {
+    type Target = dyn ClockAndIds;
+
+    fn deref(&self) -> &Self::Target {
+        self.0.as_ref()
+    }
+}
+
+impl SingletonManager {
+    /// Create a new, empty, singleton manager using the given `ClockAndIds` for its timestamps,
+    /// expiry checks and ids.
+    pub fn with_clock_and_ids(clock_and_ids: impl ClockAndIds + 'static) -> SingletonManager {
+        Self::with_shared_clock(SharedClock::new(clock_and_ids))
+    }
+
+    /// The current time of the clock of the manager.
+    pub fn now(&self) -> SystemTime {
+        self.clock_and_ids.now()
+    }
+
+    /// True if the time to live, starting at the time, has passed on the clock of the manager.
+    pub fn is_expired(&self, since: SystemTime, ttl: Duration) -> bool {
+        self.clock_and_ids.is_expired(since, ttl)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{ClockAndIds, IdGenerator, SingletonManager, TestClockAndIds, Uuid};
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn test_virtual_clock_and_ids() {
+        let clock = TestClockAndIds::default();
+        let mut manager = SingletonManager::with_clock_and_ids(clock.clone());
+        manager.set("first", 1_u32).unwrap();
+        clock.advance(Duration::from_secs(5));
+        manager.set("second", 2_u32).unwrap();
+
+        let history = manager.history();
+        assert_eq!(UNIX_EPOCH, history[0].at);
+        assert_eq!(UNIX_EPOCH + Duration::from_secs(5), history[1].at);
+        assert_eq!(Uuid::from_u128(2), manager.service_id("second").unwrap());
+        assert_eq!(Uuid::from_u128(3), clock.generate("third"));
+
+        assert!(!manager.is_expired(UNIX_EPOCH, Duration::from_secs(6)));
+        assert!(manager.is_expired(UNIX_EPOCH, Duration::from_secs(5)));
+        clock.set_now(UNIX_EPOCH);
+        assert_eq!(
+            Duration::ZERO,
+            clock.elapsed_since(UNIX_EPOCH + Duration::from_secs(5))
+        );
+    }
+}
@@ -0,0 +1,305 @@
+//! # Collision policies
+//! Resolving registrations of an alias that is already registered.
+//!
+//! In ecosystems where libraries register defaults, and applications register the same aliases
+//! to replace them, failing with `Error::ServiceAlreadyExists` forces the application to know
+//! which aliases the libraries registered. The collision policy decides what happens instead,
+//! for all aliases or for the aliases of a namespace, where the namespace of an alias is the
+//! part before the first `/`, e.g. the tenant of a tenant alias.
+//! ```
+//! use singleton_manager::{CollisionPolicy, SingletonManager};
+//!
+//! let mut manager = SingletonManager::new();
+//! manager.set_collision_policy(CollisionPolicy::LastWins).unwrap();
+//!
+//! manager.set("greeting", "hello from the library".to_string()).unwrap();
+//! manager.set("greeting", "hello from the app".to_string()).unwrap();
+//! assert_eq!("hello from the app", manager.get::<String>("greeting").unwrap());
+//! ```
+use crate::{Alias, Error, Operation, Registry, Result, SingletonManager, Uuid};
+use std::fmt::{Display, Formatter};
+use std::ops::{Deref, DerefMut};
+use std::sync::RwLockWriteGuard;
+
+/// What happens when registering an alias that is already registered.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum CollisionPolicy {
+    /// Failing with `Error::ServiceAlreadyExists`.
+    #[default]
+    Error,
+    /// Keeping the registration already there, dropping the new one.
+    FirstWins,
+    /// Replacing the registration already there, unless it is borrowed or frozen.
+    LastWins,
+    /// Panicking, for aliases that must never be registered twice.
+    /// The panic is raised once the singleton manager is unlocked, so it does not poison it.
+    Panic,
+}
+
+impl Display for CollisionPolicy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Error => write!(f, "error"),
+            Self::FirstWins => write!(f, "first wins"),
+            Self::LastWins => write!(f, "last wins"),
+            Self::Panic => write!(f, "panic"),
+        }
+    }
+}
+
+/// The namespace of the alias, the part before the first `/`.
+fn namespace_of(alias: &str) -> Option<&str> {
+    alias.split_once('/').map(|(namespace, _)| namespace)
+}
+
+impl Registry {
+    /// The collision policy applying to the alias.
+    fn collision_policy_of(&self, alias: &str) -> CollisionPolicy {
+        namespace_of(alias)
+            .and_then(|namespace| self.namespace_collision_policies.get(namespace))
+            .copied()
+            .unwrap_or(self.collision_policy)
+    }
+
+    /// Resolving the registration of an alias that may already be registered, returning true
+    /// if the registration already there is kept and the new one must be dropped.
+    pub(crate) fn resolve_collision(&mut self, alias: &str) -> Result<bool> {
+        let id = match self.alias.get(alias) {
+            Some(id) => *id,
+            None => return Ok(false),
+        };
+        match self.collision_policy_of(alias) {
+            CollisionPolicy::Error => Err(Error::ServiceAlreadyExists(alias.into())),
+            CollisionPolicy::FirstWins => {
+                log::debug!("Service `{}` is already registered, keeping it", alias);
+                Ok(true)
+            }
+            CollisionPolicy::LastWins => {
+                log::debug!("Service `{}` is already registered, replacing it", alias);
+                self.check_removable(&[(id, alias.to_string())])?;
+                match self.replacing.is_some() {
+                    true => self.detach_alias(alias),
+                    false => {
+                        self.remove_alias(alias);
+                    }
+                }
+                self.record(Operation::Remove, alias, None);
+                Ok(false)
+            }
+            CollisionPolicy::Panic => {
+                self.registered_twice = Some(alias.into());
+                Err(Error::ServiceAlreadyExists(alias.into()))
+            }
+        }
+    }
+
+    /// Moving the registration of the alias aside while a transaction is applied, so it can be
+    /// restored if the transaction is rolled back.
+    fn detach_alias(&mut self, alias: &str) {
+        if let Some(detached) = self.alias.remove_entry(alias) {
+            self.unindex_alias(alias);
+            self.invalidate_facades();
+            self.replacing.get_or_insert_with(Vec::new).push(detached);
+        }
+    }
+
+    /// Starting to apply a transaction, where the registrations replaced are moved aside.
+    pub(crate) fn begin_replacing(&mut self) {
+        self.replacing = Some(Vec::new());
+    }
+
+    /// Finishing to apply a transaction, removing the registrations it replaced if it is kept,
+    /// and restoring them if it is rolled back.
+    pub(crate) fn finish_replacing(&mut self, keep: bool) {
+        for (alias, id) in self.replacing.take().unwrap_or_default() {
+            if keep {
+                self.forget_registration(&alias, id);
+            } else {
+                self.index_alias(alias.clone());
+                self.alias.insert(alias, id);
+                self.invalidate_facades();
+            }
+        }
+    }
+}
+
+/// The registrations moved aside while a transaction is applied.
+pub(crate) type Replaced = Vec<(Alias, Uuid)>;
+
+/// The write lock of the registry, dropping the removed instances and raising the panic of the
+/// `Panic` collision policy once the lock is released.
+pub(crate) struct RegistryMut<'a> {
+    guard: Option<RwLockWriteGuard<'a, Registry>>,
+}
+
+impl<'a> RegistryMut<'a> {
+    pub(crate) fn new(guard: RwLockWriteGuard<'a, Registry>) -> Self {
+        Self { guard: Some(guard) }
+    }
+}
+
+impl Deref for RegistryMut<'_> {
+    type Target = Registry;
+
+    fn deref(&self) -> &Registry {
+        self.guard
+            .as_ref()
+            .expect("The registry is locked until dropped")
+    }
+}
+
+impl DerefMut for RegistryMut<'_> {
+    fn deref_mut(&mut self) -> &mut Registry {
+        self.guard
+            .as_mut()
+            .expect("The registry is locked until dropped")
+    }
+}
+
+impl Drop for RegistryMut<'_> {
+    fn drop(&mut self) {
+        let (registered_twice, released) = match self.guard.as_mut() {
+            Some(registry) => (
+                registry.registered_twice.take(),
+                std::mem::take(&mut registry.released),
+            ),
+            None => (None, Vec::new()),
+        };
+        drop(self.guard.take());
+        drop(released);
+        if let Some(alias) = registered_twice {
+            if !std::thread::panicking() {
+                panic!("Service `{}` is registered twice", alias);
+            }
+        }
+    }
+}
+
+impl SingletonManager {
+    /// Setting the collision policy of all aliases without a namespace policy.
+    pub fn set_collision_policy(&self, policy: CollisionPolicy) -> Result<()> {
+        self.registry_mut()?.collision_policy = policy;
+        Ok(())
+    }
+
+    /// Setting the collision policy of the aliases in the namespace, overriding the policy set
+    /// by `set_collision_policy`.
+    pub fn set_namespace_collision_policy(
+        &self,
+        namespace: &str,
+        policy: CollisionPolicy,
+    ) -> Result<()> {
+        self.registry_mut()?
+            .namespace_collision_policies
+            .insert(namespace.to_string(), policy);
+        Ok(())
+    }
+
+    /// The collision policy applying to the alias.
+    pub fn collision_policy(&self, service_name: &str) -> CollisionPolicy {
+        self.registry()
+            .map(|registry| registry.collision_policy_of(service_name))
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{CollisionPolicy, Error, SingletonManager};
+
+    #[test]
+    fn test_collision_policies() {
+        let mut manager = SingletonManager::new();
+        manager
+            .set_collision_policy(CollisionPolicy::FirstWins)
+            .unwrap();
+        manager
+            .set_namespace_collision_policy("strict", CollisionPolicy::Error)
+            .unwrap();
+
+        manager.set_factory("port", || Box::new(80_u16)).unwrap();
+        assert_eq!(80, *manager.set("port", 8080_u16).unwrap());
+        manager.set_factory("port", || Box::new(443_u16)).unwrap();
+        assert_eq!(80, *manager.get::<u16>("port").unwrap());
+
+        manager.set("strict/port", 1_u16).unwrap();
+        assert!(matches!(
+            manager.set("strict/port", 2_u16),
+            Err(crate::SetError::ServiceAlreadyExists(_))
+        ));
+        assert!(matches!(
+            manager.transaction(|tx| {
+                tx.set("port", 1_u16)?;
+                tx.set("strict/port", 2_u16)
+            }),
+            Err(Error::ServiceAlreadyExists(_))
+        ));
+        assert_eq!(80, *manager.get::<u16>("port").unwrap());
+
+        manager
+            .set_namespace_collision_policy("strict", CollisionPolicy::LastWins)
+            .unwrap();
+        let port = manager.get_ref::<u16>("strict/port").unwrap();
+        assert!(matches!(
+            manager.transaction(|tx| tx.set("strict/port", 3_u16)),
+            Err(Error::AlreadyBorrowed(_, _))
+        ));
+        drop(port);
+        assert_eq!(3, *manager.set("strict/port", 3_u16).unwrap());
+        assert_eq!(
+            CollisionPolicy::LastWins,
+            manager.collision_policy("strict/port")
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "registered twice")]
+    fn test_panic_policy() {
+        let mut manager = SingletonManager::new();
+        manager
+            .set_collision_policy(CollisionPolicy::Panic)
+            .unwrap();
+        manager.set_factory("port", || Box::new(80_u16)).unwrap();
+        let _ = manager.set_factory("port", || Box::new(443_u16));
+    }
+
+    #[test]
+    fn test_panic_policy_does_not_poison_the_manager() {
+        let mut manager = SingletonManager::new();
+        manager
+            .set_collision_policy(CollisionPolicy::Panic)
+            .unwrap();
+        manager.set("port", 80_u16).unwrap();
+
+        let registered = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            manager.set("port", 443_u16).map(|_| ())
+        }));
+        assert!(registered.is_err());
+        assert_eq!(80, *manager.get_ref::<u16>("port").unwrap());
+    }
+
+    #[test]
+    fn test_rollback_restores_replaced_registrations() {
+        let mut manager = SingletonManager::new();
+        manager
+            .set_collision_policy(CollisionPolicy::LastWins)
+            .unwrap();
+        manager.set("port", 80_u16).unwrap();
+        manager.set("host", "localhost".to_string()).unwrap();
+        let host = manager.get_ref::<String>("host").unwrap();
+
+        assert!(matches!(
+            manager.transaction(|tx| {
+                tx.set("port", 443_u16)?;
+                tx.set("host", "example.com".to_string())
+            }),
+            Err(Error::AlreadyBorrowed(alias, _)) if alias == "host"
+        ));
+        drop(host);
+        assert_eq!(80, *manager.get_ref::<u16>("port").unwrap());
+
+        manager.transaction(|tx| tx.set("port", 443_u16)).unwrap();
+        assert_eq!(443, *manager.get_ref::<u16>("port").unwrap());
+        assert_eq!(2, manager.count());
+    }
+}
@@ -0,0 +1,148 @@
+//! # Configuration
+//! Configuring the behavior of a singleton manager when building it.
+//!
+//! The behavior of the singleton manager is configured per instance rather than at compile
+//! time, so one binary can run a strict manager in its tests, e.g. panicking on any alias
+//! registered twice, and a lenient one in production. The configuration is given to the
+//! builder, and queried at runtime with `SingletonManager::config`.
+//! ```
+//! use singleton_manager::{CollisionPolicy, ManagerConfig, SingletonManager};
+//! use std::time::Duration;
+//!
+//! let manager = SingletonManager::builder()
+//!     .with_config(ManagerConfig {
+//!         collision_policy: CollisionPolicy::LastWins,
+//!         strict_send_sync: true,
+//!         tracing: false,
+//!         wait_timeout: Some(Duration::from_secs(30)),
+//!         ..ManagerConfig::default()
+//!     })
+//!     .with_service("region", "eu-west-1".to_string())
+//!     .build()
+//!     .unwrap();
+//!
+//! let config = manager.config();
+//! assert_eq!(CollisionPolicy::LastWins, config.collision_policy);
+//! assert!(config.metrics);
+//! assert!(manager.history().is_empty());
+//! assert!(manager
+//!     .set_unsync("handle", std::rc::Rc::new(1))
+//!     .unwrap_err()
+//!     .is_not_send_sync());
+//! ```
+use crate::{CollisionPolicy, SingletonManager};
+use std::time::Duration;
+
+/// The behavior of a singleton manager, see `SingletonManagerBuilder::with_config`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManagerConfig {
+    /// The collision policy of all aliases without a namespace policy, which can still be
+    /// changed with `SingletonManager::set_collision_policy`.
+    pub collision_policy: CollisionPolicy,
+    /// Rejecting the services that are not `Send + Sync`, failing `set_unsync` and
+    /// `set_unsync_factory` with `SetError::NotSendSync`.
+    pub strict_send_sync: bool,
+    /// Recording how long the guards of the services are held, see
+    /// `SingletonManager::hold_times`, which services are retrieved and the lookups of aliases
+    /// not registered, see `SingletonManager::usage_report`.
+    pub metrics: bool,
+    /// Recording the operations in the history, see `SingletonManager::history`.
+    pub tracing: bool,
+    /// The construction budget of the factories set by `set_factory`, see
+    /// `SingletonManager::set_factory_with_timeout`.
+    pub factory_timeout: Option<Duration>,
+    /// The time `wait_for` waits when not given a timeout, forever if none.
+    pub wait_timeout: Option<Duration>,
+    /// The time `wait_ready` waits when not given a timeout, forever if none.
+    pub ready_timeout: Option<Duration>,
+}
+
+impl Default for ManagerConfig {
+    fn default() -> Self {
+        Self {
+            collision_policy: CollisionPolicy::default(),
+            strict_send_sync: false,
+            metrics: true,
+            tracing: true,
+            factory_timeout: None,
+            wait_timeout: None,
+            ready_timeout: None,
+        }
+    }
+}
+
+impl SingletonManager {
+    /// The configuration of the manager, with the collision policy currently set.
+    pub fn config(&self) -> ManagerConfig {
+        self.registry()
+            .map(|registry| ManagerConfig {
+                collision_policy: registry.collision_policy,
+                ..registry.config.clone()
+            })
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{ManagerConfig, SetError, SingletonManager};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_config_changes_the_behavior() {
+        let mut manager = SingletonManager::builder()
+            .with_config(ManagerConfig {
+                metrics: false,
+                factory_timeout: Some(Duration::from_millis(10)),
+                wait_timeout: Some(Duration::from_millis(10)),
+                ..ManagerConfig::default()
+            })
+            .with_factory("slow", || {
+                std::thread::sleep(Duration::from_millis(500));
+                Box::new(1_u32)
+            })
+            .build()
+            .unwrap();
+        manager.set_factory("port", || Box::new(8080_u16)).unwrap();
+        manager.set_unsync("handle", std::rc::Rc::new(1)).unwrap();
+
+        assert!(manager.get::<u32>("slow").unwrap_err().is_timeout());
+        drop(manager.get_ref::<u16>("port").unwrap());
+        assert_eq!(0, manager.hold_times("port").unwrap().count);
+        assert!(!manager.history().is_empty());
+
+        let _reservation = manager.reserve::<u32>("db").unwrap();
+        let started = Instant::now();
+        assert!(manager.wait_for::<u32>("db").is_err());
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert!(manager.get_ref::<u8>("prot").is_err());
+        assert!(manager.usage_report().unused.contains(&"port".to_string()));
+        assert!(manager.usage_report().failed_lookups.is_empty());
+
+        // Without a timeout, waiting for readiness is not bound by the wait timeout.
+        manager.add_to_group("port", "infrastructure").unwrap();
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                std::thread::sleep(Duration::from_millis(50));
+                manager.set_ready("port", true).unwrap();
+            });
+            assert!(manager.wait_ready("infrastructure", None));
+        });
+
+        let strict = SingletonManager::builder()
+            .with_config(ManagerConfig {
+                strict_send_sync: true,
+                ..ManagerConfig::default()
+            })
+            .build()
+            .unwrap();
+        assert!(matches!(
+            strict.set_unsync("handle", std::rc::Rc::new(1)),
+            Err(SetError::NotSendSync(alias)) if alias == "handle"
+        ));
+
+        let forked = manager.fork().unwrap();
+        assert_eq!(manager.config(), forked.config());
+        assert_eq!(ManagerConfig::default(), SingletonManager::new().config());
+    }
+}
@@ -0,0 +1,138 @@
+//! # Deadline guards
+//! Catching the guards held for longer than expected.
+//!
+//! A guard of `get_mut` blocks every other thread needing the service for as long as it is
+//! held, which is easily far longer than intended, e.g. when an `.await` slips in between
+//! getting the guard and dropping it. `SingletonManager::get_guard_with_deadline` hands out a
+//! guard checking, once dropped, whether it was held beyond its deadline. If so, a warning
+//! naming the service and the location the guard was taken at is logged, and debug builds
+//! panic, so the mistake is caught by the tests. A guard never dropped, e.g. by a deadlocked
+//! holder, is reported once the first borrow contending with it is beyond the deadline.
+//! ```
+//! use singleton_manager::SingletonManager;
+//! use std::time::Duration;
+//!
+//! let mut manager = SingletonManager::new();
+//! manager.set_factory("sessions", || Box::new(Vec::<String>::new())).unwrap();
+//!
+//! let mut sessions = manager
+//!     .get_guard_with_deadline::<Vec<String>>("sessions", Duration::from_secs(1))
+//!     .unwrap();
+//! sessions.push("3f2a".to_string());
+//! drop(sessions);
+//! ```
+use crate::{GetError, ServiceRefMut, SingletonManager};
+use std::fmt::{Debug, Formatter};
+use std::ops::{Deref, DerefMut};
+use std::panic::Location;
+use std::time::{Duration, Instant};
+
+/// A mutable borrow of a service, checked against its deadline when dropped, see
+/// `SingletonManager::get_guard_with_deadline`.
+pub struct DeadlineGuard<'a, T> {
+    guard: ServiceRefMut<'a, T>,
+    alias: String,
+    deadline: Duration,
+    location: &'static Location<'static>,
+    acquired: Instant,
+}
+
+impl<T> DeadlineGuard<'_, T> {
+    /// The time the guard may still be held, zero once beyond the deadline.
+    pub fn remaining(&self) -> Duration {
+        self.deadline.saturating_sub(self.acquired.elapsed())
+    }
+}
+
+impl<T> Deref for DeadlineGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<T> DerefMut for DeadlineGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.guard
+    }
+}
+
+impl<T> Drop for DeadlineGuard<'_, T> {
+    fn drop(&mut self) {
+        let held = self.acquired.elapsed();
+        if held <= self.deadline {
+            return;
+        }
+        let message = format!(
+            "Service `{}` was held for {:?} by {}, beyond its deadline of {:?}",
+            self.alias, held, self.location, self.deadline
+        );
+        log::warn!("{}", message);
+        // The borrow is still released, as the fields are dropped while unwinding.
+        if cfg!(debug_assertions) && !std::thread::panicking() {
+            panic!("{}", message);
+        }
+    }
+}
+
+impl<T: Debug> Debug for DeadlineGuard<'_, T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.guard.fmt(f)
+    }
+}
+
+impl SingletonManager {
+    /// Mutably borrowing the service as `get_mut` does, with a guard expected to be dropped
+    /// before the deadline, see the module documentation.
+    #[track_caller]
+    pub fn get_guard_with_deadline<T: 'static>(
+        &self,
+        service_name: &str,
+        deadline: Duration,
+    ) -> std::result::Result<DeadlineGuard<'_, T>, GetError> {
+        let location = Location::caller();
+        let guard = self.get_mut_by_alias::<T>(service_name)?;
+        guard.set_deadline(deadline);
+        Ok(DeadlineGuard {
+            guard,
+            alias: service_name.to_string(),
+            deadline,
+            location,
+            acquired: Instant::now(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::SingletonManager;
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+    use std::time::Duration;
+
+    #[test]
+    #[cfg(debug_assertions)]
+    fn test_guards_held_beyond_their_deadline_panic() {
+        let mut manager = SingletonManager::new();
+        manager.set_factory("counter", || Box::new(0_u32)).unwrap();
+
+        let mut counter = manager
+            .get_guard_with_deadline::<u32>("counter", Duration::from_secs(60))
+            .unwrap();
+        *counter += 1;
+        assert!(counter.remaining() > Duration::ZERO);
+        drop(counter);
+
+        let held = catch_unwind(AssertUnwindSafe(|| {
+            let mut counter = manager
+                .get_guard_with_deadline::<u32>("counter", Duration::from_millis(1))
+                .unwrap();
+            std::thread::sleep(Duration::from_millis(5));
+            *counter += 1;
+        }));
+        let message = held.unwrap_err().downcast::<String>().unwrap();
+        assert!(message.starts_with("Service `counter` was held for"));
+        assert!(message.contains(file!()));
+        assert_eq!(2, *manager.get_ref::<u32>("counter").unwrap());
+    }
+}
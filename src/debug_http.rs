@@ -0,0 +1,116 @@
+//! # HTTP debug endpoint
+//! Answering debug requests about the registry, behind the `debug_http` feature.
+//!
+//! `SingletonManager::debug_http` maps the path of a request to a response built from the dumps,
+//! see `SingletonManager::dump`, and the health checks, as a `(status, content type, body)`
+//! tuple, so any HTTP framework can serve it from a route of its own:
+//!
+//! - `/singletons` lists every registration with the counters of the registry.
+//! - `/singletons/{name}` describes a single registration.
+//! - `/singletons/{name}/health` runs the health check of the service, answering `503` if the
+//!   service is unhealthy.
+//!
+//! Every other path is answered with `404`.
+//! ```
+//! use singleton_manager::SingletonManager;
+//!
+//! let mut manager = SingletonManager::new();
+//! manager.set_factory("db", || Box::new("postgres".to_string())).unwrap();
+//!
+//! let (status, content_type, body) = manager.debug_http("/singletons/db");
+//! assert_eq!(200, status);
+//! assert_eq!("application/json", content_type);
+//! assert!(body.starts_with(r#"{"alias":"db""#));
+//! assert_eq!(404, manager.debug_http("/singletons/missing").0);
+//! ```
+use crate::dump::json_string;
+use crate::{Health, SingletonManager};
+
+/// The content type of every response.
+const JSON: &str = "application/json";
+
+/// The response to a request that does not match a registration or route.
+fn not_found(reason: &str) -> (u16, &'static str, String) {
+    (404, JSON, format!(r#"{{"error":{}}}"#, json_string(reason)))
+}
+
+impl SingletonManager {
+    /// Answering a debug request for the path, with the status, content type and body of the
+    /// response. A query string is ignored.
+    pub fn debug_http(&self, path: &str) -> (u16, &'static str, String) {
+        let path = path
+            .split('?')
+            .next()
+            .unwrap_or_default()
+            .trim_end_matches('/');
+        let name = match path.strip_prefix("/singletons") {
+            Some("") => return (200, JSON, self.dump().to_json()),
+            Some(name) => match name.strip_prefix('/') {
+                Some(name) => name,
+                None => return not_found("Unknown path"),
+            },
+            None => return not_found("Unknown path"),
+        };
+
+        if let Some(name) = name.strip_suffix("/health") {
+            return match self.health(name) {
+                Ok(health) => {
+                    let status = match health {
+                        Health::Unhealthy(_) => 503,
+                        _ => 200,
+                    };
+                    let body = format!(
+                        r#"{{"alias":{},"health":{}}}"#,
+                        json_string(name),
+                        json_string(&health.to_string())
+                    );
+                    (status, JSON, body)
+                }
+                Err(e) => not_found(&e.to_string()),
+            };
+        }
+        match self
+            .dump()
+            .services
+            .into_iter()
+            .find(|service| service.alias == name)
+        {
+            Some(service) => (200, JSON, service.to_json()),
+            None => not_found(&format!("Service `{}` does not exist", name)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::SingletonManager;
+
+    #[test]
+    fn test_debug_http_routes() {
+        let mut manager = SingletonManager::new();
+        manager.set("queue", Vec::<u32>::new()).unwrap();
+        manager
+            .set_health_check::<Vec<u32>, _>("queue", |queue| match queue.is_empty() {
+                true => Err("no consumers".to_string()),
+                false => Ok(()),
+            })
+            .unwrap();
+
+        let (status, _, body) = manager.debug_http("/singletons/?pretty");
+        assert_eq!(200, status);
+        assert!(body.starts_with(r#"{"services":[{"alias":"queue""#));
+        assert_eq!(
+            (
+                503,
+                "application/json",
+                r#"{"alias":"queue","health":"unhealthy: no consumers"}"#.to_string()
+            ),
+            manager.debug_http("/singletons/queue/health")
+        );
+        manager.get::<Vec<u32>>("queue").unwrap().push(1);
+        assert_eq!(200, manager.debug_http("/singletons/queue/health").0);
+        assert_eq!(404, manager.debug_http("/singletons/missing/health").0);
+        assert_eq!(404, manager.debug_http("/singletonsx").0);
+        assert_eq!(404, manager.debug_http("/metrics").0);
+    }
+}
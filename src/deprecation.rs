@@ -0,0 +1,113 @@
+//! # Deprecation
+//! Marking services as deprecated, to drive their staged removal.
+//!
+//! Getting a deprecated service still succeeds, but the first access from every call site logs
+//! a warning with the location of the call site and the note of the deprecation, so the
+//! remaining users of the service can be found in the logs. The deprecation is part of the
+//! information about the service, see `SingletonManager::info`, and of the dumps.
+//! ```
+//! use singleton_manager::SingletonManager;
+//!
+//! let mut manager = SingletonManager::new();
+//! manager.set_factory("old_cache", || Box::new(0_u32)).unwrap();
+//! manager.deprecate("old_cache", "use `cache_v2`").unwrap();
+//!
+//! // Logs a warning, once for this call site.
+//! assert_eq!(0, *manager.get_ref::<u32>("old_cache").unwrap());
+//! assert_eq!(Some("use `cache_v2`"), manager.info("old_cache").unwrap().deprecation());
+//! ```
+use crate::{Registry, Result, SingletonManager, Uuid};
+use std::collections::HashSet;
+use std::panic::Location;
+
+/// The deprecation of a service, see `SingletonManager::deprecate`.
+#[derive(Debug, Clone)]
+pub(crate) struct Deprecation {
+    pub(crate) note: String,
+    /// The call sites already warned about the deprecation.
+    warned: HashSet<&'static Location<'static>>,
+}
+
+impl Registry {
+    /// True if the call site still has to be warned that the service is deprecated.
+    pub(crate) fn needs_deprecation_warning(
+        &self,
+        id: &Uuid,
+        location: &'static Location<'static>,
+    ) -> bool {
+        self.deprecations
+            .get(id)
+            .is_some_and(|deprecation| !deprecation.warned.contains(location))
+    }
+
+    /// Warning once per call site that the service is deprecated.
+    pub(crate) fn warn_deprecated(&mut self, id: &Uuid, location: &'static Location<'static>) {
+        let alias = self.alias_of(id).unwrap_or_default().to_string();
+        if let Some(deprecation) = self.deprecations.get_mut(id) {
+            if deprecation.warned.insert(location) {
+                log::warn!(
+                    "Service `{}` used at {} is deprecated: {}",
+                    alias,
+                    location,
+                    deprecation.note
+                );
+            }
+        }
+    }
+}
+
+impl SingletonManager {
+    /// Marking the service as deprecated with a note, e.g. naming its replacement.
+    /// Deprecating a service again replaces the note, and warns every call site again.
+    pub fn deprecate(&self, service_name: &str, note: &str) -> Result<()> {
+        let id = self.service_id(service_name)?;
+        self.registry_mut()?.deprecations.insert(
+            id,
+            Deprecation {
+                note: note.to_string(),
+                warned: HashSet::new(),
+            },
+        );
+        Ok(())
+    }
+
+    /// The note of the deprecation of the service, if it is deprecated.
+    pub fn deprecation(&self, service_name: &str) -> Option<String> {
+        let registry = self.registry().ok()?;
+        let id = registry.resolve(service_name)?;
+        registry
+            .deprecations
+            .get(&id)
+            .map(|deprecation| deprecation.note.clone())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::SingletonManager;
+
+    #[test]
+    fn test_deprecated_services_warn_once_per_call_site() {
+        let mut manager = SingletonManager::new();
+        manager.set("old_cache", 7_u32).unwrap();
+        manager.deprecate("old_cache", "use `cache_v2`").unwrap();
+
+        for _ in 0..3 {
+            assert_eq!(7, *manager.get::<u32>("old_cache").unwrap());
+        }
+        assert_eq!(7, *manager.get_ref::<u32>("old_cache").unwrap());
+        let id = manager.service_id("old_cache").unwrap();
+        assert_eq!(
+            2,
+            manager.registry().unwrap().deprecations[&id].warned.len()
+        );
+
+        let dump = manager.dump();
+        assert_eq!(
+            Some("use `cache_v2`"),
+            dump.services[0].deprecation.as_deref()
+        );
+        assert!(dump.to_json().contains(r#""deprecation":"use `cache_v2`""#));
+        assert_eq!(None, manager.deprecation("cache_v2"));
+    }
+}
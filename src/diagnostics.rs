@@ -0,0 +1,204 @@
+//! # Diagnostics
+//! Checking the wiring of the singleton manager for suspicious registrations.
+//!
+//! `SingletonManager::diagnose` produces a report meant for humans, e.g. to be printed by a
+//! `--diagnose` command line option of an application, listing:
+//!
+//! 1. Factories that were never instantiated.
+//! 2. Services that were instantiated but never retrieved.
+//! 3. Different types sharing the same type name, e.g. two versions of the same crate.
+//! 4. The same type registered under different aliases.
+//!
+//! ```
+//! use singleton_manager::SingletonManager;
+//!
+//! let mut manager = SingletonManager::new();
+//! manager.set("primary_db", "postgres".to_string()).unwrap();
+//! manager.set("replica_db", "postgres".to_string()).unwrap();
+//! manager.set_factory("cache", || Box::new(1_u32)).unwrap();
+//! manager.get::<String>("primary_db").unwrap();
+//!
+//! let report = manager.diagnose();
+//! assert_eq!(vec!["cache".to_string()], report.never_instantiated);
+//! assert_eq!(vec!["replica_db".to_string()], report.never_retrieved);
+//! println!("{}", report);
+//! ```
+use crate::{SingletonManager, ThreadBound};
+use std::any::TypeId;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{Display, Formatter};
+
+/// The type name used for services whose type has not been seen yet.
+pub(crate) const UNKNOWN_TYPE: &str = "<unknown type>";
+
+/// The findings of `SingletonManager::diagnose`, every list is sorted.
+#[derive(Debug, Clone, Default)]
+pub struct DiagnosticsReport {
+    /// Aliases with a factory that was never instantiated.
+    pub never_instantiated: Vec<String>,
+    /// Aliases of instantiated services that were never retrieved.
+    pub never_retrieved: Vec<String>,
+    /// Type names shared by different types, with the aliases of the services having them.
+    pub type_name_collisions: Vec<(String, Vec<String>)>,
+    /// Types registered under more than one alias, with the aliases.
+    /// The versions of a versioned singleton are not counted as duplicates of each other.
+    pub duplicated_types: Vec<(String, Vec<String>)>,
+}
+
+impl DiagnosticsReport {
+    /// True if nothing suspicious was found.
+    pub fn is_clean(&self) -> bool {
+        self.never_instantiated.is_empty()
+            && self.never_retrieved.is_empty()
+            && self.type_name_collisions.is_empty()
+            && self.duplicated_types.is_empty()
+    }
+}
+
+impl Display for DiagnosticsReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.is_clean() {
+            return writeln!(f, "No wiring issues found");
+        }
+        if !self.never_instantiated.is_empty() {
+            writeln!(f, "Factories never instantiated:")?;
+            for alias in &self.never_instantiated {
+                writeln!(f, "  - {}", alias)?;
+            }
+        }
+        if !self.never_retrieved.is_empty() {
+            writeln!(f, "Services never retrieved:")?;
+            for alias in &self.never_retrieved {
+                writeln!(f, "  - {}", alias)?;
+            }
+        }
+        if !self.type_name_collisions.is_empty() {
+            writeln!(f, "Different types with the same name:")?;
+            for (name, aliases) in &self.type_name_collisions {
+                writeln!(f, "  - {}: {}", name, aliases.join(", "))?;
+            }
+        }
+        if !self.duplicated_types.is_empty() {
+            writeln!(f, "Types registered under multiple aliases:")?;
+            for (name, aliases) in &self.duplicated_types {
+                writeln!(f, "  - {}: {}", name, aliases.join(", "))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl SingletonManager {
+    /// Checking the registrations for wiring issues.
+    pub fn diagnose(&self) -> DiagnosticsReport {
+        let mut report = DiagnosticsReport::default();
+        let registry = match self.registry() {
+            Ok(registry) => registry,
+            Err(_) => return report,
+        };
+
+        let mut by_name: BTreeMap<&str, HashMap<TypeId, Vec<String>>> = BTreeMap::new();
+        let mut by_type: HashMap<TypeId, (&str, Vec<String>)> = HashMap::new();
+        for (alias, id) in registry.alias.iter() {
+            let service = match registry.singletons.get(id) {
+                Some(service) => service,
+                None => {
+                    if registry.singleton_factories.contains_key(id) {
+                        report.never_instantiated.push(alias.to_string());
+                    }
+                    continue;
+                }
+            };
+            if !registry.retrieved.contains(id) {
+                report.never_retrieved.push(alias.to_string());
+            }
+            let type_id = ThreadBound::inner(service.as_ref()).type_id();
+            let name = registry.type_names.get(id).copied().unwrap_or(UNKNOWN_TYPE);
+            if name != UNKNOWN_TYPE {
+                by_name
+                    .entry(name)
+                    .or_default()
+                    .entry(type_id)
+                    .or_default()
+                    .push(alias.to_string());
+            }
+            let (type_name, aliases) = by_type.entry(type_id).or_insert((name, Vec::new()));
+            if *type_name == UNKNOWN_TYPE {
+                *type_name = name;
+            }
+            aliases.push(alias.to_string());
+        }
+
+        report.never_instantiated.sort();
+        report.never_retrieved.sort();
+        for (name, types) in by_name {
+            if types.len() > 1 {
+                let mut aliases = types.into_values().flatten().collect::<Vec<_>>();
+                aliases.sort();
+                report
+                    .type_name_collisions
+                    .push((name.to_string(), aliases));
+            }
+        }
+        for (name, mut aliases) in by_type.into_values() {
+            let mut unversioned = aliases
+                .iter()
+                .map(|alias| alias.split('@').next().unwrap_or_default())
+                .collect::<Vec<_>>();
+            unversioned.sort_unstable();
+            unversioned.dedup();
+            if unversioned.len() > 1 {
+                aliases.sort();
+                report.duplicated_types.push((name.to_string(), aliases));
+            }
+        }
+        report.duplicated_types.sort();
+        report
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::SingletonManager;
+
+    mod v1 {
+        pub struct Client;
+    }
+
+    mod v2 {
+        pub struct Client;
+    }
+
+    #[test]
+    fn test_diagnose_type_issues() {
+        let mut manager = SingletonManager::new();
+        manager.set("old_client", v1::Client).unwrap();
+        manager.set("new_client", v2::Client).unwrap();
+        manager.set_versioned("api", "v1", 1_u32).unwrap();
+        manager.set_versioned("api", "v2", 2_u32).unwrap();
+        assert!(manager.diagnose().duplicated_types.is_empty());
+
+        manager.set_factory("port", || Box::new(3_u32)).unwrap();
+        manager.get::<u32>("port").unwrap();
+
+        let report = manager.diagnose();
+        assert!(report.never_instantiated.is_empty());
+        assert_eq!(
+            vec!["api@v1", "api@v2", "new_client", "old_client"],
+            report.never_retrieved
+        );
+        assert_eq!(
+            vec![(
+                "u32".to_string(),
+                vec![
+                    "api@v1".to_string(),
+                    "api@v2".to_string(),
+                    "port".to_string()
+                ]
+            )],
+            report.duplicated_types
+        );
+        assert!(report.type_name_collisions.is_empty());
+        assert!(!report.is_clean());
+    }
+}
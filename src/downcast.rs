@@ -0,0 +1,113 @@
+//! # Downcast cache
+//! Skipping the downcast of services fetched repeatedly from the same call site.
+//!
+//! Every `get::<T>` checks the service is a `T` before handing it out. Once a call site got the
+//! service as a `T`, the pair of the registration and the type is cached for the call site, and
+//! further gets from it skip the check, until the instance of the service changes. The cache
+//! entry holds the generation of the instances it was checked at, and is only used when read
+//! under the same lock as the instance, at the same generation. Debug builds keep verifying the
+//! type on every get.
+use crate::{Alias, Registry, Result, SingletonManager, ThreadBound, Uuid};
+use std::any::TypeId;
+use std::panic::Location;
+use std::sync::atomic::Ordering;
+
+impl Registry {
+    /// Forgetting the downcasts verified for the service, when its instance is dropped.
+    pub(crate) fn forget_downcasts(&mut self, id: &Uuid) {
+        self.downcasts.retain(|_, (verified, _, _)| verified != id);
+    }
+}
+
+impl SingletonManager {
+    /// Getting the instance of the service as a `T`, skipping the check if the call site already
+    /// did for the same instance.
+    pub(crate) fn cached_downcast<T: 'static>(
+        &self,
+        id: &Uuid,
+        service_name: &str,
+        location: &'static Location<'static>,
+        alias: impl FnOnce() -> Alias,
+    ) -> Result<*mut T> {
+        let type_id = TypeId::of::<T>();
+        let (service, generation, verified) = {
+            let registry = self.registry()?;
+            let generation = registry.generation.load(Ordering::Acquire);
+            match registry.instance_ptr(id) {
+                // The service is kept in the storage while the registry is locked.
+                Some(service) => (
+                    unsafe { ThreadBound::checked(service, service_name) }?,
+                    Some(generation),
+                    registry.downcasts.get(location) == Some(&(*id, type_id, generation)),
+                ),
+                None => {
+                    drop(registry);
+                    (self.singleton_get(id, service_name)?, None, false)
+                }
+            }
+        };
+        if verified && !cfg!(debug_assertions) {
+            return Ok(service as *mut T);
+        }
+        let service = unsafe { crate::downcast_mut::<T>(service, alias) }? as *mut T;
+        if let Some(generation) = generation.filter(|_| !verified) {
+            let mut registry = self.registry_mut()?;
+            // Caching only if the instance checked is still the current one.
+            if registry.generation.load(Ordering::Acquire) == generation {
+                registry
+                    .downcasts
+                    .insert(location, (*id, type_id, generation));
+            }
+        }
+        Ok(service)
+    }
+
+    /// The number of call sites with a cached downcast.
+    pub fn cached_downcasts(&self) -> usize {
+        self.registry()
+            .map(|registry| registry.downcasts.len())
+            .unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{CollisionPolicy, SingletonManager};
+
+    #[test]
+    fn test_downcast_cached_per_call_site() {
+        let mut manager = SingletonManager::new();
+        manager.set_factory("counter", || Box::new(0_u32)).unwrap();
+        manager.add_to_group("counter", "counters").unwrap();
+
+        for _ in 0..3 {
+            *manager.get::<u32>("counter").unwrap() += 1;
+        }
+        assert_eq!(1, manager.cached_downcasts());
+        assert!(manager.get::<String>("counter").is_err());
+        assert_eq!(3, *manager.get::<u32>("counter").unwrap());
+        assert_eq!(2, manager.cached_downcasts());
+
+        manager.shutdown_group("counters").unwrap();
+        assert_eq!(0, manager.cached_downcasts());
+        assert_eq!(0, *manager.get::<u32>("counter").unwrap());
+    }
+
+    #[test]
+    fn test_cached_downcast_does_not_outlive_the_instance() {
+        let mut manager = SingletonManager::new();
+        manager
+            .set_collision_policy(CollisionPolicy::LastWins)
+            .unwrap();
+        manager.set("value", 1_u32).unwrap();
+
+        let mut found = Vec::new();
+        for replace in [false, true] {
+            if replace {
+                manager.set("value", "one".to_string()).unwrap();
+            }
+            found.push(manager.get::<u32>("value").map(|value| *value).ok());
+        }
+        assert_eq!(vec![Some(1), None], found);
+    }
+}
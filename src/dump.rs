@@ -0,0 +1,269 @@
+//! # Dumps
+//! Dumping the state of the registry, for humans and for tools.
+//!
+//! `SingletonManager::dump` takes a snapshot of every registration, its type, state and
+//! counters, which formats itself as text, by `Display`, or as JSON, by `RegistryDump::to_json`.
+//! Backing an admin HTTP endpoint or a `SIGUSR1` handler with it needs no formatting code in the
+//! application.
+//! ```
+//! use singleton_manager::SingletonManager;
+//!
+//! let mut manager = SingletonManager::new();
+//! manager.set("db", "postgres".to_string()).unwrap();
+//! manager.set_factory("cache", || Box::new(1_u32)).unwrap();
+//!
+//! let dump = manager.dump();
+//! assert_eq!(2, dump.metrics.services);
+//! assert_eq!(1, dump.metrics.instantiated);
+//! println!("{}", dump);
+//! assert!(dump.to_json().starts_with(r#"{"services":[{"alias":"cache""#));
+//! ```
+use crate::diagnostics::UNKNOWN_TYPE;
+use crate::{GroupMetrics, Phase, SingletonManager, UnsafeStats};
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter, Write};
+
+/// The state of a registration, see `SingletonManager::dump`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServiceDump {
+    pub alias: String,
+    /// The type name of the service, or `<unknown type>` while only the factory was set.
+    pub type_name: String,
+    pub phase: Phase,
+    pub instantiated: bool,
+    pub retrieved: bool,
+    pub borrowed: bool,
+    pub frozen: bool,
+    /// The number of references handed out without tracking, see `SingletonManager::unsafe_stats`.
+    pub raw_references: usize,
+    pub tags: BTreeMap<String, String>,
+    /// The note of the deprecation of the service, if it is deprecated.
+    pub deprecation: Option<String>,
+}
+
+impl ServiceDump {
+    /// The state of the service, as shown in the dumps.
+    pub fn state(&self) -> &'static str {
+        match self {
+            Self { frozen: true, .. } => "frozen",
+            Self { borrowed: true, .. } => "borrowed",
+            Self {
+                instantiated: true, ..
+            } => "instantiated",
+            _ => "factory",
+        }
+    }
+
+    /// The registration as a JSON object.
+    pub fn to_json(&self) -> String {
+        let mut json = format!(
+            r#"{{"alias":{},"type":{},"state":"{}","phase":{},"instantiated":{},"retrieved":{},"borrowed":{},"frozen":{},"raw_references":{},"tags":{{"#,
+            json_string(&self.alias),
+            json_string(&self.type_name),
+            self.state(),
+            self.phase.order(),
+            self.instantiated,
+            self.retrieved,
+            self.borrowed,
+            self.frozen,
+            self.raw_references,
+        );
+        for (i, (key, value)) in self.tags.iter().enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+            let _ = write!(json, "{}:{}", json_string(key), json_string(value));
+        }
+        json.push_str("},\"deprecation\":");
+        match &self.deprecation {
+            Some(note) => json.push_str(&json_string(note)),
+            None => json.push_str("null"),
+        }
+        json.push('}');
+        json
+    }
+}
+
+/// A snapshot of the registry, see `SingletonManager::dump`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RegistryDump {
+    /// The registrations, in alias order.
+    pub services: Vec<ServiceDump>,
+    /// The registrations counted by their state.
+    pub metrics: GroupMetrics,
+    /// The counters of the references handed out without tracking, kept in every build, see
+    /// `SingletonManager::unsafe_stats`.
+    pub unsafe_stats: UnsafeStats,
+}
+
+impl RegistryDump {
+    /// The dump as a JSON object, with a `services` array and `metrics` and `unsafe_stats`
+    /// objects.
+    pub fn to_json(&self) -> String {
+        let mut json = String::from(r#"{"services":["#);
+        for (i, service) in self.services.iter().enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+            json.push_str(&service.to_json());
+        }
+        let metrics = &self.metrics;
+        let stats = &self.unsafe_stats;
+        let _ = write!(
+            json,
+            r#"],"metrics":{{"services":{},"instantiated":{},"retrieved":{},"borrowed":{}}},"unsafe_stats":{{"outstanding":{},"services":{},"dangling":{},"deferred":{}}}}}"#,
+            metrics.services,
+            metrics.instantiated,
+            metrics.retrieved,
+            metrics.borrowed,
+            stats.outstanding,
+            stats.services,
+            stats.dangling,
+            stats.deferred,
+        );
+        json
+    }
+}
+
+/// Quoting and escaping the string as a JSON string.
+pub(crate) fn json_string(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(quoted, "\\u{:04x}", c as u32);
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+impl Display for RegistryDump {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let metrics = &self.metrics;
+        writeln!(
+            f,
+            "{} services, {} instantiated, {} retrieved, {} borrowed",
+            metrics.services, metrics.instantiated, metrics.retrieved, metrics.borrowed
+        )?;
+        for service in &self.services {
+            write!(
+                f,
+                "  - {}: {} [{}, phase {}]",
+                service.alias,
+                service.type_name,
+                service.state(),
+                service.phase
+            )?;
+            if service.raw_references > 0 {
+                write!(f, " {} untracked references", service.raw_references)?;
+            }
+            for (key, value) in &service.tags {
+                write!(f, " {}={}", key, value)?;
+            }
+            if let Some(note) = &service.deprecation {
+                write!(f, " deprecated: {}", note)?;
+            }
+            writeln!(f)?;
+        }
+        let stats = &self.unsafe_stats;
+        writeln!(
+            f,
+            "Untracked references: {} outstanding, {} dangling, {} instances deferred",
+            stats.outstanding, stats.dangling, stats.deferred
+        )
+    }
+}
+
+impl SingletonManager {
+    /// Taking a snapshot of the registrations, their types, states and counters.
+    pub fn dump(&self) -> RegistryDump {
+        let mut dump = match self.registry() {
+            Ok(registry) => {
+                let mut services = registry
+                    .alias
+                    .iter()
+                    .map(|(alias, id)| {
+                        let state = registry.borrows.get(id);
+                        ServiceDump {
+                            alias: alias.to_string(),
+                            type_name: registry
+                                .type_names
+                                .get(id)
+                                .copied()
+                                .unwrap_or(UNKNOWN_TYPE)
+                                .to_string(),
+                            phase: registry.phases.get(id).copied().unwrap_or_default(),
+                            instantiated: registry.has_instance(id),
+                            retrieved: registry.retrieved.contains(id),
+                            borrowed: state.is_some_and(|state| state.is_borrowed()),
+                            frozen: registry.frozen.contains(id),
+                            raw_references: state
+                                .map_or(0, |state| state.raw_references(registry.raw_epoch)),
+                            tags: registry
+                                .tags
+                                .get(id)
+                                .into_iter()
+                                .flatten()
+                                .map(|(key, value)| (key.clone(), value.clone()))
+                                .collect(),
+                            deprecation: registry
+                                .deprecations
+                                .get(id)
+                                .map(|deprecation| deprecation.note.clone()),
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                services.sort_by(|a, b| a.alias.cmp(&b.alias));
+                RegistryDump {
+                    metrics: registry.metrics_of(registry.alias.values()),
+                    services,
+                    ..RegistryDump::default()
+                }
+            }
+            Err(_) => return RegistryDump::default(),
+        };
+        dump.unsafe_stats = self.unsafe_stats();
+        dump
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::SingletonManager;
+
+    #[test]
+    fn test_dump_as_text_and_json() {
+        let mut manager = SingletonManager::new();
+        manager.set("db", "postgres".to_string()).unwrap();
+        manager.set_tag("db", "owner", "team \"a\"").unwrap();
+        manager.set_factory("cache", || Box::new(1_u32)).unwrap();
+        let _guard = manager.borrow::<String>("db").unwrap();
+
+        let dump = manager.dump();
+        assert_eq!(vec!["factory", "borrowed"], {
+            dump.services.iter().map(|s| s.state()).collect::<Vec<_>>()
+        });
+        assert_eq!(1, dump.metrics.borrowed);
+
+        let text = dump.to_string();
+        assert!(text.starts_with("2 services, 1 instantiated, 1 retrieved, 1 borrowed\n"));
+        assert!(text.contains("  - db: alloc::string::String [borrowed, phase "));
+
+        let json = dump.to_json();
+        assert!(json.starts_with(r#"{"services":[{"#));
+        assert!(json.contains(r#""alias":"db","type":"alloc::string::String","state":"borrowed""#));
+        assert!(json.contains(r#""tags":{"owner":"team \"a\""}"#));
+        assert!(json.contains(
+            r#"],"metrics":{"services":2,"instantiated":1,"retrieved":1,"borrowed":1},"unsafe_stats":{"#
+        ));
+    }
+}
@@ -0,0 +1,99 @@
+//! # Environment
+//! Setting services from environment variables.
+//!
+//! Applications configured by their environment parse every variable by hand, and report a
+//! missing one at a time. `require_env` checks all variables needed at startup at once, failing
+//! with `Error::MissingEnv` listing every missing one, and `set_from_env` parses a variable into
+//! a service, so the configuration goes through the validators of the registry like any other
+//! service. The variable a service was set from is kept as its `env` tag.
+//! ```
+//! use singleton_manager::{Error, SingletonManager};
+//!
+//! std::env::set_var("EXAMPLE_PORT", "8080");
+//!
+//! let manager = SingletonManager::new();
+//! match manager.require_env(&["EXAMPLE_PORT", "EXAMPLE_HOST", "EXAMPLE_USER"]) {
+//!     Err(Error::MissingEnv(missing)) => {
+//!         assert_eq!(vec!["EXAMPLE_HOST", "EXAMPLE_USER"], missing)
+//!     }
+//!     _ => panic!("Expected the host and user to be missing"),
+//! }
+//!
+//! manager.set_from_env::<u16>("port", "EXAMPLE_PORT").unwrap();
+//! assert_eq!(8080, *manager.get_ref::<u16>("port").unwrap());
+//! assert_eq!(Some("EXAMPLE_PORT"), manager.info("port").unwrap().tag("env"));
+//! ```
+use crate::{Error, Result, SingletonManager};
+use std::env::VarError;
+use std::fmt::Display;
+use std::panic::Location;
+use std::str::FromStr;
+
+impl SingletonManager {
+    /// Failing with `Error::MissingEnv` if any of the environment variables is not set, listing
+    /// all of them in the order given.
+    pub fn require_env(&self, variables: &[&str]) -> Result<()> {
+        let missing = variables
+            .iter()
+            .filter(|variable| std::env::var_os(variable).is_none())
+            .map(|variable| variable.to_string())
+            .collect::<Vec<_>>();
+        match missing.is_empty() {
+            true => Ok(()),
+            false => Err(Error::MissingEnv(missing)),
+        }
+    }
+
+    /// Setting the service parsed from the environment variable.
+    /// Fails with `Error::MissingEnv` if the variable is not set, and with `Error::InvalidEnv`
+    /// if it can not be parsed as a `T`.
+    #[track_caller]
+    pub fn set_from_env<T>(&self, service_name: &str, variable: &str) -> Result<()>
+    where
+        T: FromStr + Send + Sync + 'static,
+        T::Err: Display,
+    {
+        let location = Location::caller();
+        let invalid =
+            |reason: String| Error::InvalidEnv(service_name.into(), variable.to_string(), reason);
+        let value = match std::env::var(variable) {
+            Ok(value) => value,
+            Err(VarError::NotPresent) => return Err(Error::MissingEnv(vec![variable.to_string()])),
+            Err(e) => return Err(invalid(e.to_string())),
+        };
+        let service = value.parse::<T>().map_err(|e| invalid(e.to_string()))?;
+        self.store(service_name, service, &[("env", variable)], location)
+            .map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Error, SingletonManager};
+
+    #[test]
+    fn test_services_from_env() {
+        std::env::set_var("SM_TEST_ENV_WORKERS", "four");
+        std::env::set_var("SM_TEST_ENV_TIMEOUT", "30");
+        let manager = SingletonManager::new();
+
+        assert!(manager
+            .require_env(&["SM_TEST_ENV_WORKERS", "SM_TEST_ENV_TIMEOUT"])
+            .is_ok());
+        assert!(matches!(
+            manager.set_from_env::<u8>("workers", "SM_TEST_ENV_WORKERS"),
+            Err(Error::InvalidEnv(alias, variable, _))
+                if alias == "workers" && variable == "SM_TEST_ENV_WORKERS"
+        ));
+        assert!(!manager.has("workers"));
+        assert!(matches!(
+            manager.set_from_env::<u8>("retries", "SM_TEST_ENV_RETRIES"),
+            Err(Error::MissingEnv(missing)) if missing == ["SM_TEST_ENV_RETRIES"]
+        ));
+
+        manager
+            .set_from_env::<u64>("timeout", "SM_TEST_ENV_TIMEOUT")
+            .unwrap();
+        assert_eq!(30, *manager.get_ref::<u64>("timeout").unwrap());
+    }
+}
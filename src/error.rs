@@ -0,0 +1,730 @@
+//! # Errors per operation
+//! The errors of the individual operations of the singleton manager.
+//!
+//! The umbrella `Error` covers every failure of the singleton manager, which forces matching on
+//! variants that can never happen for the operation at hand. Getting and setting services
+//! return their own error types, holding only the failures possible for the operation, which
+//! convert into `Error` so they can still be propagated with `?`.
+//!
+//! Every failure concerning a service carries its alias, and the errors have predicates for the
+//! kinds of failures, so calling code does not need to match on variants or on the message.
+//!
+//! The alias is carried as an `Alias`, the messages are only formatted when displayed. Getting a
+//! registered service allocates nothing, neither when it succeeds nor when it fails because the
+//! service is of another type or borrowed, the errors share the interned alias of the service.
+//! ```
+//! use singleton_manager::{GetError, SingletonManager};
+//!
+//! let mut manager = SingletonManager::new();
+//! match manager.get::<u32>("db") {
+//!     Err(GetError::ServiceDoesNotExist(alias, _)) => assert_eq!("db", alias),
+//!     _ => panic!("Expected the service to not exist"),
+//! }
+//!
+//! let e = manager.get::<u32>("db").unwrap_err();
+//! assert!(e.is_not_found());
+//! assert_eq!(Some("db"), e.alias());
+//!
+//! fn get_port(manager: &mut SingletonManager) -> singleton_manager::Result<u32> {
+//!     Ok(*manager.get::<u32>("port")?)
+//! }
+//! assert!(get_port(&mut manager).is_err());
+//! ```
+use crate::{Alias, Error, ServiceOrigin, Suggestions};
+use std::fmt::{Display, Formatter};
+use std::panic::Location;
+use std::thread::ThreadId;
+use std::time::Duration;
+
+/// The failures of getting a service.
+#[derive(Debug, Clone)]
+pub enum GetError {
+    /// The alias looked up and the aliases registered that are close to it.
+    ServiceDoesNotExist(Alias, Suggestions),
+    ServiceNotInstantiated(Alias),
+    FailedToDowncastRefOfService(Alias),
+    AlreadyBorrowed(Alias, &'static Location<'static>),
+    /// The alias of the service and the disabled flag.
+    FeatureDisabled(Alias, String),
+    ServiceFrozen(Alias),
+    /// The alias of the service, the thread owning it and the thread calling.
+    WrongThread(Alias, ThreadId, ThreadId),
+    AccessDenied(Alias),
+    FaultInjected(Alias),
+    /// The factory of the service needs the service itself, directly or through other factories.
+    RecursiveFactory(Alias),
+    /// The alias of the service, the version requirement, and the crate that registered the
+    /// service if it is known.
+    IncompatibleVersion(Alias, String, Option<ServiceOrigin>),
+    /// The alias of the service and why the version requirement could not be parsed.
+    InvalidVersion(Alias, String),
+    /// The service is not instantiated and has no factory to create it from, e.g. a memoized
+    /// service that is only created per key.
+    NoFactoryFunctionAvailable(Alias),
+    /// The service had to be created from its factory, which failed.
+    Factory(FactoryError),
+    MutexGotPoison,
+    UnknownError(String),
+}
+
+/// The failures of setting a service or a factory.
+#[derive(Debug, Clone)]
+pub enum SetError {
+    ServiceAlreadyExists(Alias),
+    ValidationFailed(Alias, String),
+    /// The registration already there is kept by the collision policy, and is of another type.
+    FailedToDowncastRefOfService(Alias),
+    /// The registration already there is borrowed, so the collision policy can not replace it.
+    AlreadyBorrowed(Alias, &'static Location<'static>),
+    /// The registration already there is frozen, so the collision policy can not replace it.
+    ServiceFrozen(Alias),
+    FailedToStoreService(Alias),
+    FailedToStoreFactory(Alias),
+    /// The alias of the service and its size in bytes, too large to be stored inline.
+    TooLargeToInline(Alias, usize),
+    /// The service is not `Send + Sync`, and the configuration rejects such services.
+    NotSendSync(Alias),
+    MutexGotPoison,
+    UnknownError(String),
+}
+
+/// The failures of creating a service from its factory.
+#[derive(Debug, Clone)]
+pub enum FactoryError {
+    ValidationFailed(Alias, String),
+    FailedToDowncastFactoryOutput(Alias),
+    /// The alias of the service and the time waited for its factory.
+    FactoryTimeout(Alias, Duration),
+    /// The alias of the service and the time until its factory is run again.
+    CircuitOpen(Alias, Duration),
+}
+
+impl Display for GetError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Factory(ref e) => Display::fmt(e, f),
+            e => Display::fmt(&Error::from(e.clone()), f),
+        }
+    }
+}
+
+impl Display for SetError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(&Error::from(self.clone()), f)
+    }
+}
+
+impl Display for FactoryError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(&Error::from(self.clone()), f)
+    }
+}
+
+impl std::error::Error for GetError {}
+
+impl std::error::Error for SetError {}
+
+impl std::error::Error for FactoryError {}
+
+impl From<GetError> for Error {
+    fn from(e: GetError) -> Self {
+        match e {
+            GetError::ServiceDoesNotExist(s, suggestions) => {
+                Self::ServiceDoesNotExist(s, suggestions)
+            }
+            GetError::ServiceNotInstantiated(s) => Self::ServiceNotInstantiated(s),
+            GetError::FailedToDowncastRefOfService(s) => Self::FailedToDowncastRefOfService(s),
+            GetError::AlreadyBorrowed(s, location) => Self::AlreadyBorrowed(s, location),
+            GetError::FeatureDisabled(s, flag) => Self::FeatureDisabled(s, flag),
+            GetError::ServiceFrozen(s) => Self::ServiceFrozen(s),
+            GetError::WrongThread(s, owner, caller) => Self::WrongThread(s, owner, caller),
+            GetError::AccessDenied(s) => Self::AccessDenied(s),
+            GetError::FaultInjected(s) => Self::FaultInjected(s),
+            GetError::RecursiveFactory(s) => Self::RecursiveFactory(s),
+            GetError::IncompatibleVersion(s, requirement, origin) => {
+                Self::IncompatibleVersion(s, requirement, origin)
+            }
+            GetError::InvalidVersion(s, reason) => Self::InvalidVersion(s, reason),
+            GetError::NoFactoryFunctionAvailable(s) => Self::NoFactoryFunctionAvailable(s),
+            GetError::Factory(e) => e.into(),
+            GetError::MutexGotPoison => Self::MutexGotPoison,
+            GetError::UnknownError(s) => Self::UnknownError(s),
+        }
+    }
+}
+
+impl From<SetError> for Error {
+    fn from(e: SetError) -> Self {
+        match e {
+            SetError::ServiceAlreadyExists(s) => Self::ServiceAlreadyExists(s),
+            SetError::ValidationFailed(s, reason) => Self::ValidationFailed(s, reason),
+            SetError::FailedToDowncastRefOfService(s) => Self::FailedToDowncastRefOfService(s),
+            SetError::AlreadyBorrowed(s, location) => Self::AlreadyBorrowed(s, location),
+            SetError::ServiceFrozen(s) => Self::ServiceFrozen(s),
+            SetError::FailedToStoreService(s) => Self::FailedToStoreService(s),
+            SetError::FailedToStoreFactory(s) => Self::FailedToStoreFactory(s),
+            SetError::TooLargeToInline(s, size) => Self::TooLargeToInline(s, size),
+            SetError::NotSendSync(s) => Self::NotSendSync(s),
+            SetError::MutexGotPoison => Self::MutexGotPoison,
+            SetError::UnknownError(s) => Self::UnknownError(s),
+        }
+    }
+}
+
+impl From<FactoryError> for Error {
+    fn from(e: FactoryError) -> Self {
+        match e {
+            FactoryError::ValidationFailed(s, reason) => Self::ValidationFailed(s, reason),
+            FactoryError::FailedToDowncastFactoryOutput(s) => {
+                Self::FailedToDowncastFactoryOutput(s)
+            }
+            FactoryError::FactoryTimeout(s, elapsed) => Self::FactoryTimeout(s, elapsed),
+            FactoryError::CircuitOpen(s, retry_after) => Self::CircuitOpen(s, retry_after),
+        }
+    }
+}
+
+impl GetError {
+    /// Narrowing an error of the singleton manager to the failures of getting a service.
+    /// Validation only happens when getting a service if it is created from its factory.
+    pub(crate) fn from_error(e: Error, service_name: &str) -> Self {
+        match e {
+            // Errors about ids are about the alias resolved to them.
+            Error::ServiceDoesNotExist(s, suggestions) if s == service_name => {
+                Self::ServiceDoesNotExist(s, suggestions)
+            }
+            Error::ServiceDoesNotExist(_, _) => {
+                Self::ServiceDoesNotExist(service_name.into(), Default::default())
+            }
+            Error::ServiceNotInstantiated(s) => Self::ServiceNotInstantiated(s),
+            Error::FailedToDowncastRefOfService(s) => Self::FailedToDowncastRefOfService(s),
+            Error::AlreadyBorrowed(s, location) => Self::AlreadyBorrowed(s, location),
+            Error::FeatureDisabled(s, flag) => Self::FeatureDisabled(s, flag),
+            Error::ServiceFrozen(s) => Self::ServiceFrozen(s),
+            Error::WrongThread(s, owner, caller) => Self::WrongThread(s, owner, caller),
+            Error::AccessDenied(s) => Self::AccessDenied(s),
+            Error::FaultInjected(s) => Self::FaultInjected(s),
+            Error::RecursiveFactory(s) => Self::RecursiveFactory(s),
+            Error::IncompatibleVersion(s, requirement, origin) => {
+                Self::IncompatibleVersion(s, requirement, origin)
+            }
+            Error::InvalidVersion(s, reason) => Self::InvalidVersion(s, reason),
+            Error::NoFactoryFunctionAvailable(s) => Self::NoFactoryFunctionAvailable(s),
+            Error::ValidationFailed(s, reason) => {
+                Self::Factory(FactoryError::ValidationFailed(s, reason))
+            }
+            Error::FailedToDowncastFactoryOutput(s) => {
+                Self::Factory(FactoryError::FailedToDowncastFactoryOutput(s))
+            }
+            Error::FactoryTimeout(s, elapsed) => {
+                Self::Factory(FactoryError::FactoryTimeout(s, elapsed))
+            }
+            Error::CircuitOpen(s, retry_after) => {
+                Self::Factory(FactoryError::CircuitOpen(s, retry_after))
+            }
+            Error::MutexGotPoison => Self::MutexGotPoison,
+            e => Self::UnknownError(e.to_string()),
+        }
+    }
+}
+
+impl SetError {
+    /// Narrowing an error of the singleton manager to the failures of setting a service.
+    pub(crate) fn from_error(e: Error, service_name: &str) -> Self {
+        match e {
+            Error::ServiceAlreadyExists(_) => Self::ServiceAlreadyExists(service_name.into()),
+            Error::ValidationFailed(s, reason) => Self::ValidationFailed(s, reason),
+            Error::FailedToDowncastRefOfService(s) => Self::FailedToDowncastRefOfService(s),
+            Error::AlreadyBorrowed(s, location) => Self::AlreadyBorrowed(s, location),
+            Error::ServiceFrozen(s) => Self::ServiceFrozen(s),
+            Error::FailedToStoreService(s) | Error::FailedToStoreServiceAlias(s) => {
+                Self::FailedToStoreService(s)
+            }
+            Error::FailedToStoreFactory(s) => Self::FailedToStoreFactory(s),
+            Error::TooLargeToInline(s, size) => Self::TooLargeToInline(s, size),
+            Error::NotSendSync(s) => Self::NotSendSync(s),
+            Error::MutexGotPoison => Self::MutexGotPoison,
+            e => Self::UnknownError(e.to_string()),
+        }
+    }
+}
+
+impl Error {
+    /// The alias of the service the error is about, if any.
+    pub fn alias(&self) -> Option<&str> {
+        match self {
+            Self::ServiceDoesNotExist(s, _)
+            | Self::ServiceNotInstantiated(s)
+            | Self::FailedToDowncastRefOfService(s)
+            | Self::FailedToStoreService(s)
+            | Self::NoFactoryFunctionAvailable(s)
+            | Self::SetFailedToReturnAServiceReference(s)
+            | Self::FailedToDowncastFactoryOutput(s)
+            | Self::NoServiceWithStorageRequest(s)
+            | Self::FailedToStoreServiceAlias(s)
+            | Self::ServiceAlreadyExists(s)
+            | Self::FailedToStoreFactory(s)
+            | Self::ValidationFailed(s, _)
+            | Self::ReservationCancelled(s)
+            | Self::ReservationTimeout(s)
+            | Self::FeatureDisabled(s, _)
+            | Self::ServiceFrozen(s)
+            | Self::ServiceNotFrozen(s)
+            | Self::ServiceNotFlagged(s)
+            | Self::AlreadyBorrowed(s, _)
+            | Self::NotRunnable(s)
+            | Self::NotScriptable(s)
+            | Self::StartFailed(s, _)
+            | Self::StopFailed(s, _)
+            | Self::DependencyCycle(s, _)
+            | Self::MissingDependency(s, _)
+            | Self::FactoryTimeout(s, _)
+            | Self::CircuitOpen(s, _)
+            | Self::FactoryNotSend(s)
+            | Self::WrongThread(s, _, _)
+            | Self::AccessDenied(s)
+            | Self::FaultInjected(s)
+            | Self::RecursiveFactory(s)
+            | Self::NoMailbox(s)
+            | Self::HookTimeout(s, _)
+            | Self::InvalidEnv(s, _, _)
+            | Self::InvalidSeed(s, _)
+            | Self::IncompatibleVersion(s, _, _)
+            | Self::InvalidVersion(s, _)
+            | Self::NotSendSync(s)
+            | Self::TooLargeToInline(s, _) => Some(s),
+            Self::TenantDoesNotExist(_)
+            | Self::TenantAlreadyExists(_)
+            | Self::InstanceAlreadyInitialized
+            | Self::InstanceInitFailed(_)
+            | Self::ModuleAlreadyInstalled(_)
+            | Self::ModuleNotInstalled(_)
+            | Self::InvalidManifest(_)
+            | Self::ManifestMismatch(_)
+            | Self::MissingEnv(_)
+            | Self::InvalidSeedData(_)
+            | Self::SecretNotFound(_)
+            | Self::SignalHandler(_)
+            | Self::MutexGotPoison
+            | Self::UnknownError(_) => None,
+        }
+    }
+
+    /// True if the service, or the secret, is not registered.
+    pub fn is_not_found(&self) -> bool {
+        matches!(
+            self,
+            Self::ServiceDoesNotExist(_, _) | Self::SecretNotFound(_)
+        )
+    }
+
+    /// True if the service is reserved but not yet set.
+    pub fn is_not_instantiated(&self) -> bool {
+        matches!(self, Self::ServiceNotInstantiated(_))
+    }
+
+    /// True if the service, or the output of its factory, is not of the requested type.
+    pub fn is_type_mismatch(&self) -> bool {
+        matches!(
+            self,
+            Self::FailedToDowncastRefOfService(_) | Self::FailedToDowncastFactoryOutput(_)
+        )
+    }
+
+    /// True if a service is already registered under the alias.
+    pub fn is_already_exists(&self) -> bool {
+        matches!(self, Self::ServiceAlreadyExists(_))
+    }
+
+    /// True if the service is borrowed in a conflicting way.
+    pub fn is_already_borrowed(&self) -> bool {
+        matches!(self, Self::AlreadyBorrowed(_, _))
+    }
+
+    /// True if the service was rejected by a validator.
+    pub fn is_validation_failed(&self) -> bool {
+        matches!(self, Self::ValidationFailed(_, _))
+    }
+
+    /// True if the service is disabled by a feature flag.
+    pub fn is_feature_disabled(&self) -> bool {
+        matches!(self, Self::FeatureDisabled(_, _))
+    }
+
+    /// True if the service is frozen.
+    pub fn is_frozen(&self) -> bool {
+        matches!(self, Self::ServiceFrozen(_))
+    }
+
+    /// True if the factory of the service did not finish within its budget.
+    pub fn is_timeout(&self) -> bool {
+        matches!(self, Self::FactoryTimeout(_, _))
+    }
+
+    /// True if the factory of the service failed too often, and is not run until the cool-down
+    /// passed.
+    pub fn is_circuit_open(&self) -> bool {
+        matches!(self, Self::CircuitOpen(_, _))
+    }
+
+    /// True if the service is bound to another thread.
+    pub fn is_wrong_thread(&self) -> bool {
+        matches!(self, Self::WrongThread(_, _, _))
+    }
+
+    /// True if the service is not within the aliases a scoped accessor may access.
+    pub fn is_access_denied(&self) -> bool {
+        matches!(self, Self::AccessDenied(_))
+    }
+
+    /// True if the failure was injected by the `chaos` feature.
+    pub fn is_fault_injected(&self) -> bool {
+        matches!(self, Self::FaultInjected(_))
+    }
+
+    /// True if the factory of the service needs the service itself.
+    pub fn is_recursive_factory(&self) -> bool {
+        matches!(self, Self::RecursiveFactory(_))
+    }
+
+    /// True if the version of the crate that registered the service does not satisfy the
+    /// version requirement, or is not known.
+    pub fn is_incompatible(&self) -> bool {
+        matches!(self, Self::IncompatibleVersion(_, _, _))
+    }
+
+    /// True if the registry lock got poisoned.
+    pub fn is_poisoned(&self) -> bool {
+        matches!(self, Self::MutexGotPoison)
+    }
+}
+
+impl GetError {
+    /// The alias of the service the error is about, if any.
+    pub fn alias(&self) -> Option<&str> {
+        match self {
+            Self::ServiceDoesNotExist(s, _)
+            | Self::ServiceNotInstantiated(s)
+            | Self::FailedToDowncastRefOfService(s)
+            | Self::AlreadyBorrowed(s, _)
+            | Self::FeatureDisabled(s, _)
+            | Self::ServiceFrozen(s)
+            | Self::WrongThread(s, _, _)
+            | Self::AccessDenied(s)
+            | Self::FaultInjected(s)
+            | Self::RecursiveFactory(s)
+            | Self::IncompatibleVersion(s, _, _)
+            | Self::InvalidVersion(s, _)
+            | Self::NoFactoryFunctionAvailable(s) => Some(s),
+            Self::Factory(e) => e.alias(),
+            Self::MutexGotPoison | Self::UnknownError(_) => None,
+        }
+    }
+
+    /// True if the service is not registered.
+    pub fn is_not_found(&self) -> bool {
+        matches!(self, Self::ServiceDoesNotExist(_, _))
+    }
+
+    /// True if the service is reserved but not yet set.
+    pub fn is_not_instantiated(&self) -> bool {
+        matches!(self, Self::ServiceNotInstantiated(_))
+    }
+
+    /// True if the service, or the output of its factory, is not of the requested type.
+    pub fn is_type_mismatch(&self) -> bool {
+        match self {
+            Self::FailedToDowncastRefOfService(_) => true,
+            Self::Factory(e) => e.is_type_mismatch(),
+            _ => false,
+        }
+    }
+
+    /// True if the service is borrowed in a conflicting way.
+    pub fn is_already_borrowed(&self) -> bool {
+        matches!(self, Self::AlreadyBorrowed(_, _))
+    }
+
+    /// True if the output of the factory was rejected by a validator.
+    pub fn is_validation_failed(&self) -> bool {
+        matches!(self, Self::Factory(e) if e.is_validation_failed())
+    }
+
+    /// True if the service is disabled by a feature flag.
+    pub fn is_feature_disabled(&self) -> bool {
+        matches!(self, Self::FeatureDisabled(_, _))
+    }
+
+    /// True if the service is frozen.
+    pub fn is_frozen(&self) -> bool {
+        matches!(self, Self::ServiceFrozen(_))
+    }
+
+    /// True if the factory of the service did not finish within its budget.
+    pub fn is_timeout(&self) -> bool {
+        matches!(self, Self::Factory(e) if e.is_timeout())
+    }
+
+    /// True if the factory of the service failed too often, and is not run until the cool-down
+    /// passed.
+    pub fn is_circuit_open(&self) -> bool {
+        matches!(self, Self::Factory(e) if e.is_circuit_open())
+    }
+
+    /// True if the service is bound to another thread.
+    pub fn is_wrong_thread(&self) -> bool {
+        matches!(self, Self::WrongThread(_, _, _))
+    }
+
+    /// True if the service is not within the aliases a scoped accessor may access.
+    pub fn is_access_denied(&self) -> bool {
+        matches!(self, Self::AccessDenied(_))
+    }
+
+    /// True if the failure was injected by the `chaos` feature.
+    pub fn is_fault_injected(&self) -> bool {
+        matches!(self, Self::FaultInjected(_))
+    }
+
+    /// True if the factory of the service needs the service itself.
+    pub fn is_recursive_factory(&self) -> bool {
+        matches!(self, Self::RecursiveFactory(_))
+    }
+
+    /// True if the version of the crate that registered the service does not satisfy the
+    /// version requirement, or is not known.
+    pub fn is_incompatible(&self) -> bool {
+        matches!(self, Self::IncompatibleVersion(_, _, _))
+    }
+
+    /// True if the registry lock got poisoned.
+    pub fn is_poisoned(&self) -> bool {
+        matches!(self, Self::MutexGotPoison)
+    }
+}
+
+impl SetError {
+    /// The alias of the service the error is about, if any.
+    pub fn alias(&self) -> Option<&str> {
+        match self {
+            Self::ServiceAlreadyExists(s)
+            | Self::ValidationFailed(s, _)
+            | Self::FailedToDowncastRefOfService(s)
+            | Self::AlreadyBorrowed(s, _)
+            | Self::ServiceFrozen(s)
+            | Self::FailedToStoreService(s)
+            | Self::FailedToStoreFactory(s)
+            | Self::TooLargeToInline(s, _)
+            | Self::NotSendSync(s) => Some(s),
+            Self::MutexGotPoison | Self::UnknownError(_) => None,
+        }
+    }
+
+    /// True if a service is already registered under the alias.
+    pub fn is_already_exists(&self) -> bool {
+        matches!(self, Self::ServiceAlreadyExists(_))
+    }
+
+    /// True if the service was rejected by a validator.
+    pub fn is_validation_failed(&self) -> bool {
+        matches!(self, Self::ValidationFailed(_, _))
+    }
+
+    /// True if the registration already there is kept, and is not of the type set.
+    pub fn is_type_mismatch(&self) -> bool {
+        matches!(self, Self::FailedToDowncastRefOfService(_))
+    }
+
+    /// True if the registration already there is borrowed, and can not be replaced.
+    pub fn is_already_borrowed(&self) -> bool {
+        matches!(self, Self::AlreadyBorrowed(_, _))
+    }
+
+    /// True if the registration already there is frozen, and can not be replaced.
+    pub fn is_frozen(&self) -> bool {
+        matches!(self, Self::ServiceFrozen(_))
+    }
+
+    /// True if the service is too large to be stored inline.
+    pub fn is_too_large_to_inline(&self) -> bool {
+        matches!(self, Self::TooLargeToInline(_, _))
+    }
+
+    /// True if the service is not `Send + Sync`, and the configuration rejects such services.
+    pub fn is_not_send_sync(&self) -> bool {
+        matches!(self, Self::NotSendSync(_))
+    }
+
+    /// True if the registry lock got poisoned.
+    pub fn is_poisoned(&self) -> bool {
+        matches!(self, Self::MutexGotPoison)
+    }
+}
+
+impl FactoryError {
+    /// The alias of the service the factory creates.
+    pub fn alias(&self) -> Option<&str> {
+        match self {
+            Self::ValidationFailed(s, _)
+            | Self::FailedToDowncastFactoryOutput(s)
+            | Self::FactoryTimeout(s, _)
+            | Self::CircuitOpen(s, _) => Some(s),
+        }
+    }
+
+    /// True if the output of the factory is not of the requested type.
+    pub fn is_type_mismatch(&self) -> bool {
+        matches!(self, Self::FailedToDowncastFactoryOutput(_))
+    }
+
+    /// True if the output of the factory was rejected by a validator.
+    pub fn is_validation_failed(&self) -> bool {
+        matches!(self, Self::ValidationFailed(_, _))
+    }
+
+    /// True if the factory did not finish within its budget.
+    pub fn is_timeout(&self) -> bool {
+        matches!(self, Self::FactoryTimeout(_, _))
+    }
+
+    /// True if the factory failed too often, and is not run until the cool-down passed.
+    pub fn is_circuit_open(&self) -> bool {
+        matches!(self, Self::CircuitOpen(_, _))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{CollisionPolicy, Error, FactoryError, GetError, SetError, SingletonManager};
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::cell::Cell;
+
+    thread_local! {
+        static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    /// Counting the allocations per thread, so tests running in parallel do not interfere.
+    struct CountingAllocator;
+
+    unsafe impl GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+            System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            System.dealloc(ptr, layout)
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+    fn allocations(mut f: impl FnMut()) -> usize {
+        let before = ALLOCATIONS.with(Cell::get);
+        f();
+        ALLOCATIONS.with(Cell::get) - before
+    }
+
+    #[test]
+    fn test_operation_errors_convert_into_error() {
+        let mut manager = SingletonManager::new();
+        manager
+            .add_validator(|info, _| {
+                Err(Error::ValidationFailed(
+                    info.alias().into(),
+                    "rejected".to_string(),
+                ))
+            })
+            .unwrap();
+        manager.set_factory("db", || Box::new(1_u32)).unwrap();
+
+        let e = manager.get::<u32>("db").unwrap_err();
+        assert!(matches!(
+            e,
+            GetError::Factory(FactoryError::ValidationFailed(_, _))
+        ));
+        assert!(matches!(Error::from(e), Error::ValidationFailed(_, _)));
+
+        match manager.set_factory("db", || Box::new(2_u32)) {
+            Err(SetError::ServiceAlreadyExists(alias)) => assert_eq!("db", alias),
+            _ => panic!("Expected the service to exist"),
+        }
+        assert!(matches!(
+            manager.set("cache", 1_u32),
+            Err(SetError::ValidationFailed(_, _))
+        ));
+    }
+
+    #[test]
+    fn test_collisions_narrow_to_set_errors() {
+        let mut manager = SingletonManager::new();
+        manager
+            .set_collision_policy(CollisionPolicy::FirstWins)
+            .unwrap();
+        manager.set("port", 8080_u16).unwrap();
+        let e = manager.set("port", "8080".to_string()).unwrap_err();
+        assert!(matches!(&e, SetError::FailedToDowncastRefOfService(alias) if alias == "port"));
+        assert!(e.is_type_mismatch());
+
+        manager
+            .set_collision_policy(CollisionPolicy::LastWins)
+            .unwrap();
+        let port = manager.get_ref::<u16>("port").unwrap();
+        let e = manager.set_send_factory("port", || 443_u16).unwrap_err();
+        assert!(matches!(&e, SetError::AlreadyBorrowed(alias, _) if alias == "port"));
+        assert!(e.is_already_borrowed());
+        drop(port);
+
+        manager.freeze("port").unwrap();
+        let e = manager.set("port", 443_u16).unwrap_err();
+        assert!(matches!(&e, SetError::ServiceFrozen(alias) if alias == "port"));
+        assert!(Error::from(e).is_frozen());
+    }
+
+    #[test]
+    fn test_errors_carry_alias() {
+        let mut manager = SingletonManager::new();
+        manager.set("port", 8080_u32).unwrap();
+        manager
+            .set_flagged("beta", "beta_enabled", || Box::new(1_u32))
+            .unwrap();
+
+        let e = manager.get::<String>("port").unwrap_err();
+        assert!(e.is_type_mismatch());
+        assert!(!e.is_not_found());
+        assert_eq!(Some("port"), e.alias());
+
+        let e = manager.get::<u32>("beta").unwrap_err();
+        assert!(e.is_feature_disabled());
+        assert_eq!(Some("beta"), e.alias());
+
+        let e = manager.set("port", 1_u32).unwrap_err();
+        assert!(e.is_already_exists());
+        assert_eq!(Some("port"), e.alias());
+        let e = Error::from(e);
+        assert!(e.is_already_exists());
+        assert_eq!(Some("port"), e.alias());
+        assert!(Error::MutexGotPoison.alias().is_none());
+    }
+
+    #[test]
+    fn test_lookups_of_registered_services_do_not_allocate() {
+        let mut manager = SingletonManager::new();
+        manager.set_factory("port", || Box::new(8080_u32)).unwrap();
+        manager.get_ref::<u32>("port").unwrap();
+
+        assert_eq!(
+            0,
+            allocations(|| drop(manager.get_ref::<u32>("port").unwrap()))
+        );
+        assert_eq!(0, allocations(|| drop(manager.get_ref::<u64>("port"))));
+        assert_eq!(0, allocations(|| drop(manager.get::<u64>("port"))));
+        let borrowed = manager.get_mut::<u32>("port").unwrap();
+        assert_eq!(0, allocations(|| drop(manager.borrow::<u32>("port"))));
+        drop(borrowed);
+        drop(manager.get_ref::<u32>("counted"));
+        assert_eq!(1, allocations(|| drop(manager.get_ref::<u32>("unknown"))));
+        assert_eq!(0, allocations(|| drop(manager.get_ref::<u32>("unknown"))));
+    }
+}
@@ -0,0 +1,151 @@
+//! # Facades
+//! Calling services bound as trait objects without looking them up on every call.
+//!
+//! A `Facade` resolves the service bound as a `dyn Trait` in the global singleton manager once,
+//! see `SingletonManager::bind`, and keeps the pointer to the trait object. The registry counts
+//! every change of its instances, aliases and flags as a new generation, and a facade only
+//! resolves the service again once the generation changed, so in steady state a call through the
+//! facade is a virtual call behind a single atomic load.
+//!
+//! The `facade!` macro generates a facade implementing the trait itself, by forwarding the
+//! methods listed to the service bound.
+//!
+//! Like the references handed out by `get`, the pointer is not tracked as a borrow, the instance
+//! it points to is kept until the `gc` after it was removed or replaced.
+//! ```
+//! use singleton_manager::{sm, Facade};
+//!
+//! trait Clock: Send + Sync {
+//!     fn now(&self) -> u64;
+//! }
+//!
+//! struct Fixed(u64);
+//!
+//! impl Clock for Fixed {
+//!     fn now(&self) -> u64 {
+//!         self.0
+//!     }
+//! }
+//!
+//! static CLOCK: Facade<dyn Clock> = Facade::new();
+//!
+//! sm().bind::<dyn Clock>(Box::new(Fixed(42))).unwrap();
+//! assert_eq!(42, CLOCK.get().unwrap().now());
+//! ```
+use crate::{bound_alias, GetError, Registry, Result, SingletonManager};
+use std::panic::Location;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, PoisonError, RwLock};
+
+/// The pointer to the service, and the generation of the registry it was resolved in.
+struct Resolved<T: ?Sized> {
+    service: *const T,
+    generation: Arc<AtomicU64>,
+    seen: u64,
+}
+
+/// The service bound as a `T` in the global singleton manager, usually a `dyn Trait`, resolved
+/// again only once the registry changed.
+pub struct Facade<T: ?Sized + 'static> {
+    resolved: RwLock<Option<Resolved<T>>>,
+}
+
+// The facade only hands out shared references to the service.
+unsafe impl<T: ?Sized + Sync> Send for Facade<T> {}
+unsafe impl<T: ?Sized + Sync> Sync for Facade<T> {}
+
+impl<T: ?Sized + Send + Sync + 'static> Facade<T> {
+    /// A facade resolving the service on first use.
+    pub const fn new() -> Self {
+        Self {
+            resolved: RwLock::new(None),
+        }
+    }
+
+    /// Getting the service, resolving it if the registry changed since it was last resolved.
+    #[track_caller]
+    pub fn get(&self) -> std::result::Result<&T, GetError> {
+        let location = Location::caller();
+        {
+            let resolved = self.resolved.read().unwrap_or_else(PoisonError::into_inner);
+            if let Some(resolved) = resolved
+                .as_ref()
+                .filter(|resolved| resolved.generation.load(Ordering::Acquire) == resolved.seen)
+            {
+                return Ok(unsafe { &*resolved.service });
+            }
+        }
+        let resolved = crate::sm()
+            .resolve_facade::<T>(location)
+            .map_err(|e| GetError::from_error(e, bound_alias::<T>()))?;
+        let service = resolved.service;
+        *self
+            .resolved
+            .write()
+            .unwrap_or_else(PoisonError::into_inner) = Some(resolved);
+        Ok(unsafe { &*service })
+    }
+}
+
+impl<T: ?Sized + Send + Sync + 'static> Default for Facade<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Registry {
+    /// Starting a new generation, making the facades resolve their service again.
+    pub(crate) fn invalidate_facades(&self) {
+        self.generation.fetch_add(1, Ordering::AcqRel);
+    }
+}
+
+impl SingletonManager {
+    fn resolve_facade<T: ?Sized + 'static>(
+        &self,
+        location: &'static Location<'static>,
+    ) -> Result<Resolved<T>> {
+        let generation = self.registry()?.generation.clone();
+        // Reading the generation first, a change while resolving resolves again on next use.
+        let seen = generation.load(Ordering::Acquire);
+        let service = self.unchecked_get::<Box<T>>(bound_alias::<T>(), location)?;
+        Ok(Resolved {
+            service: unsafe { &**service } as *const T,
+            generation,
+            seen,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{bound_alias, sm, Facade};
+
+    trait Shape: Send + Sync {
+        fn sides(&self) -> u32;
+    }
+
+    struct Polygon(u32);
+
+    impl Shape for Polygon {
+        fn sides(&self) -> u32 {
+            self.0
+        }
+    }
+
+    static SHAPE: Facade<dyn Shape> = Facade::new();
+
+    #[test]
+    fn test_facade_resolves_again_on_generation_change() {
+        assert!(SHAPE.get().is_err_and(|e| e.is_not_found()));
+        sm().bind::<dyn Shape>(Box::new(Polygon(3))).unwrap();
+        assert_eq!(3, SHAPE.get().unwrap().sides());
+        let first = SHAPE.get().unwrap() as *const dyn Shape;
+        assert!(std::ptr::addr_eq(first, SHAPE.get().unwrap()));
+
+        sm().take::<Box<dyn Shape>>(bound_alias::<dyn Shape>())
+            .unwrap();
+        sm().bind::<dyn Shape>(Box::new(Polygon(4))).unwrap();
+        assert_eq!(4, SHAPE.get().unwrap().sides());
+    }
+}
@@ -0,0 +1,112 @@
+//! # Fallback chains
+//! Serving a replacement when a service is unavailable.
+//!
+//! An alias can have an ordered chain of fallback aliases, e.g. `primary_cache` → `local_cache`
+//! → `noop_cache`. `get_with_fallback` serves the first entry of the chain that can be served,
+//! and records in the history when a fallback was served in place of the requested service.
+//! ```
+//! use singleton_manager::SingletonManager;
+//!
+//! let mut manager = SingletonManager::new();
+//! manager.set_factory("noop_cache", || Box::new("noop".to_string())).unwrap();
+//! manager.set_fallbacks("primary_cache", &["local_cache", "noop_cache"]).unwrap();
+//!
+//! let cache = manager.get_with_fallback::<String>("primary_cache").unwrap();
+//! assert_eq!("noop", cache.as_str());
+//! ```
+use crate::{GetError, Operation, Result, ServiceRef, SingletonManager};
+use std::panic::Location;
+
+impl SingletonManager {
+    /// Setting the ordered chain of fallbacks for an alias, replacing any previous chain.
+    /// The alias itself does not need to be registered, neither do the fallbacks, they are only
+    /// looked up when getting the service.
+    pub fn set_fallbacks(&self, service_name: &str, fallbacks: &[&str]) -> Result<()> {
+        self.registry_mut()?.fallbacks.insert(
+            service_name.to_string(),
+            fallbacks.iter().map(|alias| alias.to_string()).collect(),
+        );
+        Ok(())
+    }
+
+    /// Getting the chain of fallbacks for an alias.
+    pub fn fallbacks(&self, service_name: &str) -> Vec<String> {
+        self.registry()
+            .ok()
+            .and_then(|registry| registry.fallbacks.get(service_name).cloned())
+            .unwrap_or_default()
+    }
+
+    /// Getting a shared reference to the service, or to the first fallback that can be served,
+    /// as `get_ref` does.
+    /// An entry of the chain is skipped if it is not registered, its feature flag is disabled,
+    /// its factory output fails validation, or it is not of the requested type.
+    ///
+    /// Serving a fallback is recorded in the history as `Operation::Fallback`, with the fallback
+    /// served and its level in the chain, starting at 1 for the first fallback.
+    /// If nothing can be served the error of the requested service is returned.
+    #[track_caller]
+    pub fn get_with_fallback<T: 'static>(
+        &self,
+        service_name: &str,
+    ) -> std::result::Result<ServiceRef<'_, T>, GetError> {
+        let location = Location::caller();
+        let primary = match self.get_ref_by_alias::<T>(service_name) {
+            Ok(service) => return Ok(service),
+            Err(e) => e,
+        };
+        for (level, fallback) in self.fallbacks(service_name).into_iter().enumerate() {
+            if let Ok(service) = self.get_ref_by_alias::<T>(&fallback) {
+                if let Ok(mut registry) = self.registry_mut() {
+                    let operation = Operation::Fallback(fallback, level + 1);
+                    registry.record(operation, service_name, Some(location));
+                }
+                return Ok(service);
+            }
+        }
+        Err(primary)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{GetError, Operation, SingletonManager};
+
+    #[test]
+    fn test_fallback_chain_serves_first_available() {
+        let mut manager = SingletonManager::new();
+        manager.set("local_cache", "wrong type").unwrap();
+        manager.set("noop_cache", 3_u32).unwrap();
+        manager
+            .set_fallbacks("primary_cache", &["missing", "local_cache", "noop_cache"])
+            .unwrap();
+
+        assert_eq!(
+            3,
+            *manager.get_with_fallback::<u32>("primary_cache").unwrap()
+        );
+        assert_eq!(
+            Operation::Fallback("noop_cache".to_string(), 3),
+            manager.history().last().unwrap().operation
+        );
+
+        manager.set("primary_cache", 1_u32).unwrap();
+        assert_eq!(
+            1,
+            *manager.get_with_fallback::<u32>("primary_cache").unwrap()
+        );
+        assert_eq!(Operation::Set, manager.history().last().unwrap().operation);
+    }
+
+    #[test]
+    fn test_fallback_chain_exhausted() {
+        let manager = SingletonManager::new();
+        manager.set_fallbacks("db", &["replica"]).unwrap();
+
+        assert!(matches!(
+            manager.get_with_fallback::<u32>("db"),
+            Err(GetError::ServiceDoesNotExist(_, _))
+        ));
+        assert!(manager.history().is_empty());
+    }
+}
@@ -0,0 +1,293 @@
+//! # C API
+//! Sharing the registry with C and C++ code in the same process, behind the `ffi` feature.
+//!
+//! The singleton manager is handed to C as an opaque handle, the global instance by `sm_global`
+//! or a manager of its own by `sm_new`. C code registers its services as raw pointers, with an
+//! optional destructor run when the service is removed or the manager freed, and gets them back
+//! by alias. Every function returns an `SmStatus`, panics do not unwind into the caller.
+//!
+//! To link the functions into a C program, build the crate as a static or dynamic library, e.g.
+//! `cargo rustc --release --features ffi --crate-type staticlib`.
+//! ```c
+//! SingletonManager *sm = sm_global();
+//! sm_set_ptr(sm, "logger", logger, (void (*)(void *))logger_free);
+//!
+//! void *found = NULL;
+//! if (sm_get_ptr(sm, "logger", &found) == SmOk) {
+//!     logger_write((Logger *)found, "started");
+//! }
+//! sm_remove(sm, "logger");
+//! ```
+use crate::{Error, Result, SingletonManager};
+use std::ffi::{c_void, CStr};
+use std::os::raw::c_char;
+use std::panic::{catch_unwind, AssertUnwindSafe, Location};
+
+/// The destructor of a service set from C.
+pub type SmDestructor = Option<unsafe extern "C" fn(*mut c_void)>;
+
+/// The outcome of a function of the C API.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SmStatus {
+    SmOk = 0,
+    /// No service is registered under the alias.
+    SmNotFound = 1,
+    /// A service is already registered under the alias.
+    SmAlreadyExists = 2,
+    /// The service was not set from C, and is no pointer.
+    SmTypeMismatch = 3,
+    /// The service is borrowed or frozen.
+    SmInUse = 4,
+    /// The service is registered by a factory and not yet created.
+    SmNotInstantiated = 5,
+    /// A pointer passed is null, or the alias is not UTF-8.
+    SmInvalidArgument = 6,
+    /// The call panicked.
+    SmPanicked = 7,
+    SmError = 8,
+}
+
+impl From<&Error> for SmStatus {
+    fn from(e: &Error) -> Self {
+        if e.is_not_found() {
+            Self::SmNotFound
+        } else if e.is_already_exists() {
+            Self::SmAlreadyExists
+        } else if e.is_type_mismatch() {
+            Self::SmTypeMismatch
+        } else if e.is_already_borrowed() || e.is_frozen() {
+            Self::SmInUse
+        } else if e.is_not_instantiated() {
+            Self::SmNotInstantiated
+        } else {
+            Self::SmError
+        }
+    }
+}
+
+/// A service set from C, running its destructor when dropped.
+struct ForeignPtr {
+    ptr: *mut c_void,
+    destructor: SmDestructor,
+}
+
+// The C code setting the pointer is responsible for the service being usable from any thread.
+unsafe impl Send for ForeignPtr {}
+unsafe impl Sync for ForeignPtr {}
+
+impl Drop for ForeignPtr {
+    fn drop(&mut self) {
+        if let Some(destructor) = self.destructor {
+            unsafe { destructor(self.ptr) }
+        }
+    }
+}
+
+/// Running a call of the C API, turning its failure or panic into a status.
+fn call(f: impl FnOnce() -> std::result::Result<(), SmStatus>) -> SmStatus {
+    match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => SmStatus::SmOk,
+        Ok(Err(status)) => status,
+        Err(_) => SmStatus::SmPanicked,
+    }
+}
+
+/// The manager and alias behind the arguments, if neither is null and the alias is UTF-8.
+unsafe fn arguments<'a>(
+    handle: *mut SingletonManager,
+    name: *const c_char,
+) -> std::result::Result<(&'a mut SingletonManager, &'a str), SmStatus> {
+    if name.is_null() {
+        return Err(SmStatus::SmInvalidArgument);
+    }
+    let name = CStr::from_ptr(name)
+        .to_str()
+        .map_err(|_| SmStatus::SmInvalidArgument)?;
+    handle
+        .as_mut()
+        .map(|manager| (manager, name))
+        .ok_or(SmStatus::SmInvalidArgument)
+}
+
+fn status<T>(result: Result<T>) -> std::result::Result<T, SmStatus> {
+    result.map_err(|e| SmStatus::from(&e))
+}
+
+/// The handle of the global instance, see `SingletonManager::instance`, or null if creating it
+/// failed.
+#[no_mangle]
+pub extern "C" fn sm_global() -> *mut SingletonManager {
+    catch_unwind(SingletonManager::try_instance_mut)
+        .ok()
+        .and_then(|instance| instance.ok())
+        .map_or(std::ptr::null_mut(), |instance| instance as *mut _)
+}
+
+/// The handle of a new, empty, singleton manager, to be freed by `sm_free`.
+#[no_mangle]
+pub extern "C" fn sm_new() -> *mut SingletonManager {
+    catch_unwind(SingletonManager::new).map_or(std::ptr::null_mut(), |manager| {
+        Box::into_raw(Box::new(manager))
+    })
+}
+
+/// Freeing a singleton manager created by `sm_new`, dropping its services.
+///
+/// # Safety
+/// The handle must be null or created by `sm_new`, and not used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn sm_free(handle: *mut SingletonManager) -> SmStatus {
+    if handle.is_null() {
+        return SmStatus::SmInvalidArgument;
+    }
+    call(|| {
+        drop(Box::from_raw(handle));
+        Ok(())
+    })
+}
+
+/// Setting the pointer as the service registered under the name. The destructor, if not null,
+/// is run with the pointer when the service is removed or the manager freed, or right away if
+/// setting fails, the manager owns the pointer from the call on.
+/// Returns `SmAlreadyExists` also if the collision policy keeps the service already registered.
+///
+/// # Safety
+/// The handle must be valid and the name a valid C string. The pointer must be usable from any
+/// thread for as long as the service is registered.
+#[no_mangle]
+pub unsafe extern "C" fn sm_set_ptr(
+    handle: *mut SingletonManager,
+    name: *const c_char,
+    ptr: *mut c_void,
+    destructor: SmDestructor,
+) -> SmStatus {
+    call(|| {
+        let (manager, name) = arguments(handle, name)?;
+        let service = ForeignPtr { ptr, destructor };
+        manager.end_raw_references();
+        let stored = status(manager.registry_mut().and_then(|mut registry| {
+            registry.store_service(
+                name,
+                Box::new(service),
+                std::any::type_name::<ForeignPtr>(),
+                &[],
+                Location::caller(),
+            )
+        }))?;
+        stored.map(drop).ok_or(SmStatus::SmAlreadyExists)
+    })
+}
+
+/// Writing the pointer set under the name to `out`.
+///
+/// # Safety
+/// The handle must be valid, the name a valid C string and `out` writable. The pointer written
+/// is only valid until the service is removed.
+#[no_mangle]
+pub unsafe extern "C" fn sm_get_ptr(
+    handle: *mut SingletonManager,
+    name: *const c_char,
+    out: *mut *mut c_void,
+) -> SmStatus {
+    call(|| {
+        let (manager, name) = arguments(handle, name)?;
+        let out = out.as_mut().ok_or(SmStatus::SmInvalidArgument)?;
+        let service = status(
+            manager
+                .get_ref_by_alias::<ForeignPtr>(name)
+                .map_err(Error::from),
+        )?;
+        *out = service.ptr;
+        Ok(())
+    })
+}
+
+/// Removing the service set under the name, running its destructor.
+/// Only services set from C can be removed.
+///
+/// # Safety
+/// The handle must be valid and the name a valid C string.
+#[no_mangle]
+pub unsafe extern "C" fn sm_remove(handle: *mut SingletonManager, name: *const c_char) -> SmStatus {
+    call(|| {
+        let (manager, name) = arguments(handle, name)?;
+        status(manager.take::<ForeignPtr>(name).map_err(Error::from)).map(drop)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{sm_free, sm_get_ptr, sm_new, sm_remove, sm_set_ptr, SmStatus};
+    use crate::CollisionPolicy;
+    use std::ffi::c_void;
+    use std::os::raw::c_char;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static FREED: AtomicUsize = AtomicUsize::new(0);
+
+    unsafe extern "C" fn free_counter(ptr: *mut c_void) {
+        drop(Box::from_raw(ptr as *mut u32));
+        FREED.fetch_add(1, Ordering::SeqCst);
+    }
+
+    #[test]
+    fn test_pointers_round_trip_through_the_c_api() {
+        let sm = sm_new();
+        let counter = Box::into_raw(Box::new(7_u32)) as *mut c_void;
+        let name = b"counter\0".as_ptr() as *const c_char;
+        unsafe {
+            assert_eq!(
+                SmStatus::SmOk,
+                sm_set_ptr(sm, name, counter, Some(free_counter))
+            );
+            assert_eq!(
+                SmStatus::SmAlreadyExists,
+                sm_set_ptr(sm, name, counter, None)
+            );
+
+            let mut found = std::ptr::null_mut();
+            assert_eq!(SmStatus::SmOk, sm_get_ptr(sm, name, &mut found));
+            assert_eq!(7, *(found as *mut u32));
+            (*sm).set("rust_only", 1_u32).unwrap();
+            assert_eq!(
+                SmStatus::SmTypeMismatch,
+                sm_get_ptr(sm, b"rust_only\0".as_ptr() as *const c_char, &mut found)
+            );
+
+            assert_eq!(SmStatus::SmOk, sm_remove(sm, name));
+            assert_eq!(1, FREED.load(Ordering::SeqCst));
+            assert_eq!(SmStatus::SmNotFound, sm_get_ptr(sm, name, &mut found));
+            assert_eq!(SmStatus::SmInvalidArgument, sm_remove(sm, std::ptr::null()));
+            assert_eq!(SmStatus::SmOk, sm_free(sm));
+        }
+    }
+
+    #[test]
+    fn test_kept_registrations_reported_as_collisions() {
+        let sm = sm_new();
+        let name = b"logger ".as_ptr() as *const c_char;
+        let dropped = AtomicUsize::new(0);
+        unsafe extern "C" fn count_drop(ptr: *mut c_void) {
+            (*(ptr as *const AtomicUsize)).fetch_add(1, Ordering::SeqCst);
+        }
+        let counter = &dropped as *const AtomicUsize as *mut c_void;
+        unsafe {
+            (*sm)
+                .set_collision_policy(CollisionPolicy::FirstWins)
+                .unwrap();
+            assert_eq!(SmStatus::SmOk, sm_set_ptr(sm, name, counter, None));
+            assert_eq!(
+                SmStatus::SmAlreadyExists,
+                sm_set_ptr(sm, name, counter, Some(count_drop))
+            );
+            assert_eq!(1, dropped.load(Ordering::SeqCst));
+
+            let mut found = std::ptr::null_mut();
+            assert_eq!(SmStatus::SmOk, sm_get_ptr(sm, name, &mut found));
+            assert_eq!(counter, found);
+            assert_eq!(SmStatus::SmOk, sm_free(sm));
+            assert_eq!(1, dropped.load(Ordering::SeqCst));
+        }
+    }
+}
@@ -0,0 +1,231 @@
+//! # Fixtures
+//! Declaring the registrations of a test, and reverting them when the test ends.
+//!
+//! A `Fixture` collects services and factories, and `apply` registers them all at once, moving
+//! the registrations already under their aliases aside. The guard returned reverts everything
+//! when dropped, also when the test panics: the registrations of the fixture are removed and the
+//! registrations moved aside are restored, with their instances and everything set on them.
+//! ```
+//! use singleton_manager::{Fixture, SingletonManager};
+//!
+//! let mut manager = SingletonManager::new();
+//! manager.set_factory("db", || Box::new("postgres".to_string())).unwrap();
+//!
+//! {
+//!     let _fixture = Fixture::new()
+//!         .with("db", "sqlite::memory:".to_string())
+//!         .with_factory("log", || Box::new(Vec::<String>::new()))
+//!         .apply_to(&manager)
+//!         .unwrap();
+//!     assert_eq!("sqlite::memory:", *manager.get_ref::<String>("db").unwrap());
+//!     assert!(manager.has("log"));
+//! }
+//! assert_eq!("postgres", *manager.get_ref::<String>("db").unwrap());
+//! assert!(!manager.has("log"));
+//! ```
+use crate::{sm, Alias, Error, Registry, Result, SingletonManager, Transaction, Uuid};
+use std::any::Any;
+use std::panic::Location;
+
+/// The registrations of a test, see `Fixture::apply`.
+#[derive(Default)]
+pub struct Fixture {
+    staged: Transaction,
+    /// The first error staging a registration, returned when applying the fixture.
+    error: Option<Error>,
+}
+
+impl Fixture {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adding a service, as `SingletonManager::set` does.
+    #[track_caller]
+    pub fn with<T: Send + Sync + 'static>(mut self, service_name: &str, service: T) -> Self {
+        let staged = self.staged.set(service_name, service);
+        self.note(staged);
+        self
+    }
+
+    /// Adding a factory, as `SingletonManager::set_factory` does.
+    #[track_caller]
+    pub fn with_factory<F: 'static + Fn() -> Box<dyn Any + Send + Sync> + Send + Sync>(
+        mut self,
+        service_name: &str,
+        factory: F,
+    ) -> Self {
+        let staged = self.staged.set_factory(service_name, factory);
+        self.note(staged);
+        self
+    }
+
+    fn note(&mut self, staged: Result<()>) {
+        if let Err(e) = staged {
+            self.error.get_or_insert(e);
+        }
+    }
+
+    /// Registering the fixture in the global singleton manager, see `apply_to`.
+    #[track_caller]
+    pub fn apply(self) -> Result<FixtureGuard<'static>> {
+        self.apply_to(sm())
+    }
+
+    /// Registering the services and factories of the fixture, moving the registrations already
+    /// under their aliases aside until the guard returned is dropped.
+    /// Fails with the first error adding a service or factory, e.g. `Error::ServiceAlreadyExists`
+    /// if an alias is added twice, and with `Error::ServiceFrozen` if a registration to move aside
+    /// is frozen. Nothing is registered if it fails.
+    #[track_caller]
+    pub fn apply_to(self, manager: &SingletonManager) -> Result<FixtureGuard<'_>> {
+        if let Some(e) = self.error {
+            return Err(e);
+        }
+        let aliases = self
+            .staged
+            .aliases()
+            .into_iter()
+            .map(str::to_string)
+            .collect::<Vec<_>>();
+        let mut registry = manager.registry_mut()?;
+        for alias in &aliases {
+            if let Some(id) = registry.alias.get(alias.as_str()) {
+                registry.check_unfrozen(id, alias)?;
+            }
+        }
+        let moved = aliases
+            .iter()
+            .filter_map(|alias| registry.move_aside(alias))
+            .collect::<Vec<_>>();
+        if let Err(e) = registry.apply(self.staged) {
+            for registration in moved.into_iter().rev() {
+                let _ = registry.move_back(registration);
+            }
+            return Err(e);
+        }
+        let registered = aliases
+            .into_iter()
+            .filter_map(|alias| Some((*registry.alias.get(alias.as_str())?, alias)))
+            .collect();
+        Ok(FixtureGuard {
+            manager,
+            registered,
+            moved,
+            location: Location::caller(),
+        })
+    }
+}
+
+/// Reverting the registrations of a fixture when dropped, see `Fixture::apply`.
+pub struct FixtureGuard<'a> {
+    manager: &'a SingletonManager,
+    registered: Vec<(Uuid, String)>,
+    /// The registrations moved aside, with the aliases they are restored under.
+    moved: Vec<(Alias, Uuid)>,
+    /// Where the fixture was applied, recorded as the location of the removals.
+    location: &'static Location<'static>,
+}
+
+impl FixtureGuard<'_> {
+    /// The aliases registered by the fixture, in the order they were added.
+    pub fn aliases(&self) -> Vec<&str> {
+        self.registered
+            .iter()
+            .map(|(_, alias)| alias.as_str())
+            .collect()
+    }
+}
+
+impl Registry {
+    /// Moving the registration of the alias out of the aliases while a fixture is applied, so it
+    /// is neither resolved nor listed, keeping everything stored for it.
+    fn move_aside(&mut self, alias: &str) -> Option<(Alias, Uuid)> {
+        let registration = self.alias.remove_entry(alias)?;
+        self.unindex_alias(alias);
+        self.invalidate_facades();
+        Some(registration)
+    }
+
+    /// Restoring a registration moved aside, or handing it back if its alias is in use.
+    fn move_back(&mut self, (alias, id): (Alias, Uuid)) -> std::result::Result<(), (Alias, Uuid)> {
+        if self.alias.contains_key(&alias) {
+            return Err((alias, id));
+        }
+        self.index_alias(alias.clone());
+        self.alias.insert(alias, id);
+        self.invalidate_facades();
+        Ok(())
+    }
+}
+
+impl Drop for FixtureGuard<'_> {
+    /// Removing the registrations of the fixture and restoring the registrations moved aside.
+    /// A registration of the fixture still borrowed, or frozen, is kept, and the registration
+    /// moved aside from its alias is removed instead.
+    fn drop(&mut self) {
+        let mut torn_down = Vec::new();
+        if let Ok(mut registry) = self.manager.registry_mut() {
+            for service in self.registered.iter().rev() {
+                if registry.resolve(&service.1) != Some(service.0) {
+                    continue;
+                }
+                match registry.teardown(std::slice::from_ref(service), self.location) {
+                    Ok(services) => torn_down.extend(services),
+                    Err(e) => log::warn!("Service `{}` outlives its fixture: {}", service.1, e),
+                }
+            }
+            for (alias, id) in self.moved.drain(..).rev() {
+                if let Err((alias, id)) = registry.move_back((alias, id)) {
+                    log::warn!("Service `{}` could not be restored", alias);
+                    registry.forget_registration(&alias, id);
+                }
+            }
+        }
+        torn_down.into_iter().for_each(drop);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Error, Fixture, SingletonManager};
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    #[test]
+    fn test_fixtures_revert_on_drop_even_on_panic() {
+        let mut manager = SingletonManager::new();
+        manager.set_factory("db", || Box::new(1_u32)).unwrap();
+        *manager.get_mut::<u32>("db").unwrap() += 1;
+        manager.deprecate("db", "use `db_v2`").unwrap();
+
+        let panicked = catch_unwind(AssertUnwindSafe(|| {
+            let fixture = Fixture::new()
+                .with("db", 10_u32)
+                .with_factory("log", || Box::new(0_u8))
+                .apply_to(&manager)
+                .unwrap();
+            assert_eq!(vec!["db", "log"], fixture.aliases());
+            assert_eq!(10, *manager.get_ref::<u32>("db").unwrap());
+            assert_eq!(None, manager.deprecation("db"));
+            assert_eq!(2, manager.dump().services.len());
+            assert_eq!(2, manager.entries_with_prefix("").len());
+            panic!("test failed");
+        }));
+        assert!(panicked.is_err());
+        assert_eq!(2, *manager.get_ref::<u32>("db").unwrap());
+        assert_eq!(Some("use `db_v2`".to_string()), manager.deprecation("db"));
+        assert!(!manager.has("log"));
+        assert_eq!(1, manager.dump().services.len());
+
+        let duplicated = Fixture::new()
+            .with("log", 1_u8)
+            .with("log", 2_u8)
+            .apply_to(&manager);
+        assert!(matches!(duplicated, Err(Error::ServiceAlreadyExists(alias)) if alias == "log"));
+
+        manager.freeze("db").unwrap();
+        let frozen = Fixture::new().with("db", 3_u32).apply_to(&manager);
+        assert!(matches!(frozen, Err(Error::ServiceFrozen(alias)) if alias == "db"));
+        assert_eq!(2, *manager.get_ref::<u32>("db").unwrap());
+    }
+}
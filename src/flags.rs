@@ -0,0 +1,242 @@
+//! # Feature flags
+//! Services gated behind feature flags.
+//!
+//! A service registered with `set_flagged` is only served while its flag is enabled, otherwise
+//! getting it serves the designated fallback service, or fails with `GetError::FeatureDisabled`.
+//! This allows dark-launching a subsystem through the registry and toggling it at runtime.
+//! ```
+//! use singleton_manager::SingletonManager;
+//!
+//! let mut manager = SingletonManager::new();
+//! manager.set_flagged("search", "new_search", || Box::new("elastic".to_string())).unwrap();
+//! manager.set("legacy_search", "sql".to_string()).unwrap();
+//! manager.set_flag_fallback("search", "legacy_search").unwrap();
+//!
+//! assert_eq!("sql", manager.get::<String>("search").unwrap());
+//!
+//! manager.enable_flag("new_search").unwrap();
+//! assert_eq!("elastic", manager.get::<String>("search").unwrap());
+//! ```
+use crate::{Error, Operation, Registry, Result, SingletonManager, Uuid};
+use std::any::Any;
+use std::panic::Location;
+use std::sync::Arc;
+
+/// The flag gating a service, and the alias of the service served while the flag is disabled.
+#[derive(Debug, Clone)]
+pub(crate) struct FlagGate {
+    flag: String,
+    fallback: Option<String>,
+}
+
+impl Registry {
+    /// Getting the id of the service to serve for the id of the alias, following the fallbacks
+    /// of disabled flags.
+    pub(crate) fn gate(&self, id: Uuid, alias: &str) -> Result<Uuid> {
+        let mut id = id;
+        // Every hop visits a different registration, unless the fallbacks form a cycle.
+        for _ in 0..=self.alias.len() {
+            let gate = match self.gates.get(&id) {
+                Some(gate) if !self.flags.get(&gate.flag).copied().unwrap_or(false) => gate,
+                _ => return Ok(id),
+            };
+            id = gate
+                .fallback
+                .as_deref()
+                .and_then(|fallback| self.resolve(fallback))
+                .ok_or_else(|| Error::FeatureDisabled(alias.into(), gate.flag.clone()))?;
+        }
+        Err(Error::FeatureDisabled(
+            alias.into(),
+            self.gates
+                .get(&id)
+                .map(|gate| gate.flag.clone())
+                .unwrap_or_default(),
+        ))
+    }
+}
+
+impl Registry {
+    /// Getting the id of the service to serve for the alias, as `SingletonManager::serving_id`
+    /// does, under a lock already held.
+    pub(crate) fn serving_id(
+        &self,
+        service_name: &str,
+        location: &'static Location<'static>,
+    ) -> Result<Uuid> {
+        #[cfg(feature = "chaos")]
+        self.chaos.check_get(service_name)?;
+        match self.resolve_for_use(service_name, location) {
+            Some(id) => self.gate(id, service_name),
+            None => {
+                let alias = self.note_failed_lookup(service_name);
+                let suggestions = self.suggestions(alias.clone());
+                Err(Error::ServiceDoesNotExist(alias, suggestions))
+            }
+        }
+    }
+}
+
+impl SingletonManager {
+    /// Setting a factory for a service that is only served while the flag is enabled.
+    /// Flags are disabled until enabled through `enable_flag` or `set_flag`.
+    #[track_caller]
+    pub fn set_flagged<F: 'static + Fn() -> Box<dyn Any + Send + Sync> + Send + Sync>(
+        &self,
+        service_name: &str,
+        flag_name: &str,
+        factory: F,
+    ) -> Result<()> {
+        let location = Location::caller();
+        let mut registry = self.registry_mut()?;
+        let id = registry.store_alias(service_name)?;
+        registry.singleton_factory_set(id, Arc::new(factory))?;
+        registry.gates.insert(
+            id,
+            FlagGate {
+                flag: flag_name.to_string(),
+                fallback: None,
+            },
+        );
+        registry.record(Operation::SetFactory, service_name, Some(location));
+        Ok(())
+    }
+
+    /// Setting the service served in place of a flagged service while its flag is disabled.
+    /// Fails with `Error::ServiceDoesNotExist` holding the alias missing if the service or the
+    /// fallback is not registered, and with `Error::ServiceNotFlagged` if the service is not
+    /// flagged.
+    pub fn set_flag_fallback(&self, service_name: &str, fallback_name: &str) -> Result<()> {
+        let mut registry = self.registry_mut()?;
+        let id = registry
+            .resolve(service_name)
+            .ok_or_else(|| Error::ServiceDoesNotExist(service_name.into(), Default::default()))?;
+        if registry.resolve(fallback_name).is_none() {
+            return Err(Error::ServiceDoesNotExist(
+                fallback_name.into(),
+                Default::default(),
+            ));
+        }
+        registry
+            .gates
+            .get_mut(&id)
+            .map(|gate| gate.fallback = Some(fallback_name.to_string()))
+            .ok_or_else(|| Error::ServiceNotFlagged(service_name.into()))
+    }
+
+    /// Enabling or disabling a flag.
+    pub fn set_flag(&self, flag_name: &str, enabled: bool) -> Result<()> {
+        let mut registry = self.registry_mut()?;
+        registry.flags.insert(flag_name.to_string(), enabled);
+        registry.invalidate_facades();
+        Ok(())
+    }
+
+    /// Enabling a flag.
+    pub fn enable_flag(&self, flag_name: &str) -> Result<()> {
+        self.set_flag(flag_name, true)
+    }
+
+    /// Disabling a flag.
+    pub fn disable_flag(&self, flag_name: &str) -> Result<()> {
+        self.set_flag(flag_name, false)
+    }
+
+    /// True if the flag is enabled.
+    pub fn is_flag_enabled(&self, flag_name: &str) -> bool {
+        self.registry()
+            .map(|registry| registry.flags.get(flag_name).copied().unwrap_or(false))
+            .unwrap_or(false)
+    }
+
+    /// Getting the id of the service to serve for the alias, taking the flags into account.
+    /// A lookup of an alias not registered is counted, see `usage_report`.
+    pub(crate) fn serving_id(
+        &self,
+        service_name: &str,
+        location: &'static Location<'static>,
+    ) -> Result<Uuid> {
+        self.registry()?.serving_id(service_name, location)
+    }
+
+    /// Getting the id of the service to serve for the alias as `serving_id` does, or nothing if
+    /// the alias is not registered, which is not counted as a failed lookup.
+    pub(crate) fn optional_serving_id(
+        &self,
+        service_name: &str,
+        location: &'static Location<'static>,
+    ) -> Result<Option<Uuid>> {
+        let registry = self.registry()?;
+        #[cfg(feature = "chaos")]
+        registry.chaos.check_get(service_name)?;
+        registry
+            .resolve_for_use(service_name, location)
+            .map(|id| registry.gate(id, service_name))
+            .transpose()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Error, GetError, SingletonManager};
+
+    #[test]
+    fn test_flagged_service_without_fallback() {
+        let mut manager = SingletonManager::new();
+        manager
+            .set_flagged("beta", "beta_enabled", || Box::new(1_u32))
+            .unwrap();
+
+        match manager.get::<u32>("beta") {
+            Err(GetError::FeatureDisabled(_, flag)) => assert_eq!("beta_enabled", flag),
+            _ => panic!("Expected the feature to be disabled"),
+        }
+        assert!(!manager.info("beta").unwrap().is_instantiated());
+
+        manager.enable_flag("beta_enabled").unwrap();
+        assert_eq!(1, *manager.borrow::<u32>("beta").unwrap());
+
+        manager.disable_flag("beta_enabled").unwrap();
+        assert!(matches!(
+            manager.borrow::<u32>("beta"),
+            Err(GetError::FeatureDisabled(_, _))
+        ));
+    }
+
+    #[test]
+    fn test_flag_fallback_chain() {
+        let mut manager = SingletonManager::new();
+        manager
+            .set_flagged("a", "flag_a", || Box::new(1_u32))
+            .unwrap();
+        manager
+            .set_flagged("b", "flag_b", || Box::new(2_u32))
+            .unwrap();
+        manager.set("c", 3_u32).unwrap();
+        manager.set_flag_fallback("a", "b").unwrap();
+        manager.set_flag_fallback("b", "c").unwrap();
+        assert!(matches!(
+            manager.set_flag_fallback("c", "a"),
+            Err(Error::ServiceNotFlagged(alias)) if alias == "c"
+        ));
+        assert!(matches!(
+            manager.set_flag_fallback("a", "d"),
+            Err(Error::ServiceDoesNotExist(alias, _)) if alias == "d"
+        ));
+        assert!(matches!(
+            manager.set_flag_fallback("d", "a"),
+            Err(Error::ServiceDoesNotExist(alias, _)) if alias == "d"
+        ));
+
+        assert_eq!(3, *manager.get::<u32>("a").unwrap());
+        manager.set_flag("flag_b", true).unwrap();
+        assert_eq!(2, *manager.get::<u32>("a").unwrap());
+
+        manager.set_flag("flag_b", false).unwrap();
+        manager.set_flag_fallback("b", "a").unwrap();
+        assert!(matches!(
+            manager.get::<u32>("a"),
+            Err(GetError::FeatureDisabled(_, _))
+        ));
+    }
+}
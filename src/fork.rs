@@ -0,0 +1,164 @@
+//! # Forking
+//! Copying the wiring of a singleton manager, without its instances.
+//!
+//! Worker threads, or processes spawned with the same setup, may need their own instances of the
+//! services instead of sharing them, e.g. a connection per worker instead of one pool guarded by
+//! a lock. Forking copies the factory registrations, with their phases, tags, dependencies and
+//! hooks, into an independent manager, where every service is instantiated again by its factory.
+//!
+//! Services set directly, memoized services and reservations are not copied, their instances
+//! can not be created again.
+//! ```
+//! use singleton_manager::SingletonManager;
+//!
+//! let mut manager = SingletonManager::new();
+//! manager.set_factory("buffer", || Box::new(Vec::<u8>::new())).unwrap();
+//!
+//! let worker = manager.fork().unwrap();
+//! std::thread::spawn(move || worker.get_mut::<Vec<u8>>("buffer").unwrap().push(1))
+//!     .join()
+//!     .unwrap();
+//! assert!(manager.get_ref::<Vec<u8>>("buffer").unwrap().is_empty());
+//! ```
+use crate::{BorrowState, Registry, Result, SingletonManager, Uuid, NEXT_INSTANCE_ID};
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, RwLock};
+
+/// The entries of the map for the registrations copied.
+fn copied<V: Clone>(map: &HashMap<Uuid, V>, ids: &HashSet<Uuid>) -> HashMap<Uuid, V> {
+    map.iter()
+        .filter(|(id, _)| ids.contains(id))
+        .map(|(id, value)| (*id, value.clone()))
+        .collect()
+}
+
+impl Registry {
+    /// A registry with the factory registrations of this one, and none of its instances.
+    fn fork(&self) -> Registry {
+        let ids: HashSet<Uuid> = self.singleton_factories.keys().copied().collect();
+        let alias: HashMap<_, _> = self
+            .alias
+            .iter()
+            .filter(|(_, id)| ids.contains(id))
+            .map(|(alias, id)| (alias.clone(), *id))
+            .collect();
+        let dependencies = copied(&self.dependencies, &ids)
+            .into_iter()
+            .map(|(id, mut dependencies)| {
+                dependencies.retain(|dependency| ids.contains(dependency));
+                (id, dependencies)
+            })
+            .collect();
+        let groups = self
+            .groups
+            .iter()
+            .map(|(group, members)| {
+                let members = members.iter().filter(|id| ids.contains(id)).copied();
+                (group.clone(), members.collect())
+            })
+            .collect();
+        Registry {
+            singleton_factories: self.singleton_factories.clone(),
+            prefix_index: Arc::new(RwLock::new(alias.keys().cloned().collect())),
+            borrows: alias
+                .iter()
+                .map(|(alias, id)| {
+                    let state = BorrowState::new(alias.clone(), self.config.metrics);
+                    (*id, Arc::new(state))
+                })
+                .collect(),
+            phases: copied(&self.phases, &ids),
+            tags: copied(&self.tags, &ids),
+            reconcilers: self.reconcilers.clone(),
+            validators: self.validators.clone(),
+            history: self.history.emptied(),
+            forwarding: self
+                .forwarding
+                .iter()
+                .filter(|(_, target)| alias.contains_key::<str>(target))
+                .map(|(old, target)| (old.clone(), target.clone()))
+                .collect(),
+            gates: copied(&self.gates, &ids),
+            flags: self.flags.clone(),
+            fallbacks: self.fallbacks.clone(),
+            default_versions: self.default_versions.clone(),
+            type_names: copied(&self.type_names, &ids),
+            profile: self.profile.clone(),
+            groups,
+            health_checks: copied(&self.health_checks, &ids),
+            runnables: copied(&self.runnables, &ids),
+            script_adapters: copied(&self.script_adapters, &ids),
+            breakers: copied(&self.breakers, &ids),
+            allowed_callers: copied(&self.allowed_callers, &ids),
+            deprecations: copied(&self.deprecations, &ids),
+            origins: copied(&self.origins, &ids),
+            config: self.config.clone(),
+            dependencies,
+            drains: copied(&self.drains, &ids),
+            lifecycles: copied(&self.lifecycles, &ids),
+            unsync_factories: self.unsync_factories.intersection(&ids).copied().collect(),
+            collision_policy: self.collision_policy,
+            namespace_collision_policies: self.namespace_collision_policies.clone(),
+            priorities: copied(&self.priorities, &ids),
+            bindings: self
+                .bindings
+                .iter()
+                .filter(|(bound, _)| alias.contains_key(bound.as_str()))
+                .map(|(bound, bindings)| (bound.clone(), bindings.clone()))
+                .collect(),
+            auto_gc: self.auto_gc,
+            #[cfg(feature = "serde")]
+            seeders: self.seeders.clone(),
+            clock: self.clock.clone(),
+            alias,
+            ..Registry::default()
+        }
+    }
+}
+
+impl SingletonManager {
+    /// Creating an independent manager with the factory registrations of this one, and none of
+    /// its instances.
+    pub fn fork(&self) -> Result<SingletonManager> {
+        Ok(SingletonManager {
+            registry: RwLock::new(self.registry()?.fork()),
+            clock_and_ids: self.clock_and_ids.clone(),
+            instance_id: NEXT_INSTANCE_ID.fetch_add(1, Ordering::Relaxed),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Phase, SingletonManager};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_fork_copies_registrations_not_instances() {
+        let created = Arc::new(AtomicUsize::new(0));
+        let mut manager = SingletonManager::new();
+        let counted = created.clone();
+        manager
+            .set_factory("worker_id", move || {
+                Box::new(counted.fetch_add(1, Ordering::SeqCst))
+            })
+            .unwrap();
+        manager
+            .set_phase("worker_id", Phase::Infrastructure)
+            .unwrap();
+        manager.set("config", "production".to_string()).unwrap();
+        assert_eq!(0, *manager.get_ref::<usize>("worker_id").unwrap());
+
+        let mut fork = manager.fork().unwrap();
+        assert!(!fork.is_instantiated("worker_id"));
+        assert!(!fork.has("config"));
+        assert_eq!(Phase::Infrastructure, fork.phase("worker_id").unwrap());
+        assert_eq!(1, *fork.get_ref::<usize>("worker_id").unwrap());
+        assert_eq!(0, *manager.get_ref::<usize>("worker_id").unwrap());
+
+        fork.set_factory("cache", || Box::new(1_u8)).unwrap();
+        assert!(!manager.has("cache"));
+    }
+}
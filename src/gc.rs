@@ -0,0 +1,171 @@
+//! # Deferred drops
+//! Reclaiming removed services once the references to them are out of use.
+//!
+//! A service removed or replaced while references handed out by `get` or `set` may still be in
+//! flight can not be dropped right away without risking a use after free. Such instances are
+//! moved to a deferral queue instead, and `gc` drops them later, at points where the application
+//! knows no references are in flight, e.g. between two requests.
+//!
+//! Dropping is epoch based, every `gc` starts a new epoch and only drops the instances deferred
+//! in earlier epochs, so references taken in the epoch an instance was removed in have a whole
+//! epoch to go out of use. Instances without references handed out are dropped right away, the
+//! references are counted in every build, see `unsafe_stats`.
+//! The instances are always dropped after the singleton manager is unlocked, so dropping them may
+//! use the singleton manager.
+//! ```
+//! use singleton_manager::SingletonManager;
+//!
+//! let mut manager = SingletonManager::new();
+//! manager.set("pool", vec![1_u32, 2, 3]).unwrap();
+//! manager.take::<Vec<u32>>("pool").unwrap();
+//! manager.set_factory("cache", || Box::new(1_u32)).unwrap();
+//! manager.get::<u32>("cache").unwrap();
+//! manager.add_to_group("cache", "caches").unwrap();
+//! manager.shutdown_group("caches").unwrap();
+//!
+//! assert_eq!(1, manager.unsafe_stats().deferred);
+//! assert_eq!(0, manager.gc());
+//! assert_eq!(1, manager.gc());
+//! ```
+use crate::{Registry, Result, Service, SingletonManager, Uuid};
+
+/// An instance removed while references handed out to it may still be in flight.
+pub(crate) struct Deferred {
+    alias: String,
+    instance: Service,
+    /// The number of references handed out to the instance.
+    references: usize,
+    /// The epoch the instance was removed in.
+    epoch: u64,
+}
+
+impl Registry {
+    /// Removing the instance of the service from the storage, returning it so it can be dropped,
+    /// unless references handed out to it may still be in flight, in which case it is deferred.
+    pub(crate) fn drop_instance(&mut self, id: &Uuid) -> Option<Service> {
+        let (instance, references) = self.remove_instance(id)?;
+        if references == 0 {
+            return Some(instance);
+        }
+        self.deferred.push(Deferred {
+            alias: self.alias_of(id).unwrap_or_default().to_string(),
+            instance,
+            references,
+            epoch: self.epoch,
+        });
+        if self
+            .auto_gc
+            .is_some_and(|limit| self.deferred.len() > limit)
+        {
+            let reclaimed = self.reclaim();
+            self.released.extend(reclaimed);
+        }
+        None
+    }
+
+    /// Taking the instances deferred in earlier epochs out of the deferral queue, and starting a
+    /// new epoch.
+    fn reclaim(&mut self) -> Vec<Service> {
+        let epoch = self.epoch;
+        self.epoch += 1;
+        let (reclaimed, deferred): (Vec<_>, Vec<_>) = self
+            .deferred
+            .drain(..)
+            .partition(|deferred| deferred.epoch < epoch);
+        self.deferred = deferred;
+        reclaimed
+            .into_iter()
+            .map(|deferred| {
+                self.note_dangling(&deferred.alias, deferred.references);
+                deferred.instance
+            })
+            .collect()
+    }
+}
+
+impl SingletonManager {
+    /// Dropping the instances deferred before the previous `gc`, and starting a new epoch,
+    /// returning the number of instances dropped.
+    /// The instances are dropped after the singleton manager is unlocked.
+    pub fn gc(&self) -> usize {
+        let reclaimed = match self.registry_mut() {
+            Ok(mut registry) => registry.reclaim(),
+            Err(_) => return 0,
+        };
+        let count = reclaimed.len();
+        reclaimed.into_iter().for_each(drop);
+        count
+    }
+
+    /// Running `gc` automatically when more than the limit of instances are deferred, or never
+    /// if the limit is `None`, which is the default.
+    pub fn set_auto_gc(&self, limit: Option<usize>) -> Result<()> {
+        self.registry_mut()?.auto_gc = limit;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::SingletonManager;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    struct Counted(Arc<AtomicUsize>);
+
+    impl Drop for Counted {
+        fn drop(&mut self) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn test_deferred_instances_dropped_by_gc() {
+        let mut manager = SingletonManager::new();
+        let dropped = Arc::new(AtomicUsize::new(0));
+        manager.set("first", Counted(dropped.clone())).unwrap();
+        manager.set("second", Counted(dropped.clone())).unwrap();
+        manager.set("third", Counted(dropped.clone())).unwrap();
+        manager.shutdown().unwrap();
+        // Only the reference returned by the last `set` may still be used.
+        assert_eq!(2, dropped.load(Ordering::SeqCst));
+
+        manager.gc();
+        assert_eq!(2, dropped.load(Ordering::SeqCst));
+        manager.gc();
+        assert_eq!(3, dropped.load(Ordering::SeqCst));
+
+        manager.set_auto_gc(Some(1)).unwrap();
+        for alias in ["fourth", "fifth", "sixth"] {
+            manager.set(alias, Counted(dropped.clone())).unwrap();
+            manager.shutdown().unwrap();
+        }
+        assert_eq!(5, dropped.load(Ordering::SeqCst));
+        assert_eq!(1, manager.unsafe_stats().deferred);
+    }
+
+    struct Probe(&'static SingletonManager, Arc<AtomicUsize>);
+
+    impl Drop for Probe {
+        fn drop(&mut self) {
+            self.1.store(self.0.count() + 1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn test_replaced_instances_dropped_after_unlocking() {
+        let manager: &'static SingletonManager = Box::leak(Box::new(SingletonManager::new()));
+        let counted = Arc::new(AtomicUsize::new(0));
+        manager
+            .set_collision_policy(crate::CollisionPolicy::LastWins)
+            .unwrap();
+        let probe = counted.clone();
+        manager
+            .set_send_factory("probe", move || Probe(manager, probe.clone()))
+            .unwrap();
+        drop(manager.get_ref::<Probe>("probe").unwrap());
+
+        manager.set_send_factory("probe", || 1_u32).unwrap();
+        assert_eq!(2, counted.load(Ordering::SeqCst));
+    }
+}
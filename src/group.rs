@@ -0,0 +1,335 @@
+//! # Groups
+//! Operating on named groups of services at once.
+//!
+//! Services can be assigned to any number of groups, e.g. `background` for the workers of a
+//! daemon, and a group can be initialized, shut down and inspected as a whole. This allows
+//! stopping all background workers while keeping the HTTP stack alive during a drain.
+//! ```
+//! use singleton_manager::SingletonManager;
+//!
+//! let mut manager = SingletonManager::new();
+//! manager.set_factory("mailer", || Box::new("mailer".to_string())).unwrap();
+//! manager.set_factory("indexer", || Box::new("indexer".to_string())).unwrap();
+//! manager.add_to_group("mailer", "background").unwrap();
+//! manager.add_to_group("indexer", "background").unwrap();
+//!
+//! assert!(manager.init_group("background").is_success());
+//! assert_eq!(2, manager.metrics_of_group("background").instantiated);
+//!
+//! manager.shutdown_group("background").unwrap();
+//! assert_eq!(0, manager.metrics_of_group("background").instantiated);
+//! ```
+use crate::callers::manager_caller;
+use crate::{Error, Registry, Result, SingletonManager, Uuid};
+use std::cmp::Reverse;
+use std::fmt::{Display, Formatter};
+use std::panic::Location;
+use std::sync::Arc;
+
+/// Checking the health of a service, see `SingletonManager::set_health_check`.
+pub(crate) type HealthCheck = Arc<dyn Fn(&SingletonManager, &str) -> Health + Send + Sync>;
+
+/// The health of a single service.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Health {
+    Healthy,
+    /// The service failed its health check, or could not be checked, for the contained reason.
+    Unhealthy(String),
+    /// The service is registered, but not instantiated.
+    NotInstantiated,
+}
+
+impl Display for Health {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Healthy => write!(f, "healthy"),
+            Self::Unhealthy(ref reason) => write!(f, "unhealthy: {}", reason),
+            Self::NotInstantiated => write!(f, "not instantiated"),
+        }
+    }
+}
+
+/// The result of `SingletonManager::init_group`.
+#[derive(Debug, Clone, Default)]
+pub struct GroupReport {
+    /// The aliases of the services that was instantiated, or already was.
+    pub initialized: Vec<String>,
+    /// The aliases of the services that could not be instantiated.
+    pub failed: Vec<(String, Error)>,
+}
+
+impl GroupReport {
+    /// True if all services of the group was instantiated.
+    pub fn is_success(&self) -> bool {
+        self.failed.is_empty()
+    }
+}
+
+/// Counters of the services of a group, see `SingletonManager::metrics_of_group`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GroupMetrics {
+    /// The number of services in the group.
+    pub services: usize,
+    /// The number of services that are instantiated.
+    pub instantiated: usize,
+    /// The number of services that have been retrieved.
+    pub retrieved: usize,
+    /// The number of services that are currently borrowed.
+    pub borrowed: usize,
+}
+
+impl Registry {
+    /// Counting the services by their state.
+    pub(crate) fn metrics_of<'a, I>(&self, ids: I) -> GroupMetrics
+    where
+        I: IntoIterator<Item = &'a Uuid>,
+    {
+        let mut metrics = GroupMetrics::default();
+        for id in ids {
+            metrics.services += 1;
+            if self.has_instance(id) {
+                metrics.instantiated += 1;
+            }
+            if self.retrieved.contains(id) {
+                metrics.retrieved += 1;
+            }
+            if self
+                .borrows
+                .get(id)
+                .is_some_and(|state| state.is_borrowed())
+            {
+                metrics.borrowed += 1;
+            }
+        }
+        metrics
+    }
+
+    /// The services of the group, in startup phase order, and within a phase in the order they
+    /// were added to the group.
+    fn group_services(&self, group: &str) -> Vec<(Uuid, String)> {
+        let mut services: Vec<(Uuid, String)> = self
+            .groups
+            .get(group)
+            .into_iter()
+            .flatten()
+            .filter_map(|id| Some((*id, self.alias_of(id)?.to_string())))
+            .collect();
+        services.sort_by_key(|(id, _)| self.phases.get(id).copied().unwrap_or_default());
+        services
+    }
+}
+
+impl SingletonManager {
+    /// Adding a service to a group, a service can be in any number of groups.
+    pub fn add_to_group(&self, service_name: &str, group: &str) -> Result<()> {
+        let id = self.service_id(service_name)?;
+        let mut registry = self.registry_mut()?;
+        let ids = registry.groups.entry(group.to_string()).or_default();
+        if !ids.contains(&id) {
+            ids.push(id);
+        }
+        Ok(())
+    }
+
+    /// The names of the groups, in alphabetical order.
+    pub fn groups(&self) -> Vec<String> {
+        self.registry()
+            .map(|registry| registry.groups.keys().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// The aliases of the services of a group, in startup phase order.
+    pub fn group_members(&self, group: &str) -> Vec<String> {
+        self.registry()
+            .map(|registry| {
+                registry
+                    .group_services(group)
+                    .into_iter()
+                    .map(|(_, alias)| alias)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Instantiating all services of a group, in startup phase order.
+    pub fn init_group(&self, group: &str) -> GroupReport {
+        let mut report = GroupReport::default();
+        let services = self
+            .registry()
+            .map(|registry| registry.group_services(group))
+            .unwrap_or_default();
+        for (id, alias) in services {
+            match self.singleton_get(&id, &alias) {
+                Ok(_) => report.initialized.push(alias),
+                Err(e) => report.failed.push((alias, e)),
+            }
+        }
+        report
+    }
+
+    /// Shutting down all services of a group, in reverse startup phase order, returning the
+    /// aliases of the services shut down.
+    /// The instances are dropped after the singleton manager is unlocked. Services with a factory
+    /// stay registered and are instantiated again when needed, services set directly are removed.
+    ///
+    /// Nothing is shut down if any of the services is borrowed or frozen.
+    #[track_caller]
+    pub fn shutdown_group(&self, group: &str) -> Result<Vec<String>> {
+        let location = Location::caller();
+        let mut registry = self.registry_mut()?;
+        let mut services = registry.group_services(group);
+        services.retain(|(id, _)| registry.singletons.contains_key(id));
+        services
+            .sort_by_key(|(id, _)| Reverse(registry.phases.get(id).copied().unwrap_or_default()));
+        registry.check_removable(&services)?;
+        let mut shut_down = Vec::with_capacity(services.len());
+        for service in &services {
+            shut_down.extend(registry.shut_down(service, location)?);
+        }
+        drop(registry);
+        shut_down.into_iter().for_each(drop);
+        Ok(services.into_iter().map(|(_, alias)| alias).collect())
+    }
+
+    /// Setting the health check of a service, used by `health_of_group`.
+    /// The check is given a shared borrow of the service, and returns the reason the service is
+    /// unhealthy if it is.
+    pub fn set_health_check<T, F>(&self, service_name: &str, check: F) -> Result<()>
+    where
+        T: 'static,
+        F: Fn(&T) -> std::result::Result<(), String> + Send + Sync + 'static,
+    {
+        let id = self.service_id(service_name)?;
+        let check: HealthCheck = Arc::new(move |manager, alias| {
+            let location = manager_caller();
+            match manager
+                .shared_borrow::<T>(alias, location, |state| state.try_borrow(alias, location))
+            {
+                Ok(service) => check(&service)
+                    .err()
+                    .map_or(Health::Healthy, Health::Unhealthy),
+                Err(e) => Health::Unhealthy(e.to_string()),
+            }
+        });
+        self.registry_mut()?.health_checks.insert(id, check);
+        Ok(())
+    }
+
+    /// Checking the health of all services of a group, in startup phase order.
+    /// Services are not instantiated to check them, and instantiated services without a health
+    /// check are healthy.
+    pub fn health_of_group(&self, group: &str) -> Vec<(String, Health)> {
+        let services = match self.registry() {
+            Ok(registry) => registry
+                .group_services(group)
+                .into_iter()
+                .map(|(id, alias)| {
+                    let instantiated = registry.has_instance(&id);
+                    (
+                        alias,
+                        instantiated,
+                        registry.health_checks.get(&id).cloned(),
+                    )
+                })
+                .collect::<Vec<_>>(),
+            Err(_) => return Vec::new(),
+        };
+        services
+            .into_iter()
+            .map(|(alias, instantiated, check)| {
+                let health = match check {
+                    _ if !instantiated => Health::NotInstantiated,
+                    Some(check) => check(self, &alias),
+                    None => Health::Healthy,
+                };
+                (alias, health)
+            })
+            .collect()
+    }
+
+    /// Checking the health of a service, as `health_of_group` does.
+    pub fn health(&self, service_name: &str) -> Result<Health> {
+        let (instantiated, check) = {
+            let registry = self.registry()?;
+            let id = registry.resolve(service_name).ok_or_else(|| {
+                Error::ServiceDoesNotExist(service_name.into(), Default::default())
+            })?;
+            (
+                registry.has_instance(&id),
+                registry.health_checks.get(&id).cloned(),
+            )
+        };
+        Ok(match check {
+            _ if !instantiated => Health::NotInstantiated,
+            Some(check) => check(self, service_name),
+            None => Health::Healthy,
+        })
+    }
+
+    /// Counting the services of a group by their state.
+    pub fn metrics_of_group(&self, group: &str) -> GroupMetrics {
+        let registry = match self.registry() {
+            Ok(registry) => registry,
+            Err(_) => return GroupMetrics::default(),
+        };
+        let services = registry.group_services(group);
+        registry.metrics_of(services.iter().map(|(id, _)| id))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Error, Health, Phase, SingletonManager};
+
+    #[test]
+    fn test_group_operations() {
+        let mut manager = SingletonManager::new();
+        manager.set_factory("worker", || Box::new(3_u32)).unwrap();
+        manager.set("queue", "jobs".to_string()).unwrap();
+        manager.set_factory("http", || Box::new(80_u16)).unwrap();
+        manager.set_phase("queue", Phase::Infrastructure).unwrap();
+        manager.add_to_group("worker", "background").unwrap();
+        manager.add_to_group("queue", "background").unwrap();
+        manager.add_to_group("http", "interface").unwrap();
+        manager
+            .set_health_check("worker", |workers: &u32| match *workers {
+                0 => Err("no workers".to_string()),
+                _ => Ok(()),
+            })
+            .unwrap();
+
+        assert_eq!(vec!["background", "interface"], manager.groups());
+        assert_eq!(vec!["queue", "worker"], manager.group_members("background"));
+        assert_eq!(
+            vec![
+                ("queue".to_string(), Health::Healthy),
+                ("worker".to_string(), Health::NotInstantiated)
+            ],
+            manager.health_of_group("background")
+        );
+
+        let report = manager.init_group("background");
+        assert_eq!(vec!["queue", "worker"], report.initialized);
+        assert!(!manager.is_instantiated("http"));
+        *manager.get_mut::<u32>("worker").unwrap() = 0;
+        assert_eq!(
+            Health::Unhealthy("no workers".to_string()),
+            manager.health_of_group("background")[1].1
+        );
+
+        let worker = manager.get_ref::<u32>("worker").unwrap();
+        assert_eq!(1, manager.metrics_of_group("background").borrowed);
+        assert!(matches!(
+            manager.shutdown_group("background"),
+            Err(Error::AlreadyBorrowed(_, _))
+        ));
+        drop(worker);
+        assert_eq!(
+            vec!["worker", "queue"],
+            manager.shutdown_group("background").unwrap()
+        );
+        assert!(!manager.has("queue"));
+        assert!(!manager.is_instantiated("worker"));
+        assert_eq!(3, *manager.get_ref::<u32>("worker").unwrap());
+    }
+}
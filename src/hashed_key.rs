@@ -0,0 +1,336 @@
+//! # Hashed keys
+//! Validating and hashing aliases at compile time.
+//!
+//! The `key!` macro turns a string literal into a `HashedKey`, evaluated as a constant, so an
+//! alias that is empty or has leading or trailing whitespace fails compilation, and the hash of
+//! the alias is computed by the compiler. Getting a service by a hashed key looks its id up in
+//! the table of the keys used with the manager, keyed by the precomputed hash, instead of hashing
+//! the alias on every lookup. The table is rebuilt whenever aliases are registered, removed or
+//! renamed.
+//!
+//! Every `key!` also adds its literal to a table of the keys of the process, the first time it is
+//! evaluated, so two literals with the same hash are caught, and `unresolved_keys` lists the keys
+//! no service is registered under.
+//!
+//! With the `strict-keys` feature, getting services by raw strings through `get`, `get_ref`,
+//! `get_mut`, `borrow` and `borrow_mut` is deprecated in favour of `get_by_key`,
+//! `get_ref_by_key`, `get_mut_by_key`, `borrow_by_key` and `borrow_mut_by_key`, so
+//! `#![deny(deprecated)]` in a crate denies lookups not going through `key!`.
+//! ```
+//! use singleton_manager::{key, SingletonManager};
+//!
+//! let mut manager = SingletonManager::new();
+//! manager.set_factory("database", || Box::new("postgres".to_string())).unwrap();
+//!
+//! let database = manager.get_ref_by_key::<String>(key!("database")).unwrap();
+//! assert_eq!("postgres", database.as_str());
+//! drop(database);
+//!
+//! let _ = key!("databse");
+//! let unresolved = manager.unresolved_keys().unwrap();
+//! assert!(unresolved.iter().any(|key| key.alias() == "databse"));
+//! assert!(unresolved.iter().all(|key| key.alias() != "database"));
+//! ```
+//! An invalid alias fails compilation:
+//! ```compile_fail
+//! let key = singleton_manager::key!(" database");
+//! ```
+use crate::{GetError, Registry, Result, ServiceRef, ServiceRefMut, SingletonManager, Uuid};
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{BuildHasherDefault, Hasher};
+use std::panic::Location;
+use std::sync::atomic::Ordering;
+use std::sync::{PoisonError, RwLock};
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// The 64 bit FNV-1a hash of the bytes, continuing from the state.
+const fn fnv1a(mut state: u64, bytes: &[u8]) -> u64 {
+    let mut i = 0;
+    while i < bytes.len() {
+        state = (state ^ bytes[i] as u64).wrapping_mul(FNV_PRIME);
+        i += 1;
+    }
+    state
+}
+
+/// The aliases of the keys created with `key!`, by their hash, shared by all managers.
+static KEY_TABLE: RwLock<BTreeMap<u64, &'static str>> = RwLock::new(BTreeMap::new());
+
+/// An alias validated and hashed at compile time, see `key!`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HashedKey {
+    alias: &'static str,
+    hash: u64,
+}
+
+impl HashedKey {
+    /// Hashing the alias, panicking if it is empty or has leading or trailing whitespace.
+    /// Evaluated as a constant, as `key!` does, the panic fails compilation.
+    pub const fn new(alias: &'static str) -> Self {
+        let bytes = alias.as_bytes();
+        if bytes.is_empty() {
+            panic!("The alias of a key can not be empty");
+        }
+        if bytes[0].is_ascii_whitespace() || bytes[bytes.len() - 1].is_ascii_whitespace() {
+            panic!("The alias of a key can not start or end with whitespace");
+        }
+        Self {
+            alias,
+            hash: fnv1a(FNV_OFFSET, bytes),
+        }
+    }
+
+    /// The alias of the key.
+    pub fn alias(&self) -> &'static str {
+        self.alias
+    }
+
+    /// The hash of the alias, computed when the key was created.
+    pub fn digest(&self) -> u64 {
+        self.hash
+    }
+
+    /// Adding the key to the table of the keys created with `key!`, as `key!` does once.
+    /// Panics if another alias with the same hash was added.
+    #[doc(hidden)]
+    pub fn register(self) {
+        let mut table = KEY_TABLE.write().unwrap_or_else(PoisonError::into_inner);
+        let alias = *table.entry(self.hash).or_insert(self.alias);
+        if alias != self.alias {
+            panic!(
+                "The keys `{}` and `{}` have the same hash, rename one of them",
+                alias, self.alias
+            );
+        }
+    }
+
+    /// The keys created with `key!` so far, in the order of their hash.
+    pub fn registered() -> Vec<HashedKey> {
+        KEY_TABLE
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .map(|(&hash, &alias)| HashedKey { alias, hash })
+            .collect()
+    }
+}
+
+/// Creating a `HashedKey` for a string literal at compile time, failing compilation if the alias
+/// is invalid, and adding it to the table of the keys of the process the first time it is used.
+#[macro_export]
+macro_rules! key {
+    ($alias:literal) => {{
+        const KEY: $crate::HashedKey = $crate::HashedKey::new($alias);
+        static REGISTERED: ::std::sync::Once = ::std::sync::Once::new();
+        REGISTERED.call_once(|| KEY.register());
+        KEY
+    }};
+}
+
+/// Using the precomputed hashes of the keys as they are, without hashing them again.
+#[derive(Default)]
+pub(crate) struct KeyHasher(u64);
+
+impl Hasher for KeyHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        self.0 = fnv1a(self.0 ^ FNV_OFFSET, bytes);
+    }
+
+    fn write_u64(&mut self, hash: u64) {
+        self.0 = hash;
+    }
+}
+
+/// The ids of the keys used, by the hash of their alias.
+pub(crate) type HashedKeys = HashMap<u64, KeyedId, BuildHasherDefault<KeyHasher>>;
+
+/// The id a key resolved to, valid while the generation of the registry is unchanged.
+pub(crate) struct KeyedId {
+    alias: &'static str,
+    id: Uuid,
+    generation: u64,
+}
+
+impl Registry {
+    /// The id the key resolved to, if it was resolved in the current generation.
+    fn keyed_id(&self, key: HashedKey) -> Option<Uuid> {
+        let keyed = self.hashed_keys.get(&key.hash)?;
+        (keyed.generation == self.generation.load(Ordering::Acquire) && keyed.alias == key.alias)
+            .then_some(keyed.id)
+    }
+}
+
+impl SingletonManager {
+    /// Resolving the id of the service served under the key, through the table of the keys used.
+    fn key_id(&self, key: HashedKey, location: &'static Location<'static>) -> Result<Uuid> {
+        let seen = {
+            let registry = self.registry()?;
+            if let Some(id) = registry.keyed_id(key) {
+                #[cfg(feature = "chaos")]
+                registry.chaos.check_get(key.alias)?;
+                return Ok(id);
+            }
+            // Reading the generation first, a change while resolving resolves again on next use.
+            registry.generation.load(Ordering::Acquire)
+        };
+        let id = self.serving_id(key.alias, location)?;
+        let mut registry = self.registry_mut()?;
+        // Forwarding aliases are not added, so every call site using them is warned about.
+        if !registry.forwarding.contains_key(key.alias) {
+            registry.hashed_keys.insert(
+                key.hash,
+                KeyedId {
+                    alias: key.alias,
+                    id,
+                    generation: seen,
+                },
+            );
+        }
+        Ok(id)
+    }
+
+    /// The keys created with `key!` that no service is registered under, in the order of their
+    /// hash, to catch keys with a typo before they are looked up.
+    pub fn unresolved_keys(&self) -> Result<Vec<HashedKey>> {
+        let registry = self.registry()?;
+        Ok(HashedKey::registered()
+            .into_iter()
+            .filter(|key| registry.resolve(key.alias).is_none())
+            .collect())
+    }
+
+    /// Getting a singleton by a hashed key, as `get` does by alias.
+    #[track_caller]
+    pub fn get_by_key<T: 'static>(
+        &mut self,
+        key: HashedKey,
+    ) -> std::result::Result<&mut T, GetError> {
+        let location = Location::caller();
+        self.end_raw_references();
+        self.key_id(key, location)
+            .and_then(|id| self.unchecked_get_id::<T>(&id, key.alias, location))
+            .map(|service| unsafe { &mut *service })
+            .map_err(|e| GetError::from_error(e, key.alias))
+    }
+
+    /// Borrowing a singleton by a hashed key, as `borrow` does by alias.
+    #[track_caller]
+    pub fn borrow_by_key<T: 'static>(
+        &self,
+        key: HashedKey,
+    ) -> std::result::Result<ServiceRef<'_, T>, GetError> {
+        let location = Location::caller();
+        self.key_id(key, location)
+            .and_then(|id| {
+                self.shared_borrow_id(&id, key.alias, location, |state| {
+                    state.try_borrow(key.alias, location)
+                })
+            })
+            .map_err(|e| GetError::from_error(e, key.alias))
+    }
+
+    /// Mutably borrowing a singleton by a hashed key, as `borrow_mut` does by alias.
+    #[track_caller]
+    pub fn borrow_mut_by_key<T: 'static>(
+        &self,
+        key: HashedKey,
+    ) -> std::result::Result<ServiceRefMut<'_, T>, GetError> {
+        let location = Location::caller();
+        self.key_id(key, location)
+            .and_then(|id| {
+                self.exclusive_borrow_id(&id, key.alias, location, |state| {
+                    state.try_borrow_mut(key.alias, location)
+                })
+            })
+            .map_err(|e| GetError::from_error(e, key.alias))
+    }
+
+    /// Getting a shared reference to a singleton by a hashed key, as `get_ref` does by alias.
+    #[track_caller]
+    pub fn get_ref_by_key<T: 'static>(
+        &self,
+        key: HashedKey,
+    ) -> std::result::Result<ServiceRef<'_, T>, GetError> {
+        let location = Location::caller();
+        #[cfg(feature = "tokio")]
+        self.lint_sync_guard(key.alias, location);
+        self.key_id(key, location)
+            .and_then(|id| {
+                self.shared_borrow_id(&id, key.alias, location, |state| {
+                    state.wait_borrow(location);
+                    Ok(())
+                })
+            })
+            .map_err(|e| GetError::from_error(e, key.alias))
+    }
+
+    /// Getting an exclusive reference to a singleton by a hashed key, as `get_mut` does by alias.
+    #[track_caller]
+    pub fn get_mut_by_key<T: 'static>(
+        &self,
+        key: HashedKey,
+    ) -> std::result::Result<ServiceRefMut<'_, T>, GetError> {
+        let location = Location::caller();
+        #[cfg(feature = "tokio")]
+        self.lint_sync_guard(key.alias, location);
+        self.key_id(key, location)
+            .and_then(|id| {
+                self.exclusive_borrow_id(&id, key.alias, location, |state| {
+                    state.wait_borrow_mut(location);
+                    Ok(())
+                })
+            })
+            .map_err(|e| GetError::from_error(e, key.alias))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{fnv1a, FNV_OFFSET};
+    use crate::SingletonManager;
+
+    #[test]
+    fn test_hashed_keys_resolve_again_after_changes() {
+        assert_eq!(0xaf63_dc4c_8601_ec8c, fnv1a(FNV_OFFSET, b"a"));
+        assert_eq!(fnv1a(FNV_OFFSET, b"cache"), key!("cache").digest());
+
+        let mut manager = SingletonManager::new();
+        manager.set_factory("cache", || Box::new(1_u32)).unwrap();
+        *manager.get_mut_by_key::<u32>(key!("cache")).unwrap() += 1;
+        assert_eq!(2, *manager.get_ref_by_key::<u32>(key!("cache")).unwrap());
+        assert_eq!(1, manager.registry().unwrap().hashed_keys.len());
+
+        manager.rename("cache", "cache_v2").unwrap();
+        assert!(manager
+            .get_ref_by_key::<u32>(key!("cache"))
+            .unwrap_err()
+            .is_not_found());
+        manager.set_factory("cache", || Box::new(3_u32)).unwrap();
+        assert_eq!(3, *manager.get_ref_by_key::<u32>(key!("cache")).unwrap());
+        assert_eq!(2, *manager.get_ref_by_key::<u32>(key!("cache_v2")).unwrap());
+
+        *manager.get_by_key::<u32>(key!("cache")).unwrap() += 1;
+        let borrowed = manager.borrow_mut_by_key::<u32>(key!("cache")).unwrap();
+        assert!(manager
+            .borrow_by_key::<u32>(key!("cache"))
+            .unwrap_err()
+            .is_already_borrowed());
+        drop(borrowed);
+        assert_eq!(4, *manager.borrow_by_key::<u32>(key!("cache")).unwrap());
+    }
+
+    #[test]
+    #[should_panic(expected = "have the same hash")]
+    fn test_keys_with_the_same_hash_panic() {
+        key!("cache").register();
+        let mut colliding = key!("cache");
+        colliding.alias = "other";
+        colliding.register();
+    }
+}
@@ -0,0 +1,242 @@
+//! # History
+//! A bounded audit log of the operations done on the singleton manager.
+//!
+//! The last operations are kept in a ring buffer, so a postmortem can reconstruct the sequence of
+//! registrations and instantiations that led to a certain state.
+//! ```
+//! use singleton_manager::{Operation, SingletonManager};
+//!
+//! let mut manager = SingletonManager::new();
+//! manager.set("db", 1).unwrap();
+//!
+//! let history = manager.history();
+//! assert_eq!(Operation::Set, history[0].operation);
+//! assert_eq!("db", history[0].alias);
+//! ```
+use crate::{Registry, Result, SingletonManager};
+use std::collections::VecDeque;
+use std::fmt::{Display, Formatter};
+use std::panic::Location;
+use std::thread;
+use std::time::SystemTime;
+
+/// The number of operations kept in the history, unless configured otherwise.
+pub const DEFAULT_HISTORY_CAPACITY: usize = 100;
+
+/// The kind of operation recorded in the history.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Operation {
+    /// A service was set directly.
+    Set,
+    /// A factory was set for the alias.
+    SetFactory,
+    /// A service was created from its factory.
+    Instantiate,
+    /// A service was taken out of the singleton manager.
+    Take,
+    /// A registration was renamed to the contained alias.
+    Rename(String),
+    /// An alias was reserved for a service provided later.
+    Reserve,
+    /// The contained fallback was served in place of the alias, at the contained level of the
+    /// fallback chain.
+    Fallback(String, usize),
+    /// A registration was removed together with everything stored for it.
+    Remove,
+    /// The instance of a service was dropped, keeping the registration.
+    Shutdown,
+    /// The instance of a service was replaced by a new instance from its factory.
+    Rebuild,
+}
+
+impl Display for Operation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Set => write!(f, "set"),
+            Self::SetFactory => write!(f, "set factory"),
+            Self::Instantiate => write!(f, "instantiate"),
+            Self::Take => write!(f, "take"),
+            Self::Rename(ref new) => write!(f, "rename to `{}`", new),
+            Self::Reserve => write!(f, "reserve"),
+            Self::Remove => write!(f, "remove"),
+            Self::Shutdown => write!(f, "shut down"),
+            Self::Rebuild => write!(f, "rebuild"),
+            Self::Fallback(ref fallback, level) => {
+                write!(f, "serve fallback `{}` (level {})", fallback, level)
+            }
+        }
+    }
+}
+
+/// A single operation recorded in the history.
+#[derive(Debug, Clone)]
+pub struct HistoryEntry {
+    pub operation: Operation,
+    /// The alias the operation was done on.
+    pub alias: String,
+    /// When the operation was done.
+    pub at: SystemTime,
+    /// The location of the code doing the operation, when available.
+    pub location: Option<&'static Location<'static>>,
+    /// The name of the thread doing the operation, if the thread is named.
+    pub thread: Option<String>,
+}
+
+impl Display for HistoryEntry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.operation {
+            Operation::Rename(ref new) => write!(f, "rename `{}` to `{}`", self.alias, new)?,
+            Operation::Fallback(ref fallback, level) => write!(
+                f,
+                "serve fallback `{}` (level {}) for `{}`",
+                fallback, level, self.alias
+            )?,
+            ref operation => write!(f, "{} `{}`", operation, self.alias)?,
+        }
+        if let Some(location) = self.location {
+            write!(f, " at {}", location)?;
+        }
+        if let Some(ref thread) = self.thread {
+            write!(f, " on thread `{}`", thread)?;
+        }
+        Ok(())
+    }
+}
+
+/// The ring buffer of the history.
+#[derive(Debug)]
+pub(crate) struct History {
+    entries: VecDeque<HistoryEntry>,
+    capacity: usize,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self {
+            entries: VecDeque::new(),
+            capacity: DEFAULT_HISTORY_CAPACITY,
+        }
+    }
+}
+
+impl History {
+    /// An empty history with the same capacity.
+    pub(crate) fn emptied(&self) -> Self {
+        Self {
+            entries: VecDeque::new(),
+            capacity: self.capacity,
+        }
+    }
+
+    /// Setting the entries aside, so the entries recorded from now on can be discarded, e.g. the
+    /// entries of a transaction that is rolled back.
+    pub(crate) fn set_aside(&mut self) -> VecDeque<HistoryEntry> {
+        std::mem::take(&mut self.entries)
+    }
+
+    /// Putting the entries set aside back, followed by the entries recorded since if they are
+    /// kept.
+    pub(crate) fn put_back(&mut self, mut entries: VecDeque<HistoryEntry>, keep_recorded: bool) {
+        if keep_recorded {
+            entries.append(&mut self.entries);
+        }
+        self.entries = entries;
+        self.truncate();
+    }
+
+    fn truncate(&mut self) {
+        while self.entries.len() > self.capacity {
+            self.entries.pop_front();
+        }
+    }
+}
+
+impl Registry {
+    pub(crate) fn record(
+        &mut self,
+        operation: Operation,
+        alias: &str,
+        location: Option<&'static Location<'static>>,
+    ) {
+        if self.history.capacity == 0 || !self.config.tracing {
+            return;
+        }
+        self.history.entries.push_back(HistoryEntry {
+            operation,
+            alias: alias.to_string(),
+            at: self.clock.now(),
+            location,
+            thread: thread::current().name().map(str::to_string),
+        });
+        self.history.truncate();
+    }
+}
+
+impl SingletonManager {
+    /// Getting the recorded operations, oldest first.
+    pub fn history(&self) -> Vec<HistoryEntry> {
+        self.registry()
+            .map(|registry| registry.history.entries.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Setting how many operations are kept in the history, `0` disables the history.
+    /// Defaults to `DEFAULT_HISTORY_CAPACITY`.
+    pub fn set_history_capacity(&self, capacity: usize) -> Result<()> {
+        let mut registry = self.registry_mut()?;
+        registry.history.capacity = capacity;
+        registry.history.truncate();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_history_records_operations_with_location() {
+        let mut manager = SingletonManager::new();
+        manager.set("db", 1_u32).unwrap();
+        let set_line = line!() - 1;
+        manager.set_factory("log", || Box::new(2_u32)).unwrap();
+        manager.get::<u32>("log").unwrap();
+
+        let history = manager.history();
+        assert_eq!(
+            vec![
+                Operation::Set,
+                Operation::SetFactory,
+                Operation::Instantiate
+            ],
+            history
+                .iter()
+                .map(|e| e.operation.clone())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(set_line, history[0].location.unwrap().line());
+        assert_eq!(file!(), history[0].location.unwrap().file());
+        assert_eq!("log", history[2].alias);
+        assert!(history[2].location.is_none());
+    }
+
+    #[test]
+    fn test_history_is_bounded() {
+        let mut manager = SingletonManager::new();
+        manager.set_history_capacity(2).unwrap();
+        for alias in ["a", "b", "c"] {
+            manager.set(alias, 1_u32).unwrap();
+        }
+
+        let aliases = manager
+            .history()
+            .into_iter()
+            .map(|e| e.alias)
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["b".to_string(), "c".to_string()], aliases);
+
+        manager.set_history_capacity(0).unwrap();
+        manager.set("d", 1_u32).unwrap();
+        assert!(manager.history().is_empty());
+    }
+}
@@ -0,0 +1,170 @@
+//! # Hold times
+//! Histograms of how long the guards of the services are held.
+//!
+//! Every guard handed out by `borrow`, `borrow_mut`, `get_ref` and `get_mut` records how long it
+//! was held once dropped, in a histogram per service. The percentiles of the histograms point
+//! out the services whose guards are held for long, e.g. across a slow call inside a request
+//! handler, blocking the other threads waiting for the service and causing tail latency.
+//!
+//! The histograms have a bucket per power of two microseconds, so the percentiles are the upper
+//! bounds of their buckets, capped by the longest hold.
+//! ```
+//! use singleton_manager::SingletonManager;
+//!
+//! let mut manager = SingletonManager::new();
+//! manager.set_factory("config", || Box::new(1_u32)).unwrap();
+//! manager.set_factory("pool", || Box::new(Vec::<u32>::new())).unwrap();
+//! for _ in 0..10 {
+//!     let _config = manager.get_ref::<u32>("config").unwrap();
+//! }
+//! let pool = manager.get_mut::<Vec<u32>>("pool").unwrap();
+//! std::thread::sleep(std::time::Duration::from_millis(5));
+//! drop(pool);
+//!
+//! assert_eq!(10, manager.hold_times("config").unwrap().count);
+//! let report = manager.hold_time_report();
+//! assert_eq!("pool", report[0].0);
+//! assert!(report[0].1.p99 >= std::time::Duration::from_millis(5));
+//! ```
+use crate::{Error, Result, SingletonManager};
+use std::convert::TryFrom;
+use std::time::Duration;
+
+/// The number of buckets, the last one holding the holds of over 2^30 microseconds.
+const BUCKETS: usize = 32;
+
+/// The histogram of the hold times of the guards of a service.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct HoldHistogram {
+    /// The number of holds of under 2^i microseconds, and of at least half that, by bucket i.
+    buckets: [u64; BUCKETS],
+    count: u64,
+    total: Duration,
+    max: Duration,
+}
+
+impl HoldHistogram {
+    pub(crate) fn record(&mut self, held: Duration) {
+        let micros = u64::try_from(held.as_micros()).unwrap_or(u64::MAX);
+        let bucket = (u64::BITS - micros.leading_zeros()) as usize;
+        self.buckets[bucket.min(BUCKETS - 1)] += 1;
+        self.count += 1;
+        self.total = self.total.saturating_add(held);
+        self.max = self.max.max(held);
+    }
+
+    /// The hold time the fraction of the holds is at most as long as.
+    fn percentile(&self, fraction: f64) -> Duration {
+        let rank = ((self.count as f64 * fraction).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (bucket, count) in self.buckets.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                let bound = Duration::from_micros(1 << bucket);
+                return bound.min(self.max);
+            }
+        }
+        self.max
+    }
+
+    fn hold_times(&self) -> HoldTimes {
+        HoldTimes {
+            count: self.count,
+            total: self.total,
+            max: self.max,
+            p50: self.percentile(0.5),
+            p90: self.percentile(0.9),
+            p99: self.percentile(0.99),
+        }
+    }
+}
+
+/// The hold times of the guards of a service, see `SingletonManager::hold_times`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HoldTimes {
+    /// The number of guards dropped.
+    pub count: u64,
+    /// The time all guards were held together.
+    pub total: Duration,
+    /// The longest time a guard was held.
+    pub max: Duration,
+    pub p50: Duration,
+    pub p90: Duration,
+    pub p99: Duration,
+}
+
+impl HoldTimes {
+    /// The average time a guard was held.
+    pub fn mean(&self) -> Duration {
+        match u32::try_from(self.count) {
+            Ok(0) => Duration::ZERO,
+            Ok(count) => self.total / count,
+            Err(_) => Duration::from_secs_f64(self.total.as_secs_f64() / self.count as f64),
+        }
+    }
+}
+
+impl SingletonManager {
+    /// The hold times of the guards of the service.
+    pub fn hold_times(&self, service_name: &str) -> Result<HoldTimes> {
+        let registry = self.registry()?;
+        registry
+            .resolve(service_name)
+            .and_then(|id| registry.borrows.get(&id))
+            .map(|state| state.hold_histogram().hold_times())
+            .ok_or_else(|| Error::ServiceDoesNotExist(service_name.into(), Default::default()))
+    }
+
+    /// The hold times of the services whose guards were held, longest 99th percentile first.
+    pub fn hold_time_report(&self) -> Vec<(String, HoldTimes)> {
+        let mut report = self
+            .registry()
+            .map(|registry| {
+                registry
+                    .alias
+                    .iter()
+                    .filter_map(|(alias, id)| {
+                        let hold_times = registry.borrows.get(id)?.hold_histogram().hold_times();
+                        (hold_times.count > 0).then(|| (alias.to_string(), hold_times))
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        report.sort_by(|(a, a_times), (b, b_times)| b_times.p99.cmp(&a_times.p99).then(a.cmp(b)));
+        report
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::HoldHistogram;
+    use crate::SingletonManager;
+    use std::time::Duration;
+
+    #[test]
+    fn test_hold_time_percentiles() {
+        let mut histogram = HoldHistogram::default();
+        for _ in 0..98 {
+            histogram.record(Duration::from_micros(3));
+        }
+        histogram.record(Duration::from_millis(2));
+        histogram.record(Duration::from_millis(40));
+        let hold_times = histogram.hold_times();
+        assert_eq!(100, hold_times.count);
+        assert_eq!(Duration::from_micros(4), hold_times.p50);
+        assert_eq!(Duration::from_micros(4), hold_times.p90);
+        assert_eq!(Duration::from_micros(2048), hold_times.p99);
+        assert_eq!(Duration::from_millis(40), hold_times.max);
+        assert_eq!(Duration::from_nanos(422_940), hold_times.mean());
+
+        let mut manager = SingletonManager::new();
+        manager.set_factory("config", || Box::new(1_u32)).unwrap();
+        assert_eq!(0, manager.hold_times("config").unwrap().count);
+        assert!(manager.hold_time_report().is_empty());
+        drop(manager.borrow::<u32>("config").unwrap());
+        drop(manager.borrow_mut::<u32>("config").unwrap());
+        assert!(manager.borrow_mut::<String>("config").is_err());
+        assert_eq!(2, manager.hold_times("config").unwrap().count);
+        assert!(manager.hold_times("missing").is_err());
+    }
+}
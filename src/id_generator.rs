@@ -0,0 +1,133 @@
+//! # Id Generators
+//! Every alias stored in the singleton manager is linked to its singleton storage through an id.
+//! By default these ids are random (`Uuid::new_v4`), but a manager can be constructed with any
+//! other `IdGenerator`, e.g. sequential ids for deterministic tests or name based ids for
+//! identifiers that are stable across runs.
+//!
+//! ```
+//! use singleton_manager::{SingletonManager, SequentialIdGenerator, Uuid};
+//!
+//! let mut manager = SingletonManager::with_id_generator(SequentialIdGenerator::default());
+//! manager.set("my_service", "hello".to_string()).unwrap();
+//!
+//! assert_eq!(Uuid::from_u128(1), manager.service_id("my_service").unwrap());
+//! ```
+use std::sync::atomic::{AtomicU64, Ordering};
+use uuid::Uuid;
+
+/// Generator of the ids used to link an alias to the singleton storage.
+///
+/// Any `Fn(&str) -> Uuid` closure can be used as a generator.
+pub trait IdGenerator: Send + Sync {
+    /// Generate the id for the given alias.
+    fn generate(&self, alias: &str) -> Uuid;
+}
+
+impl<F> IdGenerator for F
+where
+    F: Fn(&str) -> Uuid + Send + Sync,
+{
+    fn generate(&self, alias: &str) -> Uuid {
+        self(alias)
+    }
+}
+
+/// The default generator, creating a random (v4) id for every alias.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RandomIdGenerator;
+
+impl IdGenerator for RandomIdGenerator {
+    fn generate(&self, _alias: &str) -> Uuid {
+        Uuid::new_v4()
+    }
+}
+
+/// Generator creating sequential ids starting from `1`.
+/// Mostly useful for tests that need deterministic ids.
+#[derive(Debug, Default)]
+pub struct SequentialIdGenerator {
+    counter: AtomicU64,
+}
+
+impl SequentialIdGenerator {
+    /// Create a generator where the first generated id is `start`.
+    pub fn starting_at(start: u64) -> Self {
+        Self {
+            counter: AtomicU64::new(start.saturating_sub(1)),
+        }
+    }
+}
+
+impl IdGenerator for SequentialIdGenerator {
+    fn generate(&self, _alias: &str) -> Uuid {
+        Uuid::from_u128(u128::from(self.counter.fetch_add(1, Ordering::SeqCst) + 1))
+    }
+}
+
+/// Generator creating name based (v5) ids from the alias.
+/// The same alias will always get the same id, which makes the ids usable as stable identifiers
+/// across runs, e.g. in persisted manifests.
+#[derive(Debug, Clone, Copy)]
+pub struct NameBasedIdGenerator {
+    namespace: Uuid,
+}
+
+impl NameBasedIdGenerator {
+    /// Create a generator hashing the aliases within the given namespace.
+    pub fn new(namespace: Uuid) -> Self {
+        Self { namespace }
+    }
+}
+
+impl Default for NameBasedIdGenerator {
+    fn default() -> Self {
+        Self::new(Uuid::NAMESPACE_OID)
+    }
+}
+
+impl IdGenerator for NameBasedIdGenerator {
+    fn generate(&self, alias: &str) -> Uuid {
+        Uuid::new_v5(&self.namespace, alias.as_bytes())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::SingletonManager;
+
+    #[test]
+    fn test_sequential_ids() {
+        let generator = SequentialIdGenerator::default();
+        assert_eq!(Uuid::from_u128(1), generator.generate("a"));
+        assert_eq!(Uuid::from_u128(2), generator.generate("b"));
+
+        let generator = SequentialIdGenerator::starting_at(10);
+        assert_eq!(Uuid::from_u128(10), generator.generate("a"));
+    }
+
+    #[test]
+    fn test_name_based_ids_are_stable() {
+        let first = NameBasedIdGenerator::default();
+        let second = NameBasedIdGenerator::default();
+        assert_eq!(first.generate("my_service"), second.generate("my_service"));
+        assert_ne!(
+            first.generate("my_service"),
+            first.generate("other_service")
+        );
+    }
+
+    #[test]
+    fn test_manager_uses_injected_generator() {
+        let mut manager = SingletonManager::with_id_generator(|alias: &str| match alias {
+            "db" => Uuid::from_u128(42),
+            _ => Uuid::nil(),
+        });
+        manager.set("db", 1_u32).unwrap();
+        manager.set_factory("log", || Box::new(2_u32)).unwrap();
+
+        assert_eq!(Uuid::from_u128(42), manager.service_id("db").unwrap());
+        assert_eq!(Uuid::nil(), manager.service_id("log").unwrap());
+        assert_eq!(2, *manager.get::<u32>("log").unwrap());
+    }
+}
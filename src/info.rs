@@ -0,0 +1,245 @@
+//! # Service information
+//! Introspection of the registrations in the singleton manager.
+//!
+//! Registrations can carry arbitrary key/value tags, which allows tooling to select services by
+//! attribute instead of by hardcoded lists of aliases.
+//! ```
+//! use singleton_manager::SingletonManager;
+//!
+//! let mut manager = SingletonManager::new();
+//! manager.set_with_meta("db", "postgres".to_string(), &[("tier", "critical")]).unwrap();
+//! manager.set_with_meta("cache", "redis".to_string(), &[("tier", "optional")]).unwrap();
+//!
+//! let critical = manager.find(|info| info.tag("tier") == Some("critical"));
+//! assert_eq!(vec!["db".to_string()], critical);
+//! ```
+use crate::{Error, InlineService, Phase, Registry, Result, SingletonManager, ThreadBound, Uuid};
+use std::collections::HashMap;
+
+/// A snapshot of the information about a single registration.
+#[derive(Debug, Clone)]
+pub struct ServiceInfo {
+    alias: String,
+    id: Uuid,
+    phase: Phase,
+    instantiated: bool,
+    tags: HashMap<String, String>,
+    deprecation: Option<String>,
+}
+
+impl ServiceInfo {
+    /// The alias the service is registered under.
+    pub fn alias(&self) -> &str {
+        &self.alias
+    }
+
+    /// The id linking the alias to the singleton storage.
+    pub fn id(&self) -> Uuid {
+        self.id
+    }
+
+    /// The startup phase of the service.
+    pub fn phase(&self) -> Phase {
+        self.phase
+    }
+
+    /// True if the service has been instantiated, false if only the factory exists.
+    pub fn is_instantiated(&self) -> bool {
+        self.instantiated
+    }
+
+    /// Getting the value of a tag.
+    pub fn tag(&self, key: &str) -> Option<&str> {
+        self.tags.get(key).map(String::as_str)
+    }
+
+    /// All tags of the service.
+    pub fn tags(&self) -> &HashMap<String, String> {
+        &self.tags
+    }
+
+    /// The note of the deprecation of the service, if it is deprecated.
+    pub fn deprecation(&self) -> Option<&str> {
+        self.deprecation.as_deref()
+    }
+}
+
+impl Registry {
+    pub(crate) fn info(&self, alias: &str, id: &Uuid) -> ServiceInfo {
+        ServiceInfo {
+            alias: alias.to_string(),
+            id: *id,
+            phase: self.phases.get(id).copied().unwrap_or_default(),
+            instantiated: self.has_instance(id),
+            tags: self.tags.get(id).cloned().unwrap_or_default(),
+            deprecation: self
+                .deprecations
+                .get(id)
+                .map(|deprecation| deprecation.note.clone()),
+        }
+    }
+}
+
+impl SingletonManager {
+    /// Setting a tag on a registration, replacing any previous value of the tag.
+    pub fn set_tag(&self, service_name: &str, key: &str, value: &str) -> Result<()> {
+        let id = self.service_id(service_name)?;
+        self.registry_mut()?
+            .tags
+            .entry(id)
+            .or_default()
+            .insert(key.to_string(), value.to_string());
+        Ok(())
+    }
+
+    /// Getting the information about a registration.
+    pub fn info(&self, service_name: &str) -> Result<ServiceInfo> {
+        let registry = self.registry()?;
+        registry
+            .resolve(service_name)
+            .and_then(|id| Some(registry.info(registry.alias_of(&id)?, &id)))
+            .ok_or_else(|| Error::ServiceDoesNotExist(service_name.into(), Default::default()))
+    }
+
+    /// Finding the aliases of all registrations matching the predicate, in alias order.
+    pub fn find<P>(&self, predicate: P) -> Vec<String>
+    where
+        P: Fn(&ServiceInfo) -> bool,
+    {
+        let mut aliases = self
+            .registry()
+            .map(|registry| {
+                registry
+                    .alias
+                    .iter()
+                    .map(|(alias, id)| registry.info(alias, id))
+                    .filter(|info| predicate(info))
+                    .map(|info| info.alias)
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        aliases.sort();
+        aliases
+    }
+
+    /// The number of registrations.
+    pub fn count(&self) -> usize {
+        self.registry()
+            .map(|registry| registry.alias.len())
+            .unwrap_or(0)
+    }
+
+    /// True if the service is registered and instantiated, false if only its factory exists.
+    pub fn is_instantiated(&self, service_name: &str) -> bool {
+        self.registry()
+            .map(|registry| {
+                registry
+                    .resolve(service_name)
+                    .is_some_and(|id| registry.has_instance(&id))
+            })
+            .unwrap_or(false)
+    }
+
+    /// True if the service is registered and instantiated as a `T`.
+    pub fn contains<T: 'static>(&self, service_name: &str) -> bool {
+        self.registry()
+            .map(|registry| {
+                registry.resolve(service_name).is_some_and(|id| {
+                    match (registry.singletons.get(&id), registry.inline.get(&id)) {
+                        (Some(service), _) => ThreadBound::inner(service.as_ref()).is::<T>(),
+                        (None, inline) => inline.is_some_and(InlineService::is::<T>),
+                    }
+                })
+            })
+            .unwrap_or(false)
+    }
+
+    /// True if any instantiated service is a `T`, regardless of its alias.
+    /// Services only registered by a factory are not known to be a `T` until instantiated.
+    pub fn has_type<T: 'static>(&self) -> bool {
+        self.registry()
+            .map(|registry| {
+                registry
+                    .singletons
+                    .values()
+                    .any(|service| ThreadBound::inner(service.as_ref()).is::<T>())
+            })
+            .unwrap_or(false)
+    }
+
+    /// The aliases of all instantiated services that are a `T`, in alias order.
+    /// Services only registered by a factory are not known to be a `T` until instantiated.
+    pub fn aliases_of_type<T: 'static>(&self) -> Vec<String> {
+        let mut aliases =
+            self.registry()
+                .map(|registry| {
+                    registry
+                        .alias
+                        .iter()
+                        .filter(|(_, id)| {
+                            registry.singletons.get(id).is_some_and(|service| {
+                                ThreadBound::inner(service.as_ref()).is::<T>()
+                            })
+                        })
+                        .map(|(alias, _)| alias.to_string())
+                        .collect::<Vec<_>>()
+                })
+                .unwrap_or_default();
+        aliases.sort();
+        aliases
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::SingletonManager;
+
+    #[test]
+    fn test_find_by_tag() {
+        let mut manager = SingletonManager::new();
+        manager
+            .set_with_meta("db", 1_u32, &[("tier", "critical"), ("owner", "platform")])
+            .unwrap();
+        manager.set("cache", 2_u32).unwrap();
+        manager.set_factory("queue", || Box::new(3_u32)).unwrap();
+        manager.set_tag("queue", "tier", "critical").unwrap();
+
+        assert_eq!(
+            vec!["db".to_string(), "queue".to_string()],
+            manager.find(|info| info.tag("tier") == Some("critical"))
+        );
+        assert_eq!(
+            vec!["queue".to_string()],
+            manager.find(|info| !info.is_instantiated())
+        );
+
+        let info = manager.info("db").unwrap();
+        assert_eq!("platform", info.tag("owner").unwrap());
+        assert_eq!(manager.service_id("db").unwrap(), info.id());
+        assert!(manager.info("unknown").is_err());
+    }
+
+    #[test]
+    fn test_queries_by_type() {
+        let mut manager = SingletonManager::new();
+        manager.set("primary", 1_u32).unwrap();
+        manager.set("replica", 2_u32).unwrap();
+        manager.set("name", "db".to_string()).unwrap();
+        manager.set_factory("port", || Box::new(8080_u16)).unwrap();
+
+        assert_eq!(4, manager.count());
+        assert!(manager.has_type::<u32>());
+        assert!(!manager.has_type::<u16>());
+        assert!(!manager.is_instantiated("port"));
+        assert!(manager.contains::<u32>("primary"));
+        assert!(!manager.contains::<String>("primary"));
+        assert_eq!(
+            vec!["primary".to_string(), "replica".to_string()],
+            manager.aliases_of_type::<u32>()
+        );
+
+        manager.get::<u16>("port").unwrap();
+        assert!(manager.is_instantiated("port"));
+        assert!(manager.has_type::<u16>());
+    }
+}
@@ -0,0 +1,265 @@
+//! # Inline services
+//! Storing small `Copy` services next to their registration, without boxing them.
+//!
+//! Every service is stored boxed, so getting it follows a pointer into its own allocation. For
+//! the extremely hot small services, e.g. feature flags and numeric configuration, services of
+//! at most `INLINE_CAPACITY` bytes can be stored inline instead, and are copied out of the
+//! registry by `get_copy`. `set_inline` stores a service inline, while `set_copy` selects the
+//! storage by the size of the service, boxing the services too large to be stored inline.
+//!
+//! References can not be handed out to inline services, as they move with the registry, so
+//! `get` and `get_ref` fail with `GetError::FailedToDowncastRefOfService` on them. Copying an
+//! inline service out takes the read lock of the registry once, and is gated by flags and the
+//! allowed callers as getting any other service is.
+//! ```
+//! use singleton_manager::SingletonManager;
+//!
+//! let manager = SingletonManager::new();
+//! manager.set_inline("max_connections", 128_u32).unwrap();
+//! manager.set_copy("retry_backoff", (1.5_f64, 3_u8)).unwrap();
+//! manager.set_copy("weights", [0.25_f64; 4]).unwrap();
+//!
+//! assert_eq!(128, manager.get_copy::<u32>("max_connections").unwrap());
+//! assert!(manager.is_inline("retry_backoff"));
+//! assert!(!manager.is_inline("weights"));
+//! assert_eq!([0.25; 4], manager.get_copy::<[f64; 4]>("weights").unwrap());
+//! ```
+use crate::{
+    CallKind, Error, GetError, Operation, Registry, Result, SetError, SingletonManager, Uuid,
+};
+use std::any::TypeId;
+use std::mem::{align_of, size_of, MaybeUninit};
+use std::panic::Location;
+
+/// The largest service stored inline, in bytes.
+pub const INLINE_CAPACITY: usize = 16;
+
+/// The bytes of an inline service, aligned for any service fitting in them.
+#[derive(Clone, Copy)]
+#[repr(C, align(16))]
+struct InlineBytes([MaybeUninit<u8>; INLINE_CAPACITY]);
+
+/// A service stored inline, see `SingletonManager::set_inline`.
+pub(crate) struct InlineService {
+    type_id: TypeId,
+    bytes: InlineBytes,
+}
+
+impl InlineService {
+    /// True if a `T` can be stored inline.
+    fn fits<T>() -> bool {
+        size_of::<T>() <= INLINE_CAPACITY && align_of::<T>() <= align_of::<InlineBytes>()
+    }
+
+    fn new<T: Copy + 'static>(service: T) -> Self {
+        assert!(Self::fits::<T>());
+        let mut bytes = InlineBytes([MaybeUninit::uninit(); INLINE_CAPACITY]);
+        // The bytes are large and aligned enough for a `T`, checked above.
+        unsafe { std::ptr::write(bytes.0.as_mut_ptr() as *mut T, service) };
+        Self {
+            type_id: TypeId::of::<T>(),
+            bytes,
+        }
+    }
+
+    /// Copying the service out, if it is a `T`.
+    fn get<T: Copy + 'static>(&self) -> Option<T> {
+        // The bytes hold a `T` written by `new`, checked by its type id.
+        (self.type_id == TypeId::of::<T>())
+            .then(|| unsafe { std::ptr::read(self.bytes.0.as_ptr() as *const T) })
+    }
+
+    pub(crate) fn is<T: 'static>(&self) -> bool {
+        self.type_id == TypeId::of::<T>()
+    }
+}
+
+impl Registry {
+    /// True if the service of the id is instantiated, boxed or stored inline.
+    pub(crate) fn has_instance(&self, id: &Uuid) -> bool {
+        self.singletons.contains_key(id) || self.inline.contains_key(id)
+    }
+
+    /// Storing the service inline under a new alias, after validating it.
+    /// Returns false if the alias is already registered, and the collision policy keeps the
+    /// registration already there.
+    fn store_inline<T: Copy + Send + Sync + 'static>(
+        &mut self,
+        alias: &str,
+        service: T,
+        location: &'static Location<'static>,
+    ) -> Result<bool> {
+        if self.resolve_collision(alias)? {
+            return Ok(false);
+        }
+        let id = self.store_alias(alias)?;
+        let type_name = std::any::type_name::<T>();
+        self.tags.insert(id, Default::default());
+        self.type_names.insert(id, type_name);
+        if let Err(e) = self.validate(alias, &id, &service) {
+            self.remove_alias(alias);
+            return Err(e);
+        }
+        self.record(Operation::Set, alias, Some(location));
+        if self.is_recording() {
+            self.record_call(CallKind::Set, alias, Some(type_name), location);
+        }
+        self.inline.insert(id, InlineService::new(service));
+        Ok(true)
+    }
+}
+
+impl SingletonManager {
+    /// Setting a service stored inline, see the module documentation.
+    /// Fails with `SetError::TooLargeToInline` if the service is larger than `INLINE_CAPACITY`
+    /// bytes, or aligned to more than 16 bytes.
+    #[track_caller]
+    pub fn set_inline<T: Copy + Send + Sync + 'static>(
+        &self,
+        service_name: &str,
+        service: T,
+    ) -> std::result::Result<(), SetError> {
+        if !InlineService::fits::<T>() {
+            return Err(SetError::TooLargeToInline(
+                service_name.into(),
+                size_of::<T>(),
+            ));
+        }
+        let location = Location::caller();
+        self.registry_mut()
+            .and_then(|mut registry| registry.store_inline(service_name, service, location))
+            .map(|_| ())
+            .map_err(|e| SetError::from_error(e, service_name))
+    }
+
+    /// Setting a service, stored inline if it is small enough, and boxed otherwise.
+    #[track_caller]
+    pub fn set_copy<T: Copy + Send + Sync + 'static>(
+        &self,
+        service_name: &str,
+        service: T,
+    ) -> std::result::Result<(), SetError> {
+        if InlineService::fits::<T>() {
+            return self.set_inline(service_name, service);
+        }
+        self.store(service_name, service, &[], Location::caller())
+            .map(|_| ())
+            .map_err(|e| SetError::from_error(e, service_name))
+    }
+
+    /// Getting a copy of a service, whether stored inline or boxed.
+    #[track_caller]
+    pub fn get_copy<T: Copy + 'static>(
+        &self,
+        service_name: &str,
+    ) -> std::result::Result<T, GetError> {
+        let location = Location::caller();
+        self.copy_of(service_name, location)
+            .map_err(|e| GetError::from_error(e, service_name))
+    }
+
+    /// Copying the service out, resolving it once for both storages.
+    fn copy_of<T: Copy + 'static>(
+        &self,
+        service_name: &str,
+        location: &'static Location<'static>,
+    ) -> Result<T> {
+        let (id, inline) = {
+            let registry = self.registry()?;
+            let id = registry.serving_id(service_name, location)?;
+            registry.check_caller(&id, service_name, location)?;
+            let inline = match registry.inline.get(&id) {
+                Some(inline) => Some(
+                    inline
+                        .get::<T>()
+                        .ok_or_else(|| Error::FailedToDowncastRefOfService(service_name.into()))?,
+                ),
+                None => None,
+            };
+            (id, inline)
+        };
+        match inline {
+            Some(service) => {
+                self.note_access::<T>(&id, location);
+                Ok(service)
+            }
+            None => self
+                .shared_borrow_id::<T>(&id, service_name, location, |state| {
+                    state.wait_borrow(location);
+                    Ok(())
+                })
+                .map(|service| *service),
+        }
+    }
+
+    /// True if the service is stored inline.
+    pub fn is_inline(&self, service_name: &str) -> bool {
+        self.registry()
+            .map(|registry| {
+                registry
+                    .resolve(service_name)
+                    .is_some_and(|id| registry.inline.contains_key(&id))
+            })
+            .unwrap_or(false)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{CollisionPolicy, GetError, SetError, SingletonManager};
+
+    #[test]
+    fn test_inline_services_are_copied_out() {
+        let manager = SingletonManager::new();
+        manager.set_inline("dark_mode", true).unwrap();
+        manager.set_copy("rate", (2.5_f64, 10_u32)).unwrap();
+        manager.set_copy("matrix", [1_u64; 3]).unwrap();
+        assert!(manager.is_inline("dark_mode"));
+        assert!(manager.is_inline("rate"));
+        assert!(!manager.is_inline("matrix"));
+        assert!(manager.is_instantiated("dark_mode"));
+        assert!(manager.contains::<bool>("dark_mode"));
+
+        assert!(manager.get_copy::<bool>("dark_mode").unwrap());
+        let dark_mode = manager.dump().services.remove(0);
+        assert_eq!(
+            ("dark_mode", "instantiated"),
+            (&*dark_mode.alias, dark_mode.state())
+        );
+        assert!(dark_mode.retrieved);
+        assert_eq!(3, manager.dump().metrics.instantiated);
+        assert_eq!((2.5, 10), manager.get_copy::<(f64, u32)>("rate").unwrap());
+        assert_eq!([1; 3], manager.get_copy::<[u64; 3]>("matrix").unwrap());
+        assert!(matches!(
+            manager.get_copy::<u8>("dark_mode"),
+            Err(GetError::FailedToDowncastRefOfService(_))
+        ));
+        assert!(matches!(
+            manager.get_ref::<bool>("dark_mode"),
+            Err(GetError::FailedToDowncastRefOfService(_))
+        ));
+        assert!(matches!(
+            manager.set_inline("matrix_inline", [1_u64; 3]),
+            Err(SetError::TooLargeToInline(alias, 24)) if alias == "matrix_inline"
+        ));
+        assert!(matches!(
+            manager.set_inline("dark_mode", false),
+            Err(SetError::ServiceAlreadyExists(_))
+        ));
+
+        manager
+            .restrict_callers("dark_mode", &["src/flags.rs"])
+            .unwrap();
+        assert!(manager
+            .get_copy::<bool>("dark_mode")
+            .unwrap_err()
+            .is_access_denied());
+
+        manager
+            .set_collision_policy(CollisionPolicy::LastWins)
+            .unwrap();
+        manager.set_copy("rate", [2_u64; 3]).unwrap();
+        assert!(!manager.is_inline("rate"));
+        assert_eq!([2; 3], manager.get_copy::<[u64; 3]>("rate").unwrap());
+    }
+}
@@ -0,0 +1,174 @@
+//! # Global instance
+//! Creating the global instance without panicking into the host process.
+//!
+//! `SingletonManager::instance` and `sm()` panic if creating the global instance fails. Embedders
+//! calling in from other languages can not let a panic unwind across the boundary, so
+//! `try_instance` returns the failure instead. What happens on failure is chosen globally by the
+//! `InitFailureStrategy`: propagating the panic, returning an `InitError`, or falling back to an
+//! empty singleton manager.
+//! ```
+//! use singleton_manager::{InitFailureStrategy, SingletonManager};
+//!
+//! SingletonManager::set_init_failure_strategy(InitFailureStrategy::FallbackEmpty);
+//! let manager = SingletonManager::try_instance().unwrap();
+//! manager.set_send_factory("instance_example", || 1_u32).unwrap();
+//! ```
+use crate::{Error, SingletonManager, INSTANCE, ONCE};
+use std::any::Any;
+use std::fmt::{Display, Formatter};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::ptr::addr_of_mut;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Once;
+
+static FAILURE_STRATEGY: AtomicU8 = AtomicU8::new(InitFailureStrategy::Error as u8);
+
+/// What happens when creating the global instance fails.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InitFailureStrategy {
+    /// Propagating the panic, as `instance` always did.
+    Panic = 0,
+    /// Catching the panic, `try_instance` returns it as an `InitError`. The default.
+    Error = 1,
+    /// Catching the panic, logging it and using an empty singleton manager as global instance.
+    FallbackEmpty = 2,
+}
+
+impl InitFailureStrategy {
+    fn current() -> Self {
+        match FAILURE_STRATEGY.load(Ordering::Acquire) {
+            0 => Self::Panic,
+            2 => Self::FallbackEmpty,
+            _ => Self::Error,
+        }
+    }
+}
+
+/// The failures of creating the global instance.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InitError {
+    /// Creating the global instance panicked, with the message of the panic.
+    Panicked(String),
+    /// The global instance was not created.
+    Uninitialized,
+}
+
+impl Display for InitError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Panicked(ref message) => {
+                write!(f, "Creating the global instance panicked: {}", message)
+            }
+            Self::Uninitialized => write!(f, "The global instance was not created"),
+        }
+    }
+}
+
+impl std::error::Error for InitError {}
+
+impl From<InitError> for Error {
+    fn from(e: InitError) -> Self {
+        Error::InstanceInitFailed(e)
+    }
+}
+
+/// The message of a panic, if it has one.
+fn panic_message(panic: &(dyn Any + Send)) -> String {
+    panic
+        .downcast_ref::<&str>()
+        .map(|message| message.to_string())
+        .or_else(|| panic.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic".to_string())
+}
+
+/// Creating the manager in the slot once, handling a failure as the strategy says.
+/// A creation that failed before is attempted again.
+///
+/// # Safety
+/// The slot must only be written through the once.
+unsafe fn initialize(
+    once: &Once,
+    slot: *mut Option<SingletonManager>,
+    strategy: InitFailureStrategy,
+    create: impl FnOnce() -> SingletonManager,
+) -> Result<&'static mut SingletonManager, InitError> {
+    let init = || once.call_once_force(|_| *slot = Some(create()));
+    if strategy == InitFailureStrategy::Panic {
+        init();
+    } else if let Err(panic) = catch_unwind(AssertUnwindSafe(init)) {
+        let e = InitError::Panicked(panic_message(panic.as_ref()));
+        if strategy == InitFailureStrategy::Error {
+            return Err(e);
+        }
+        log::error!("Falling back to an empty singleton manager: {}", e);
+        once.call_once_force(|_| *slot = Some(SingletonManager::new()));
+    }
+    (*slot).as_mut().ok_or(InitError::Uninitialized)
+}
+
+impl SingletonManager {
+    /// Getting the global instance, creating it on first use, see `instance`.
+    /// Fails with `InitError` if creating it fails, unless the `InitFailureStrategy` set
+    /// propagates the panic or falls back to an empty singleton manager.
+    pub fn try_instance() -> Result<&'static SingletonManager, InitError> {
+        Self::try_instance_mut().map(|manager| &*manager)
+    }
+
+    /// Getting the global instance as `try_instance` does, for `instance` and `sm`, which hand
+    /// it out mutably.
+    pub(crate) fn try_instance_mut() -> Result<&'static mut SingletonManager, InitError> {
+        unsafe {
+            initialize(
+                &ONCE,
+                addr_of_mut!(INSTANCE),
+                InitFailureStrategy::current(),
+                SingletonManager::new,
+            )
+        }
+    }
+
+    /// Setting what happens when creating the global instance fails, for all later attempts.
+    pub fn set_init_failure_strategy(strategy: InitFailureStrategy) {
+        FAILURE_STRATEGY.store(strategy as u8, Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{initialize, InitError, InitFailureStrategy};
+    use crate::{Error, SingletonManager};
+    use std::sync::Once;
+
+    #[test]
+    fn test_failed_initialization_follows_the_strategy() {
+        let once = Box::leak(Box::new(Once::new()));
+        let slot = Box::leak(Box::new(None));
+        let failing = || -> SingletonManager { panic!("No entropy") };
+
+        let e = unsafe { initialize(once, slot, InitFailureStrategy::Error, failing) };
+        assert_eq!(
+            Err(InitError::Panicked("No entropy".to_string())),
+            e.map(|_| ())
+        );
+        let manager =
+            unsafe { initialize(once, slot, InitFailureStrategy::FallbackEmpty, failing) }.unwrap();
+        manager.set("fallback", 1_u32).unwrap();
+
+        let manager = unsafe { initialize(once, slot, InitFailureStrategy::Panic, failing) };
+        assert!(manager.unwrap().has("fallback"));
+    }
+
+    #[test]
+    fn test_init_error_converts_to_its_own_error() {
+        let e = Error::from(InitError::Panicked("No entropy".to_string()));
+        assert_eq!(
+            Error::InstanceInitFailed(InitError::Panicked("No entropy".to_string())),
+            e
+        );
+        assert_eq!(None, e.alias());
+        assert_eq!(
+            "Creating the global instance panicked: No entropy",
+            e.to_string()
+        );
+    }
+}
@@ -0,0 +1,138 @@
+//! # Instrumentation
+//! Recording where a singleton is accessed from.
+//!
+//! Instrumenting a service counts every access to it by the location of the calling code, so
+//! the code paths still using a legacy singleton can be found before removing it.
+//! Accesses through `get`, `borrow`, `borrow_mut`, `get_ref`, `get_mut` and `get_cloned` are
+//! all recorded, as are the accesses through the `Instrumented` proxy.
+//! ```
+//! use singleton_manager::SingletonManager;
+//!
+//! let mut manager = SingletonManager::new();
+//! manager.set("legacy_db", "mysql".to_string()).unwrap();
+//!
+//! let legacy_db = manager.instrument::<String>("legacy_db").unwrap();
+//! for _ in 0..3 {
+//!     assert_eq!("mysql", *legacy_db.get().unwrap());
+//! }
+//!
+//! let call_sites = manager.call_sites("legacy_db");
+//! assert_eq!(1, call_sites.len());
+//! assert_eq!(3, call_sites[0].count);
+//! println!("{}", call_sites[0]);
+//! ```
+use crate::{GetError, Result, ServiceRef, ServiceRefMut, SingletonManager};
+use std::fmt::{Display, Formatter};
+use std::marker::PhantomData;
+use std::panic::Location;
+
+/// A location in the code accessing an instrumented service, and how often it did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CallSite {
+    pub location: &'static Location<'static>,
+    pub count: u64,
+}
+
+impl Display for CallSite {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({} calls)", self.location, self.count)
+    }
+}
+
+/// A lightweight proxy of an instrumented service, recording the location of the calling code
+/// on every access.
+pub struct Instrumented<'a, T> {
+    manager: &'a SingletonManager,
+    alias: String,
+    _service: PhantomData<fn() -> T>,
+}
+
+impl<'a, T: 'static> Instrumented<'a, T> {
+    /// The alias of the instrumented service.
+    pub fn alias(&self) -> &str {
+        &self.alias
+    }
+
+    /// Getting a shared reference to the service, as `SingletonManager::get_ref` does.
+    #[track_caller]
+    pub fn get(&self) -> std::result::Result<ServiceRef<'a, T>, GetError> {
+        self.manager.get_ref_by_alias::<T>(&self.alias)
+    }
+
+    /// Getting an exclusive reference to the service, as `SingletonManager::get_mut` does.
+    #[track_caller]
+    pub fn get_mut(&self) -> std::result::Result<ServiceRefMut<'a, T>, GetError> {
+        self.manager.get_mut_by_alias::<T>(&self.alias)
+    }
+}
+
+impl SingletonManager {
+    /// Starting to record the call sites accessing a service, and getting a proxy to it.
+    /// Instrumenting a service that is already instrumented keeps the recorded call sites.
+    pub fn instrument<T: 'static>(&self, service_name: &str) -> Result<Instrumented<'_, T>> {
+        let id = self.service_id(service_name)?;
+        self.registry_mut()?.call_sites.entry(id).or_default();
+        Ok(Instrumented {
+            manager: self,
+            alias: service_name.to_string(),
+            _service: PhantomData,
+        })
+    }
+
+    /// Getting the recorded call sites of an instrumented service, most frequent first.
+    pub fn call_sites(&self, service_name: &str) -> Vec<CallSite> {
+        let mut call_sites = self
+            .service_id(service_name)
+            .and_then(|id| {
+                Ok(self
+                    .registry()?
+                    .call_sites
+                    .get(&id)
+                    .map(|call_sites| {
+                        call_sites
+                            .iter()
+                            .map(|(location, count)| CallSite {
+                                location,
+                                count: *count,
+                            })
+                            .collect::<Vec<_>>()
+                    })
+                    .unwrap_or_default())
+            })
+            .unwrap_or_default();
+        call_sites.sort_by(|a, b| {
+            b.count
+                .cmp(&a.count)
+                .then_with(|| a.location.to_string().cmp(&b.location.to_string()))
+        });
+        call_sites
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::SingletonManager;
+
+    #[test]
+    fn test_call_sites_recorded_by_location() {
+        let mut manager = SingletonManager::new();
+        manager.set("legacy", 1_u32).unwrap();
+        manager.get::<u32>("legacy").unwrap();
+        assert!(manager.call_sites("legacy").is_empty());
+
+        manager.instrument::<u32>("legacy").unwrap();
+        for _ in 0..2 {
+            manager.get::<u32>("legacy").unwrap();
+        }
+        let get_line = line!() - 2;
+        *manager.get_mut::<u32>("legacy").unwrap() += 1;
+        let get_mut_line = line!() - 1;
+
+        let call_sites = manager.call_sites("legacy");
+        assert_eq!(2, call_sites.len());
+        assert_eq!(get_line, call_sites[0].location.line());
+        assert_eq!(2, call_sites[0].count);
+        assert_eq!(get_mut_line, call_sites[1].location.line());
+        assert_eq!(file!(), call_sites[1].location.file());
+    }
+}
@@ -0,0 +1,216 @@
+//! # Keys
+//! A fast path for getting frozen singletons.
+//!
+//! Getting a service by alias costs hashing the alias, hashing the id and downcasting the
+//! service, on top of locking the registry. For services on the hot path of every request this
+//! shows up in profiles. A frozen service can never be removed, replaced or mutably accessed
+//! again, which allows a typed `Key` to cache the reference to it, so getting it through the key
+//! only costs a single atomic load and a pointer cast.
+//! ```
+//! use singleton_manager::{Key, SingletonManager};
+//!
+//! static ROUTES: Key<Vec<String>> = Key::new("routes");
+//!
+//! let mut manager = SingletonManager::new();
+//! manager.set_factory("routes", || Box::new(vec!["/".to_string()])).unwrap();
+//! manager.freeze("routes").unwrap();
+//!
+//! assert_eq!(vec!["/".to_string()], *manager.get_key(&ROUTES).unwrap());
+//! ```
+use crate::{Error, Registry, Result, SingletonManager, Uuid};
+use std::marker::PhantomData;
+use std::panic::Location;
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, Ordering};
+
+/// The reference cached in a key, together with the manager it belongs to.
+struct Cached {
+    manager: u64,
+    service: *const (),
+}
+
+/// A typed key of a frozen singleton, caching the reference to it.
+/// The reference is cached for the first manager the key is used with, using the key with other
+/// managers works but always takes the slow path.
+pub struct Key<T> {
+    alias: &'static str,
+    cache: AtomicPtr<Cached>,
+    _service: PhantomData<fn() -> T>,
+}
+
+impl<T> Key<T> {
+    /// Creating a key for the alias.
+    pub const fn new(alias: &'static str) -> Self {
+        Self {
+            alias,
+            cache: AtomicPtr::new(ptr::null_mut()),
+            _service: PhantomData,
+        }
+    }
+
+    /// The alias of the key.
+    pub fn alias(&self) -> &'static str {
+        self.alias
+    }
+}
+
+impl<T> Drop for Key<T> {
+    fn drop(&mut self) {
+        let cached = *self.cache.get_mut();
+        if !cached.is_null() {
+            drop(unsafe { Box::from_raw(cached) });
+        }
+    }
+}
+
+impl Registry {
+    /// Failing with `Error::ServiceFrozen` if the service is frozen.
+    pub(crate) fn check_unfrozen(&self, id: &Uuid, alias: &str) -> Result<()> {
+        if self.frozen.contains(id) {
+            Err(Error::ServiceFrozen(alias.into()))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl SingletonManager {
+    /// Freezing a singleton, instantiating it if needed.
+    /// A frozen singleton can no longer be taken out of the manager or mutably accessed, where
+    /// `get`, `borrow_mut` and `get_mut` fail with `GetError::ServiceFrozen`, and `take` with
+    /// `Error::ServiceFrozen`.
+    /// This allows getting it through a `Key`.
+    #[track_caller]
+    pub fn freeze(&self, service_name: &str) -> Result<()> {
+        let id = self.serving_id(service_name, Location::caller())?;
+        self.singleton_get(&id, service_name)?;
+        self.borrow_state(&id, service_name)?
+            .check_unborrowed(service_name)?;
+        self.registry_mut()?.frozen.insert(id);
+        Ok(())
+    }
+
+    /// True if the singleton is frozen.
+    pub fn is_frozen(&self, service_name: &str) -> bool {
+        self.registry()
+            .ok()
+            .and_then(|registry| {
+                let id = registry.resolve(service_name)?;
+                Some(registry.frozen.contains(&id))
+            })
+            .unwrap_or(false)
+    }
+
+    /// Getting a frozen singleton through its key.
+    /// Once cached in the key this is a single atomic load and a pointer cast, the cache is not
+    /// affected by changes to feature flags, default versions or renames afterwards.
+    /// Fails with `Error::ServiceNotFrozen` if the singleton is not frozen.
+    #[inline]
+    #[track_caller]
+    pub fn get_key<T: 'static>(&self, key: &Key<T>) -> Result<&T> {
+        match self.cached_key(key) {
+            Some(service) => Ok(service),
+            None => self.get_key_slow(key, Location::caller()),
+        }
+    }
+
+    /// The reference cached in the key, if it was cached for this manager.
+    #[inline]
+    pub(crate) fn cached_key<T: 'static>(&self, key: &Key<T>) -> Option<&T> {
+        let cached = key.cache.load(Ordering::Acquire);
+        if cached.is_null() {
+            return None;
+        }
+        // The cache is never replaced while the key is alive.
+        let cached = unsafe { &*cached };
+        // Frozen services are kept in the storage for as long as the manager lives.
+        (cached.manager == self.instance_id).then(|| unsafe { &*(cached.service as *const T) })
+    }
+
+    #[cold]
+    pub(crate) fn get_key_slow<T: 'static>(
+        &self,
+        key: &Key<T>,
+        location: &'static Location<'static>,
+    ) -> Result<&T> {
+        let id = self.serving_id(key.alias, location)?;
+        let service = {
+            let registry = self.registry()?;
+            if !registry.frozen.contains(&id) {
+                return Err(Error::ServiceNotFrozen(key.alias.into()));
+            }
+            registry
+                .singletons
+                .get(&id)
+                .and_then(|service| service.downcast_ref::<T>())
+                .ok_or_else(|| Error::FailedToDowncastRefOfService(key.alias.into()))?
+                as *const T
+        };
+        let cached = Box::into_raw(Box::new(Cached {
+            manager: self.instance_id,
+            service: service as *const (),
+        }));
+        if key
+            .cache
+            .compare_exchange(ptr::null_mut(), cached, Ordering::AcqRel, Ordering::Acquire)
+            .is_err()
+        {
+            drop(unsafe { Box::from_raw(cached) });
+        }
+        Ok(unsafe { &*service })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Error, GetError, Key, SingletonManager};
+
+    #[test]
+    fn test_key_requires_frozen_service() {
+        let mut manager = SingletonManager::new();
+        let key = Key::<u32>::new("port");
+        manager.set("port", 8080_u32).unwrap();
+
+        assert!(matches!(
+            manager.get_key(&key),
+            Err(Error::ServiceNotFrozen(_))
+        ));
+        manager.freeze("port").unwrap();
+        assert!(manager.is_frozen("port"));
+        assert_eq!(8080, *manager.get_key(&key).unwrap());
+        assert_eq!(8080, *manager.get_key(&key).unwrap());
+
+        assert!(matches!(
+            manager.get::<u32>("port"),
+            Err(GetError::ServiceFrozen(_))
+        ));
+        assert!(matches!(
+            manager.get_mut::<u32>("port"),
+            Err(GetError::ServiceFrozen(_))
+        ));
+        assert!(matches!(
+            manager.take::<u32>("port"),
+            Err(GetError::ServiceFrozen(_))
+        ));
+        assert_eq!(8080, *manager.get_ref::<u32>("port").unwrap());
+    }
+
+    #[test]
+    fn test_key_used_with_multiple_managers() {
+        let key = Key::<u32>::new("port");
+        let mut first = SingletonManager::new();
+        let mut second = SingletonManager::new();
+        first.set_factory("port", || Box::new(1_u32)).unwrap();
+        second.set_factory("port", || Box::new(2_u32)).unwrap();
+        first.freeze("port").unwrap();
+        second.freeze("port").unwrap();
+
+        assert_eq!(1, *first.get_key(&key).unwrap());
+        assert_eq!(2, *second.get_key(&key).unwrap());
+        assert_eq!(1, *first.get_key(&key).unwrap());
+        assert!(matches!(
+            first.get_key(&Key::<String>::new("port")),
+            Err(Error::FailedToDowncastRefOfService(_))
+        ));
+    }
+}
@@ -0,0 +1,351 @@
+//! # Keyed scopes
+//! Services held per key of a scope, e.g. per web session.
+//!
+//! A keyed scope holds one set of service instances per key, e.g. the rate limiter and the cart
+//! of every session, behind the same registry as the singletons. The services of a key are
+//! reached through the `KeyedScope` view returned by `SingletonManager::keyed_scope`, and are
+//! dropped when the key is invalidated, e.g. on logout, or when the key was not used for longer
+//! than the time to live of the scope.
+//! ```
+//! use singleton_manager::SingletonManager;
+//! use std::sync::Mutex;
+//! use std::time::Duration;
+//!
+//! let manager = SingletonManager::new();
+//! manager.set_keyed_scope_ttl("session", Duration::from_secs(30 * 60)).unwrap();
+//!
+//! let session = manager.keyed_scope("session", "3f2a");
+//! let cart = session
+//!     .get_or_insert_with("cart", || Mutex::new(Vec::<String>::new()))
+//!     .unwrap();
+//! cart.lock().unwrap().push("book".to_string());
+//!
+//! let cart = manager
+//!     .keyed_scope("session", "3f2a")
+//!     .get::<Mutex<Vec<String>>>("cart")
+//!     .unwrap();
+//! assert_eq!(vec!["book".to_string()], *cart.lock().unwrap());
+//!
+//! assert_eq!(1, session.invalidate().unwrap());
+//! assert!(session.get::<Mutex<Vec<String>>>("cart").unwrap_err().is_not_found());
+//! ```
+use crate::{Error, GetError, Result, SingletonManager};
+use std::any::Any;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+type Services = HashMap<String, Arc<dyn Any + Send + Sync>>;
+
+/// The services of the keys of a scope.
+#[derive(Default)]
+pub(crate) struct KeyedServices {
+    /// The time the services of a key are kept without being used, forever if not set.
+    ttl: Option<Duration>,
+    keys: HashMap<String, KeyEntry>,
+}
+
+/// The nanoseconds since the Unix epoch, as the times keys were used are kept.
+fn nanos(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_nanos() as u64)
+}
+
+struct KeyEntry {
+    /// The time the key was last used, in `nanos`, updated under the read lock of the registry.
+    last_used: AtomicU64,
+    services: Services,
+}
+
+impl KeyEntry {
+    /// Marking the key used, never moving the time it was last used back.
+    fn touch(&self, now: SystemTime) {
+        self.last_used.fetch_max(nanos(now), Ordering::Relaxed);
+    }
+}
+
+impl KeyedServices {
+    fn is_expired(&self, entry: &KeyEntry, now: SystemTime) -> bool {
+        self.ttl.is_some_and(|ttl| {
+            let unused = nanos(now).saturating_sub(entry.last_used.load(Ordering::Relaxed));
+            u128::from(unused) > ttl.as_nanos()
+        })
+    }
+
+    /// Removing the key if it expired, returning its entry.
+    fn remove_expired(&mut self, key: &str, now: SystemTime) -> Option<KeyEntry> {
+        match self.keys.get(key) {
+            Some(entry) if self.is_expired(entry, now) => self.keys.remove(key),
+            _ => None,
+        }
+    }
+
+    /// Removing the keys not used for longer than the time to live, returning their services.
+    fn expire(&mut self, now: SystemTime) -> Vec<Services> {
+        let expired = self
+            .keys
+            .iter()
+            .filter(|(_, entry)| self.is_expired(entry, now))
+            .map(|(key, _)| key.clone())
+            .collect::<Vec<_>>();
+        expired
+            .iter()
+            .filter_map(|key| self.keys.remove(key))
+            .map(|entry| entry.services)
+            .collect()
+    }
+}
+
+/// A view of the services of a key of a keyed scope, see `SingletonManager::keyed_scope`.
+pub struct KeyedScope<'a> {
+    manager: &'a SingletonManager,
+    scope: String,
+    key: String,
+}
+
+impl KeyedScope<'_> {
+    /// The name of the scope.
+    pub fn scope(&self) -> &str {
+        &self.scope
+    }
+
+    /// The key of the scope the services are held for.
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// The alias the service is reported under in errors, `<scope>/<key>/<service>`.
+    fn alias(&self, service_name: &str) -> String {
+        format!("{}/{}/{}", self.scope, self.key, service_name)
+    }
+
+    /// Setting a service of the key, replacing the service set before.
+    pub fn set<T: Send + Sync + 'static>(&self, service_name: &str, service: T) -> Result<()> {
+        let replaced = self.with_services(|services| {
+            services.insert(service_name.to_string(), Arc::new(service))
+        })?;
+        drop(replaced);
+        Ok(())
+    }
+
+    /// Getting a service of the key.
+    /// Fails with `GetError::ServiceDoesNotExist` if the service is not set for the key, or the
+    /// key expired, and with `GetError::FailedToDowncastRefOfService` if it is not a `T`.
+    pub fn get<T: Send + Sync + 'static>(
+        &self,
+        service_name: &str,
+    ) -> std::result::Result<Arc<T>, GetError> {
+        let alias = self.alias(service_name);
+        self.used_service(service_name)
+            .and_then(|service| {
+                service.ok_or_else(|| {
+                    Error::ServiceDoesNotExist(alias.as_str().into(), Default::default())
+                })
+            })
+            .and_then(|service| {
+                service
+                    .downcast::<T>()
+                    .map_err(|_| Error::FailedToDowncastRefOfService(alias.as_str().into()))
+            })
+            .map_err(|e| GetError::from_error(e, &alias))
+    }
+
+    /// Getting a service of the key, setting it to the output of the function if it is not set.
+    /// The function is run while holding the lock of the singleton manager, so it must not
+    /// access the singleton manager.
+    pub fn get_or_insert_with<T: Send + Sync + 'static>(
+        &self,
+        service_name: &str,
+        f: impl FnOnce() -> T,
+    ) -> std::result::Result<Arc<T>, GetError> {
+        let alias = self.alias(service_name);
+        self.with_services(|services| {
+            services
+                .entry(service_name.to_string())
+                .or_insert_with(|| Arc::new(f()))
+                .clone()
+        })
+        .and_then(|service| {
+            service
+                .downcast::<T>()
+                .map_err(|_| Error::FailedToDowncastRefOfService(alias.as_str().into()))
+        })
+        .map_err(|e| GetError::from_error(e, &alias))
+    }
+
+    /// Dropping the services of the key, returning how many were dropped.
+    /// Services still in use are kept alive by their users.
+    pub fn invalidate(&self) -> Result<usize> {
+        let removed = self
+            .manager
+            .registry_mut()?
+            .keyed_scopes
+            .get_mut(&self.scope)
+            .and_then(|scope| scope.keys.remove(&self.key));
+        Ok(removed.map_or(0, |entry| entry.services.len()))
+    }
+
+    /// Running the function on the services of the key, creating the key if needed, and marking
+    /// it used. The services of an expired key are dropped first, after releasing the lock.
+    fn with_services<R>(&self, f: impl FnOnce(&mut Services) -> R) -> Result<R> {
+        let now = self.manager.clock_and_ids.now();
+        let mut registry = self.manager.registry_mut()?;
+        let scope = registry.keyed_scopes.entry(self.scope.clone()).or_default();
+        let expired = scope.remove_expired(&self.key, now);
+        let entry = scope
+            .keys
+            .entry(self.key.clone())
+            .or_insert_with(|| KeyEntry {
+                last_used: AtomicU64::new(0),
+                services: Services::new(),
+            });
+        entry.touch(now);
+        let result = f(&mut entry.services);
+        drop(registry);
+        drop(expired);
+        Ok(result)
+    }
+
+    /// Getting a service of the key under the read lock, marking the key used, or nothing if the
+    /// key does not hold it. Nothing is created for the scope or the key, and only an expired key
+    /// is removed, under the write lock, dropping its services after releasing the lock.
+    fn used_service(&self, service_name: &str) -> Result<Option<Arc<dyn Any + Send + Sync>>> {
+        let now = self.manager.clock_and_ids.now();
+        {
+            let registry = self.manager.registry()?;
+            let scope = match registry.keyed_scopes.get(&self.scope) {
+                Some(scope) => scope,
+                None => return Ok(None),
+            };
+            match scope.keys.get(&self.key) {
+                None => return Ok(None),
+                Some(entry) if !scope.is_expired(entry, now) => {
+                    entry.touch(now);
+                    return Ok(entry.services.get(service_name).cloned());
+                }
+                Some(_) => {}
+            }
+        }
+        let expired = self
+            .manager
+            .registry_mut()?
+            .keyed_scopes
+            .get_mut(&self.scope)
+            .and_then(|scope| scope.remove_expired(&self.key, now));
+        drop(expired);
+        Ok(None)
+    }
+}
+
+impl SingletonManager {
+    /// The view of the services of the key of a keyed scope, e.g. of a session id of the
+    /// `session` scope. Scopes are created when first used.
+    pub fn keyed_scope(&self, scope: &str, key: &str) -> KeyedScope<'_> {
+        KeyedScope {
+            manager: self,
+            scope: scope.to_string(),
+            key: key.to_string(),
+        }
+    }
+
+    /// Setting the time the services of a key of the scope are kept without being used.
+    /// Expired keys are dropped when used again, or by `expire_keyed_scopes`.
+    pub fn set_keyed_scope_ttl(&self, scope: &str, ttl: Duration) -> Result<()> {
+        self.registry_mut()?
+            .keyed_scopes
+            .entry(scope.to_string())
+            .or_default()
+            .ttl = Some(ttl);
+        Ok(())
+    }
+
+    /// The keys of the scope holding services, sorted.
+    pub fn keyed_scope_keys(&self, scope: &str) -> Vec<String> {
+        let mut keys = self
+            .registry()
+            .map(|registry| {
+                registry
+                    .keyed_scopes
+                    .get(scope)
+                    .map(|scope| scope.keys.keys().cloned().collect::<Vec<_>>())
+                    .unwrap_or_default()
+            })
+            .unwrap_or_default();
+        keys.sort();
+        keys
+    }
+
+    /// Dropping the services of the keys of all scopes not used for longer than the time to live
+    /// of their scope, returning how many keys expired.
+    /// Meant to be called periodically, so the services of abandoned keys are dropped.
+    pub fn expire_keyed_scopes(&self) -> Result<usize> {
+        let now = self.clock_and_ids.now();
+        let expired = self
+            .registry_mut()?
+            .keyed_scopes
+            .values_mut()
+            .flat_map(|scope| scope.expire(now))
+            .collect::<Vec<_>>();
+        Ok(expired.len())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{GetError, SingletonManager, TestClockAndIds};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[test]
+    fn test_keyed_scopes_expire_unused_keys() {
+        let clock = TestClockAndIds::default();
+        let manager = SingletonManager::with_clock_and_ids(clock.clone());
+        manager
+            .set_keyed_scope_ttl("session", Duration::from_secs(60))
+            .unwrap();
+        let alice = manager.keyed_scope("session", "alice");
+        let bob = manager.keyed_scope("session", "bob");
+        alice.set("limiter", 10_u32).unwrap();
+        bob.set("limiter", 20_u32).unwrap();
+        assert!(matches!(
+            alice.get::<String>("limiter"),
+            Err(GetError::FailedToDowncastRefOfService(alias)) if alias == "session/alice/limiter"
+        ));
+
+        clock.advance(Duration::from_secs(45));
+        assert_eq!(10, *alice.get::<u32>("limiter").unwrap());
+        clock.advance(Duration::from_secs(45));
+        assert_eq!(1, manager.expire_keyed_scopes().unwrap());
+        assert_eq!(
+            vec!["alice".to_string()],
+            manager.keyed_scope_keys("session")
+        );
+
+        let limiter = alice.get::<u32>("limiter").unwrap();
+        clock.advance(Duration::from_secs(61));
+        assert!(alice.get::<u32>("limiter").unwrap_err().is_not_found());
+        assert_eq!(10, *limiter);
+        assert_eq!(
+            Arc::new(5_u32),
+            alice.get_or_insert_with("limiter", || 5_u32).unwrap()
+        );
+        assert_eq!(
+            0,
+            manager.keyed_scope("cache", "alice").invalidate().unwrap()
+        );
+
+        assert!(manager
+            .keyed_scope("cache", "carol")
+            .get::<u32>("limiter")
+            .unwrap_err()
+            .is_not_found());
+        assert!(manager
+            .keyed_scope("session", "carol")
+            .get::<u32>("limiter")
+            .is_err());
+        let registry = manager.registry().unwrap();
+        assert!(!registry.keyed_scopes.contains_key("cache"));
+        assert!(!registry.keyed_scopes["session"].keys.contains_key("carol"));
+    }
+}
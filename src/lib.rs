@@ -1,4 +1,6 @@
-#![feature(fn_traits)]
+#![cfg_attr(test, feature(fn_traits))]
+// The tests look services up by raw strings, `strict-keys` only deprecates it for the users.
+#![cfg_attr(all(test, feature = "strict-keys"), allow(deprecated))]
 //! # Singleton Manager
 //! A singleton manger for handling and holding singletons in a system
 //!
@@ -58,35 +60,358 @@
 //!assert_eq!("My Message".to_string(), different_service.get());
 //! ```
 extern crate uuid;
+// The code generated by `#[inject]` names the crate by its path, also within the crate itself.
+extern crate self as singleton_manager;
 
-use std::any::Any;
-use std::cell::Cell;
-use std::collections::HashMap;
+mod access;
+mod actor;
+mod alias;
+#[cfg(feature = "tokio")]
+mod async_guard;
+mod borrow;
+mod bound;
+mod builder;
+mod callers;
+#[cfg(feature = "chaos")]
+mod chaos;
+mod circuit;
+mod clock;
+mod collision;
+mod config;
+mod deadline;
+#[cfg(feature = "debug_http")]
+mod debug_http;
+mod deprecation;
+mod diagnostics;
+mod downcast;
+mod dump;
+mod env;
+mod error;
+mod facade;
+mod fallback;
+#[cfg(feature = "ffi")]
+mod ffi;
+mod fixtures;
+mod flags;
+mod fork;
+mod gc;
+mod group;
+mod hashed_key;
+mod history;
+mod hold_times;
+mod id_generator;
+mod info;
+mod inline;
+mod instance;
+mod instrument;
+mod key;
+mod keyed_scope;
+mod lifecycle;
+#[macro_use]
+mod macros;
+mod mailbox;
+mod manifest;
+mod memoize;
+#[cfg(feature = "mock")]
+mod mock;
+mod module;
+#[cfg(feature = "napi")]
+mod node;
+mod noop;
+mod origin;
+mod pair;
+mod prefix;
+mod preheat;
+mod priority;
+mod provider;
+#[cfg(feature = "pyo3")]
+mod python;
+mod readiness;
+mod rebuild;
+mod reconcile;
+mod recording;
+#[cfg(feature = "proptest")]
+mod registry_script;
+mod remote;
+mod reservation;
+mod runnable;
+mod safety;
+mod scope;
+mod script;
+mod secrets;
+#[cfg(feature = "serde")]
+mod seed;
+mod shutdown;
+#[cfg(all(unix, feature = "signals"))]
+mod signals;
+mod single_flight;
+mod startup;
+mod static_singleton;
+#[cfg(feature = "stress")]
+mod stress;
+mod suggestions;
+#[cfg(feature = "tokio")]
+mod tasks;
+mod tenant;
+mod timeout;
+mod transaction;
+mod tree;
+mod unsync;
+mod usage;
+mod validation;
+mod versions;
+mod view;
+mod weak;
+
+use std::any::{Any, TypeId};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::fmt::{Debug, Display, Formatter};
-use std::ops::DerefMut;
-use std::sync::Once;
-use uuid::Uuid;
+use std::panic::Location;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, Once, PoisonError, RwLock, RwLockReadGuard};
+use std::thread::ThreadId;
+use std::time::Duration;
 
-static mut INSTANCE: Cell<Option<SingletonManager>> = Cell::new(None);
-static mut ONCE: Once = Once::new();
+pub use access::ScopedAccess;
+pub use actor::GetMutAsync;
+pub use alias::Alias;
+use alias::NewAlias;
+#[cfg(feature = "tokio")]
+pub use async_guard::{AsyncServiceRef, AsyncServiceRefMut};
+use borrow::BorrowState;
+pub use borrow::{ServiceRef, ServiceRefMut};
+pub use bound::bound_alias;
+pub use builder::SingletonManagerBuilder;
+#[cfg(feature = "chaos")]
+pub use chaos::Fault;
+use circuit::Breaker;
+use clock::SharedClock;
+pub use clock::{ClockAndIds, SystemClock, TestClockAndIds};
+pub use collision::CollisionPolicy;
+use collision::{RegistryMut, Replaced};
+pub use config::ManagerConfig;
+pub use deadline::DeadlineGuard;
+use deprecation::Deprecation;
+pub use diagnostics::DiagnosticsReport;
+pub use dump::{RegistryDump, ServiceDump};
+pub use error::{FactoryError, GetError, SetError};
+pub use facade::Facade;
+#[cfg(feature = "ffi")]
+pub use ffi::{
+    sm_free, sm_get_ptr, sm_global, sm_new, sm_remove, sm_set_ptr, SmDestructor, SmStatus,
+};
+pub use fixtures::{Fixture, FixtureGuard};
+use flags::FlagGate;
+use gc::Deferred;
+use group::HealthCheck;
+pub use group::{GroupMetrics, GroupReport, Health};
+pub use hashed_key::HashedKey;
+use hashed_key::HashedKeys;
+use history::History;
+pub use history::{HistoryEntry, Operation, DEFAULT_HISTORY_CAPACITY};
+use hold_times::HoldHistogram;
+pub use hold_times::HoldTimes;
+pub use id_generator::{
+    IdGenerator, NameBasedIdGenerator, RandomIdGenerator, SequentialIdGenerator,
+};
+pub use info::ServiceInfo;
+use inline::InlineService;
+pub use inline::INLINE_CAPACITY;
+pub use instance::{InitError, InitFailureStrategy};
+pub use instrument::{CallSite, Instrumented};
+pub use key::Key;
+pub use keyed_scope::KeyedScope;
+use keyed_scope::KeyedServices;
+use lifecycle::Hooks;
+pub use lifecycle::Lifecycle;
+pub use mailbox::Mailbox;
+use mailbox::Mailboxes;
+pub use manifest::{Manifest, ManifestDiff};
+use memoize::Memoized;
+use module::InstalledModule;
+pub use module::{Module, Registrar};
+pub use noop::Noop;
+pub use origin::ServiceOrigin;
+use preheat::PreheatSlot;
+pub use priority::{Binding, DEFAULT_PRIORITY};
+pub use provider::{provider_fn, ProviderFn};
+#[cfg(feature = "pyo3")]
+pub use python::py_singleton_manager;
+pub use readiness::ReadyBarrier;
+use readiness::ReadySignal;
+pub use rebuild::Drain;
+use rebuild::DrainFn;
+pub use reconcile::Reconcile;
+use reconcile::Reconciler;
+use recording::Recorders;
+pub use recording::{CallKind, RecordedCall, RecordingScope};
+#[cfg(feature = "proptest")]
+pub use registry_script::{RegistryScript, ScriptOp, ScriptViolation, SCRIPT_ALIASES};
+pub use remote::CachedRemote;
+pub use reservation::Reservation;
+use reservation::ReservationSlot;
+use runnable::AsRunnable;
+pub use runnable::Runnable;
+pub use safety::UnsafeStats;
+pub use scope::Scope;
+use script::ScriptShim;
+pub use script::ScriptValue;
+pub use secrets::{EnvSecrets, FileSecrets, Secret, SecretStore};
+pub use shutdown::ShutdownReport;
+#[cfg(all(unix, feature = "signals"))]
+pub use signals::{Signal, SignalShutdown};
+pub use single_flight::Lazy;
+use single_flight::{Flight, InitLatch, InstancePtr};
+/// Generating variants of a function with its parameters resolved from the singleton manager.
+///
+/// Every parameter taken by shared reference is resolved as the service bound as its type, see
+/// `SingletonManager::bind`, and the parameters taken by value or by mutable reference are kept,
+/// in their order. Functions of an impl block can be injected when their signature names `Self`,
+/// e.g. constructors, which are called as `Self::<name>`. Next to the function itself,
+/// `<name>_injected(..)` calls it with the services bound in the global singleton manager, and
+/// `<name>_injected_from(&manager, ..)` with the services bound in the manager given. Both fail
+/// with the `GetError` of the first service that can not be resolved.
+///
+/// ```
+/// use singleton_manager::{inject, sm};
+///
+/// pub trait Clock: Send + Sync {
+///     fn now(&self) -> u64;
+/// }
+///
+/// struct FixedClock;
+///
+/// impl Clock for FixedClock {
+///     fn now(&self) -> u64 {
+///         42
+///     }
+/// }
+///
+/// pub struct Config {
+///     greeting: String,
+/// }
+///
+/// #[inject]
+/// pub fn greet(name: String, config: &Config, clock: &dyn Clock) -> String {
+///     format!("{} {} at {}", config.greeting, name, clock.now())
+/// }
+///
+/// sm().bind::<dyn Clock>(Box::new(FixedClock)).unwrap();
+/// sm().bind(Box::new(Config { greeting: "hello".to_string() })).unwrap();
+/// assert_eq!("hello world at 42", greet_injected("world".to_string()).unwrap());
+/// ```
+pub use singleton_manager_macros::inject;
+pub use startup::{InitReport, Phase, PhaseReport, ServiceStartup, StartupReport};
+pub use static_singleton::StaticSingleton;
+#[cfg(feature = "stress")]
+pub use stress::{StressReport, StressTest, StressViolation};
+pub use suggestions::Suggestions;
+#[cfg(feature = "tokio")]
+use tasks::ManagedTask;
+#[cfg(feature = "tokio")]
+pub use tasks::TaskStatus;
+pub use tenant::{tenant_alias, Tenant};
+use timeout::TimedOut;
+pub use transaction::Transaction;
+use unsync::ThreadBound;
+pub use usage::UsageReport;
+pub use uuid::Uuid;
+pub use validation::Validator;
+pub use versions::versioned_alias;
+pub use view::RegistryView;
+pub use weak::WeakHandle;
+
+#[doc(hidden)]
+pub use paste::paste as __paste;
+
+static mut INSTANCE: Option<SingletonManager> = None;
+static ONCE: Once = Once::new();
+static NEXT_INSTANCE_ID: AtomicU64 = AtomicU64::new(1);
 
 /// Common Result used in the library.
 pub type Result<T> = std::result::Result<T, Error>;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Error {
-    ServiceDoesNotExist(String),
-    ServiceNotInstantiated(String),
-    FailedToDowncastRefOfService(String),
-    FailedToStoreService(String),
-    NoFactoryFunctionAvailable(String),
-    SetFailedToReturnAServiceReference(String),
-    FailedToDowncastFactoryOutput(String),
-    NoServiceWithStorageRequest,
-    FailedToStoreServiceAlias,
+    /// The alias looked up and the aliases registered that are close to it.
+    ServiceDoesNotExist(Alias, Suggestions),
+    ServiceNotInstantiated(Alias),
+    FailedToDowncastRefOfService(Alias),
+    FailedToStoreService(Alias),
+    NoFactoryFunctionAvailable(Alias),
+    SetFailedToReturnAServiceReference(Alias),
+    FailedToDowncastFactoryOutput(Alias),
+    NoServiceWithStorageRequest(Alias),
+    FailedToStoreServiceAlias(Alias),
     MutexGotPoison,
-    ServiceAlreadyExists,
-    FailedToStoreFactory,
+    ServiceAlreadyExists(Alias),
+    FailedToStoreFactory(Alias),
+    ValidationFailed(Alias, String),
+    ReservationCancelled(Alias),
+    ReservationTimeout(Alias),
+    FeatureDisabled(Alias, String),
+    ServiceFrozen(Alias),
+    ServiceNotFrozen(Alias),
+    /// The service is not gated by a feature flag.
+    ServiceNotFlagged(Alias),
+    AlreadyBorrowed(Alias, &'static Location<'static>),
+    TenantDoesNotExist(String),
+    TenantAlreadyExists(String),
+    InstanceAlreadyInitialized,
+    /// Creating the global instance failed.
+    InstanceInitFailed(InitError),
+    ModuleAlreadyInstalled(String),
+    ModuleNotInstalled(String),
+    NotRunnable(Alias),
+    /// The service has no adapter to be called from scripts.
+    NotScriptable(Alias),
+    StartFailed(Alias, String),
+    StopFailed(Alias, String),
+    DependencyCycle(Alias, String),
+    /// The alias of the service and of the dependency not registered.
+    MissingDependency(Alias, String),
+    /// The alias of the service and the time waited for its factory.
+    FactoryTimeout(Alias, Duration),
+    /// The alias of the service and the time until its factory is run again.
+    CircuitOpen(Alias, Duration),
+    FactoryNotSend(Alias),
+    /// The alias of the service, the thread owning it and the thread calling.
+    WrongThread(Alias, ThreadId, ThreadId),
+    AccessDenied(Alias),
+    /// The line of the manifest that could not be parsed.
+    InvalidManifest(String),
+    ManifestMismatch(ManifestDiff),
+    /// A fault injected by the `chaos` feature.
+    FaultInjected(Alias),
+    /// The factory of the service needs the service itself, directly or through other factories.
+    RecursiveFactory(Alias),
+    /// The service has no mailbox open for the type of the message.
+    NoMailbox(Alias),
+    /// The lifecycle hook of the service did not finish within the budget.
+    HookTimeout(Alias, Duration),
+    /// The environment variables not set.
+    MissingEnv(Vec<String>),
+    /// The alias of the service, the environment variable and why it could not be parsed.
+    InvalidEnv(Alias, String, String),
+    /// The alias of the service and why its seed data could not be deserialized.
+    InvalidSeed(Alias, String),
+    /// Why the seed data could not be parsed as the seed data of the services by alias.
+    InvalidSeedData(String),
+    /// The alias of the service, the version requirement, and the crate that registered the
+    /// service if it is known.
+    IncompatibleVersion(Alias, String, Option<ServiceOrigin>),
+    /// The alias of the service and why its version, or the version requirement, could not be
+    /// parsed.
+    InvalidVersion(Alias, String),
+    /// The service is not `Send + Sync`, which the configuration of the manager requires.
+    NotSendSync(Alias),
+    /// The alias of the service and its size in bytes, as it is too large, or too aligned, to be
+    /// stored inline.
+    TooLargeToInline(Alias, usize),
+    /// The key of the secret not found in the secret store.
+    SecretNotFound(String),
+    /// The signal handlers could not be installed, with the reason.
+    SignalHandler(String),
     UnknownError(String),
 }
 
@@ -99,7 +424,14 @@ impl From<String> for Error {
 impl Display for Error {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
-            Self::ServiceDoesNotExist(ref s) => write!(f, "Service `{}` does not exist", s),
+            Self::ServiceDoesNotExist(ref s, ref suggestions) => {
+                write!(f, "Service `{}` does not exist", s)?;
+                match &suggestions[..] {
+                    [] => Ok(()),
+                    [suggestion] => write!(f, ", did you mean `{}`?", suggestion),
+                    _ => write!(f, ", did you mean one of `{}`?", suggestions.join("`, `")),
+                }
+            }
             Self::ServiceNotInstantiated(ref s) => write!(f, "Service `{}` is not instantiated", s),
             Self::FailedToDowncastRefOfService(ref s) => {
                 write!(f, "Failed to downcast service {}", s)
@@ -117,11 +449,146 @@ impl Display for Error {
                 write!(f, "Failed to downcast Factory output for service {}", s)
             }
 
-            Self::NoServiceWithStorageRequest => write!(f, "No service with storage request"),
-            Self::FailedToStoreServiceAlias => write!(f, "Service Could not be stored"),
+            Self::NoServiceWithStorageRequest(ref s) => {
+                write!(f, "No service `{}` with storage request", s)
+            }
+            Self::FailedToStoreServiceAlias(ref s) => {
+                write!(f, "Service alias `{}` could not be stored", s)
+            }
             Self::MutexGotPoison => write!(f, "Mutex poison"),
-            Self::ServiceAlreadyExists => write!(f, "Service already exists"),
-            Self::FailedToStoreFactory => write!(f, "Failed to store factory"),
+            Self::ServiceAlreadyExists(ref s) => write!(f, "Service `{}` already exists", s),
+            Self::FailedToStoreFactory(ref s) => write!(f, "Failed to store factory of `{}`", s),
+            Self::ValidationFailed(ref s, ref reason) => {
+                write!(f, "Service `{}` failed validation: {}", s, reason)
+            }
+            Self::ReservationCancelled(ref s) => {
+                write!(f, "Reservation of service `{}` was cancelled", s)
+            }
+            Self::ReservationTimeout(ref s) => {
+                write!(f, "Timed out waiting for reserved service `{}`", s)
+            }
+            Self::FeatureDisabled(ref s, ref flag) => {
+                write!(f, "Service `{}` is disabled by feature `{}`", s, flag)
+            }
+            Self::ServiceFrozen(ref s) => write!(f, "Service `{}` is frozen", s),
+            Self::ServiceNotFrozen(ref s) => write!(f, "Service `{}` is not frozen", s),
+            Self::ServiceNotFlagged(ref s) => {
+                write!(f, "Service `{}` is not gated by a feature flag", s)
+            }
+            Self::AlreadyBorrowed(ref s, holder) => {
+                write!(f, "Service `{}` is already borrowed at {}", s, holder)
+            }
+            Self::TenantDoesNotExist(ref tenant) => write!(f, "Tenant `{}` does not exist", tenant),
+            Self::TenantAlreadyExists(ref tenant) => {
+                write!(f, "Tenant `{}` already exists", tenant)
+            }
+            Self::InstanceAlreadyInitialized => {
+                write!(f, "The global instance is already initialized")
+            }
+            Self::InstanceInitFailed(ref e) => write!(f, "{}", e),
+            Self::ModuleAlreadyInstalled(ref module) => {
+                write!(f, "Module `{}` is already installed", module)
+            }
+            Self::ModuleNotInstalled(ref module) => {
+                write!(f, "Module `{}` is not installed", module)
+            }
+            Self::NotRunnable(ref s) => write!(f, "Service `{}` is not runnable", s),
+            Self::NotScriptable(ref s) => {
+                write!(f, "Service `{}` has no adapter for scripts", s)
+            }
+            Self::StartFailed(ref s, ref reason) => {
+                write!(f, "Service `{}` failed to start: {}", s, reason)
+            }
+            Self::StopFailed(ref s, ref reason) => {
+                write!(f, "Service `{}` failed to stop: {}", s, reason)
+            }
+            Self::DependencyCycle(ref s, ref dependency) => write!(
+                f,
+                "Service `{}` can not depend on `{}`, which depends on it",
+                s, dependency
+            ),
+            Self::MissingDependency(ref s, ref dependency) => write!(
+                f,
+                "Service `{}` depends on `{}`, which is not registered",
+                s, dependency
+            ),
+            Self::FactoryTimeout(ref s, ref elapsed) => write!(
+                f,
+                "Factory of service `{}` did not finish within {:?}",
+                s, elapsed
+            ),
+            Self::CircuitOpen(ref s, ref retry_after) => write!(
+                f,
+                "Circuit of service `{}` is open, its factory is retried in {:?}",
+                s, retry_after
+            ),
+            Self::MissingEnv(ref variables) => {
+                write!(f, "Environment variables not set: {}", variables.join(", "))
+            }
+            Self::InvalidEnv(ref s, ref variable, ref reason) => write!(
+                f,
+                "Environment variable `{}` of service `{}` is invalid: {}",
+                variable, s, reason
+            ),
+            Self::InvalidSeed(ref s, ref reason) => {
+                write!(f, "Seed data of service `{}` is invalid: {}", s, reason)
+            }
+            Self::InvalidSeedData(ref reason) => write!(f, "Invalid seed data: {}", reason),
+            Self::SecretNotFound(ref key) => write!(f, "Secret `{}` does not exist", key),
+            Self::FactoryNotSend(ref s) => write!(
+                f,
+                "Factory of service `{}` can not be run on another thread",
+                s
+            ),
+            Self::WrongThread(ref s, ref owner, ref caller) => write!(
+                f,
+                "Service `{}` is bound to thread {:?} and can not be used on thread {:?}",
+                s, owner, caller
+            ),
+            Self::AccessDenied(ref s) => write!(f, "Access to service `{}` is denied", s),
+            Self::TooLargeToInline(ref s, ref size) => write!(
+                f,
+                "Service `{}` of {} bytes can not be stored inline",
+                s, size
+            ),
+            Self::InvalidManifest(ref line) => write!(f, "Invalid manifest line `{}`", line),
+            Self::FaultInjected(ref s) => write!(f, "Fault injected into service `{}`", s),
+            Self::NoMailbox(ref s) => {
+                write!(f, "Service `{}` has no mailbox for the message", s)
+            }
+            Self::HookTimeout(ref s, ref budget) => write!(
+                f,
+                "Lifecycle hook of service `{}` did not finish within {:?}",
+                s, budget
+            ),
+            Self::SignalHandler(ref reason) => {
+                write!(f, "Failed to install the signal handlers: {}", reason)
+            }
+            Self::IncompatibleVersion(ref s, ref requirement, Some(ref origin)) => write!(
+                f,
+                "Service `{}` registered by {} does not satisfy the version requirement `{}`",
+                s, origin, requirement
+            ),
+            Self::IncompatibleVersion(ref s, ref requirement, None) => write!(
+                f,
+                "Service `{}` has no version recorded to check against the version requirement \
+                 `{}`",
+                s, requirement
+            ),
+            Self::InvalidVersion(ref s, ref reason) => {
+                write!(f, "Invalid version of service `{}`: {}", s, reason)
+            }
+            Self::NotSendSync(ref s) => write!(
+                f,
+                "Service `{}` is not `Send + Sync`, which the manager requires",
+                s
+            ),
+            Self::RecursiveFactory(ref s) => {
+                write!(f, "The factory of service `{}` needs the service itself", s)
+            }
+            Self::ManifestMismatch(ref diff) => {
+                write!(f, "Registrations do not match the manifest:\n{}", diff)
+            }
             Self::UnknownError(s) => write!(f, "An unknown error happened: {}", s),
         }
     }
@@ -143,22 +610,481 @@ impl std::error::Error for Error {}
 /// function to be set. In the case that the Singleton is never used the factory will stay dormant.
 ///
 pub struct SingletonManager {
+    /// The storage of the singletons, their factories and aliases.
+    registry: RwLock<Registry>,
+    /// The source of the timestamps and of the ids linking the aliases to the singleton storage.
+    clock_and_ids: SharedClock,
+    /// Unique id of the manager, identifying it in the caches of the keys.
+    instance_id: u64,
+}
+
+/// A stored service, shareable across threads, so the manager is.
+type Service = Box<dyn Any + Send + Sync>;
+/// A factory creating a service.
+type Factory = Arc<dyn Fn() -> Service + Send + Sync>;
+
+/// The storage of the singleton manager, only accessed through the lock of the manager.
+#[derive(Default)]
+struct Registry {
     /// The singleton for the "service" or structure that needs a singular instantiation.
-    singletons: HashMap<Uuid, Box<dyn Any>>,
+    singletons: HashMap<Uuid, Service>,
     /// A factory function that can be used for creating the singleton
-    singleton_factories: HashMap<Uuid, Box<dyn Fn() -> Box<dyn Any>>>,
+    singleton_factories: HashMap<Uuid, Factory>,
     // instance_type: HashMap<Uuid, String>,
     /// Alias for the actual Singleton. This is linking an actual name to the singleton storage.
-    alias: HashMap<String, Uuid>,
+    alias: HashMap<Alias, Uuid>,
+    /// The registered aliases in order, for the operations on the aliases starting with a prefix.
+    prefix_index: Arc<RwLock<BTreeSet<Alias>>>,
+    /// Tracking of the borrows handed out by `borrow` and `borrow_mut`.
+    borrows: HashMap<Uuid, Arc<BorrowState>>,
+    /// The startup phase of the singleton, used by `init_all`.
+    phases: HashMap<Uuid, Phase>,
+    /// Key/value tags attached to the registration.
+    tags: HashMap<Uuid, HashMap<String, String>>,
+    /// Reconciling the values registered under an alias already holding an equivalent value,
+    /// by the type of the values.
+    reconcilers: HashMap<TypeId, Reconciler>,
+    /// Validation hooks run before a singleton is stored.
+    validators: Vec<Validator>,
+    /// The last operations done on the registry.
+    history: History,
+    /// Deprecated aliases forwarding to the alias a registration was renamed to.
+    forwarding: HashMap<Alias, Alias>,
+    /// The call sites already warned about using a deprecated forwarding alias.
+    forwarding_warned: Mutex<HashSet<(Alias, &'static Location<'static>)>>,
+    /// Reserved singletons waiting to be provided.
+    reservations: HashMap<Uuid, Arc<ReservationSlot>>,
+    /// The feature flags gating services.
+    gates: HashMap<Uuid, FlagGate>,
+    /// The state of the feature flags, flags not set are disabled.
+    flags: HashMap<String, bool>,
+    /// The ordered fallback chains, by alias.
+    fallbacks: HashMap<String, Vec<String>>,
+    /// The default version of the aliases with versions.
+    default_versions: HashMap<String, String>,
+    /// The type names of the services, known once set or retrieved.
+    type_names: HashMap<Uuid, &'static str>,
+    /// The services that have been retrieved.
+    retrieved: HashSet<Uuid>,
+    /// The number of accesses by call site, of the instrumented services.
+    call_sites: HashMap<Uuid, HashMap<&'static Location<'static>, u64>>,
+    /// The frozen services, which are never removed or mutably accessed again.
+    frozen: HashSet<Uuid>,
+    /// The keyed factories and their instances, of the memoized services.
+    memoized: HashMap<Uuid, Memoized>,
+    /// The registrations of the tenants, in the order they were registered.
+    tenants: BTreeMap<String, Vec<Uuid>>,
+    /// The profile selected when building the manager.
+    profile: Option<String>,
+    /// The installed modules, by name.
+    modules: HashMap<String, InstalledModule>,
+    /// The services of the groups, in the order they were added.
+    groups: BTreeMap<String, Vec<Uuid>>,
+    /// The services that reported they are ready.
+    ready: HashSet<Uuid>,
+    /// Waking the barriers waiting for groups to be ready.
+    ready_signal: Arc<ReadySignal>,
+    /// The health checks of the services.
+    health_checks: HashMap<Uuid, HealthCheck>,
+    /// Getting the `Runnable` implementation of the runnable services.
+    runnables: HashMap<Uuid, AsRunnable>,
+    /// The prefixes of the source files allowed to resolve the restricted services.
+    allowed_callers: HashMap<Uuid, Vec<String>>,
+    /// The deprecated services, with the note of their deprecation.
+    deprecations: HashMap<Uuid, Deprecation>,
+    /// The number of lookups of the aliases not registered, see `SingletonManager::usage_report`,
+    /// counted under the shared lock of the registry.
+    failed_lookups: RwLock<HashMap<Alias, AtomicU64>>,
+    /// The circuit breakers of the factories.
+    breakers: HashMap<Uuid, Breaker>,
+    /// The ids the hashed keys used resolved to, by the hash of their alias.
+    hashed_keys: HashedKeys,
+    /// The services held per key of the keyed scopes, by scope.
+    keyed_scopes: HashMap<String, KeyedServices>,
+    /// The background tasks spawned, by name.
+    #[cfg(feature = "tokio")]
+    tasks: BTreeMap<String, ManagedTask>,
+    /// The call sites already warned about taking a blocking guard within an async runtime.
+    #[cfg(feature = "tokio")]
+    sync_guard_warnings: Mutex<HashSet<&'static Location<'static>>>,
+    /// The adapters calling the services from scripts.
+    script_adapters: HashMap<Uuid, ScriptShim>,
+    /// The services each service depends on.
+    dependencies: HashMap<Uuid, Vec<Uuid>>,
+    /// The results of the factories running on background threads.
+    preheating: HashMap<Uuid, Arc<PreheatSlot>>,
+    /// The small `Copy` services stored inline, see `SingletonManager::set_inline`.
+    inline: HashMap<Uuid, InlineService>,
+    /// The pointers to the stored instances, so getting them only needs the shared lock.
+    instances: HashMap<Uuid, InstancePtr>,
+    /// The latches of the factories running, opened when they finished.
+    initializing: HashMap<Uuid, Arc<InitLatch>>,
+    /// The service each thread waits for the factory of.
+    awaiting: HashMap<ThreadId, Uuid>,
+    /// The sending ends of the mailboxes of the services.
+    mailboxes: Mailboxes,
+    /// Draining the replaced instances of the services, see `SingletonManager::rebuild`.
+    drains: HashMap<Uuid, DrainFn>,
+    /// The lifecycle hooks of the services.
+    lifecycles: HashMap<Uuid, Hooks>,
+    /// The services started by `init_all`, and not stopped since.
+    started: HashSet<Uuid>,
+    /// The factories creating thread-bound services, which can not be run on another thread.
+    unsync_factories: HashSet<Uuid>,
+    /// The registration and type each call site of `get` already downcasted to, and the
+    /// generation the instance was checked at.
+    downcasts: HashMap<&'static Location<'static>, (Uuid, TypeId, u64)>,
+    /// The number of references handed out to instances dropped since.
+    dangling_references: usize,
+    /// Counting the exclusive accesses of the manager, each ending the references handed out
+    /// without tracking before it.
+    raw_epoch: u64,
+    /// Counting the changes of the instances and aliases, invalidating the facades.
+    generation: Arc<AtomicU64>,
+    /// The collision policy of the aliases without a namespace policy.
+    collision_policy: CollisionPolicy,
+    /// The collision policies of the namespaces.
+    namespace_collision_policies: HashMap<String, CollisionPolicy>,
+    /// The alias registered twice under the `Panic` collision policy, panicked about once the
+    /// registry is unlocked.
+    registered_twice: Option<Alias>,
+    /// The registrations replaced by the transaction being applied, if any.
+    replacing: Option<Replaced>,
+    /// The priorities of the registrations made with a priority.
+    priorities: HashMap<Uuid, i32>,
+    /// The bindings made with a priority, by alias.
+    bindings: HashMap<String, Vec<Binding>>,
+    /// The faults injected into the resolution of services.
+    #[cfg(feature = "chaos")]
+    chaos: chaos::Chaos,
+    /// The recorders of the recording scopes.
+    recorders: Recorders,
+    /// The removed instances waiting to be dropped by `gc`.
+    deferred: Vec<Deferred>,
+    /// The removed instances waiting to be dropped once the registry is unlocked.
+    released: Vec<Service>,
+    /// The current epoch of the deferred drops.
+    epoch: u64,
+    /// The number of deferred instances above which `gc` runs automatically.
+    auto_gc: Option<usize>,
+    /// The clock of the manager, timestamping the history and generating the ids of the aliases.
+    clock: SharedClock,
+    /// Deserializing the seed data of the services, by alias.
+    #[cfg(feature = "serde")]
+    seeders: HashMap<String, seed::Seeder>,
+    /// The crate that registered the singleton, and its version, checked by `get_compat`.
+    origins: HashMap<Uuid, ServiceOrigin>,
+    /// The configuration of the manager, set when building it.
+    config: ManagerConfig,
+}
+
+impl Registry {
+    fn store_alias<'a>(&mut self, alias: impl Into<NewAlias<'a>>) -> Result<Uuid> {
+        let new_alias = alias.into();
+        let alias = new_alias.as_str();
+        if self.alias.contains_key(alias) || self.default_versions.contains_key(alias) {
+            Err(Error::ServiceAlreadyExists(alias.into()))
+        } else {
+            let id = self.clock.generate(alias);
+            let interned = self.intern(new_alias);
+            self.alias.insert(interned.clone(), id);
+            self.index_alias(interned.clone());
+            if self.forwarding.remove(alias).is_some() {
+                self.forget_forwarding_warnings();
+            }
+            self.borrows.insert(
+                id,
+                Arc::new(BorrowState::new(interned.clone(), self.config.metrics)),
+            );
+            self.invalidate_facades();
+            if let Some(id) = self.alias.get(alias) {
+                Ok(*id)
+            } else {
+                Err(Error::FailedToStoreServiceAlias(alias.into()))
+            }
+        }
+    }
+
+    /// Storing the service under a new alias together with its tags, after validating it.
+    /// Returns nothing if the alias is already registered, and the collision policy keeps the
+    /// registration already there.
+    fn store_service<'a>(
+        &mut self,
+        alias: impl Into<NewAlias<'a>>,
+        service: Service,
+        type_name: &'static str,
+        tags: &[(&str, &str)],
+        location: &'static Location<'static>,
+    ) -> Result<Option<*mut dyn Any>> {
+        let new_alias = alias.into();
+        let alias = new_alias.as_str();
+        let service = match self.reconcile(alias, service)? {
+            Some(service) => service,
+            None => return Ok(None),
+        };
+        if self.resolve_collision(alias)? {
+            return Ok(None);
+        }
+        let id = self.store_alias(new_alias)?;
+        self.tags.insert(
+            id,
+            tags.iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+        );
+        self.type_names.insert(id, type_name);
+        if let Err(e) = self.validate(alias, &id, service.as_ref()) {
+            self.remove_alias(alias);
+            return Err(e);
+        }
+        self.record(Operation::Set, alias, Some(location));
+        if self.is_recording() {
+            self.record_call(CallKind::Set, alias, Some(type_name), location);
+        }
+        self.singleton_set(id, service).map(Some)
+    }
+
+    /// Storing the factory under a new alias, returning false if the alias is already
+    /// registered, and the collision policy keeps the registration already there.
+    fn store_factory<'a>(
+        &mut self,
+        alias: impl Into<NewAlias<'a>>,
+        factory: Factory,
+        location: &'static Location<'static>,
+    ) -> Result<bool> {
+        let new_alias = alias.into();
+        let alias = new_alias.as_str();
+        if self.resolve_collision(alias)? {
+            return Ok(false);
+        }
+        let id = self.store_alias(new_alias)?;
+        self.singleton_factory_set(id, factory)?;
+        self.record(Operation::SetFactory, alias, Some(location));
+        if self.is_recording() {
+            self.record_call(CallKind::Set, alias, None, location);
+        }
+        Ok(true)
+    }
+
+    /// Removing the alias and everything stored for it.
+    fn remove_alias(&mut self, alias: &str) -> Option<Uuid> {
+        let id = *self.alias.get(alias)?;
+        self.forget_registration(alias, id);
+        self.alias.remove(alias);
+        self.unindex_alias(alias);
+        Some(id)
+    }
+
+    /// Adding the alias to the ordered index of the aliases.
+    fn index_alias(&mut self, alias: Alias) {
+        self.prefix_index
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(alias);
+    }
+
+    /// Removing the alias from the ordered index of the aliases.
+    fn unindex_alias(&mut self, alias: &str) {
+        self.prefix_index
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(alias);
+    }
+
+    /// Removing everything stored for the registration whose alias was removed.
+    fn forget_registration(&mut self, alias: &str, id: Uuid) {
+        if let Some(instance) = self.drop_instance(&id) {
+            self.released.push(instance);
+        }
+        self.invalidate_facades();
+        self.singleton_factories.remove(&id);
+        self.borrows.remove(&id);
+        self.phases.remove(&id);
+        self.tags.remove(&id);
+        self.reservations.remove(&id);
+        self.gates.remove(&id);
+        self.type_names.remove(&id);
+        self.retrieved.remove(&id);
+        self.call_sites.remove(&id);
+        self.frozen.remove(&id);
+        self.memoized.remove(&id);
+        self.inline.remove(&id);
+        for ids in self.tenants.values_mut() {
+            ids.retain(|tenant_id| *tenant_id != id);
+        }
+        for module in self.modules.values_mut() {
+            module.forget(&id);
+        }
+        self.ready.remove(&id);
+        self.origins.remove(&id);
+        if self.groups.values().any(|ids| ids.contains(&id)) {
+            // The groups may be ready without the service.
+            self.ready_signal.notify();
+        }
+        for ids in self.groups.values_mut() {
+            ids.retain(|member| *member != id);
+        }
+        self.health_checks.remove(&id);
+        self.runnables.remove(&id);
+        self.script_adapters.remove(&id);
+        self.breakers.remove(&id);
+        self.allowed_callers.remove(&id);
+        self.deprecations.remove(&id);
+        self.dependencies.remove(&id);
+        self.preheating.remove(&id);
+        self.unsync_factories.remove(&id);
+        self.mailboxes.retain(|(service, _), _| *service != id);
+        self.drains.remove(&id);
+        self.lifecycles.remove(&id);
+        self.started.remove(&id);
+        for dependencies in self.dependencies.values_mut() {
+            dependencies.retain(|dependency| *dependency != id);
+        }
+        self.priorities.remove(&id);
+        self.bindings.remove(alias);
+        self.forwarding.retain(|_, target| &**target != alias);
+        self.forget_forwarding_warnings();
+    }
+
+    /// The state of the registry still held for the id of a registration, none once it is
+    /// forgotten. The hashed keys are not listed, as they are checked against the generation of
+    /// the registry instead.
+    #[cfg(test)]
+    fn held_for(&self, id: &Uuid) -> Vec<&'static str> {
+        let mut held = Vec::new();
+        macro_rules! check {
+            ($($state:ident: $holds:expr),* $(,)?) => {
+                $(if $holds { held.push(stringify!($state)); })*
+            };
+        }
+        check!(
+            singletons: self.singletons.contains_key(id),
+            singleton_factories: self.singleton_factories.contains_key(id),
+            alias: self.alias.values().any(|alias| alias == id),
+            borrows: self.borrows.contains_key(id),
+            phases: self.phases.contains_key(id),
+            tags: self.tags.contains_key(id),
+            reservations: self.reservations.contains_key(id),
+            gates: self.gates.contains_key(id),
+            type_names: self.type_names.contains_key(id),
+            retrieved: self.retrieved.contains(id),
+            call_sites: self.call_sites.contains_key(id),
+            frozen: self.frozen.contains(id),
+            memoized: self.memoized.contains_key(id),
+            tenants: self.tenants.values().any(|ids| ids.contains(id)),
+            modules: self.modules.values().any(|module| module.owns(id)),
+            groups: self.groups.values().any(|ids| ids.contains(id)),
+            ready: self.ready.contains(id),
+            health_checks: self.health_checks.contains_key(id),
+            runnables: self.runnables.contains_key(id),
+            allowed_callers: self.allowed_callers.contains_key(id),
+            deprecations: self.deprecations.contains_key(id),
+            breakers: self.breakers.contains_key(id),
+            script_adapters: self.script_adapters.contains_key(id),
+            dependencies: self.dependencies.contains_key(id)
+                || self.dependencies.values().any(|ids| ids.contains(id)),
+            preheating: self.preheating.contains_key(id),
+            inline: self.inline.contains_key(id),
+            instances: self.instances.contains_key(id),
+            initializing: self.initializing.contains_key(id),
+            awaiting: self.awaiting.values().any(|awaited| awaited == id),
+            mailboxes: self.mailboxes.keys().any(|(service, _)| service == id),
+            drains: self.drains.contains_key(id),
+            lifecycles: self.lifecycles.contains_key(id),
+            started: self.started.contains(id),
+            unsync_factories: self.unsync_factories.contains(id),
+            downcasts: self.downcasts.values().any(|(downcast, _, _)| downcast == id),
+            priorities: self.priorities.contains_key(id),
+            origins: self.origins.contains_key(id),
+        );
+        held
+    }
+
+    /// Failing if any of the services is borrowed or frozen.
+    fn check_removable(&self, services: &[(Uuid, String)]) -> Result<()> {
+        for (id, alias) in services {
+            self.check_unfrozen(id, alias)?;
+            if let Some(state) = self.borrows.get(id) {
+                state.check_unborrowed(alias)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Removing the registrations in the given order, returning the services removed so they
+    /// can be dropped after releasing the lock.
+    /// Nothing is removed if any of the services is borrowed or frozen.
+    fn teardown(
+        &mut self,
+        services: &[(Uuid, String)],
+        location: &'static Location<'static>,
+    ) -> Result<Vec<Service>> {
+        self.check_removable(services)?;
+        let mut torn_down = Vec::with_capacity(services.len());
+        for (id, alias) in services {
+            torn_down.extend(self.drop_instance(id));
+            self.remove_alias(alias);
+            self.record(Operation::Remove, alias, Some(location));
+        }
+        Ok(torn_down)
+    }
+
+    /// Finding the alias linked to the id.
+    pub(crate) fn alias_of(&self, id: &Uuid) -> Option<&str> {
+        self.alias
+            .iter()
+            .find(|(_, alias_id)| *alias_id == id)
+            .map(|(alias, _)| alias.as_str())
+    }
+
+    fn singleton_set(&mut self, id: Uuid, service: Service) -> Result<*mut dyn Any> {
+        Ok(self.insert_instance(id, service))
+    }
+
+    fn singleton_factory_set(&mut self, id: Uuid, factory: Factory) -> Result<()> {
+        self.singleton_factories.insert(id, factory);
+        if self.singleton_factories.contains_key(&id) {
+            Ok(())
+        } else {
+            Err(Error::FailedToStoreFactory(
+                self.alias_of(&id).unwrap_or_default().into(),
+            ))
+        }
+    }
+}
+
+impl Default for SingletonManager {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl SingletonManager {
-    fn new() -> SingletonManager {
+    /// Create a new, empty, singleton manager using random ids.
+    /// Note that this is not the global instance, for that use `SingletonManager::instance()`.
+    pub fn new() -> SingletonManager {
+        Self::with_id_generator(RandomIdGenerator)
+    }
+
+    /// Create a new, empty, singleton manager using the given `IdGenerator` for the ids linking
+    /// the aliases to the singleton storage.
+    /// ```
+    /// use singleton_manager::{SingletonManager, NameBasedIdGenerator};
+    ///
+    /// let manager = SingletonManager::with_id_generator(NameBasedIdGenerator::default());
+    /// ```
+    pub fn with_id_generator(id_generator: impl IdGenerator + 'static) -> SingletonManager {
+        Self::with_clock_and_ids(SystemClock::new(id_generator))
+    }
+
+    fn with_shared_clock(clock_and_ids: SharedClock) -> SingletonManager {
         SingletonManager {
-            singletons: HashMap::new(),
-            singleton_factories: HashMap::new(),
-            // instance_type: HashMap::new(),
-            alias: HashMap::new(),
+            registry: RwLock::new(Registry {
+                clock: clock_and_ids.clone(),
+                ..Registry::default()
+            }),
+            clock_and_ids,
+            instance_id: NEXT_INSTANCE_ID.fetch_add(1, Ordering::Relaxed),
         }
     }
 
@@ -170,14 +1096,10 @@ impl SingletonManager {
     /// let sm = SingletonManager::instance();
     /// ```
     /// A simple way to get the singleton manager
+    ///
+    /// This panics if creating the instance fails, see `try_instance` to handle the failure.
     pub fn instance() -> &'static mut SingletonManager {
-        unsafe {
-            ONCE.call_once(|| INSTANCE = Cell::new(Some(SingletonManager::new())));
-            match *INSTANCE.as_ptr() {
-                Some(ref mut messenger) => messenger,
-                None => panic!("Failed to get instance"),
-            }
-        }
+        Self::try_instance_mut().unwrap_or_else(|e| panic!("Failed to get instance: {}", e))
     }
 
     /// Implementation of provider sets
@@ -192,6 +1114,11 @@ impl SingletonManager {
     /// storage or create the service in its own storage before serving it. Giving the
     /// Singleton Manager total access over when a service should be created and reused.
     ///
+    /// Before the service is created, its dependencies have to be registered, or this fails with
+    /// `Error::MissingDependency`, and its configuration is pulled from the registry if it has
+    /// one. The dependencies are declared on the service together with setting it, see
+    /// `depends_on`, and if one of them would create a cycle the service is not set.
+    ///
     /// Usage:
     /// ```
     /// use singleton_manager::{SingletonManager, SingletonProvider};
@@ -217,6 +1144,7 @@ impl SingletonManager {
     /// impl SingletonProvider for MyService {
     ///     type Output = MyService;
     ///     type Error = String;
+    ///     type Config = String;
     ///
     ///     fn service() -> Result<&'static mut Self::Output, Self::Error> {
     ///         SingletonManager::instance().get::<Self::Output>("my_service").map_err(|_| "err".to_string())
@@ -226,22 +1154,83 @@ impl SingletonManager {
     ///         "my_service"
     ///     }
     ///
-    ///     fn get_service(&self) -> Result<Self::Output, Self::Error> {
+    ///     fn config_name() -> Option<&'static str> {
+    ///         Some("my_service_greeting")
+    ///     }
+    ///
+    ///     fn get_service(&self, greeting: Option<&String>) -> Result<Self::Output, Self::Error> {
     ///         Ok(MyService{
-    ///             message: "".to_string(),
+    ///             message: greeting.cloned().unwrap_or_default(),
     ///             guard: Mutex::new(()),
     ///         })
     ///     }
     /// }
     ///
+    /// SingletonManager::instance().set("my_service_greeting", "hello".to_string()).unwrap();
     /// SingletonManager::instance().provide(MyService {
     ///     message: "".to_string(),
     ///     guard: Mutex::new(()),
-    /// });
+    /// }).unwrap();
+    /// assert_eq!("hello", MyService::service().unwrap().get());
     /// ```
-    pub fn provide(&'static mut self, sp: impl SingletonProvider) -> Result<()> {
-        let t = sp.get_service().map_err(|e| e.into())?;
-        self.set(sp.get_name(), t).map(|_| ())
+    #[track_caller]
+    pub fn provide<P: SingletonProvider>(&mut self, sp: P) -> Result<()> {
+        let name = sp.get_name();
+        if let Some(missing) = P::dependencies()
+            .iter()
+            .find(|dependency| !self.has(dependency))
+        {
+            return Err(Error::MissingDependency(name.into(), missing.to_string()));
+        }
+        let t = match P::config_name() {
+            Some(config_name) => {
+                let config = self.get_ref_by_alias::<P::Config>(config_name)?;
+                sp.get_service(Some(&config))
+            }
+            None => sp.get_service(None),
+        }
+        .map_err(|e| e.into())?;
+        let mut registry = self.registry_mut()?;
+        let stored = registry.store_service(
+            NewAlias::Static(name),
+            Box::new(t),
+            std::any::type_name::<P::Output>(),
+            &[],
+            Location::caller(),
+        )?;
+        let id = registry
+            .resolve(name)
+            .ok_or_else(|| Error::FailedToStoreService(name.into()))?;
+        let dependencies = P::dependencies()
+            .iter()
+            .map(|dependency| match registry.resolve(dependency) {
+                Some(dependency_id)
+                    if dependency_id == id || registry.depends_on(&dependency_id, &id) =>
+                {
+                    Err(Error::DependencyCycle(name.into(), dependency.to_string()))
+                }
+                Some(dependency_id) => Ok(dependency_id),
+                None => Err(Error::MissingDependency(
+                    name.into(),
+                    dependency.to_string(),
+                )),
+            })
+            .collect::<Result<Vec<_>>>();
+        match dependencies {
+            Ok(dependencies) => dependencies
+                .into_iter()
+                .for_each(|dependency_id| registry.add_dependency(id, dependency_id)),
+            // A service set by this call is removed again, one kept by the collision policy is
+            // left as it was.
+            Err(e) => {
+                if stored.is_some() {
+                    registry.remove_alias(name);
+                }
+                return Err(e);
+            }
+        }
+        drop(registry);
+        self.stored::<P::Output>(name, stored).map(drop)
     }
 
     /// get with default,
@@ -250,24 +1239,34 @@ impl SingletonManager {
     /// If the singleton does not exist it will automatically create it from the default factory
     /// function and then store the build singleton.
     ///
-    pub fn get_default<T: 'static, F: 'static>(
-        &self,
+    #[track_caller]
+    pub fn get_default<T: 'static, F>(
+        &mut self,
         service_name: &str,
         factory: F,
-    ) -> Result<&'static mut T>
+    ) -> std::result::Result<&mut T, GetError>
     where
-        F: Fn() -> Box<dyn Any>,
+        F: 'static + Fn() -> Box<dyn Any + Send + Sync> + Send + Sync,
     {
         if !self.has(service_name) {
-            SingletonManager::instance()
-                .set_factory(service_name, factory)
+            self.store_factory_with_default_timeout(service_name, factory, Location::caller())
                 .ok();
         }
-        sm().get::<T>(service_name)
+        self.get_by_alias::<T>(service_name)
     }
 
     pub fn has(&self, service_name: &str) -> bool {
-        self.alias.contains_key(service_name)
+        self.registry()
+            .map(|registry| registry.resolve(service_name).is_some())
+            .unwrap_or(false)
+    }
+
+    /// Getting the id linking the alias to the singleton storage.
+    /// The id is created by the `IdGenerator` of the manager when the alias is stored.
+    pub fn service_id(&self, service_name: &str) -> Result<Uuid> {
+        self.registry()?
+            .resolve(service_name)
+            .ok_or_else(|| Error::ServiceDoesNotExist(service_name.into(), Default::default()))
     }
 
     /// Getting a singleton from the singleton manager.
@@ -292,116 +1291,656 @@ impl SingletonManager {
     ///
     /// this will give you the `my_service` that have been set previously.
     /// A full example of its usage can be found here:
-    pub fn get<T: 'static>(&'static mut self, service_name: &str) -> Result<&'static mut T> {
-        SingletonManager::instance()
-            .alias
-            .get(service_name)
-            .ok_or_else(|| Error::ServiceDoesNotExist(service_name.to_string()))
-            .and_then(|id| sm().singleton_get(id))
-            .and_then(|service_box| {
-                service_box
-                    .downcast_mut::<T>()
-                    .ok_or_else(|| Error::FailedToDowncastRefOfService(service_name.to_string()))
-            })
+    ///
+    /// The reference returned is not tracked, but if the service is currently borrowed through
+    /// `borrow` or `borrow_mut` this will fail with `GetError::AlreadyBorrowed`.
+    #[track_caller]
+    #[cfg_attr(feature = "strict-keys", deprecated(note = "use `get_by_key` instead"))]
+    pub fn get<T: 'static>(&mut self, service_name: &str) -> std::result::Result<&mut T, GetError> {
+        self.get_by_alias(service_name)
+    }
+
+    /// Getting a singleton by a raw alias as `get` does, without the deprecation of `strict-keys`
+    /// for the lookups of the crate itself.
+    #[track_caller]
+    pub(crate) fn get_by_alias<T: 'static>(
+        &mut self,
+        service_name: &str,
+    ) -> std::result::Result<&mut T, GetError> {
+        let location = Location::caller();
+        self.end_raw_references();
+        self.unchecked_get::<T>(service_name, location)
+            .map(|service| unsafe { &mut *service })
+            .map_err(|e| GetError::from_error(e, service_name))
+    }
+
+    /// Getting a pointer to the singleton, after checking it is neither frozen nor borrowed.
+    fn unchecked_get<T: 'static>(
+        &self,
+        service_name: &str,
+        location: &'static Location<'static>,
+    ) -> Result<*mut T> {
+        let id = self.serving_id(service_name, location)?;
+        self.unchecked_get_id(&id, service_name, location)
+    }
+
+    /// Getting a pointer to the singleton served for the alias, as `unchecked_get` does.
+    fn unchecked_get_id<T: 'static>(
+        &self,
+        id: &Uuid,
+        service_name: &str,
+        location: &'static Location<'static>,
+    ) -> Result<*mut T> {
+        let id = *id;
+        let epoch = {
+            let registry = self.registry()?;
+            registry.check_caller(&id, service_name, location)?;
+            registry.check_unfrozen(&id, service_name)?;
+            registry.raw_epoch
+        };
+        let state = self.borrow_state(&id, service_name)?;
+        state.check_unborrowed(service_name)?;
+        self.singleton_get(&id, service_name)?;
+        let service = self.cached_downcast::<T>(&id, service_name, location, || {
+            state.alias_for(service_name)
+        })?;
+        self.note_access::<T>(&id, location);
+        state.note_raw_reference(epoch);
+        Ok(service)
+    }
+
+    /// Getting an optional singleton from the singleton manager.
+    /// Works like `get`, but returns `None` if no service is registered under the alias, for
+    /// dependencies that are not required. A service that exists but has a different type is
+    /// still an error, as is a factory failing to create the service.
+    /// ```
+    /// use singleton_manager::SingletonManager;
+    ///
+    /// let mut manager = SingletonManager::new();
+    /// manager.set("metrics", 1_u32).unwrap();
+    ///
+    /// assert_eq!(Some(&1), manager.get_optional::<u32>("metrics").unwrap());
+    /// assert_eq!(None, manager.get_optional::<u32>("tracing").unwrap());
+    /// assert!(manager.get_optional::<String>("metrics").is_err());
+    /// ```
+    #[track_caller]
+    pub fn get_optional<T: 'static>(
+        &mut self,
+        service_name: &str,
+    ) -> std::result::Result<Option<&T>, GetError> {
+        // Only the alias itself missing is absent, a service missing while getting it, e.g. in
+        // its factory, is an error. The absent alias is not counted as a failed lookup.
+        let location = Location::caller();
+        self.end_raw_references();
+        let id = match self.optional_serving_id(service_name, location) {
+            Ok(Some(id)) => id,
+            Ok(None) => return Ok(None),
+            Err(e) => return Err(GetError::from_error(e, service_name)),
+        };
+        self.unchecked_get_id::<T>(&id, service_name, location)
+            .map(|service| Some(unsafe { &*service }))
+            .map_err(|e| GetError::from_error(e, service_name))
+    }
+
+    /// Borrowing a singleton from the singleton manager.
+    /// Works like `RefCell::borrow`, any number of shared borrows can be held at the same time,
+    /// but borrowing while the service is mutably borrowed will fail with
+    /// `GetError::AlreadyBorrowed`, containing the location of the code holding the mutable borrow.
+    /// ```
+    /// use singleton_manager::SingletonManager;
+    ///
+    /// let mut manager = SingletonManager::new();
+    /// manager.set_factory("my_service", || Box::new("hello".to_string())).unwrap();
+    ///
+    /// let service = manager.borrow::<String>("my_service").unwrap();
+    /// assert_eq!("hello", service.as_str());
+    /// ```
+    #[track_caller]
+    #[cfg_attr(
+        feature = "strict-keys",
+        deprecated(note = "use `borrow_by_key` instead")
+    )]
+    pub fn borrow<T: 'static>(
+        &self,
+        service_name: &str,
+    ) -> std::result::Result<ServiceRef<'_, T>, GetError> {
+        self.borrow_by_alias(service_name)
+    }
+
+    /// Borrowing a singleton by a raw alias as `borrow` does, without the deprecation.
+    #[track_caller]
+    pub(crate) fn borrow_by_alias<T: 'static>(
+        &self,
+        service_name: &str,
+    ) -> std::result::Result<ServiceRef<'_, T>, GetError> {
+        let location = Location::caller();
+        self.shared_borrow(service_name, location, |state| {
+            state.try_borrow(service_name, location)
+        })
+        .map_err(|e| GetError::from_error(e, service_name))
+    }
+
+    /// Getting a shared reference to a singleton from the singleton manager.
+    /// Works like `RwLock::read`, any number of readers can hold a reference at the same time, even
+    /// across threads, but while the service is mutably borrowed this will block until the
+    /// mutable borrow is released.
+    ///
+    /// Blocking on a service already mutably borrowed by the current thread will deadlock, use
+    /// `borrow` to get an error instead.
+    /// ```
+    /// use singleton_manager::SingletonManager;
+    ///
+    /// let mut manager = SingletonManager::new();
+    /// manager.set_factory("my_service", || Box::new("hello".to_string())).unwrap();
+    ///
+    /// let first = manager.get_ref::<String>("my_service").unwrap();
+    /// let second = manager.get_ref::<String>("my_service").unwrap();
+    /// assert_eq!(*first, *second);
+    /// ```
+    #[track_caller]
+    #[cfg_attr(
+        feature = "strict-keys",
+        deprecated(note = "use `get_ref_by_key` instead")
+    )]
+    pub fn get_ref<T: 'static>(
+        &self,
+        service_name: &str,
+    ) -> std::result::Result<ServiceRef<'_, T>, GetError> {
+        self.get_ref_by_alias(service_name)
+    }
+
+    /// Getting a shared reference by a raw alias as `get_ref` does, without the deprecation.
+    #[track_caller]
+    pub(crate) fn get_ref_by_alias<T: 'static>(
+        &self,
+        service_name: &str,
+    ) -> std::result::Result<ServiceRef<'_, T>, GetError> {
+        let location = Location::caller();
+        #[cfg(feature = "tokio")]
+        self.lint_sync_guard(service_name, location);
+        self.shared_borrow(service_name, location, |state| {
+            state.wait_borrow(location);
+            Ok(())
+        })
+        .map_err(|e| GetError::from_error(e, service_name))
+    }
+
+    /// Getting a copy of a singleton from the singleton manager.
+    /// Meant for services that are cheap to clone, like configuration snapshots or `Arc` wrapped
+    /// pools, where the caller only needs an owned value and never a reference into the storage.
+    /// The service is shared borrowed while cloning, so this will fail with
+    /// `GetError::AlreadyBorrowed` if the service is currently mutably borrowed.
+    /// ```
+    /// use singleton_manager::SingletonManager;
+    /// use std::sync::Arc;
+    ///
+    /// let mut manager = SingletonManager::new();
+    /// manager.set_factory("config", || Box::new(Arc::new("production".to_string()))).unwrap();
+    ///
+    /// let config = manager.get_cloned::<Arc<String>>("config").unwrap();
+    /// assert_eq!("production", config.as_str());
+    /// ```
+    #[track_caller]
+    pub fn get_cloned<T: 'static + Clone>(
+        &self,
+        service_name: &str,
+    ) -> std::result::Result<T, GetError> {
+        self.borrow_by_alias::<T>(service_name)
+            .map(|service| T::clone(&service))
+    }
+
+    /// Mutably borrowing a singleton from the singleton manager.
+    /// Works like `RefCell::borrow_mut`, only a single mutable borrow can be held at the time, and
+    /// only when no shared borrows are held. A conflicting borrow will fail with
+    /// `GetError::AlreadyBorrowed`, containing the location of the code holding the other borrow.
+    /// ```
+    /// use singleton_manager::{GetError, SingletonManager};
+    ///
+    /// let mut manager = SingletonManager::new();
+    /// manager.set_factory("my_service", || Box::new("hello".to_string())).unwrap();
+    ///
+    /// let mut service = manager.borrow_mut::<String>("my_service").unwrap();
+    /// service.push_str(" world");
+    ///
+    /// assert!(matches!(
+    ///     manager.borrow::<String>("my_service"),
+    ///     Err(GetError::AlreadyBorrowed(_, _))
+    /// ));
+    /// ```
+    #[track_caller]
+    #[cfg_attr(
+        feature = "strict-keys",
+        deprecated(note = "use `borrow_mut_by_key` instead")
+    )]
+    pub fn borrow_mut<T: 'static>(
+        &self,
+        service_name: &str,
+    ) -> std::result::Result<ServiceRefMut<'_, T>, GetError> {
+        let location = Location::caller();
+        self.exclusive_borrow(service_name, location, |state| {
+            state.try_borrow_mut(service_name, location)
+        })
+        .map_err(|e| GetError::from_error(e, service_name))
+    }
+
+    /// Getting an exclusive reference to a singleton from the singleton manager.
+    /// Works like `RwLock::write`, this will block until all other borrows of the service are
+    /// released, and while the reference is held all other borrows will either block or fail.
+    ///
+    /// Blocking on a service already borrowed by the current thread will deadlock, use
+    /// `borrow_mut` to get an error instead.
+    /// ```
+    /// use singleton_manager::SingletonManager;
+    ///
+    /// let mut manager = SingletonManager::new();
+    /// manager.set_factory("my_service", || Box::new("hello".to_string())).unwrap();
+    ///
+    /// manager.get_mut::<String>("my_service").unwrap().push_str(" world");
+    /// assert_eq!("hello world", *manager.get_ref::<String>("my_service").unwrap());
+    /// ```
+    #[track_caller]
+    #[cfg_attr(
+        feature = "strict-keys",
+        deprecated(note = "use `get_mut_by_key` instead")
+    )]
+    pub fn get_mut<T: 'static>(
+        &self,
+        service_name: &str,
+    ) -> std::result::Result<ServiceRefMut<'_, T>, GetError> {
+        self.get_mut_by_alias(service_name)
+    }
+
+    /// Getting an exclusive reference by a raw alias as `get_mut` does, without the deprecation.
+    #[track_caller]
+    pub(crate) fn get_mut_by_alias<T: 'static>(
+        &self,
+        service_name: &str,
+    ) -> std::result::Result<ServiceRefMut<'_, T>, GetError> {
+        let location = Location::caller();
+        #[cfg(feature = "tokio")]
+        self.lint_sync_guard(service_name, location);
+        self.exclusive_borrow(service_name, location, |state| {
+            state.wait_borrow_mut(location);
+            Ok(())
+        })
+        .map_err(|e| GetError::from_error(e, service_name))
+    }
+
+    fn shared_borrow<T: 'static>(
+        &self,
+        service_name: &str,
+        location: &'static Location<'static>,
+        acquire: impl FnOnce(&BorrowState) -> Result<()>,
+    ) -> Result<ServiceRef<'_, T>> {
+        let id = self.serving_id(service_name, location)?;
+        self.shared_borrow_id(&id, service_name, location, acquire)
+    }
+
+    /// Borrowing the service by its id, as `shared_borrow` does by its alias.
+    fn shared_borrow_id<T: 'static>(
+        &self,
+        id: &Uuid,
+        service_name: &str,
+        location: &'static Location<'static>,
+        acquire: impl FnOnce(&BorrowState) -> Result<()>,
+    ) -> Result<ServiceRef<'_, T>> {
+        self.check_caller(id, service_name, location)?;
+        // Instantiating before borrowing, as the factory may borrow other services.
+        self.singleton_get(id, service_name)?;
+        let state = self.borrow_state(id, service_name)?;
+        acquire(&state)?;
+        // Getting the instance again once borrowed, as it may have been replaced meanwhile.
+        let service = self
+            .singleton_get(id, service_name)
+            .inspect_err(|_| state.release(location))?;
+        match unsafe { downcast_ref::<T>(service, || state.alias_for(service_name)) } {
+            Ok(service) => {
+                self.note_access::<T>(id, location);
+                Ok(ServiceRef::new(service, state, location))
+            }
+            Err(e) => {
+                state.release(location);
+                Err(e)
+            }
+        }
+    }
+
+    fn exclusive_borrow<T: 'static>(
+        &self,
+        service_name: &str,
+        location: &'static Location<'static>,
+        acquire: impl FnOnce(&BorrowState) -> Result<()>,
+    ) -> Result<ServiceRefMut<'_, T>> {
+        let id = self.serving_id(service_name, location)?;
+        self.exclusive_borrow_id(&id, service_name, location, acquire)
+    }
+
+    /// Mutably borrowing the service by its id, as `exclusive_borrow` does by its alias.
+    fn exclusive_borrow_id<T: 'static>(
+        &self,
+        id: &Uuid,
+        service_name: &str,
+        location: &'static Location<'static>,
+        acquire: impl FnOnce(&BorrowState) -> Result<()>,
+    ) -> Result<ServiceRefMut<'_, T>> {
+        self.check_caller(id, service_name, location)?;
+        self.registry()?.check_unfrozen(id, service_name)?;
+        self.singleton_get(id, service_name)?;
+        let state = self.borrow_state(id, service_name)?;
+        acquire(&state)?;
+        let service = self
+            .singleton_get(id, service_name)
+            .inspect_err(|_| state.release_mut())?;
+        match unsafe { downcast_mut::<T>(service, || state.alias_for(service_name)) } {
+            Ok(service) => {
+                self.note_access::<T>(id, location);
+                Ok(ServiceRefMut::new(service, state))
+            }
+            Err(e) => {
+                state.release_mut();
+                Err(e)
+            }
+        }
     }
 
     /// Setting a specific service/object as a singleton.
     /// This is used when setting a service or other to a singleton.
-    pub fn set<T: 'static>(&self, service_name: &str, service: T) -> Result<&'static mut T> {
-        sm().store_alias(service_name).and_then(|id| {
-            sm().singleton_set(id, Box::new(service))
-                .and_then(|service_box| {
-                    service_box.downcast_mut::<T>().ok_or_else(|| {
-                        Error::FailedToDowncastRefOfService(service_name.to_string())
-                    })
-                })
-        })
+    #[track_caller]
+    pub fn set<T: Send + Sync + 'static>(
+        &mut self,
+        service_name: &str,
+        service: T,
+    ) -> std::result::Result<&mut T, SetError> {
+        self.set_with_meta(service_name, service, &[])
+    }
+
+    /// Setting a singleton together with key/value tags describing it.
+    /// The tags can be used to find services by attribute, see `find`.
+    #[track_caller]
+    pub fn set_with_meta<T: Send + Sync + 'static>(
+        &mut self,
+        service_name: &str,
+        service: T,
+        tags: &[(&str, &str)],
+    ) -> std::result::Result<&mut T, SetError> {
+        let location = Location::caller();
+        self.end_raw_references();
+        let service = self
+            .store::<T>(service_name, service, tags, location)
+            .map_err(|e| SetError::from_error(e, service_name))?;
+        self.note_raw_reference(service_name);
+        Ok(unsafe { &mut *service })
+    }
+
+    /// Storing the service together with its tags, returning a pointer to the stored service.
+    fn store<'a, T: Send + Sync + 'static>(
+        &self,
+        service_name: impl Into<NewAlias<'a>>,
+        service: T,
+        tags: &[(&str, &str)],
+        location: &'static Location<'static>,
+    ) -> Result<*mut T> {
+        let service_name = service_name.into();
+        let stored = self.registry_mut()?.store_service(
+            service_name,
+            Box::new(service),
+            std::any::type_name::<T>(),
+            tags,
+            location,
+        )?;
+        self.stored::<T>(service_name.as_str(), stored)
     }
 
-    pub fn set_factory<F: 'static + Fn() -> Box<dyn Any>>(
+    /// Getting the pointer to the service stored by `Registry::store_service`, or to the
+    /// registration kept instead of it.
+    fn stored<T: 'static>(
         &self,
         service_name: &str,
+        stored: Option<*mut dyn Any>,
+    ) -> Result<*mut T> {
+        let service = match stored {
+            Some(service) => service,
+            None => self.singleton_get(&self.service_id(service_name)?, service_name)?,
+        };
+        unsafe { downcast_mut::<T>(service, || service_name.into()) }
+            .map(|service| service as *mut T)
+    }
+
+    /// Registering the factory of the service, returning the factory stored.
+    /// Registering through a shared reference, e.g. of a manager shared across threads, is done
+    /// with `set_send_factory`.
+    #[track_caller]
+    pub fn set_factory<F: 'static + Fn() -> Box<dyn Any + Send + Sync> + Send + Sync>(
+        &mut self,
+        service_name: &str,
         factory: F,
-    ) -> Result<&'static mut Box<dyn Fn() -> Box<dyn Any>>> {
-        sm().store_alias(service_name)
-            .and_then(|id| sm().singleton_factory_set(&id, Box::new(factory)))
+    ) -> std::result::Result<&mut Factory, SetError> {
+        self.store_factory_with_default_timeout(service_name, factory, Location::caller())
+            .and_then(move |_| self.registry.get_mut().map_err(|_| Error::MutexGotPoison))
+            .and_then(|registry| {
+                let id = registry.resolve(service_name).ok_or_else(|| {
+                    Error::ServiceDoesNotExist(service_name.into(), Default::default())
+                })?;
+                registry
+                    .singleton_factories
+                    .get_mut(&id)
+                    .ok_or_else(|| Error::FailedToStoreFactory(service_name.into()))
+            })
+            .map_err(|e| SetError::from_error(e, service_name))
     }
 
-    fn store_alias(&self, alias: &str) -> Result<Uuid> {
-        if sm().alias.contains_key(alias) {
-            Err(Error::ServiceAlreadyExists)
-        } else {
-            sm().alias.insert(alias.to_string(), Uuid::new_v4());
-            if let Some(id) = sm().alias.get(alias) {
-                Ok(*id)
-            } else {
-                Err(Error::FailedToStoreServiceAlias)
+    /// Storing the factory, returning false if the registration already there is kept.
+    fn store_factory<F: 'static + Fn() -> Box<dyn Any + Send + Sync> + Send + Sync>(
+        &self,
+        service_name: &str,
+        factory: F,
+        location: &'static Location<'static>,
+    ) -> Result<bool> {
+        self.registry_mut()?
+            .store_factory(service_name, Arc::new(factory), location)
+    }
+
+    /// Taking a singleton out of the singleton manager.
+    /// This removes the registration and hands over the ownership of the service, e.g. to move
+    /// a listener socket out of the manager into a dedicated task at shutdown.
+    ///
+    /// This fails with `GetError::ServiceNotInstantiated` if only a factory exists, and with
+    /// `GetError::AlreadyBorrowed` if the service is currently borrowed. If the service is not of
+    /// the type requested it is kept in the singleton manager.
+    /// ```
+    /// use singleton_manager::SingletonManager;
+    ///
+    /// let mut manager = SingletonManager::new();
+    /// manager.set("listener", "127.0.0.1:8080".to_string()).unwrap();
+    ///
+    /// let listener = manager.take::<String>("listener").unwrap();
+    /// assert_eq!("127.0.0.1:8080", listener);
+    /// assert!(!manager.has("listener"));
+    /// ```
+    #[track_caller]
+    pub fn take<T: 'static>(&mut self, service_name: &str) -> std::result::Result<T, GetError> {
+        self.take_at(service_name, Location::caller())
+            .map_err(|e| GetError::from_error(e, service_name))
+    }
+
+    /// Taking a singleton out of the singleton manager as `take` does, for the caller location.
+    fn take_at<T: 'static>(
+        &mut self,
+        service_name: &str,
+        location: &'static Location<'static>,
+    ) -> Result<T> {
+        let mut registry = self.registry_mut()?;
+        let id = registry
+            .resolve(service_name)
+            .ok_or_else(|| Error::ServiceDoesNotExist(service_name.into(), Default::default()))?;
+        registry.check_unfrozen(&id, service_name)?;
+        if let Some(state) = registry.borrows.get(&id) {
+            state.check_unborrowed(service_name)?;
+        }
+        match registry.singletons.get(&id) {
+            None => return Err(Error::ServiceNotInstantiated(service_name.into())),
+            Some(service) if !ThreadBound::inner(service.as_ref()).is::<T>() => {
+                return Err(Error::FailedToDowncastRefOfService(service_name.into()))
             }
+            Some(service) => ThreadBound::check_thread(service.as_ref(), service_name)?,
+        }
+        let service = registry
+            .take_instance(&id)
+            .ok_or_else(|| Error::ServiceNotInstantiated(service_name.into()))?;
+        if let Some(alias) = registry.alias_of(&id).map(str::to_string) {
+            registry.remove_alias(&alias);
         }
+        registry.record(Operation::Take, service_name, Some(location));
+        ThreadBound::into_inner(service)
+            .downcast::<T>()
+            .map(|service| *service)
+            .map_err(|_| Error::FailedToDowncastRefOfService(service_name.into()))
     }
 
-    fn singleton_get(&'static mut self, alias: &Uuid) -> Result<&mut Box<dyn Any>> {
-        sm().singletons
-            .get_mut(alias)
-            .ok_or_else(|| Error::ServiceDoesNotExist(alias.to_string()))
-            .or_else(|_| {
-                if sm().singleton_factories.contains_key(alias) {
-                    sm().factory(alias)
-                } else {
-                    Err(Error::ServiceDoesNotExist(alias.to_string()))
-                }
-            })
+    fn registry(&self) -> Result<RwLockReadGuard<'_, Registry>> {
+        self.registry.read().map_err(|_| Error::MutexGotPoison)
     }
 
-    fn singleton_set(&self, id: Uuid, service: Box<dyn Any>) -> Result<&'static mut Box<dyn Any>> {
-        sm().singletons.insert(id, service);
-        if sm().singletons.contains_key(&id) {
-            sm().singletons
-                .get_mut(&id)
-                .ok_or_else(|| Error::FailedToStoreService(id.to_string()))
-        } else {
-            Err(Error::ServiceAlreadyExists)
+    fn registry_mut(&self) -> Result<RegistryMut<'_>> {
+        self.registry
+            .write()
+            .map(RegistryMut::new)
+            .map_err(|_| Error::MutexGotPoison)
+    }
+
+    /// The borrow state of the service, failing with `Error::ServiceDoesNotExist` for the alias
+    /// it was looked up by if the service got removed meanwhile.
+    fn borrow_state(&self, id: &Uuid, service_name: &str) -> Result<Arc<BorrowState>> {
+        self.registry()?
+            .borrows
+            .get(id)
+            .cloned()
+            .ok_or_else(|| Error::ServiceDoesNotExist(service_name.into(), Default::default()))
+    }
+
+    /// Noting the access of the service as the type `T`, for the diagnostics and the
+    /// instrumentation.
+    fn note_access<T: 'static>(&self, id: &Uuid, location: &'static Location<'static>) {
+        if let Ok(registry) = self.registry() {
+            // Without metrics, the retrievals are not recorded, only the type of the service.
+            let noted = match registry.config.metrics {
+                true => registry.retrieved.contains(id),
+                false => registry.type_names.contains_key(id),
+            };
+            if noted
+                && !registry.call_sites.contains_key(id)
+                && !registry.is_recording()
+                && !registry.needs_deprecation_warning(id, location)
+            {
+                return;
+            }
+        }
+        if let Ok(mut registry) = self.registry_mut() {
+            if registry.config.metrics {
+                registry.retrieved.insert(*id);
+            }
+            registry.type_names.insert(*id, std::any::type_name::<T>());
+            registry.warn_deprecated(id, location);
+            if let Some(call_sites) = registry.call_sites.get_mut(id) {
+                *call_sites.entry(location).or_default() += 1;
+            }
+            if registry.is_recording() {
+                if let Some(alias) = registry.alias_of(id).map(str::to_string) {
+                    let type_name = Some(std::any::type_name::<T>());
+                    registry.record_call(CallKind::Resolve, &alias, type_name, location);
+                }
+            }
         }
     }
 
-    fn singleton_factory_set<F: 'static + Fn() -> Box<dyn Any>>(
-        &self,
-        id: &Uuid,
-        factory: Box<F>,
-    ) -> Result<&'static mut Box<dyn Fn() -> Box<dyn Any>>> {
-        sm().singleton_factories.insert(*id, factory);
-        if self.singleton_factories.contains_key(&id) {
-            sm().singleton_factories
-                .get_mut(&id)
-                .ok_or(Error::FailedToStoreFactory)
-        } else {
-            Err(Error::FailedToStoreFactory)
+    /// Getting a pointer to the singleton, creating it from the factory if needed.
+    /// The pointer stays valid for as long as the singleton is kept in the storage.
+    /// Fails with `Error::ServiceDoesNotExist` for the alias it was looked up by if the service
+    /// got removed meanwhile.
+    fn singleton_get(&self, alias: &Uuid, service_name: &str) -> Result<*mut dyn Any> {
+        let registry = self.registry()?;
+        if let Some(service) = registry.instance_ptr(alias) {
+            // The service is kept in the storage while the registry is locked.
+            return unsafe {
+                ThreadBound::checked(service, registry.alias_of(alias).unwrap_or_default())
+            };
         }
+        drop(registry);
+        self.factory(alias, service_name)
     }
 
-    fn factory(&'static mut self, alias: &Uuid) -> Result<&mut Box<dyn Any>> {
-        if let Some(box_func) = self.singleton_factories.get_mut(alias) {
-            sm().execute_factory(box_func)
-                .map(|service| self.singletons.insert(*alias, service))
-                .ok();
-            if self.singletons.contains_key(alias) {
-                sm().singletons
-                    .get_mut(alias)
-                    .ok_or_else(|| Error::ServiceDoesNotExist(alias.to_string()))
-            } else {
-                Err(Error::ServiceDoesNotExist(alias.to_string()))
+    /// Creating the singleton from its factory.
+    /// The factory is executed without holding the lock, allowing the factory to get other
+    /// singletons from the manager, and only by one thread at the time.
+    fn factory(&self, alias: &Uuid, service_name: &str) -> Result<*mut dyn Any> {
+        let _flight = match self.begin_flight(alias)? {
+            Flight::Instantiated => return self.singleton_get(alias, service_name),
+            Flight::Run(guard) => guard,
+        };
+        #[cfg(feature = "chaos")]
+        {
+            let delay = {
+                let registry = self.registry()?;
+                registry
+                    .chaos
+                    .factory_delay(registry.alias_of(alias).unwrap_or_default())
+            };
+            std::thread::sleep(delay);
+        }
+        let (factory, preheated) = {
+            let registry = self.registry()?;
+            match registry.singleton_factories.get(alias) {
+                Some(factory) => (factory.clone(), registry.preheating.get(alias).cloned()),
+                None if registry.reservations.contains_key(alias) => {
+                    return Err(Error::ServiceNotInstantiated(
+                        registry.alias_of(alias).unwrap_or_default().into(),
+                    ))
+                }
+                None if registry.inline.contains_key(alias) => {
+                    return Err(Error::FailedToDowncastRefOfService(
+                        registry.alias_of(alias).unwrap_or_default().into(),
+                    ))
+                }
+                None if registry.memoized.contains_key(alias) => {
+                    return Err(Error::NoFactoryFunctionAvailable(
+                        registry.alias_of(alias).unwrap_or_default().into(),
+                    ))
+                }
+                None => {
+                    return Err(Error::ServiceDoesNotExist(
+                        service_name.into(),
+                        Default::default(),
+                    ))
+                }
+            }
+        };
+        let circuit = self.enter_circuit(alias)?;
+        let service = match preheated.and_then(|slot| slot.take()) {
+            Some(service) => service,
+            None => Self::execute_factory(factory.as_ref())?,
+        };
+        let mut registry = self.registry_mut()?;
+        registry.preheating.remove(alias);
+        if let Some(service_name) = registry.alias_of(alias).map(str::to_string) {
+            TimedOut::check(service.as_ref(), &service_name)?;
+            registry.validate(&service_name, alias, service.as_ref())?;
+            if !registry.singletons.contains_key(alias) {
+                registry.record(Operation::Instantiate, &service_name, None);
             }
-        } else {
-            Err(Error::NoFactoryFunctionAvailable(alias.to_string()))
         }
+        let service = match registry.instance_ptr(alias) {
+            Some(stored) => stored,
+            None => registry.insert_instance(*alias, service),
+        };
+        if let Some(circuit) = circuit {
+            circuit.close(&mut registry);
+        }
+        // The service is kept in the storage while the registry is locked.
+        unsafe { ThreadBound::checked(service, registry.alias_of(alias).unwrap_or_default()) }
     }
 
-    fn execute_factory(
-        &'static mut self,
-        factory: &mut Box<dyn Fn() -> Box<dyn Any>>,
-    ) -> Result<Box<dyn Any>> {
-        let func = factory.deref_mut();
-        let service = func();
+    fn execute_factory(factory: &dyn Fn() -> Service) -> Result<Service> {
+        let service = factory();
         Ok(service)
     }
 
@@ -412,12 +1951,53 @@ impl SingletonManager {
     // }
 }
 
+/// Downcasting a pointer into the singleton storage to a shared reference.
+///
+/// # Safety
+/// The pointer must be valid, and stay valid for the lifetime of the returned reference.
+unsafe fn downcast_ref<'a, T: 'static>(
+    service: *mut dyn Any,
+    service_name: impl FnOnce() -> Alias,
+) -> Result<&'a T> {
+    (*service)
+        .downcast_ref::<T>()
+        .ok_or_else(|| Error::FailedToDowncastRefOfService(service_name()))
+}
+
+/// Downcasting a pointer into the singleton storage to a mutable reference.
+///
+/// # Safety
+/// The pointer must be valid, and stay valid for the lifetime of the returned reference.
+unsafe fn downcast_mut<'a, T: 'static>(
+    service: *mut dyn Any,
+    service_name: impl FnOnce() -> Alias,
+) -> Result<&'a mut T> {
+    (*service)
+        .downcast_mut::<T>()
+        .ok_or_else(|| Error::FailedToDowncastRefOfService(service_name()))
+}
+
+/// A service providing itself to the singleton manager, see `SingletonManager::provide`.
 pub trait SingletonProvider {
-    type Output: 'static;
+    type Output: Send + Sync + 'static;
     type Error: Into<Error>;
+    /// The configuration of the service, pulled from the registry, `()` if it has none.
+    type Config: Send + Sync + 'static;
     fn service() -> std::result::Result<&'static mut Self::Output, Self::Error>;
     fn get_name(&self) -> &'static str;
-    fn get_service(&self) -> std::result::Result<Self::Output, Self::Error>;
+    /// The alias the configuration is registered under, if the service has one.
+    fn config_name() -> Option<&'static str> {
+        None
+    }
+    /// The aliases of the services that have to be registered before the service is created.
+    fn dependencies() -> &'static [&'static str] {
+        &[]
+    }
+    /// Creating the service, with its configuration if it has one.
+    fn get_service(
+        &self,
+        config: Option<&Self::Config>,
+    ) -> std::result::Result<Self::Output, Self::Error>;
 }
 
 pub fn sm() -> &'static mut SingletonManager {
@@ -430,7 +2010,7 @@ pub fn singleton_manager() -> &'static mut SingletonManager {
 
 #[cfg(test)]
 mod test {
-    use super::SingletonManager;
+    use super::{GetError, SingletonManager};
 
     use std::ops::Deref;
     use std::sync::Mutex;
@@ -506,7 +2086,7 @@ mod test {
         }));
         my_function
             .as_ref()
-            .ok_or_else(|| super::Error::NoFactoryFunctionAvailable(service_name.to_string()))
+            .ok_or_else(|| super::Error::NoFactoryFunctionAvailable(service_name.into()))
             .map(|f| (instance_name, f))
             .map(|(instance, factory)| {
                 let func = factory.deref();
@@ -577,4 +2157,283 @@ mod test {
 
         assert_eq!("My Message".to_string(), service.get());
     }
+
+    #[test]
+    fn test_get_cloned_returns_owned_copy() {
+        let mut manager = SingletonManager::new();
+        manager.set("config", vec![1, 2, 3]).unwrap();
+
+        let mut config = manager.get_cloned::<Vec<i32>>("config").unwrap();
+        config.push(4);
+
+        assert_eq!(
+            vec![1, 2, 3],
+            manager.get_cloned::<Vec<i32>>("config").unwrap()
+        );
+        assert!(manager.get_cloned::<String>("config").is_err());
+    }
+
+    #[test]
+    fn test_take_hands_over_ownership() {
+        let mut manager = SingletonManager::new();
+        manager.set("socket", "127.0.0.1:8080".to_string()).unwrap();
+        manager
+            .set_factory("factory_only", || Box::new(1_u32))
+            .unwrap();
+
+        assert!(matches!(
+            manager.take::<u32>("socket"),
+            Err(GetError::FailedToDowncastRefOfService(_))
+        ));
+        assert!(matches!(
+            manager.take::<u32>("factory_only"),
+            Err(GetError::ServiceNotInstantiated(_))
+        ));
+
+        assert_eq!("127.0.0.1:8080", manager.take::<String>("socket").unwrap());
+        assert!(!manager.has("socket"));
+        assert!(matches!(
+            manager.take::<String>("socket"),
+            Err(GetError::ServiceDoesNotExist(_, _))
+        ));
+        manager.set("socket", 1_u32).unwrap();
+    }
+
+    #[test]
+    fn test_get_optional_distinguishes_absent_from_failing() {
+        let mut manager = SingletonManager::new();
+        manager.set_factory("lazy", || Box::new(1_u32)).unwrap();
+        manager
+            .set_factory("wrong", || Box::new("text".to_string()))
+            .unwrap();
+
+        assert_eq!(Some(&1), manager.get_optional::<u32>("lazy").unwrap());
+        assert_eq!(None, manager.get_optional::<u32>("absent").unwrap());
+        assert!(matches!(
+            manager.get_optional::<u32>("wrong"),
+            Err(GetError::FailedToDowncastRefOfService(_))
+        ));
+
+        manager
+            .set_flagged("beta", "beta_enabled", || Box::new(2_u32))
+            .unwrap();
+        assert!(manager
+            .get_optional::<u32>("beta")
+            .unwrap_err()
+            .is_feature_disabled());
+    }
+
+    struct Mailer;
+
+    impl super::SingletonProvider for Mailer {
+        type Output = String;
+        type Error = super::Error;
+        type Config = u16;
+
+        fn service() -> super::Result<&'static mut String> {
+            unreachable!()
+        }
+
+        fn get_name(&self) -> &'static str {
+            "mailer"
+        }
+
+        fn config_name() -> Option<&'static str> {
+            Some("mailer_port")
+        }
+
+        fn dependencies() -> &'static [&'static str] {
+            &["smtp"]
+        }
+
+        fn get_service(&self, port: Option<&u16>) -> super::Result<String> {
+            Ok(format!("smtp:{}", port.unwrap()))
+        }
+    }
+
+    #[test]
+    fn test_provide_pulls_config_and_checks_dependencies() {
+        let mut manager = SingletonManager::new();
+        manager.set("mailer_port", 25_u16).unwrap();
+        assert!(matches!(
+            manager.provide(Mailer),
+            Err(super::Error::MissingDependency(_, dependency)) if dependency == "smtp"
+        ));
+        assert!(!manager.has("mailer"));
+
+        manager.set("smtp", ()).unwrap();
+        manager.provide(Mailer).unwrap();
+        assert_eq!("smtp:25", manager.get_cloned::<String>("mailer").unwrap());
+        assert_eq!(vec!["smtp".to_string()], manager.dependencies("mailer"));
+    }
+
+    #[derive(Default)]
+    struct Audited;
+
+    impl super::Runnable for Audited {
+        fn start(&mut self) -> Result<(), String> {
+            Ok(())
+        }
+
+        fn stop(&mut self) -> Result<(), String> {
+            Ok(())
+        }
+
+        fn is_running(&self) -> bool {
+            true
+        }
+    }
+
+    impl super::Lifecycle for Audited {
+        fn on_start(&mut self) -> super::Result<()> {
+            Ok(())
+        }
+
+        fn on_stop(&mut self) -> super::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl super::Drain for Audited {
+        fn drain(self) {}
+    }
+
+    #[test]
+    fn test_removed_registrations_leave_no_state_behind() {
+        let mut manager = SingletonManager::new();
+        manager.set_factory("audit.db", || Box::new(1_u32)).unwrap();
+        manager
+            .set_factory("audit.worker", || Box::new(Audited))
+            .unwrap();
+        let worker = "audit.worker";
+        manager.set_origin(worker, "audit", "1.0.0").unwrap();
+        manager.set_tag(worker, "owner", "audit").unwrap();
+        manager.set_phase(worker, super::Phase::Interface).unwrap();
+        manager.add_to_group(worker, "audit").unwrap();
+        manager.set_ready(worker, true).unwrap();
+        manager
+            .set_health_check::<Audited, _>(worker, |_| Ok(()))
+            .unwrap();
+        manager.restrict_callers(worker, &["src/"]).unwrap();
+        manager.deprecate(worker, "use `audit.v2`").unwrap();
+        manager.depends_on(worker, "audit.db").unwrap();
+        manager.set_runnable::<Audited>(worker).unwrap();
+        manager
+            .set_lifecycle::<Audited>(worker, std::time::Duration::from_secs(5))
+            .unwrap();
+        manager.set_drain::<Audited>(worker).unwrap();
+        manager
+            .set_circuit_breaker(worker, 3, std::time::Duration::from_secs(1))
+            .unwrap();
+        let _mailbox = manager.open_mailbox::<u8>(worker).unwrap();
+        manager
+            .set_script_adapter::<Audited, _>(worker, |_, _, _| Ok(super::ScriptValue::None))
+            .unwrap();
+        drop(
+            manager
+                .instrument::<Audited>(worker)
+                .unwrap()
+                .get()
+                .unwrap(),
+        );
+        assert!(manager.init_all().is_success());
+        manager.get::<Audited>(worker).unwrap();
+
+        manager
+            .set_with_priority("audit.priority", 1_u8, 1)
+            .unwrap();
+        manager
+            .set_memoized("audit.memoized", |key: &u8| *key)
+            .unwrap();
+        manager.set_inline("audit.inline", 1_u8).unwrap();
+        manager
+            .set_unsync_factory("audit.unsync", || std::rc::Rc::new(1_u8))
+            .unwrap();
+        manager
+            .set_flagged("audit.flagged", "audit", || Box::new(1_u8))
+            .unwrap();
+        manager
+            .set_send_factory("audit.preheated", || 1_u8)
+            .unwrap();
+        manager.preheat(["audit.preheated"]).unwrap();
+        manager
+            .create_tenant("audit")
+            .unwrap()
+            .set("db", 1_u8)
+            .unwrap();
+
+        let ids = manager
+            .registry()
+            .unwrap()
+            .alias
+            .iter()
+            .filter(|(alias, _)| alias.starts_with("audit"))
+            .map(|(_, id)| *id)
+            .collect::<Vec<_>>();
+        assert_eq!(9, ids.len());
+        let worker_id = manager.service_id(worker).unwrap();
+        assert_eq!(25, manager.registry().unwrap().held_for(&worker_id).len());
+        manager.remove_prefix("audit").unwrap();
+        let registry = manager.registry().unwrap();
+        for id in &ids {
+            assert_eq!(Vec::<&str>::new(), registry.held_for(id));
+        }
+    }
+
+    struct Loopback;
+
+    impl super::SingletonProvider for Loopback {
+        type Output = u32;
+        type Error = super::Error;
+        type Config = ();
+
+        fn service() -> super::Result<&'static mut u32> {
+            unreachable!()
+        }
+
+        fn get_name(&self) -> &'static str {
+            "loopback"
+        }
+
+        fn dependencies() -> &'static [&'static str] {
+            &["smtp", "loopback"]
+        }
+
+        fn get_service(&self, _: Option<&()>) -> super::Result<u32> {
+            Ok(2)
+        }
+    }
+
+    #[test]
+    fn test_provide_sets_nothing_if_a_dependency_fails() {
+        let mut manager = SingletonManager::new();
+        manager.set("smtp", ()).unwrap();
+        manager.set("loopback", 1_u32).unwrap();
+
+        manager
+            .set_collision_policy(super::CollisionPolicy::FirstWins)
+            .unwrap();
+        manager.depends_on("smtp", "loopback").unwrap();
+        assert!(matches!(
+            manager.provide(Loopback),
+            Err(super::Error::DependencyCycle(_, dependency)) if dependency == "smtp"
+        ));
+        assert_eq!(1, manager.get_cloned::<u32>("loopback").unwrap());
+        assert!(manager.dependencies("loopback").is_empty());
+
+        manager
+            .set_collision_policy(super::CollisionPolicy::LastWins)
+            .unwrap();
+        assert!(matches!(
+            manager.provide(Loopback),
+            Err(super::Error::DependencyCycle(_, dependency)) if dependency == "loopback"
+        ));
+        assert!(!manager.has("loopback"));
+        assert!(manager.dependencies("smtp").is_empty());
+
+        manager
+            .set_collision_policy(super::CollisionPolicy::Error)
+            .unwrap();
+        manager.set("loopback", 3_u32).unwrap();
+    }
 }
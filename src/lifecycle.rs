@@ -0,0 +1,248 @@
+//! # Lifecycle hooks
+//! Starting services during `init_all`, and stopping them during `shutdown`.
+//!
+//! Services implementing `Lifecycle` and registered with `set_lifecycle` are started once they
+//! are instantiated by `init_all`, and stopped before they are dropped by `shutdown`. A stop hook
+//! runs on a helper thread within the budget of the service, so a hook stuck on e.g. an
+//! unreachable broker only fails its own service. A start hook runs on the thread starting the
+//! service, as the instance stays in use by the manager, and fails its service once it took
+//! longer than the budget. A failing hook does not abort the others, the failures are collected
+//! in the reports of `init_all` and `shutdown` instead.
+//! ```
+//! use singleton_manager::{Lifecycle, Result, SingletonManager};
+//! use std::time::Duration;
+//!
+//! #[derive(Default)]
+//! struct Consumer {
+//!     subscribed: bool,
+//! }
+//!
+//! impl Lifecycle for Consumer {
+//!     fn on_start(&mut self) -> Result<()> {
+//!         self.subscribed = true;
+//!         Ok(())
+//!     }
+//!
+//!     fn on_stop(&mut self) -> Result<()> {
+//!         self.subscribed = false;
+//!         Ok(())
+//!     }
+//! }
+//!
+//! let mut manager = SingletonManager::new();
+//! manager.set_factory("consumer", || Box::new(Consumer::default())).unwrap();
+//! manager
+//!     .set_lifecycle::<Consumer>("consumer", Duration::from_secs(5))
+//!     .unwrap();
+//!
+//! assert!(manager.init_all().is_success());
+//! assert!(manager.get_ref::<Consumer>("consumer").unwrap().subscribed);
+//! assert!(manager.shutdown().unwrap().failed.is_empty());
+//! ```
+use crate::{Error, Result, Service, SingletonManager, Uuid};
+use std::any::Any;
+use std::panic::Location;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+/// A service started after it is instantiated by `init_all`, and stopped before it is dropped by
+/// `shutdown`.
+pub trait Lifecycle {
+    /// Starting the service, e.g. subscribing to a broker.
+    fn on_start(&mut self) -> Result<()>;
+
+    /// Stopping the service, e.g. flushing its buffers.
+    fn on_stop(&mut self) -> Result<()>;
+}
+
+/// Getting the `Lifecycle` implementation of a type erased service.
+type AsLifecycle = fn(&mut dyn Any) -> Option<&mut dyn Lifecycle>;
+
+/// The lifecycle hooks of a service, with the time each hook may take.
+#[derive(Clone, Copy)]
+pub(crate) struct Hooks {
+    as_lifecycle: AsLifecycle,
+    budget: Duration,
+}
+
+impl Hooks {
+    /// Starting the instance on the current thread, failing with `Error::HookTimeout` if the hook
+    /// took longer than the budget, and with `Error::StartFailed` if it panicked.
+    fn start(self, service_name: &str, service: &mut dyn Any) -> Result<()> {
+        let lifecycle = match (self.as_lifecycle)(service) {
+            Some(lifecycle) => lifecycle,
+            None => return Ok(()),
+        };
+        let started = Instant::now();
+        match panic::catch_unwind(AssertUnwindSafe(|| lifecycle.on_start())) {
+            Ok(_) if started.elapsed() > self.budget => {
+                Err(Error::HookTimeout(service_name.into(), self.budget))
+            }
+            Ok(result) => result,
+            Err(_) => Err(Error::StartFailed(
+                service_name.into(),
+                "the hook panicked".to_string(),
+            )),
+        }
+    }
+
+    /// Stopping the instance of the service taken out of the storage by `shutdown` on a helper
+    /// thread, failing with `Error::HookTimeout` if the hook did not finish within the budget.
+    /// The helper thread is not interrupted, it drops the instance once the hook finishes.
+    pub(crate) fn stop(self, service_name: &str, mut service: Service) -> Result<()> {
+        let (sender, receiver) = mpsc::sync_channel(1);
+        std::thread::spawn(move || {
+            let result = match (self.as_lifecycle)(service.as_mut()) {
+                Some(lifecycle) => lifecycle.on_stop(),
+                None => Ok(()),
+            };
+            let _ = sender.send(result);
+        });
+        match receiver.recv_timeout(self.budget) {
+            Ok(result) => result,
+            Err(RecvTimeoutError::Timeout) => {
+                Err(Error::HookTimeout(service_name.into(), self.budget))
+            }
+            Err(RecvTimeoutError::Disconnected) => Err(Error::StopFailed(
+                service_name.into(),
+                "the hook panicked".to_string(),
+            )),
+        }
+    }
+}
+
+impl SingletonManager {
+    /// Registering the lifecycle hooks of the service, where the service is a `T`, each hook
+    /// failing with `Error::HookTimeout` if it does not finish within the budget.
+    /// Services bound to a thread are not started nor stopped.
+    pub fn set_lifecycle<T: Lifecycle + 'static>(
+        &self,
+        service_name: &str,
+        budget: Duration,
+    ) -> Result<()> {
+        let id = self.service_id(service_name)?;
+        let as_lifecycle: AsLifecycle = |service| {
+            service
+                .downcast_mut::<T>()
+                .map(|service| service as &mut dyn Lifecycle)
+        };
+        self.registry_mut()?.lifecycles.insert(
+            id,
+            Hooks {
+                as_lifecycle,
+                budget,
+            },
+        );
+        Ok(())
+    }
+
+    /// Starting the instantiated service, unless it has no lifecycle hooks or is started.
+    /// The instance is taken out of the storage while the hook runs, getting the service blocks
+    /// until it is stored again. An instance whose hook timed out or panicked is removed, and
+    /// created again by its factory when needed.
+    pub(crate) fn run_on_start(&self, service_name: &str, id: &Uuid) -> Result<()> {
+        let hooks = {
+            let mut registry = self.registry_mut()?;
+            match registry.lifecycles.get(id).copied() {
+                Some(hooks) if registry.started.insert(*id) => hooks,
+                _ => return Ok(()),
+            }
+        };
+        let state = self.borrow_state(id, service_name)?;
+        state.wait_borrow_mut(Location::caller());
+        let lifted = self.lift_instance(id);
+        state.release_mut();
+        let (mut service, flight) = match lifted? {
+            Some(lifted) => lifted,
+            None => {
+                self.registry_mut()?.started.remove(id);
+                return Ok(());
+            }
+        };
+
+        let result = hooks.start(service_name, service.as_mut());
+        let mut registry = self.registry_mut()?;
+        registry.insert_instance(*id, service);
+        let removed = match result {
+            Err(Error::HookTimeout(_, _)) | Err(Error::StartFailed(_, _)) => {
+                registry.drop_instance(id)
+            }
+            _ => None,
+        };
+        if result.is_err() {
+            registry.started.remove(id);
+        }
+        drop(registry);
+        drop(flight);
+        drop(removed);
+        result
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Error, Lifecycle, Result, SingletonManager};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    struct Consumer {
+        starts: Arc<AtomicUsize>,
+        start_delay: Duration,
+        stop_fails: bool,
+    }
+
+    impl Lifecycle for Consumer {
+        fn on_start(&mut self) -> Result<()> {
+            std::thread::sleep(self.start_delay);
+            self.starts.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+
+        fn on_stop(&mut self) -> Result<()> {
+            match self.stop_fails {
+                true => Err(Error::StopFailed("broker".into(), "gone".to_string())),
+                false => Ok(()),
+            }
+        }
+    }
+
+    #[test]
+    fn test_hooks_run_within_budget_and_report_failures() {
+        let starts = Arc::new(AtomicUsize::new(0));
+        let mut manager = SingletonManager::new();
+        for (alias, start_delay, stop_fails) in [
+            ("orders", Duration::ZERO, true),
+            ("payments", Duration::ZERO, false),
+            ("stuck", Duration::from_millis(500), false),
+        ] {
+            let starts = starts.clone();
+            manager
+                .set_factory(alias, move || {
+                    Box::new(Consumer {
+                        starts: starts.clone(),
+                        start_delay,
+                        stop_fails,
+                    })
+                })
+                .unwrap();
+            manager
+                .set_lifecycle::<Consumer>(alias, Duration::from_millis(100))
+                .unwrap();
+        }
+
+        let report = manager.init_all();
+        let failed = &report.phases()[0].failed;
+        assert_eq!(1, failed.len());
+        assert!(matches!(&failed[0], (alias, Error::HookTimeout(_, _)) if alias == "stuck"));
+        assert_eq!(3, starts.load(Ordering::SeqCst));
+        assert!(manager.get_ref::<Consumer>("orders").is_ok());
+        assert!(!manager.is_instantiated("stuck"));
+
+        let report = manager.shutdown().unwrap();
+        assert_eq!(vec!["payments", "orders"], report.shut_down);
+        assert_eq!(1, report.failed.len());
+        assert_eq!(Some("broker"), report.failed[0].1.alias());
+    }
+}
@@ -0,0 +1,401 @@
+/// Declaring singletons with a `lazy_static!` like syntax.
+///
+/// This is meant for codebases migrating from `lazy_static!` (or `once_cell::sync::Lazy`), where
+/// the declaration can be switched with a near-mechanical edit:
+/// ```
+/// use singleton_manager::singleton;
+/// use std::collections::HashMap;
+///
+/// singleton! {
+///     /// The configuration of the application
+///     pub static ref CONFIG: HashMap<String, String> = {
+///         let mut config = HashMap::new();
+///         config.insert("name".to_string(), "my_app".to_string());
+///         config
+///     };
+/// }
+///
+/// assert_eq!("my_app", CONFIG.get("name").unwrap());
+/// ```
+///
+/// Each declaration expands to a factory registration in the singleton manager, which is only
+/// executed on first use, and to a typed accessor. The value can be used through `Deref`, just as
+/// with `lazy_static!`, or through the accessor function `NAME::instance()` that returns the
+/// mutable reference the singleton manager hands out.
+///
+/// The singleton is registered under the alias `module_path::NAME`.
+#[macro_export]
+macro_rules! singleton {
+    ($(#[$attr:meta])* $vis:vis static ref $name:ident : $t:ty = $init:expr; $($rest:tt)*) => {
+        $(#[$attr])*
+        #[allow(non_camel_case_types, clippy::upper_case_acronyms)]
+        $vis struct $name {
+            __private_field: (),
+        }
+
+        #[doc(hidden)]
+        $vis static $name: $name = $name { __private_field: () };
+
+        impl $name {
+            /// The alias the singleton is registered under in the singleton manager.
+            pub const ALIAS: &'static str = concat!(module_path!(), "::", stringify!($name));
+
+            /// Typed accessor of the singleton, registering the factory on first use.
+            #[allow(dead_code)]
+            pub fn instance() -> &'static mut $t {
+                $crate::sm()
+                    .get_default::<$t, _>(Self::ALIAS, || Box::new($init))
+                    .expect(concat!("Failed to get singleton `", stringify!($name), "`"))
+            }
+        }
+
+        impl ::std::ops::Deref for $name {
+            type Target = $t;
+
+            fn deref(&self) -> &$t {
+                $name::instance()
+            }
+        }
+
+        $crate::singleton!($($rest)*);
+    };
+    () => ()
+}
+
+/// Registering a singleton together with a generated accessor function.
+///
+/// ```
+/// use singleton_manager::register_singleton;
+///
+/// pub struct DbPool {
+///     connections: usize,
+/// }
+///
+/// register_singleton!(pub DB_POOL: DbPool = "db_pool" => || DbPool { connections: 10 });
+///
+/// assert_eq!(10, db_pool().connections);
+/// ```
+///
+/// This will register the factory under the alias `"db_pool"` and generate the function
+/// `db_pool() -> ServiceRef<'static, DbPool>` (the lowercase name of the declaration). Every call
+/// resolves the singleton through the registry and hands out a tracked shared borrow, so a
+/// service removed or shut down in the meantime is registered and created again, instead of
+/// being read after it was dropped.
+#[macro_export]
+macro_rules! register_singleton {
+    ($(#[$attr:meta])* $vis:vis $name:ident : $t:ty = $alias:expr => $factory:expr $(;)?) => {
+        $crate::__paste! {
+            $(#[$attr])*
+            $vis fn [<$name:lower>]() -> $crate::ServiceRef<'static, $t> {
+                let manager = $crate::sm();
+                if !manager.has($alias) {
+                    manager.set_factory($alias, || Box::new(($factory)())).ok();
+                }
+                manager
+                    .get_ref::<$t>($alias)
+                    .expect(concat!("Failed to get singleton `", stringify!($name), "`"))
+            }
+        }
+    };
+}
+
+/// Declaring the aliases of singletons as typed keys, checking at compile time that no alias is
+/// declared twice in the invoking crate.
+///
+/// ```
+/// use singleton_manager::{declare_singletons, SingletonManager};
+///
+/// pub struct DbPool;
+/// pub struct Logger;
+///
+/// declare_singletons! {
+///     pub DB: DbPool = "db",
+///     LOG: Logger = "log",
+/// }
+///
+/// let mut manager = SingletonManager::new();
+/// manager.set_factory(DB.alias(), || Box::new(DbPool)).unwrap();
+/// manager.freeze(DB.alias()).unwrap();
+/// let db: &DbPool = manager.get_key(&DB).unwrap();
+/// ```
+///
+/// Each declaration expands to a static `Key` of the alias. Declaring the same alias twice, in
+/// the same or in different invocations anywhere in the crate, fails compilation with the error
+/// ``symbol `__singleton_manager_alias_<crate>_<alias>` is already defined``:
+/// ```compile_fail
+/// use singleton_manager::declare_singletons;
+///
+/// declare_singletons! {
+///     DB: String = "db",
+///     REPLICA: String = "db",
+/// }
+/// ```
+#[macro_export]
+macro_rules! declare_singletons {
+    ($($(#[$attr:meta])* $vis:vis $name:ident : $t:ty = $alias:literal),* $(,)?) => {
+        $(
+            $(#[$attr])*
+            $vis static $name: $crate::Key<$t> = $crate::Key::new($alias);
+
+            const _: () = {
+                // Only one symbol of the name can be defined in a crate, failing compilation when
+                // the alias is declared twice.
+                #[doc(hidden)]
+                #[used]
+                #[export_name = concat!(
+                    "__singleton_manager_alias_",
+                    env!("CARGO_CRATE_NAME"),
+                    "_",
+                    $alias
+                )]
+                static ALIAS: u8 = 0;
+            };
+        )*
+    };
+}
+
+/// Generating a facade implementing a trait, by forwarding the methods to the service bound as
+/// the `dyn Trait` in the global singleton manager, see `Facade`.
+///
+/// ```
+/// use singleton_manager::{facade, sm};
+///
+/// pub trait Mailer: Send + Sync {
+///     fn send(&self, to: &str, body: &str) -> bool;
+///     fn sent(&self) -> usize;
+/// }
+///
+/// struct Outbox;
+///
+/// impl Mailer for Outbox {
+///     fn send(&self, _to: &str, _body: &str) -> bool {
+///         true
+///     }
+///
+///     fn sent(&self) -> usize {
+///         0
+///     }
+/// }
+///
+/// facade! {
+///     pub struct MailerFacade: dyn Mailer {
+///         fn send(&self, to: &str, body: &str) -> bool;
+///         fn sent(&self) -> usize;
+///     }
+/// }
+///
+/// static MAILER: MailerFacade = MailerFacade::new();
+///
+/// sm().bind::<dyn Mailer>(Box::new(Outbox)).unwrap();
+/// assert!(MAILER.send("ops@example.com", "deployed"));
+/// ```
+///
+/// Only methods taking `&self` can be forwarded. Calling a method panics if no service is bound
+/// as the trait, use `Facade::get` through the `facade` field to handle that case.
+#[macro_export]
+macro_rules! facade {
+    (
+        $(#[$attr:meta])*
+        $vis:vis struct $name:ident : dyn $trait:path {
+            $(fn $method:ident(&self $(, $arg:ident : $arg_t:ty)* $(,)?) $(-> $ret:ty)?;)*
+        }
+    ) => {
+        $(#[$attr])*
+        $vis struct $name {
+            pub facade: $crate::Facade<dyn $trait>,
+        }
+
+        impl $name {
+            pub const fn new() -> Self {
+                Self {
+                    facade: $crate::Facade::new(),
+                }
+            }
+        }
+
+        impl $trait for $name {
+            $(
+                fn $method(&self $(, $arg: $arg_t)*) $(-> $ret)? {
+                    self.facade
+                        .get()
+                        .expect(concat!("No service is bound as `dyn ", stringify!($trait), "`"))
+                        .$method($($arg),*)
+                }
+            )*
+        }
+    };
+}
+
+/// Getting the namespace of the crate invoking the macro, named after its `CARGO_PKG_NAME`, see
+/// `SingletonManager::crate_scope`.
+///
+/// Library crates registering their services through their own crate scope can not collide with
+/// the services of other crates, while the application still reaches them by their fully
+/// qualified alias `<crate>/<alias>`. Without arguments the scope is in the global singleton
+/// manager, otherwise in the manager given.
+///
+/// ```
+/// use singleton_manager::{crate_scope, sm};
+///
+/// let scope = crate_scope!().unwrap();
+/// scope.set("pool_size", 8_u32).unwrap();
+///
+/// let alias = concat!(env!("CARGO_PKG_NAME"), "/pool_size");
+/// assert_eq!(8, *sm().get_ref::<u32>(alias).unwrap());
+/// ```
+#[macro_export]
+macro_rules! crate_scope {
+    () => {
+        $crate::sm().crate_scope(env!("CARGO_PKG_NAME"))
+    };
+    ($manager:expr) => {
+        $manager.crate_scope(env!("CARGO_PKG_NAME"))
+    };
+}
+
+/// Recording the crate invoking the macro, by its `CARGO_PKG_NAME` and `CARGO_PKG_VERSION`, as
+/// the origin of a service, see `SingletonManager::set_origin`.
+///
+/// Without a manager the origin is recorded in the global singleton manager, otherwise in the
+/// manager given.
+///
+/// ```
+/// use singleton_manager::{set_origin, sm};
+///
+/// sm().set_factory("origin_example_api", || Box::new(1_u32)).unwrap();
+/// set_origin!("origin_example_api").unwrap();
+///
+/// let origin = sm().origin("origin_example_api").unwrap();
+/// assert_eq!(env!("CARGO_PKG_NAME"), origin.crate_name());
+/// ```
+#[macro_export]
+macro_rules! set_origin {
+    ($service_name:expr) => {
+        $crate::sm().set_origin(
+            $service_name,
+            env!("CARGO_PKG_NAME"),
+            env!("CARGO_PKG_VERSION"),
+        )
+    };
+    ($manager:expr, $service_name:expr) => {
+        $manager.set_origin(
+            $service_name,
+            env!("CARGO_PKG_NAME"),
+            env!("CARGO_PKG_VERSION"),
+        )
+    };
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Mutex;
+
+    pub struct Counter {
+        count: Mutex<u32>,
+    }
+
+    impl Counter {
+        pub fn increment(&self) -> u32 {
+            let mut count = self.count.lock().unwrap();
+            *count += 1;
+            *count
+        }
+    }
+
+    singleton! {
+        static ref COUNTER: Counter = Counter { count: Mutex::new(0) };
+        /// A second declaration in the same block
+        pub(crate) static ref GREETING: String = "hello".to_string();
+    }
+
+    #[test]
+    fn test_singleton_macro_shares_instance() {
+        assert_eq!(1, COUNTER.increment());
+        assert_eq!(2, COUNTER.increment());
+        assert_eq!(3, COUNTER::instance().increment());
+        assert!(crate::sm().has(COUNTER::ALIAS));
+    }
+
+    #[test]
+    fn test_singleton_macro_accessor() {
+        GREETING::instance().push_str(" world");
+        assert_eq!("hello world", GREETING.as_str());
+        assert_eq!("singleton_manager::macros::test::GREETING", GREETING::ALIAS);
+    }
+
+    declare_singletons! {
+        DECLARED_PORT: u16 = "macros::declared_port",
+        /// A documented declaration
+        pub(crate) DECLARED_HOST: String = "macros::declared_host",
+    }
+
+    #[test]
+    fn test_declare_singletons_keys() {
+        let mut manager = crate::SingletonManager::new();
+        manager
+            .set_factory(DECLARED_PORT.alias(), || Box::new(8080_u16))
+            .unwrap();
+        manager.freeze(DECLARED_PORT.alias()).unwrap();
+        assert_eq!(8080, *manager.get_key(&DECLARED_PORT).unwrap());
+        assert_eq!("macros::declared_host", DECLARED_HOST.alias());
+    }
+
+    register_singleton!(REGISTERED_COUNTER: Counter = "registered_counter" => || Counter {
+        count: Mutex::new(0),
+    });
+
+    #[test]
+    fn test_register_singleton_accessor_resolves_the_registry() {
+        assert_eq!(1, registered_counter().increment());
+        assert_eq!(2, registered_counter().increment());
+        assert_eq!(
+            3,
+            crate::sm()
+                .get::<Counter>("registered_counter")
+                .unwrap()
+                .increment()
+        );
+
+        crate::sm().take::<Counter>("registered_counter").unwrap();
+        assert_eq!(1, registered_counter().increment());
+    }
+
+    pub struct Pool(u32);
+
+    #[crate::inject]
+    fn pool_size(pool: &Pool, counter: &Counter) -> u32 {
+        pool.0 + counter.increment()
+    }
+
+    /// Without a return type, and with a parameter kept
+    #[crate::inject]
+    fn touch(times: u32, counter: &Counter) {
+        (0..times).for_each(|_| {
+            counter.increment();
+        });
+    }
+
+    impl Pool {
+        #[crate::inject]
+        fn new(counter: &Counter) -> Self {
+            Pool(counter.increment())
+        }
+    }
+
+    #[test]
+    fn test_inject_resolves_bound_parameters() {
+        let manager = crate::SingletonManager::new();
+        assert!(pool_size_injected_from(&manager)
+            .unwrap_err()
+            .is_not_found());
+        manager.bind(Box::new(Pool(10))).unwrap();
+        manager
+            .bind(Box::new(Counter {
+                count: Mutex::new(0),
+            }))
+            .unwrap();
+        touch_injected_from(&manager, 2).unwrap();
+        assert_eq!(13, pool_size_injected_from(&manager).unwrap());
+        assert_eq!(4, Pool::new_injected_from(&manager).unwrap().0);
+    }
+}
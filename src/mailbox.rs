@@ -0,0 +1,156 @@
+//! # Mailboxes
+//! Sending typed messages to services, turning the registry into a directory of actors.
+//!
+//! A service that wants to receive messages, e.g. a worker queue, opens a mailbox for a message
+//! type and drains it, usually from its own thread. Any code can then send messages to the
+//! service by alias, without getting the service itself, and so without borrowing it.
+//! ```
+//! use singleton_manager::SingletonManager;
+//!
+//! struct WorkerQueue;
+//! struct Job {
+//!     id: u32,
+//! }
+//!
+//! let mut manager = SingletonManager::new();
+//! manager.set_factory("queue", || Box::new(WorkerQueue)).unwrap();
+//! let mailbox = manager.open_mailbox::<Job>("queue").unwrap();
+//!
+//! manager.send_to::<WorkerQueue, _>("queue", Job { id: 1 }).unwrap();
+//! manager.send_to::<WorkerQueue, _>("queue", Job { id: 2 }).unwrap();
+//! assert_eq!(vec![1, 2], mailbox.drain().map(|job| job.id).collect::<Vec<_>>());
+//! ```
+use crate::unsync::ThreadBound;
+use crate::{Error, Result, SingletonManager, Uuid};
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::time::Duration;
+
+/// The sending ends of the mailboxes, by service and message type.
+pub(crate) type Mailboxes = HashMap<(Uuid, TypeId), Box<dyn Any + Send + Sync>>;
+
+/// The receiving end of the mailbox of a service, see `SingletonManager::open_mailbox`.
+pub struct Mailbox<M> {
+    alias: String,
+    messages: Receiver<M>,
+}
+
+impl<M> Mailbox<M> {
+    /// The alias of the service receiving the messages.
+    pub fn alias(&self) -> &str {
+        &self.alias
+    }
+
+    /// Taking the messages received so far, without waiting for more.
+    pub fn drain(&self) -> impl Iterator<Item = M> + '_ {
+        self.messages.try_iter()
+    }
+
+    /// Waiting for the next message, returning nothing once the mailbox is closed, i.e. the
+    /// service is removed or another mailbox is opened in its place.
+    pub fn recv(&self) -> Option<M> {
+        self.messages.recv().ok()
+    }
+
+    /// Waiting for the next message for at most the timeout.
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<M> {
+        self.messages.recv_timeout(timeout).ok()
+    }
+}
+
+impl SingletonManager {
+    /// Opening the mailbox of the service for messages of type `M`.
+    /// Opening the mailbox again closes the mailbox opened before.
+    pub fn open_mailbox<M: Send + 'static>(&self, service_name: &str) -> Result<Mailbox<M>> {
+        let mut registry = self.registry_mut()?;
+        let id = registry
+            .resolve(service_name)
+            .ok_or_else(|| Error::ServiceDoesNotExist(service_name.into(), Default::default()))?;
+        let (sender, messages) = mpsc::channel::<M>();
+        registry
+            .mailboxes
+            .insert((id, TypeId::of::<M>()), Box::new(sender));
+        Ok(Mailbox {
+            alias: service_name.to_string(),
+            messages,
+        })
+    }
+
+    /// Sending a message to the service, which has to be an `S`, through its mailbox for
+    /// messages of type `M`.
+    /// Fails with `Error::NoMailbox` if the service has no open mailbox for the messages.
+    /// Services only registered by a factory are not known to be an `S` until instantiated.
+    pub fn send_to<S: 'static, M: Send + 'static>(
+        &self,
+        service_name: &str,
+        message: M,
+    ) -> Result<()> {
+        let registry = self.registry()?;
+        let id = registry
+            .resolve(service_name)
+            .ok_or_else(|| Error::ServiceDoesNotExist(service_name.into(), Default::default()))?;
+        if let Some(service) = registry.singletons.get(&id) {
+            if !ThreadBound::inner(service.as_ref()).is::<S>() {
+                return Err(Error::FailedToDowncastRefOfService(service_name.into()));
+            }
+        }
+        registry
+            .mailboxes
+            .get(&(id, TypeId::of::<M>()))
+            .and_then(|sender| sender.downcast_ref::<Sender<M>>())
+            .and_then(|sender| sender.send(message).ok())
+            .ok_or_else(|| Error::NoMailbox(service_name.into()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Error, SingletonManager};
+    use std::time::Duration;
+
+    struct WorkerQueue;
+
+    #[derive(Debug, PartialEq)]
+    enum Job {
+        Resize(u32),
+        Stop,
+    }
+
+    #[test]
+    fn test_mailbox_delivers_messages_to_the_service() {
+        let mut manager = SingletonManager::new();
+        manager.set("queue", WorkerQueue).unwrap();
+        assert!(matches!(
+            manager.send_to::<WorkerQueue, _>("queue", Job::Stop),
+            Err(Error::NoMailbox(_))
+        ));
+
+        let mailbox = manager.open_mailbox::<Job>("queue").unwrap();
+        let worker = std::thread::spawn(move || {
+            let mut resized = Vec::new();
+            while let Some(Job::Resize(size)) = mailbox.recv() {
+                resized.push(size);
+            }
+            resized
+        });
+        manager
+            .send_to::<WorkerQueue, _>("queue", Job::Resize(1))
+            .unwrap();
+        manager
+            .send_to::<WorkerQueue, _>("queue", Job::Resize(2))
+            .unwrap();
+        manager
+            .send_to::<WorkerQueue, _>("queue", Job::Stop)
+            .unwrap();
+        assert_eq!(vec![1, 2], worker.join().unwrap());
+        assert!(matches!(
+            manager.send_to::<String, _>("queue", Job::Stop),
+            Err(Error::FailedToDowncastRefOfService(_))
+        ));
+
+        let mailbox = manager.open_mailbox::<u32>("queue").unwrap();
+        manager.take::<WorkerQueue>("queue").unwrap();
+        assert_eq!(None, mailbox.recv_timeout(Duration::from_secs(1)));
+    }
+}
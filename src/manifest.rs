@@ -0,0 +1,218 @@
+//! # Manifests
+//! Verifying the registrations against the registrations expected.
+//!
+//! Refactorings can silently drop a registration, or change the type registered under an alias,
+//! which is only noticed once the service is used. Declaring the expected registrations in a
+//! manifest, e.g. checked in next to the code, and verifying it after wiring catches this drift
+//! at startup instead.
+//!
+//! A manifest can be parsed from `alias = "type name"` lines, a subset of TOML, where the type
+//! name is the one given by `std::any::type_name`, or `*` to accept any type. Lines starting with
+//! `#` and `[section]` headers are ignored.
+//! ```
+//! use singleton_manager::{Error, Manifest, SingletonManager};
+//!
+//! let manifest: Manifest = r#"
+//! [services]
+//! db = "alloc::string::String"
+//! port = "u16"
+//! "#
+//! .parse()
+//! .unwrap();
+//!
+//! let mut manager = SingletonManager::new();
+//! manager.set_factory("db", || Box::new("postgres".to_string())).unwrap();
+//! manager.set_factory("port", || Box::new(5432_u32)).unwrap();
+//! manager.get_ref::<u32>("port").unwrap();
+//!
+//! match manager.verify_manifest(&manifest) {
+//!     Err(Error::ManifestMismatch(diff)) => assert_eq!("port", diff.mistyped[0].0),
+//!     _ => panic!("the port is registered as an u32"),
+//! }
+//! ```
+use crate::diagnostics::UNKNOWN_TYPE;
+use crate::{Error, Result, SingletonManager};
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// The type name accepting any type.
+const ANY_TYPE: &str = "*";
+
+/// The registrations expected, see `SingletonManager::verify_manifest`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Manifest {
+    /// The type names expected, by alias.
+    services: BTreeMap<String, String>,
+}
+
+impl Manifest {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Expecting a `T` registered under the alias.
+    pub fn expect<T: 'static>(self, service_name: &str) -> Self {
+        self.expect_type_name(service_name, std::any::type_name::<T>())
+    }
+
+    /// Expecting a service of the type name registered under the alias, where `*` accepts any
+    /// type.
+    pub fn expect_type_name(mut self, service_name: &str, type_name: &str) -> Self {
+        self.services
+            .insert(service_name.to_string(), type_name.to_string());
+        self
+    }
+}
+
+impl FromStr for Manifest {
+    type Err = Error;
+
+    /// Parsing `alias = "type name"` lines, failing with `Error::InvalidManifest` on the first
+    /// line that is not one.
+    fn from_str(s: &str) -> Result<Self> {
+        let mut manifest = Manifest::new();
+        for line in s.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') || line.starts_with('[') {
+                continue;
+            }
+            let (alias, type_name) = line
+                .split_once('=')
+                .map(|(alias, type_name)| (alias.trim(), type_name.trim()))
+                .filter(|(alias, type_name)| {
+                    !alias.is_empty()
+                        && type_name.len() >= 2
+                        && type_name.starts_with('"')
+                        && type_name.ends_with('"')
+                })
+                .ok_or_else(|| Error::InvalidManifest(line.to_string()))?;
+            manifest = manifest
+                .expect_type_name(alias.trim_matches('"'), &type_name[1..type_name.len() - 1]);
+        }
+        Ok(manifest)
+    }
+}
+
+/// The differences between the registrations and a manifest, every list is sorted.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ManifestDiff {
+    /// Aliases in the manifest that are not registered.
+    pub missing: Vec<String>,
+    /// Aliases registered that are not in the manifest.
+    pub extra: Vec<String>,
+    /// Aliases registered with another type than expected, with the expected and the actual
+    /// type name. Services only registered by a factory have no known type until instantiated,
+    /// and are never mistyped.
+    pub mistyped: Vec<(String, String, String)>,
+}
+
+impl ManifestDiff {
+    /// True if the registrations match the manifest.
+    pub fn is_clean(&self) -> bool {
+        self.missing.is_empty() && self.extra.is_empty() && self.mistyped.is_empty()
+    }
+}
+
+impl Display for ManifestDiff {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for alias in &self.missing {
+            writeln!(f, "  - {}", alias)?;
+        }
+        for alias in &self.extra {
+            writeln!(f, "  + {}", alias)?;
+        }
+        for (alias, expected, actual) in &self.mistyped {
+            writeln!(f, "  ~ {}: expected {}, found {}", alias, expected, actual)?;
+        }
+        Ok(())
+    }
+}
+
+impl SingletonManager {
+    /// Comparing the registrations against the manifest.
+    pub fn manifest_diff(&self, manifest: &Manifest) -> Result<ManifestDiff> {
+        let registry = self.registry()?;
+        let mut diff = ManifestDiff::default();
+        for (alias, expected) in &manifest.services {
+            let id = match registry.alias.get(alias.as_str()) {
+                Some(id) => id,
+                None => {
+                    diff.missing.push(alias.clone());
+                    continue;
+                }
+            };
+            let actual = registry.type_names.get(id).copied().unwrap_or(UNKNOWN_TYPE);
+            if expected != ANY_TYPE && actual != UNKNOWN_TYPE && expected != actual {
+                diff.mistyped
+                    .push((alias.clone(), expected.clone(), actual.to_string()));
+            }
+        }
+        diff.extra = registry
+            .alias
+            .keys()
+            .map(|alias| alias.to_string())
+            .filter(|alias| !manifest.services.contains_key(alias))
+            .collect();
+        diff.extra.sort();
+        Ok(diff)
+    }
+
+    /// Verifying the registrations match the manifest, failing with `Error::ManifestMismatch`
+    /// with the differences if they do not.
+    pub fn verify_manifest(&self, manifest: &Manifest) -> Result<()> {
+        let diff = self.manifest_diff(manifest)?;
+        if diff.is_clean() {
+            Ok(())
+        } else {
+            Err(Error::ManifestMismatch(diff))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Error, Manifest, ManifestDiff, SingletonManager};
+
+    #[test]
+    fn test_manifest_diff() {
+        let mut manager = SingletonManager::new();
+        manager.set_factory("db", || Box::new(1_u32)).unwrap();
+        manager.set_factory("cache", || Box::new(1_u32)).unwrap();
+        manager.set_factory("queue", || Box::new(1_u32)).unwrap();
+        manager.set_factory("lazy", || Box::new(1_u32)).unwrap();
+        manager.get_ref::<u32>("db").unwrap();
+        manager.get_ref::<u32>("cache").unwrap();
+
+        let manifest = Manifest::new()
+            .expect::<u32>("db")
+            .expect::<String>("cache")
+            .expect::<String>("lazy")
+            .expect_type_name("mailer", "*");
+        assert_eq!(
+            ManifestDiff {
+                missing: vec!["mailer".to_string()],
+                extra: vec!["queue".to_string()],
+                mistyped: vec![(
+                    "cache".to_string(),
+                    "alloc::string::String".to_string(),
+                    "u32".to_string()
+                )],
+            },
+            manager.manifest_diff(&manifest).unwrap()
+        );
+        assert!(matches!(
+            manager.verify_manifest(&manifest),
+            Err(Error::ManifestMismatch(_))
+        ));
+
+        let manifest: Manifest =
+            "# wiring\ndb = \"u32\"\ncache = \"*\"\nqueue = \"*\"\nlazy = \"*\""
+                .parse()
+                .unwrap();
+        assert!(manager.verify_manifest(&manifest).is_ok());
+        assert!(matches!(
+            "db: u32".parse::<Manifest>(),
+            Err(Error::InvalidManifest(line)) if line == "db: u32"
+        ));
+    }
+}
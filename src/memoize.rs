@@ -0,0 +1,285 @@
+//! # Memoization by key
+//! Keyed singletons, one instance per argument of a parameterized factory.
+//!
+//! A memoized service is registered with a factory taking a key, e.g. a region, and
+//! `get_with` creates one instance per key the first time it is requested, e.g. one HTTP client
+//! per region. The instances created can be listed and evicted, evicted instances are created
+//! again when requested.
+//! ```
+//! use singleton_manager::SingletonManager;
+//!
+//! let manager = SingletonManager::new();
+//! manager
+//!     .set_memoized("http_client", |region: &String| format!("https://{}.example.com", region))
+//!     .unwrap();
+//!
+//! let client = manager.get_with::<_, String>("http_client", &"eu".to_string()).unwrap();
+//! assert_eq!("https://eu.example.com", *client);
+//! assert_eq!(vec!["eu".to_string()], manager.memoized_keys::<String>("http_client").unwrap());
+//!
+//! assert!(manager.evict("http_client", &"eu".to_string()).unwrap());
+//! assert!(manager.memoized_keys::<String>("http_client").unwrap().is_empty());
+//! ```
+use crate::{Error, GetError, Operation, Result, SetError, SingletonManager, Uuid};
+use std::any::Any;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::panic::Location;
+use std::sync::Arc;
+
+/// The factory of a memoized service with the instances created by it, erased of the key type.
+pub(crate) struct Memoized {
+    /// The `Arc<dyn Fn(&K) -> Arc<dyn Any + Send + Sync> + Send + Sync>` creating the instances.
+    factory: Box<dyn Any + Send + Sync>,
+    /// The `HashMap<K, Arc<dyn Any + Send + Sync>>` of the instances created.
+    instances: Box<dyn Any + Send + Sync>,
+    /// Evicting all instances, returning how many were evicted.
+    clear: fn(&mut dyn Any) -> usize,
+    /// Counting the handles to the instances held outside of the singleton manager.
+    handles: fn(&dyn Any) -> usize,
+}
+
+type KeyedFactory<K> = Arc<dyn Fn(&K) -> Arc<dyn Any + Send + Sync> + Send + Sync>;
+
+type Instances<K> = HashMap<K, Arc<dyn Any + Send + Sync>>;
+
+impl Memoized {
+    /// The number of handles to the instances held outside of the singleton manager.
+    pub(crate) fn handles(&self) -> usize {
+        (self.handles)(self.instances.as_ref())
+    }
+
+    /// Evicting all instances, returning how many were evicted.
+    pub(crate) fn clear(&mut self) -> usize {
+        (self.clear)(self.instances.as_mut())
+    }
+
+    fn instances<K: 'static>(&self, service_name: &str) -> Result<&Instances<K>> {
+        self.instances
+            .downcast_ref::<Instances<K>>()
+            .ok_or_else(|| Error::FailedToDowncastRefOfService(service_name.into()))
+    }
+
+    fn instances_mut<K: 'static>(&mut self, service_name: &str) -> Result<&mut Instances<K>> {
+        self.instances
+            .downcast_mut::<Instances<K>>()
+            .ok_or_else(|| Error::FailedToDowncastRefOfService(service_name.into()))
+    }
+}
+
+impl SingletonManager {
+    /// Setting a factory creating one instance of the service per key.
+    /// The instances are created on the first `get_with` of their key.
+    #[track_caller]
+    pub fn set_memoized<K, T, F>(
+        &self,
+        service_name: &str,
+        factory: F,
+    ) -> std::result::Result<(), SetError>
+    where
+        K: Hash + Eq + Send + Sync + 'static,
+        T: Send + Sync + 'static,
+        F: Fn(&K) -> T + Send + Sync + 'static,
+    {
+        let location = Location::caller();
+        let factory: KeyedFactory<K> = Arc::new(move |key| Arc::new(factory(key)));
+        let mut registry = self
+            .registry_mut()
+            .map_err(|e| SetError::from_error(e, service_name))?;
+        let id = registry
+            .store_alias(service_name)
+            .map_err(|e| SetError::from_error(e, service_name))?;
+        registry.memoized.insert(
+            id,
+            Memoized {
+                factory: Box::new(factory),
+                instances: Box::new(Instances::<K>::new()),
+                clear: |instances| {
+                    instances
+                        .downcast_mut::<Instances<K>>()
+                        .map(|instances| instances.drain().count())
+                        .unwrap_or(0)
+                },
+                handles: |instances| {
+                    instances
+                        .downcast_ref::<Instances<K>>()
+                        .map(|instances| {
+                            instances
+                                .values()
+                                .map(|instance| Arc::strong_count(instance) - 1)
+                                .sum()
+                        })
+                        .unwrap_or(0)
+                },
+            },
+        );
+        registry.record(Operation::SetFactory, service_name, Some(location));
+        Ok(())
+    }
+
+    /// Getting the instance of a memoized service for the key, creating it if needed.
+    /// The factory is executed without holding the lock, and its output is validated like the
+    /// output of any other factory.
+    ///
+    /// Fails with `GetError::FailedToDowncastRefOfService` if the key or the service is not of
+    /// the type requested.
+    #[track_caller]
+    pub fn get_with<K, T>(
+        &self,
+        service_name: &str,
+        key: &K,
+    ) -> std::result::Result<Arc<T>, GetError>
+    where
+        K: Hash + Eq + Clone + 'static,
+        T: Send + Sync + 'static,
+    {
+        let location = Location::caller();
+        self.memoized_get::<K>(service_name, key, location)
+            .and_then(|(id, service)| {
+                self.note_access::<T>(&id, location);
+                service
+                    .downcast::<T>()
+                    .map_err(|_| Error::FailedToDowncastRefOfService(service_name.into()))
+            })
+            .map_err(|e| GetError::from_error(e, service_name))
+    }
+
+    fn memoized_get<K>(
+        &self,
+        service_name: &str,
+        key: &K,
+        location: &'static Location<'static>,
+    ) -> Result<(Uuid, Arc<dyn Any + Send + Sync>)>
+    where
+        K: Hash + Eq + Clone + 'static,
+    {
+        let id = self.serving_id(service_name, location)?;
+        let factory = {
+            let registry = self.registry()?;
+            let memoized = Self::memoized(&registry.memoized, &id, service_name)?;
+            if let Some(service) = memoized.instances::<K>(service_name)?.get(key) {
+                return Ok((id, service.clone()));
+            }
+            memoized
+                .factory
+                .downcast_ref::<KeyedFactory<K>>()
+                .cloned()
+                .ok_or_else(|| Error::FailedToDowncastRefOfService(service_name.into()))?
+        };
+        let service = factory(key);
+        let mut registry = self.registry_mut()?;
+        registry.validate(service_name, &id, service.as_ref())?;
+        registry.record(Operation::Instantiate, service_name, None);
+        let service = Self::memoized_mut(&mut registry.memoized, &id, service_name)?
+            .instances_mut::<K>(service_name)?
+            .entry(key.clone())
+            .or_insert(service)
+            .clone();
+        Ok((id, service))
+    }
+
+    /// Listing the keys of the instances created for a memoized service.
+    pub fn memoized_keys<K: Clone + 'static>(&self, service_name: &str) -> Result<Vec<K>> {
+        let id = self.service_id(service_name)?;
+        let registry = self.registry()?;
+        Ok(Self::memoized(&registry.memoized, &id, service_name)?
+            .instances::<K>(service_name)?
+            .keys()
+            .cloned()
+            .collect())
+    }
+
+    /// Evicting the instance of a memoized service for the key.
+    /// Returns false if no instance was created for the key. Instances still in use are kept
+    /// alive by their users, the next `get_with` of the key creates a new instance.
+    pub fn evict<K: Hash + Eq + 'static>(&self, service_name: &str, key: &K) -> Result<bool> {
+        let id = self.service_id(service_name)?;
+        let mut registry = self.registry_mut()?;
+        Ok(
+            Self::memoized_mut(&mut registry.memoized, &id, service_name)?
+                .instances_mut::<K>(service_name)?
+                .remove(key)
+                .is_some(),
+        )
+    }
+
+    /// Evicting all instances of a memoized service, returning how many were evicted.
+    pub fn evict_all(&self, service_name: &str) -> Result<usize> {
+        let id = self.service_id(service_name)?;
+        let mut registry = self.registry_mut()?;
+        let memoized = Self::memoized_mut(&mut registry.memoized, &id, service_name)?;
+        Ok(memoized.clear())
+    }
+
+    fn memoized<'a>(
+        memoized: &'a HashMap<Uuid, Memoized>,
+        id: &Uuid,
+        service_name: &str,
+    ) -> Result<&'a Memoized> {
+        memoized
+            .get(id)
+            .ok_or_else(|| Error::NoFactoryFunctionAvailable(service_name.into()))
+    }
+
+    fn memoized_mut<'a>(
+        memoized: &'a mut HashMap<Uuid, Memoized>,
+        id: &Uuid,
+        service_name: &str,
+    ) -> Result<&'a mut Memoized> {
+        memoized
+            .get_mut(id)
+            .ok_or_else(|| Error::NoFactoryFunctionAvailable(service_name.into()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{GetError, SingletonManager};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_one_instance_per_key() {
+        let created = Arc::new(AtomicUsize::new(0));
+        let counter = created.clone();
+        let mut manager = SingletonManager::new();
+        manager
+            .set_memoized("client", move |region: &&str| {
+                counter.fetch_add(1, Ordering::SeqCst);
+                format!("client-{}", region)
+            })
+            .unwrap();
+
+        let eu = manager.get_with::<_, String>("client", &"eu").unwrap();
+        let us = manager.get_with::<_, String>("client", &"us").unwrap();
+        assert!(Arc::ptr_eq(
+            &eu,
+            &manager.get_with::<_, String>("client", &"eu").unwrap()
+        ));
+        assert_eq!("client-us", *us);
+        assert_eq!(2, created.load(Ordering::SeqCst));
+
+        let mut keys = manager.memoized_keys::<&str>("client").unwrap();
+        keys.sort_unstable();
+        assert_eq!(vec!["eu", "us"], keys);
+
+        assert!(manager.evict("client", &"eu").unwrap());
+        assert!(!manager.evict("client", &"eu").unwrap());
+        assert_eq!("client-eu", *eu);
+        manager.get_with::<_, String>("client", &"eu").unwrap();
+        assert_eq!(3, created.load(Ordering::SeqCst));
+        assert_eq!(2, manager.evict_all("client").unwrap());
+
+        assert!(matches!(
+            manager.get_with::<_, u32>("client", &"eu"),
+            Err(GetError::FailedToDowncastRefOfService(_))
+        ));
+        assert!(manager.get_with::<_, String>("client", &1_u32).is_err());
+        manager.set("port", 1_u32).unwrap();
+        assert!(manager.get_with::<_, u32>("port", &"eu").is_err());
+        assert!(matches!(
+            manager.get::<String>("client"),
+            Err(GetError::NoFactoryFunctionAvailable(alias)) if alias == "client"
+        ));
+    }
+}
@@ -0,0 +1,162 @@
+//! # Mocks
+//! Replacing services bound as trait objects by test doubles, behind the `mock` feature.
+//!
+//! `bind_mock` binds a mock, e.g. one generated by `mockall`, in place of the service bound as
+//! a `dyn Trait`, whether or not the service is bound already. The retrievals of the mock are
+//! recorded by call site, so a test can assert the code under test resolved it, and
+//! `with_mock` gives access to the mock itself to verify its expectations.
+//! ```
+//! use singleton_manager::SingletonManager;
+//! use std::sync::atomic::{AtomicUsize, Ordering};
+//!
+//! trait Mailer: Send + Sync {
+//!     fn send(&self, to: &str);
+//! }
+//!
+//! #[derive(Default)]
+//! struct MockMailer {
+//!     sent: AtomicUsize,
+//! }
+//!
+//! impl Mailer for MockMailer {
+//!     fn send(&self, _to: &str) {
+//!         self.sent.fetch_add(1, Ordering::SeqCst);
+//!     }
+//! }
+//!
+//! let manager = SingletonManager::new();
+//! manager.bind_mock::<dyn Mailer>(Box::new(MockMailer::default())).unwrap();
+//!
+//! manager.get_bound::<dyn Mailer>().unwrap().send("ops@example.com");
+//! manager.assert_mock_retrieved::<dyn Mailer>(1);
+//! ```
+use crate::{bound_alias, CallSite, GetError, Operation, Result, SingletonManager};
+use std::panic::Location;
+
+impl SingletonManager {
+    /// Binding a mock as a `T`, replacing the service bound as a `T` if any, and recording the
+    /// call sites retrieving it.
+    /// Fails if the service bound is borrowed or frozen.
+    #[track_caller]
+    pub fn bind_mock<T: ?Sized + Send + Sync + 'static>(&self, mock: Box<T>) -> Result<()> {
+        let location = Location::caller();
+        let alias = bound_alias::<T>();
+        {
+            let mut registry = self.registry_mut()?;
+            if let Some(id) = registry.alias.get(alias).copied() {
+                registry.check_removable(&[(id, alias.to_string())])?;
+                registry.remove_alias(alias);
+                registry.record(Operation::Remove, alias, Some(location));
+            }
+        }
+        self.store(alias, mock, &[], location)?;
+        self.instrument::<Box<T>>(alias)?;
+        Ok(())
+    }
+
+    /// The call sites that retrieved the mock bound as a `T`, most frequent first.
+    pub fn mock_retrievals<T: ?Sized + 'static>(&self) -> Vec<CallSite> {
+        self.call_sites(bound_alias::<T>())
+    }
+
+    /// Panicking unless the mock bound as a `T` was retrieved the number of times.
+    #[track_caller]
+    pub fn assert_mock_retrieved<T: ?Sized + 'static>(&self, times: u64) {
+        let call_sites = self.mock_retrievals::<T>();
+        let retrieved: u64 = call_sites.iter().map(|call_site| call_site.count).sum();
+        assert!(
+            retrieved == times,
+            "Mock of `{}` was retrieved {} times instead of {}, by: [{}]",
+            bound_alias::<T>(),
+            retrieved,
+            times,
+            call_sites
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+
+    /// Running the function with exclusive access to the mock bound as a `T`, e.g. to verify
+    /// its expectations, without counting as a retrieval.
+    #[track_caller]
+    pub fn with_mock<T: ?Sized + 'static, R>(
+        &self,
+        f: impl FnOnce(&mut T) -> R,
+    ) -> std::result::Result<R, GetError> {
+        let alias = bound_alias::<T>();
+        let location = Location::caller();
+        let mut mock = self
+            .exclusive_borrow::<Box<T>>(alias, location, |state| {
+                state.wait_borrow_mut(location);
+                Ok(())
+            })
+            .map_err(|e| GetError::from_error(e, alias))?;
+        if let Ok(mut registry) = self.registry_mut() {
+            if let Some(call_sites) = registry
+                .resolve(alias)
+                .and_then(|id| registry.call_sites.get_mut(&id))
+            {
+                call_sites.remove(location);
+            }
+        }
+        Ok(f(mock.as_mut()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::SingletonManager;
+
+    trait Clock: Send + Sync {
+        fn now(&self) -> u64;
+    }
+
+    struct SystemClock;
+
+    impl Clock for SystemClock {
+        fn now(&self) -> u64 {
+            1
+        }
+    }
+
+    #[derive(Default)]
+    struct MockClock {
+        now: u64,
+    }
+
+    impl Clock for MockClock {
+        fn now(&self) -> u64 {
+            self.now
+        }
+    }
+
+    #[test]
+    fn test_mock_replaces_binding() {
+        let manager = SingletonManager::new();
+        manager.bind::<dyn Clock>(Box::new(SystemClock)).unwrap();
+        manager
+            .bind_mock::<dyn Clock>(Box::new(MockClock::default()))
+            .unwrap();
+        manager.assert_mock_retrieved::<dyn Clock>(0);
+
+        manager.with_mock::<dyn Clock, _>(|_| ()).unwrap();
+        let clock = manager.get_bound::<dyn Clock>().unwrap();
+        assert_eq!(0, clock.now());
+        drop(clock);
+        assert_eq!(0, manager.get_bound::<dyn Clock>().unwrap().now());
+        assert_eq!(2, manager.mock_retrievals::<dyn Clock>().len());
+        manager.assert_mock_retrieved::<dyn Clock>(2);
+    }
+
+    #[test]
+    #[should_panic(expected = "was retrieved 0 times instead of 1")]
+    fn test_mock_not_retrieved() {
+        let manager = SingletonManager::new();
+        manager
+            .bind_mock::<dyn Clock>(Box::new(MockClock { now: 5 }))
+            .unwrap();
+        manager.assert_mock_retrieved::<dyn Clock>(1);
+    }
+}
@@ -0,0 +1,278 @@
+//! # Modules
+//! Composing the registrations of an application from modules.
+//!
+//! A module groups the registrations of a part of the application, e.g. the database pool and
+//! the repositories using it. Installing a module registers all of its services atomically, and
+//! the singleton manager keeps track of the aliases each module owns, so the module can be
+//! uninstalled again as a whole.
+//! ```
+//! use singleton_manager::{Module, Registrar, SingletonManager};
+//!
+//! #[derive(Default)]
+//! struct DbModule;
+//!
+//! impl Module for DbModule {
+//!     fn install(&self, registrar: &mut Registrar) -> singleton_manager::Result<()> {
+//!         registrar.set("db_url", "postgres://localhost".to_string())?;
+//!         registrar.set_factory("db_pool", || Box::new(10_usize))
+//!     }
+//! }
+//!
+//! let manager = SingletonManager::new();
+//! manager.install(DbModule::default()).unwrap();
+//! assert_eq!(10, *manager.get_ref::<usize>("db_pool").unwrap());
+//!
+//! manager.uninstall(DbModule::default().name()).unwrap();
+//! assert!(!manager.has("db_pool"));
+//! ```
+use crate::{Error, Registry, Result, SingletonManager, Transaction, Uuid};
+use std::any::Any;
+use std::panic::Location;
+
+/// A group of registrations installed and uninstalled as a whole.
+pub trait Module: Send + Sync {
+    /// The name the module is installed under, the type name of the module by default.
+    fn name(&self) -> &'static str {
+        std::any::type_name::<Self>()
+    }
+
+    /// Registering the services of the module.
+    fn install(&self, registrar: &mut Registrar) -> Result<()>;
+
+    /// Called before the services of the module are removed when uninstalling it, e.g. to flush
+    /// them.
+    fn uninstall(&self, _manager: &SingletonManager) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// The registrations of a module being installed.
+/// The registrations are applied atomically once `Module::install` succeeds.
+#[derive(Default)]
+pub struct Registrar {
+    transaction: Transaction,
+}
+
+impl Registrar {
+    /// Registering a service, as `SingletonManager::set` does.
+    #[track_caller]
+    pub fn set<T: Send + Sync + 'static>(&mut self, service_name: &str, service: T) -> Result<()> {
+        self.transaction.set(service_name, service)
+    }
+
+    /// Registering a factory, as `SingletonManager::set_factory` does.
+    #[track_caller]
+    pub fn set_factory<F: 'static + Fn() -> Box<dyn Any + Send + Sync> + Send + Sync>(
+        &mut self,
+        service_name: &str,
+        factory: F,
+    ) -> Result<()> {
+        self.transaction.set_factory(service_name, factory)
+    }
+}
+
+/// A module installed in the singleton manager, together with the registrations it owns.
+pub(crate) struct InstalledModule {
+    module: Box<dyn Module>,
+    ids: Vec<Uuid>,
+}
+
+impl InstalledModule {
+    /// Forgetting the registration, e.g. when it is taken out of the singleton manager.
+    pub(crate) fn forget(&mut self, id: &Uuid) {
+        self.ids.retain(|owned| owned != id);
+    }
+
+    /// True if the module registered the service.
+    #[cfg(test)]
+    pub(crate) fn owns(&self, id: &Uuid) -> bool {
+        self.ids.contains(id)
+    }
+}
+
+impl SingletonManager {
+    /// Installing a module, registering all of its services or none.
+    /// Fails with `Error::ModuleAlreadyInstalled` if a module with the same name is installed.
+    pub fn install<M: Module + 'static>(&self, module: M) -> Result<()> {
+        let name = module.name();
+        if self.registry()?.modules.contains_key(name) {
+            return Err(Error::ModuleAlreadyInstalled(name.to_string()));
+        }
+        let mut registrar = Registrar::default();
+        module.install(&mut registrar)?;
+        let mut registry = self.registry_mut()?;
+        if registry.modules.contains_key(name) {
+            return Err(Error::ModuleAlreadyInstalled(name.to_string()));
+        }
+        let ids = registry.apply(registrar.transaction)?;
+        registry.modules.insert(
+            name.to_string(),
+            InstalledModule {
+                module: Box::new(module),
+                ids,
+            },
+        );
+        Ok(())
+    }
+
+    /// Uninstalling a module, removing the services it owns in the reverse order of their
+    /// registration, returning their aliases in that order.
+    /// `Module::uninstall` is called before the services are removed, and the services are
+    /// dropped after the singleton manager is unlocked.
+    ///
+    /// Fails with `Error::ModuleNotInstalled` if the module is not installed. Nothing is removed
+    /// if any of the services is borrowed or frozen.
+    #[track_caller]
+    pub fn uninstall(&self, name: &str) -> Result<Vec<String>> {
+        let location = Location::caller();
+        let installed = {
+            let mut registry = self.registry_mut()?;
+            let installed = registry
+                .modules
+                .remove(name)
+                .ok_or_else(|| Error::ModuleNotInstalled(name.to_string()))?;
+            if let Err(e) = registry.check_removable(&registry.module_services(&installed)) {
+                registry.modules.insert(name.to_string(), installed);
+                return Err(e);
+            }
+            installed
+        };
+        let torn_down = installed.module.uninstall(self).and_then(|_| {
+            let mut registry = self.registry_mut()?;
+            let services = registry.module_services(&installed);
+            Ok((registry.teardown(&services, location)?, services))
+        });
+        match torn_down {
+            Ok((torn_down, services)) => {
+                torn_down.into_iter().for_each(drop);
+                Ok(services.into_iter().map(|(_, alias)| alias).collect())
+            }
+            Err(e) => {
+                if let Ok(mut registry) = self.registry_mut() {
+                    registry.modules.insert(name.to_string(), installed);
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// The names of the installed modules, in alphabetical order.
+    pub fn modules(&self) -> Vec<String> {
+        let mut modules = self
+            .registry()
+            .map(|registry| registry.modules.keys().cloned().collect::<Vec<_>>())
+            .unwrap_or_default();
+        modules.sort();
+        modules
+    }
+
+    /// The aliases owned by an installed module, in the order they were registered.
+    pub fn module_aliases(&self, name: &str) -> Result<Vec<String>> {
+        let registry = self.registry()?;
+        let installed = registry
+            .modules
+            .get(name)
+            .ok_or_else(|| Error::ModuleNotInstalled(name.to_string()))?;
+        Ok(installed
+            .ids
+            .iter()
+            .filter_map(|id| registry.alias_of(id))
+            .map(str::to_string)
+            .collect())
+    }
+}
+
+impl Registry {
+    /// The services owned by the module, in the reverse order of their registration.
+    fn module_services(&self, installed: &InstalledModule) -> Vec<(Uuid, String)> {
+        installed
+            .ids
+            .iter()
+            .rev()
+            .filter_map(|id| Some((*id, self.alias_of(id)?.to_string())))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Error, Module, Registrar, SingletonManager};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    struct CacheModule {
+        uninstalled: Arc<AtomicBool>,
+    }
+
+    impl Module for CacheModule {
+        fn name(&self) -> &'static str {
+            "cache"
+        }
+
+        fn install(&self, registrar: &mut Registrar) -> crate::Result<()> {
+            registrar.set("cache_size", 64_usize)?;
+            registrar.set_factory("cache", || Box::new(Vec::<u8>::new()))
+        }
+
+        fn uninstall(&self, manager: &SingletonManager) -> crate::Result<()> {
+            manager.get_mut::<Vec<u8>>("cache")?.clear();
+            self.uninstalled.store(true, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_install_and_uninstall_module() {
+        let manager = SingletonManager::new();
+        let uninstalled = Arc::new(AtomicBool::new(false));
+        manager
+            .install(CacheModule {
+                uninstalled: uninstalled.clone(),
+            })
+            .unwrap();
+        assert!(matches!(
+            manager.install(CacheModule {
+                uninstalled: uninstalled.clone(),
+            }),
+            Err(Error::ModuleAlreadyInstalled(_))
+        ));
+        assert_eq!(vec!["cache"], manager.modules());
+        assert_eq!(
+            vec!["cache_size", "cache"],
+            manager.module_aliases("cache").unwrap()
+        );
+
+        let size = manager.get_ref::<usize>("cache_size").unwrap();
+        assert!(matches!(
+            manager.uninstall("cache"),
+            Err(Error::AlreadyBorrowed(_, _))
+        ));
+        drop(size);
+        assert!(!uninstalled.load(Ordering::SeqCst));
+        assert_eq!(
+            vec!["cache", "cache_size"],
+            manager.uninstall("cache").unwrap()
+        );
+        assert!(uninstalled.load(Ordering::SeqCst));
+        assert!(!manager.has("cache_size"));
+        assert!(manager.modules().is_empty());
+        assert!(matches!(
+            manager.uninstall("cache"),
+            Err(Error::ModuleNotInstalled(_))
+        ));
+    }
+
+    #[test]
+    fn test_conflicting_module_is_not_installed() {
+        let mut manager = SingletonManager::new();
+        manager.set("cache", 1_u32).unwrap();
+        assert!(matches!(
+            manager.install(CacheModule {
+                uninstalled: Arc::default(),
+            }),
+            Err(Error::ServiceAlreadyExists(_))
+        ));
+        assert!(!manager.has("cache_size"));
+        assert!(manager.modules().is_empty());
+    }
+}
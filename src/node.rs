@@ -0,0 +1,124 @@
+//! # Node bindings
+//! Calling the services of the global instance from Node, behind the `napi` feature.
+//!
+//! Built as a Node addon, the crate exports `services`, listing the services with an adapter,
+//! see `SingletonManager::set_script_adapter`, and `call`, calling their methods, so plugins
+//! written in JavaScript reuse the services of the host.
+//!
+//! The addon is built as a dynamic library, e.g.
+//! `cargo rustc --release --features napi --crate-type cdylib`, and renamed to end in `.node`.
+//! ```js
+//! const sm = require("./singleton_manager.node");
+//!
+//! if (sm.services().includes("visits")) {
+//!     sm.call("visits", "add", [1]);
+//! }
+//! ```
+use crate::{sm, Error, ScriptValue};
+use napi::bindgen_prelude::{FromNapiValue, Null, ToNapiValue, TypeName, ValidateNapiValue};
+use napi::{sys, JsUnknown, Status, ValueType};
+use napi_derive::napi;
+
+/// The largest integer a JavaScript number holds exactly.
+const MAX_SAFE_INTEGER: f64 = 9_007_199_254_740_991.0;
+
+impl TypeName for ScriptValue {
+    fn type_name() -> &'static str {
+        "ScriptValue"
+    }
+
+    fn value_type() -> ValueType {
+        ValueType::Unknown
+    }
+}
+
+impl ValidateNapiValue for ScriptValue {}
+
+impl FromNapiValue for ScriptValue {
+    unsafe fn from_napi_value(env: sys::napi_env, value: sys::napi_value) -> napi::Result<Self> {
+        match JsUnknown::from_napi_value(env, value)?.get_type()? {
+            ValueType::Null | ValueType::Undefined => Ok(Self::None),
+            ValueType::Boolean => bool::from_napi_value(env, value).map(Self::Bool),
+            ValueType::Number => {
+                let number = f64::from_napi_value(env, value)?;
+                match number.fract() == 0.0 && number.abs() <= MAX_SAFE_INTEGER {
+                    true => Ok(Self::Int(number as i64)),
+                    false => Ok(Self::Float(number)),
+                }
+            }
+            ValueType::String => String::from_napi_value(env, value).map(Self::Str),
+            ValueType::Object if is_array(env, value)? => {
+                Vec::<ScriptValue>::from_napi_value(env, value).map(Self::List)
+            }
+            other => Err(napi::Error::new(
+                Status::InvalidArg,
+                format!("Can not pass a `{}` to a service", other),
+            )),
+        }
+    }
+}
+
+impl ToNapiValue for ScriptValue {
+    unsafe fn to_napi_value(env: sys::napi_env, value: Self) -> napi::Result<sys::napi_value> {
+        match value {
+            Self::None => Null::to_napi_value(env, Null),
+            Self::Bool(value) => bool::to_napi_value(env, value),
+            Self::Int(value) => i64::to_napi_value(env, value),
+            Self::Float(value) => f64::to_napi_value(env, value),
+            Self::Str(value) => String::to_napi_value(env, value),
+            Self::List(values) => Vec::to_napi_value(env, values),
+        }
+    }
+}
+
+unsafe fn is_array(env: sys::napi_env, value: sys::napi_value) -> napi::Result<bool> {
+    let mut is_array = false;
+    match sys::napi_is_array(env, value, &mut is_array) {
+        sys::Status::napi_ok => Ok(is_array),
+        status => Err(napi::Error::new(
+            Status::from(status),
+            "Failed to check for an array".to_string(),
+        )),
+    }
+}
+
+fn napi_err(e: Error) -> napi::Error {
+    match e {
+        e if e.is_not_found() => napi::Error::new(Status::InvalidArg, e.to_string()),
+        e => napi::Error::from_reason(e.to_string()),
+    }
+}
+
+/// Calling a method of a service.
+#[napi]
+pub fn call(alias: String, method: String, args: Vec<ScriptValue>) -> napi::Result<ScriptValue> {
+    sm().call_script(&alias, &method, &args).map_err(napi_err)
+}
+
+/// The aliases of the services that can be called.
+#[napi]
+pub fn services() -> Vec<String> {
+    sm().scriptable()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{call, services};
+    use crate::{sm, ScriptValue};
+
+    #[test]
+    fn test_node_calls_services_through_adapters() {
+        sm().set("node_counter", 1_i64).unwrap();
+        sm().set_script_adapter::<i64, _>("node_counter", |counter, _, _| {
+            *counter += 1;
+            Ok(ScriptValue::Int(*counter))
+        })
+        .unwrap();
+
+        assert!(services().contains(&"node_counter".to_string()));
+        let counted = call("node_counter".to_string(), "inc".to_string(), vec![]).unwrap();
+        assert_eq!(ScriptValue::Int(2), counted);
+        let missing = call("node_missing".to_string(), "inc".to_string(), vec![]);
+        assert_eq!(napi::Status::InvalidArg, missing.unwrap_err().status);
+    }
+}
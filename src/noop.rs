@@ -0,0 +1,145 @@
+//! # No-op implementations
+//! Disabling optional subsystems while their dependents keep resolving them.
+//!
+//! The `noop!` macro generates an implementation of a trait doing nothing, where every method
+//! returns the default of its return type, and makes it the no-op implementation of the
+//! `dyn Trait`. `SingletonManager::bind_noop` binds it in place of the real subsystem, e.g.
+//! metrics or tracing in tests and minimal builds, so the code resolving the subsystem keeps
+//! working without knowing it is disabled.
+//!
+//! No-op bindings are tagged with `noop=true`, so they show up as such in the information about
+//! the services and in the dumps of the registry.
+//! ```
+//! use singleton_manager::{noop, SingletonManager};
+//!
+//! pub trait Metrics: Send + Sync {
+//!     fn increment(&self, name: &str);
+//!     fn gauge(&self, name: &str) -> f64;
+//! }
+//!
+//! noop! {
+//!     pub struct NoopMetrics: dyn Metrics {
+//!         fn increment(&self, name: &str);
+//!         fn gauge(&self, name: &str) -> f64;
+//!     }
+//! }
+//!
+//! let manager = SingletonManager::new();
+//! manager.bind_noop::<dyn Metrics>().unwrap();
+//!
+//! let metrics = manager.get_bound::<dyn Metrics>().unwrap();
+//! metrics.increment("requests");
+//! assert_eq!(0.0, metrics.gauge("queue_depth"));
+//! assert!(manager.is_noop::<dyn Metrics>());
+//! ```
+use crate::{bound_alias, SetError, SingletonManager};
+use std::panic::Location;
+
+/// The tag of the services bound by `SingletonManager::bind_noop`.
+const NOOP_TAG: (&str, &str) = ("noop", "true");
+
+/// A type with an implementation doing nothing, usually a `dyn Trait` given one by `noop!`.
+pub trait Noop {
+    /// Creating the implementation doing nothing.
+    fn noop() -> Box<Self>;
+}
+
+/// Generating an implementation of a trait doing nothing, and making it the no-op
+/// implementation of the `dyn Trait`, see `SingletonManager::bind_noop`.
+///
+/// Only methods taking `&self` can be implemented, and their return types must implement
+/// `Default`.
+#[macro_export]
+macro_rules! noop {
+    (
+        $(#[$attr:meta])*
+        $vis:vis struct $name:ident : dyn $trait:path {
+            $(fn $method:ident(&self $(, $arg:ident : $arg_t:ty)* $(,)?) $(-> $ret:ty)?;)*
+        }
+    ) => {
+        $(#[$attr])*
+        #[derive(Debug, Clone, Copy, Default)]
+        $vis struct $name;
+
+        impl $trait for $name {
+            $(
+                #[allow(unused_variables)]
+                fn $method(&self $(, $arg: $arg_t)*) $(-> $ret)? {
+                    ::std::default::Default::default()
+                }
+            )*
+        }
+
+        impl $crate::Noop for dyn $trait {
+            fn noop() -> ::std::boxed::Box<Self> {
+                ::std::boxed::Box::new($name)
+            }
+        }
+    };
+}
+
+impl SingletonManager {
+    /// Binding the no-op implementation of a `T`, as `bind` does with a service.
+    #[track_caller]
+    pub fn bind_noop<T: ?Sized + Noop + Send + Sync + 'static>(
+        &self,
+    ) -> std::result::Result<(), SetError> {
+        let alias = bound_alias::<T>();
+        self.store(alias, T::noop(), &[NOOP_TAG], Location::caller())
+            .map(|_| ())
+            .map_err(|e| SetError::from_error(e, alias))
+    }
+
+    /// True if the service bound as a `T` is a no-op implementation bound by `bind_noop`.
+    pub fn is_noop<T: ?Sized + 'static>(&self) -> bool {
+        self.info(bound_alias::<T>())
+            .is_ok_and(|info| info.tags().get(NOOP_TAG.0).map(String::as_str) == Some(NOOP_TAG.1))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{SetError, SingletonManager};
+
+    trait Tracer: Send + Sync {
+        fn span(&self, name: &str, depth: u32) -> Option<String>;
+        fn flush(&self);
+    }
+
+    noop! {
+        struct NoopTracer: dyn Tracer {
+            fn span(&self, name: &str, depth: u32) -> Option<String>;
+            fn flush(&self);
+        }
+    }
+
+    struct StdoutTracer;
+
+    impl Tracer for StdoutTracer {
+        fn span(&self, name: &str, _depth: u32) -> Option<String> {
+            Some(name.to_string())
+        }
+
+        fn flush(&self) {}
+    }
+
+    #[test]
+    fn test_noop_bindings_resolve_and_do_nothing() {
+        let manager = SingletonManager::new();
+        assert!(!manager.is_noop::<dyn Tracer>());
+        manager.bind_noop::<dyn Tracer>().unwrap();
+        let tracer = manager.get_bound::<dyn Tracer>().unwrap();
+        assert_eq!(None, tracer.span("request", 1));
+        tracer.flush();
+        drop(tracer);
+        assert!(manager.is_noop::<dyn Tracer>());
+        assert!(matches!(
+            manager.bind::<dyn Tracer>(Box::new(StdoutTracer)),
+            Err(SetError::ServiceAlreadyExists(_))
+        ));
+
+        let manager = SingletonManager::new();
+        manager.bind::<dyn Tracer>(Box::new(StdoutTracer)).unwrap();
+        assert!(!manager.is_noop::<dyn Tracer>());
+    }
+}
@@ -0,0 +1,162 @@
+//! # Origins
+//! Checking the version of the crate that registered a service.
+//!
+//! A service shared between the crates of a workspace is an interface between them, and when
+//! the crates evolve independently, a consumer built against one version of the interface may
+//! get the service registered by a crate at an incompatible version, e.g. after a field changed
+//! its meaning. The `set_origin!` macro records the name and version of the crate invoking it
+//! as the origin of a service, and `get_compat` fails with `Error::IncompatibleVersion` unless
+//! the version satisfies a semver requirement, instead of handing out a service that silently
+//! behaves differently.
+//! ```
+//! use singleton_manager::{set_origin, GetError, SingletonManager};
+//!
+//! let mut manager = SingletonManager::new();
+//! manager.set_factory("api", || Box::new("v1".to_string())).unwrap();
+//! set_origin!(manager, "api").unwrap();
+//! let version = env!("CARGO_PKG_VERSION");
+//!
+//! assert!(manager.get_compat::<String>("api", version).is_ok());
+//! match manager.get_compat::<String>("api", ">=1000") {
+//!     Err(GetError::IncompatibleVersion(alias, requirement, Some(origin))) => {
+//!         assert_eq!("api", alias);
+//!         assert_eq!(">=1000", requirement);
+//!         assert_eq!(env!("CARGO_PKG_NAME"), origin.crate_name());
+//!     }
+//!     _ => panic!("Expected the version to be incompatible"),
+//! };
+//! ```
+use crate::{Error, GetError, Result, ServiceRef, SingletonManager};
+use semver::{Version, VersionReq};
+use std::fmt::{Display, Formatter};
+use std::panic::Location;
+
+/// The crate that registered a service, and its version, see `SingletonManager::set_origin`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServiceOrigin {
+    crate_name: String,
+    version: Version,
+}
+
+impl ServiceOrigin {
+    /// The name of the crate.
+    pub fn crate_name(&self) -> &str {
+        &self.crate_name
+    }
+
+    /// The version of the crate.
+    pub fn version(&self) -> &Version {
+        &self.version
+    }
+}
+
+impl Display for ServiceOrigin {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "`{}` {}", self.crate_name, self.version)
+    }
+}
+
+impl SingletonManager {
+    /// Recording the crate that registered the service, and its version, replacing the origin
+    /// recorded before. Usually called through the `set_origin!` macro, passing the crate
+    /// invoking it.
+    /// Fails with `Error::InvalidVersion` if the version is not a semver version.
+    pub fn set_origin(&self, service_name: &str, crate_name: &str, version: &str) -> Result<()> {
+        let version = Version::parse(version)
+            .map_err(|e| Error::InvalidVersion(service_name.into(), e.to_string()))?;
+        let id = self.service_id(service_name)?;
+        self.registry_mut()?.origins.insert(
+            id,
+            ServiceOrigin {
+                crate_name: crate_name.to_string(),
+                version,
+            },
+        );
+        Ok(())
+    }
+
+    /// The crate that registered the service, if it was recorded.
+    pub fn origin(&self, service_name: &str) -> Option<ServiceOrigin> {
+        let registry = self.registry().ok()?;
+        let id = registry.resolve(service_name)?;
+        registry.origins.get(&id).cloned()
+    }
+
+    /// Getting a shared reference to the service, as `get_ref` does, if the version of the crate
+    /// that registered it satisfies the semver requirement, e.g. `>=2, <3`.
+    /// Fails with `GetError::IncompatibleVersion` if it does not, or if no origin is recorded
+    /// for the service, and with `GetError::InvalidVersion` if the requirement can not be parsed.
+    #[track_caller]
+    pub fn get_compat<T: 'static>(
+        &self,
+        service_name: &str,
+        requirement: &str,
+    ) -> std::result::Result<ServiceRef<'_, T>, GetError> {
+        let location = Location::caller();
+        let version_req = VersionReq::parse(requirement).map_err(|e| {
+            GetError::from_error(
+                Error::InvalidVersion(service_name.into(), e.to_string()),
+                service_name,
+            )
+        })?;
+        let id = self
+            .serving_id(service_name, location)
+            .map_err(|e| GetError::from_error(e, service_name))?;
+        let origin = self
+            .registry()
+            .map_err(|e| GetError::from_error(e, service_name))?
+            .origins
+            .get(&id)
+            .cloned();
+        match origin {
+            Some(origin) if version_req.matches(&origin.version) => {
+                self.get_ref_by_alias(service_name)
+            }
+            origin => Err(GetError::IncompatibleVersion(
+                service_name.into(),
+                requirement.to_string(),
+                origin,
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Error, GetError, SingletonManager};
+
+    #[test]
+    fn test_get_compat_checks_the_version() {
+        let mut manager = SingletonManager::new();
+        manager.set_factory("api", || Box::new(2_u32)).unwrap();
+        assert!(matches!(
+            manager.get_compat::<u32>("api", ">=2"),
+            Err(GetError::IncompatibleVersion(_, _, None))
+        ));
+        assert!(matches!(
+            manager.set_origin("api", "api-impl", "two"),
+            Err(Error::InvalidVersion(alias, _)) if alias == "api"
+        ));
+
+        manager.set_origin("api", "api-impl", "2.4.1").unwrap();
+        assert_eq!(
+            "2.4.1",
+            manager.origin("api").unwrap().version().to_string()
+        );
+        assert_eq!(2, *manager.get_compat::<u32>("api", ">=2, <3").unwrap());
+        let e = manager.get_compat::<u32>("api", "^3").unwrap_err();
+        assert!(e.is_incompatible());
+        assert_eq!(
+            "Service `api` registered by `api-impl` 2.4.1 does not satisfy the version requirement `^3`",
+            e.to_string()
+        );
+        assert!(matches!(
+            manager.get_compat::<u32>("api", "three"),
+            Err(GetError::InvalidVersion(alias, _)) if alias == "api"
+        ));
+        assert!(manager
+            .get_compat::<u32>("missing", "^3")
+            .unwrap_err()
+            .is_not_found());
+    }
+}
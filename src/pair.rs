@@ -0,0 +1,103 @@
+//! # Pairs
+//! Locking two services for one operation without deadlocking.
+//!
+//! Holding `get_mut` of one service while waiting for `get_mut` of another deadlocks as soon as
+//! another thread locks the same services in the opposite order, e.g. moving jobs between a queue
+//! and a pool. `SingletonManager::lock_pair` locks both services in the order of their ids,
+//! whatever order they are asked for in, so threads locking the same pair never wait on each
+//! other in a cycle.
+//! ```
+//! use singleton_manager::SingletonManager;
+//!
+//! let mut manager = SingletonManager::new();
+//! manager.set_factory("queue", || Box::new(vec![1_u32, 2])).unwrap();
+//! manager.set_factory("pool", || Box::new(Vec::<u32>::new())).unwrap();
+//!
+//! let (mut queue, mut pool) = manager
+//!     .lock_pair::<Vec<u32>, Vec<u32>>("queue", "pool")
+//!     .unwrap();
+//! pool.extend(queue.drain(..));
+//! assert_eq!(vec![1, 2], *pool);
+//! ```
+use crate::{Error, GetError, ServiceRefMut, SingletonManager, Uuid};
+use std::panic::Location;
+
+impl SingletonManager {
+    /// Getting exclusive references to two singletons, as `get_mut` does, locking them in the
+    /// order of their ids.
+    /// Locking the same service twice fails with `GetError::AlreadyBorrowed`.
+    #[track_caller]
+    pub fn lock_pair<A: 'static, B: 'static>(
+        &self,
+        first: &str,
+        second: &str,
+    ) -> std::result::Result<(ServiceRefMut<'_, A>, ServiceRefMut<'_, B>), GetError> {
+        let location = Location::caller();
+        let first_id = self
+            .serving_id(first, location)
+            .map_err(|e| GetError::from_error(e, first))?;
+        let second_id = self
+            .serving_id(second, location)
+            .map_err(|e| GetError::from_error(e, second))?;
+        if first_id == second_id {
+            return Err(GetError::from_error(
+                Error::AlreadyBorrowed(second.into(), location),
+                second,
+            ));
+        }
+        if first_id < second_id {
+            let first = self.lock_id::<A>(&first_id, first, location)?;
+            Ok((first, self.lock_id::<B>(&second_id, second, location)?))
+        } else {
+            let second = self.lock_id::<B>(&second_id, second, location)?;
+            Ok((self.lock_id::<A>(&first_id, first, location)?, second))
+        }
+    }
+
+    /// Getting an exclusive reference to the service by its id, waiting for other borrows.
+    fn lock_id<T: 'static>(
+        &self,
+        id: &Uuid,
+        service_name: &str,
+        location: &'static Location<'static>,
+    ) -> std::result::Result<ServiceRefMut<'_, T>, GetError> {
+        self.exclusive_borrow_id(id, service_name, location, |state| {
+            state.wait_borrow_mut(location);
+            Ok(())
+        })
+        .map_err(|e: Error| GetError::from_error(e, service_name))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{GetError, SingletonManager};
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn test_pairs_locked_in_opposite_orders_do_not_deadlock() {
+        let manager = Arc::new(SingletonManager::new());
+        manager.set_send_factory("queue", || 0_u64).unwrap();
+        manager.set_send_factory("pool", || 0_u64).unwrap();
+
+        let threads = [("queue", "pool"), ("pool", "queue")].map(|(first, second)| {
+            let manager = manager.clone();
+            thread::spawn(move || {
+                for _ in 0..1000 {
+                    let (mut a, mut b) = manager.lock_pair::<u64, u64>(first, second).unwrap();
+                    *a += 1;
+                    *b += 1;
+                }
+            })
+        });
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        assert_eq!(2000, *manager.get_ref::<u64>("queue").unwrap());
+        assert!(matches!(
+            manager.lock_pair::<u64, u64>("pool", "pool"),
+            Err(GetError::AlreadyBorrowed(_, _))
+        ));
+    }
+}
@@ -0,0 +1,155 @@
+//! # Prefixes
+//! Operating on all services whose alias starts with a prefix.
+//!
+//! Aliases are commonly namespaced by a prefix, e.g. `tenant:acme:` for the services of a
+//! tenant, or `cache:` for the caches. The registry keeps its aliases in order, so the services
+//! under a prefix are found by a range scan, without going over every registration.
+//! ```
+//! use singleton_manager::SingletonManager;
+//!
+//! let mut manager = SingletonManager::new();
+//! manager.set_factory("tenant:acme:db", || Box::new(5432_u16)).unwrap();
+//! manager.set_factory("tenant:acme:cache", || Box::new(6379_u16)).unwrap();
+//! manager.set_factory("tenant:globex:db", || Box::new(5433_u16)).unwrap();
+//!
+//! let aliases: Vec<_> = manager
+//!     .entries_with_prefix("tenant:acme:")
+//!     .iter()
+//!     .map(|info| info.alias().to_string())
+//!     .collect();
+//! assert_eq!(vec!["tenant:acme:cache", "tenant:acme:db"], aliases);
+//!
+//! manager.remove_prefix("tenant:acme:").unwrap();
+//! assert_eq!(1, manager.count());
+//! ```
+use crate::{Registry, Result, ServiceInfo, SingletonManager, Uuid};
+use std::cmp::Reverse;
+use std::ops::Bound;
+use std::panic::Location;
+use std::sync::PoisonError;
+
+impl Registry {
+    /// The services whose alias starts with the prefix, in alias order.
+    fn with_prefix(&self, prefix: &str) -> Vec<(Uuid, String)> {
+        self.prefix_index
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .range::<str, _>((Bound::Included(prefix), Bound::Unbounded))
+            .take_while(|alias| alias.starts_with(prefix))
+            .filter_map(|alias| Some((*self.alias.get(alias)?, alias.to_string())))
+            .collect()
+    }
+
+    /// The services whose alias starts with the prefix, in reverse startup phase order, and
+    /// within a phase in reverse alias order.
+    fn with_prefix_in_shutdown_order(&self, prefix: &str) -> Vec<(Uuid, String)> {
+        let mut services = self.with_prefix(prefix);
+        services.reverse();
+        services.sort_by_key(|(id, _)| Reverse(self.phases.get(id).copied().unwrap_or_default()));
+        services
+    }
+}
+
+impl SingletonManager {
+    /// The information about the registrations whose alias starts with the prefix, in alias
+    /// order.
+    pub fn entries_with_prefix(&self, prefix: &str) -> Vec<ServiceInfo> {
+        self.registry()
+            .map(|registry| {
+                registry
+                    .with_prefix(prefix)
+                    .iter()
+                    .map(|(id, alias)| registry.info(alias, id))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Removing the registrations whose alias starts with the prefix, in reverse startup phase
+    /// order, and within a phase in reverse alias order, returning the aliases removed.
+    /// The services are dropped after the singleton manager is unlocked, so their `Drop` may use
+    /// the singleton manager.
+    ///
+    /// Nothing is removed if any of the services is borrowed or frozen.
+    #[track_caller]
+    pub fn remove_prefix(&self, prefix: &str) -> Result<Vec<String>> {
+        let location = Location::caller();
+        let mut registry = self.registry_mut()?;
+        let services = registry.with_prefix_in_shutdown_order(prefix);
+        let torn_down = registry.teardown(&services, location)?;
+        drop(registry);
+        torn_down.into_iter().for_each(drop);
+        Ok(services.into_iter().map(|(_, alias)| alias).collect())
+    }
+
+    /// Shutting down the instantiated services whose alias starts with the prefix, in the order
+    /// of `remove_prefix`, returning the aliases shut down.
+    /// The instances are dropped after the singleton manager is unlocked. Services with a factory
+    /// stay registered and are instantiated again when needed, services set directly are removed.
+    ///
+    /// Nothing is shut down if any of the services is borrowed or frozen.
+    #[track_caller]
+    pub fn shutdown_prefix(&self, prefix: &str) -> Result<Vec<String>> {
+        let location = Location::caller();
+        let mut registry = self.registry_mut()?;
+        let mut services = registry.with_prefix_in_shutdown_order(prefix);
+        services.retain(|(id, _)| registry.singletons.contains_key(id));
+        registry.check_removable(&services)?;
+        let mut shut_down = Vec::with_capacity(services.len());
+        for service in &services {
+            shut_down.extend(registry.shut_down(service, location)?);
+        }
+        drop(registry);
+        shut_down.into_iter().for_each(drop);
+        Ok(services.into_iter().map(|(_, alias)| alias).collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Phase, SingletonManager};
+
+    #[test]
+    fn test_prefix_operations_only_touch_the_prefix() {
+        let mut manager = SingletonManager::new();
+        manager
+            .set_factory("tenant:acme:db", || Box::new(5432_u16))
+            .unwrap();
+        manager.set("tenant:acme:region", "eu".to_string()).unwrap();
+        manager.set("tenant:acme", 1_u32).unwrap();
+        manager.set("tenant:acmf:db", 5433_u16).unwrap();
+        manager
+            .set_phase("tenant:acme:db", Phase::Infrastructure)
+            .unwrap();
+        manager.get_ref::<u16>("tenant:acme:db").unwrap();
+        assert!(manager.entries_with_prefix("tenant:zeta:").is_empty());
+
+        assert_eq!(
+            vec!["tenant:acme:region", "tenant:acme:db"],
+            manager.shutdown_prefix("tenant:acme:").unwrap()
+        );
+        assert!(!manager.is_instantiated("tenant:acme:db"));
+        assert!(!manager.has("tenant:acme:region"));
+        assert_eq!(3, manager.count());
+
+        manager
+            .rename("tenant:acmf:db", "tenant:acme:cache")
+            .unwrap();
+        let aliases: Vec<_> = manager
+            .entries_with_prefix("tenant:acme:")
+            .iter()
+            .map(|info| info.alias().to_string())
+            .collect();
+        assert_eq!(vec!["tenant:acme:cache", "tenant:acme:db"], aliases);
+
+        let cache = manager.get_ref::<u16>("tenant:acme:cache").unwrap();
+        assert!(manager.remove_prefix("tenant:acme:").is_err());
+        drop(cache);
+        assert_eq!(
+            vec!["tenant:acme:cache", "tenant:acme:db"],
+            manager.remove_prefix("tenant:acme:").unwrap()
+        );
+        assert_eq!(1, manager.count());
+        assert!(manager.has("tenant:acme"));
+    }
+}
@@ -0,0 +1,165 @@
+//! # Preheating
+//! Constructing services on background threads during startup.
+//!
+//! Services that take seconds to construct, e.g. a search index loaded from disk, would make the
+//! first request using them pay for the construction. Preheating runs their factories on
+//! background threads while the application starts, and the first `get` takes over the result,
+//! waiting for it if the construction has not finished yet.
+//!
+//! Factories are shareable across threads, so any factory can be preheated.
+//! ```
+//! use singleton_manager::sm;
+//!
+//! sm().set_send_factory("preheated_index", || vec![1_u32, 2, 3]).unwrap();
+//! sm().preheat(["preheated_index"]).unwrap();
+//!
+//! assert_eq!(3, sm().get::<Vec<u32>>("preheated_index").unwrap().len());
+//! ```
+use crate::{Error, Result, Service, SetError, SingletonManager};
+use std::panic::{AssertUnwindSafe, Location};
+use std::sync::{Arc, Condvar, Mutex, PoisonError};
+
+enum PreheatState {
+    Running,
+    Ready(Service),
+    /// The factory panicked, or the result has already been taken.
+    Done,
+}
+
+/// The result of a factory running on a background thread.
+pub(crate) struct PreheatSlot {
+    state: Mutex<PreheatState>,
+    changed: Condvar,
+}
+
+impl PreheatSlot {
+    fn finish(&self, state: PreheatState) {
+        *self.state.lock().unwrap_or_else(PoisonError::into_inner) = state;
+        self.changed.notify_all();
+    }
+
+    /// Taking the service, waiting for the factory to finish.
+    /// Returns nothing if the factory panicked, or the service has already been taken.
+    pub(crate) fn take(&self) -> Option<Service> {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        while let PreheatState::Running = *state {
+            state = self
+                .changed
+                .wait(state)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+        match std::mem::replace(&mut *state, PreheatState::Done) {
+            PreheatState::Ready(service) => Some(service),
+            _ => None,
+        }
+    }
+}
+
+impl SingletonManager {
+    /// Setting a factory returning the service itself, instead of a boxed service.
+    /// Otherwise the factory is used as any factory set with `set_factory`.
+    #[track_caller]
+    pub fn set_send_factory<T, F>(
+        &self,
+        service_name: &str,
+        factory: F,
+    ) -> std::result::Result<(), SetError>
+    where
+        T: Send + Sync + 'static,
+        F: Fn() -> T + Send + Sync + 'static,
+    {
+        self.store_factory(
+            service_name,
+            move || Box::new(factory()) as Service,
+            Location::caller(),
+        )
+        .map(|_| ())
+        .map_err(|e| SetError::from_error(e, service_name))
+    }
+
+    /// Running the factories of the services on background threads, returning the aliases of
+    /// the services being preheated.
+    /// Services already instantiated, or already being preheated, are skipped. The first `get`
+    /// of a service takes over the result of its factory, and runs the factory itself if the
+    /// background construction panicked.
+    ///
+    /// Fails with `Error::FactoryNotSend` if any of the services has no factory that can be run
+    /// on another thread, in which case nothing is preheated.
+    pub fn preheat<'a, I>(&self, service_names: I) -> Result<Vec<String>>
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        let mut registry = self.registry_mut()?;
+        let mut preheating = Vec::new();
+        for service_name in service_names {
+            let id = registry.resolve(service_name).ok_or_else(|| {
+                Error::ServiceDoesNotExist(service_name.into(), Default::default())
+            })?;
+            if registry.singletons.contains_key(&id) || registry.preheating.contains_key(&id) {
+                continue;
+            }
+            if registry.unsync_factories.contains(&id) {
+                return Err(Error::FactoryNotSend(service_name.into()));
+            }
+            let factory = registry
+                .singleton_factories
+                .get(&id)
+                .cloned()
+                .ok_or_else(|| Error::FactoryNotSend(service_name.into()))?;
+            preheating.push((id, service_name.to_string(), factory));
+        }
+        let mut aliases = Vec::with_capacity(preheating.len());
+        for (id, alias, factory) in preheating {
+            let slot = Arc::new(PreheatSlot {
+                state: Mutex::new(PreheatState::Running),
+                changed: Condvar::new(),
+            });
+            registry.preheating.insert(id, slot.clone());
+            std::thread::spawn(move || {
+                match std::panic::catch_unwind(AssertUnwindSafe(|| factory())) {
+                    Ok(service) => slot.finish(PreheatState::Ready(service)),
+                    Err(_) => slot.finish(PreheatState::Done),
+                }
+            });
+            aliases.push(alias);
+        }
+        Ok(aliases)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Error, SingletonManager};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[test]
+    fn test_preheat_runs_factory_once() {
+        let mut manager = SingletonManager::new();
+        let runs = Arc::new(AtomicUsize::new(0));
+        let counted = runs.clone();
+        manager
+            .set_send_factory("index", move || {
+                counted.fetch_add(1, Ordering::SeqCst);
+                std::thread::sleep(Duration::from_millis(50));
+                "index".to_string()
+            })
+            .unwrap();
+        manager.set_factory("local", || Box::new(1_u32)).unwrap();
+        manager.set_memoized("keyed", |key: &u32| *key).unwrap();
+
+        assert!(matches!(
+            manager.preheat(["index", "keyed"]),
+            Err(Error::FactoryNotSend(alias)) if alias == "keyed"
+        ));
+        assert_eq!(
+            vec!["index", "local"],
+            manager.preheat(["index", "local"]).unwrap()
+        );
+        assert!(manager.preheat(["index"]).unwrap().is_empty());
+        assert_eq!(1, *manager.get::<u32>("local").unwrap());
+        assert_eq!("index", manager.get::<String>("index").unwrap());
+        assert_eq!(1, runs.load(Ordering::SeqCst));
+    }
+}
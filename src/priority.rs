@@ -0,0 +1,175 @@
+//! # Priorities
+//! Resolving multiple bindings of an alias by priority.
+//!
+//! A library registers a default, the application overrides it, and a test overrides it again.
+//! Binding a service with a priority keeps the binding with the highest priority, regardless of
+//! the order the bindings are made in, and the bindings made are kept for introspection, so it
+//! can be seen which binding won. Registrations made without a priority, e.g. by `set`, have the
+//! `DEFAULT_PRIORITY`, and bindings of the same priority are resolved by the collision policy.
+//! ```
+//! use singleton_manager::SingletonManager;
+//!
+//! let mut manager = SingletonManager::new();
+//! manager.set_with_priority("greeting", "hello from a test".to_string(), 100).unwrap();
+//! manager.set_with_priority("greeting", "hello from the library".to_string(), -100).unwrap();
+//! manager.set_with_priority("greeting", "hello from the app".to_string(), 0).unwrap();
+//!
+//! assert_eq!("hello from a test", manager.get::<String>("greeting").unwrap());
+//! assert_eq!(Some(100), manager.priority("greeting"));
+//! assert_eq!(3, manager.bindings("greeting").len());
+//! ```
+use crate::{Error, Operation, Result, SetError, SingletonManager};
+use std::panic::Location;
+
+/// The priority of registrations made without a priority.
+pub const DEFAULT_PRIORITY: i32 = 0;
+
+/// A binding of an alias made with `SingletonManager::set_with_priority`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Binding {
+    pub priority: i32,
+    /// The type name of the service bound.
+    pub type_name: &'static str,
+    /// The location of the code making the binding.
+    pub location: &'static Location<'static>,
+    /// True if the binding is the one served.
+    pub active: bool,
+}
+
+impl SingletonManager {
+    /// Binding a service to the alias with a priority, returning true if the binding won.
+    /// The binding replaces the registration already there if its priority is higher, and is
+    /// dropped if its priority is lower. If the priorities are equal the collision policy
+    /// decides.
+    ///
+    /// A registration with a lower priority is not replaced while it is borrowed or frozen.
+    #[track_caller]
+    pub fn set_with_priority<T: Send + Sync + 'static>(
+        &self,
+        service_name: &str,
+        service: T,
+        priority: i32,
+    ) -> std::result::Result<bool, SetError> {
+        let location = Location::caller();
+        self.store_with_priority(service_name, service, priority, location)
+            .map_err(|e| SetError::from_error(e, service_name))
+    }
+
+    fn store_with_priority<T: Send + Sync + 'static>(
+        &self,
+        service_name: &str,
+        service: T,
+        priority: i32,
+        location: &'static Location<'static>,
+    ) -> Result<bool> {
+        let mut registry = self.registry_mut()?;
+        let mut binding = Binding {
+            priority,
+            type_name: std::any::type_name::<T>(),
+            location,
+            active: true,
+        };
+        let mut bindings = registry
+            .bindings
+            .get(service_name)
+            .cloned()
+            .unwrap_or_default();
+        if let Some(id) = registry.alias.get(service_name).copied() {
+            let current = registry
+                .priorities
+                .get(&id)
+                .copied()
+                .unwrap_or(DEFAULT_PRIORITY);
+            let replace = if priority == current {
+                !registry.resolve_collision(service_name)?
+            } else {
+                priority > current
+            };
+            if !replace {
+                binding.active = false;
+                bindings.push(binding);
+                registry.bindings.insert(service_name.to_string(), bindings);
+                return Ok(false);
+            }
+            if registry.alias.contains_key(service_name) {
+                registry.check_removable(&[(id, service_name.to_string())])?;
+                registry.remove_alias(service_name);
+                registry.record(Operation::Remove, service_name, Some(location));
+            }
+        }
+        registry.store_service(
+            service_name,
+            Box::new(service),
+            binding.type_name,
+            &[],
+            location,
+        )?;
+        let id = registry
+            .resolve(service_name)
+            .ok_or_else(|| Error::FailedToStoreService(service_name.into()))?;
+        registry.priorities.insert(id, priority);
+        bindings
+            .iter_mut()
+            .for_each(|binding| binding.active = false);
+        bindings.push(binding);
+        registry.bindings.insert(service_name.to_string(), bindings);
+        Ok(true)
+    }
+
+    /// The priority of the registration served for the alias, if it is registered.
+    pub fn priority(&self, service_name: &str) -> Option<i32> {
+        let registry = self.registry().ok()?;
+        let id = registry.resolve(service_name)?;
+        Some(
+            registry
+                .priorities
+                .get(&id)
+                .copied()
+                .unwrap_or(DEFAULT_PRIORITY),
+        )
+    }
+
+    /// The bindings made with `set_with_priority` for the alias, highest priority first, and
+    /// bindings of the same priority in the order they were made.
+    pub fn bindings(&self, service_name: &str) -> Vec<Binding> {
+        let mut bindings = self
+            .registry()
+            .ok()
+            .and_then(|registry| registry.bindings.get(service_name).cloned())
+            .unwrap_or_default();
+        bindings.sort_by_key(|binding| std::cmp::Reverse(binding.priority));
+        bindings
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{CollisionPolicy, SingletonManager};
+
+    #[test]
+    fn test_highest_priority_wins() {
+        let mut manager = SingletonManager::new();
+        manager.set("port", 80_u16).unwrap();
+        assert!(!manager.set_with_priority("port", 81_u16, -1).unwrap());
+        assert!(manager.set_with_priority("port", 8080_u16, 10).unwrap());
+        assert!(manager.set_with_priority("port", 8081_u16, 10).is_err());
+        assert_eq!(8080, *manager.get::<u16>("port").unwrap());
+
+        manager
+            .set_collision_policy(CollisionPolicy::LastWins)
+            .unwrap();
+        assert!(manager.set_with_priority("port", 8081_u16, 10).unwrap());
+        assert_eq!(8081, *manager.get::<u16>("port").unwrap());
+
+        let bindings = manager.bindings("port");
+        assert_eq!(
+            vec![(10, false), (10, true), (-1, false)],
+            bindings
+                .iter()
+                .map(|binding| (binding.priority, binding.active))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(Some(10), manager.priority("port"));
+        assert_eq!(None, manager.priority("unknown"));
+    }
+}
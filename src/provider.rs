@@ -0,0 +1,97 @@
+//! # Closure providers
+//! Providing services from closures, without implementing `SingletonProvider` per type.
+//!
+//! `provider_fn` turns a name and a closure creating the service into a `SingletonProvider`, so
+//! services without a type of their own, or types of other crates, can be handed to `provide`
+//! like any other provider.
+//! ```
+//! use singleton_manager::{provider_fn, SingletonManager};
+//!
+//! struct Greeter {
+//!     greeting: String,
+//! }
+//!
+//! let mut manager = SingletonManager::new();
+//! manager
+//!     .provide(provider_fn("greeter", || Greeter {
+//!         greeting: "hello".to_string(),
+//!     }))
+//!     .unwrap();
+//! assert_eq!("hello", manager.get_ref::<Greeter>("greeter").unwrap().greeting);
+//! ```
+use crate::{sm, Error, Result, SingletonProvider};
+use std::marker::PhantomData;
+
+/// A provider creating the service with a closure, see `provider_fn`.
+pub struct ProviderFn<T, F> {
+    name: &'static str,
+    factory: F,
+    _service: PhantomData<fn() -> T>,
+}
+
+/// Creating a provider of the service created by the closure, registered under the name.
+/// The provider has neither a configuration nor dependencies.
+pub fn provider_fn<T, F>(name: &'static str, factory: F) -> ProviderFn<T, F>
+where
+    T: Send + Sync + 'static,
+    F: Fn() -> T,
+{
+    ProviderFn {
+        name,
+        factory,
+        _service: PhantomData,
+    }
+}
+
+impl<T, F> SingletonProvider for ProviderFn<T, F>
+where
+    T: Send + Sync + 'static,
+    F: Fn() -> T,
+{
+    type Output = T;
+    type Error = Error;
+    type Config = ();
+
+    /// Getting the service from the global singleton manager by its type, as the name is not
+    /// known without the provider. If several services are a `T`, the first alias is used.
+    #[track_caller]
+    fn service() -> Result<&'static mut T> {
+        let alias = sm()
+            .aliases_of_type::<T>()
+            .into_iter()
+            .next()
+            .ok_or_else(|| {
+                Error::ServiceDoesNotExist(std::any::type_name::<T>().into(), Default::default())
+            })?;
+        sm().get_by_alias::<T>(&alias).map_err(Error::from)
+    }
+
+    fn get_name(&self) -> &'static str {
+        self.name
+    }
+
+    fn get_service(&self, _config: Option<&()>) -> Result<T> {
+        Ok((self.factory)())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{provider_fn, sm, ProviderFn, SingletonProvider};
+
+    struct Counter(u32);
+
+    #[test]
+    fn test_provider_fn_provides_the_closure_output() {
+        sm().provide(provider_fn("provider_counter", || Counter(3)))
+            .unwrap();
+        assert_eq!(3, sm().get_ref::<Counter>("provider_counter").unwrap().0);
+        assert_eq!(
+            3,
+            ProviderFn::<Counter, fn() -> Counter>::service().unwrap().0
+        );
+        assert!(ProviderFn::<u128, fn() -> u128>::service()
+            .unwrap_err()
+            .is_not_found());
+    }
+}
@@ -0,0 +1,137 @@
+//! # Python bindings
+//! Calling the services of the global instance from Python, behind the `pyo3` feature.
+//!
+//! The `singleton_manager` Python module lists the services with an adapter, see
+//! `SingletonManager::set_script_adapter`, and calls their methods. Hosts embedding Python add
+//! the module before starting the interpreter, so plugins written in Python reuse the services
+//! of the host.
+//! ```no_run
+//! use singleton_manager::py_singleton_manager;
+//!
+//! pyo3::append_to_inittab!(py_singleton_manager);
+//! pyo3::prepare_freethreaded_python();
+//! ```
+//! ```python
+//! import singleton_manager
+//!
+//! if "visits" in singleton_manager.services():
+//!     singleton_manager.call("visits", "add", 1)
+//! ```
+// The error conversions generated by `pyfunction` are flagged as useless.
+#![allow(clippy::useless_conversion)]
+use crate::{sm, Error, ScriptValue};
+use pyo3::exceptions::{PyKeyError, PyRuntimeError, PyTypeError};
+use pyo3::prelude::*;
+use pyo3::types::{PyBool, PyList, PyTuple};
+
+impl IntoPy<PyObject> for ScriptValue {
+    fn into_py(self, py: Python<'_>) -> PyObject {
+        match self {
+            Self::None => py.None(),
+            Self::Bool(value) => value.into_py(py),
+            Self::Int(value) => value.into_py(py),
+            Self::Float(value) => value.into_py(py),
+            Self::Str(value) => value.into_py(py),
+            Self::List(values) => values.into_py(py),
+        }
+    }
+}
+
+impl FromPyObject<'_> for ScriptValue {
+    fn extract_bound(value: &Bound<'_, PyAny>) -> PyResult<Self> {
+        if value.is_none() {
+            Ok(Self::None)
+        } else if let Ok(value) = value.downcast::<PyBool>() {
+            Ok(Self::Bool(value.is_true()))
+        } else if let Ok(value) = value.extract::<i64>() {
+            Ok(Self::Int(value))
+        } else if let Ok(value) = value.extract::<f64>() {
+            Ok(Self::Float(value))
+        } else if let Ok(value) = value.extract::<String>() {
+            Ok(Self::Str(value))
+        } else if value.is_instance_of::<PyList>() || value.is_instance_of::<PyTuple>() {
+            value.extract().map(Self::List)
+        } else {
+            Err(PyTypeError::new_err(format!(
+                "Can not pass a `{}` to a service",
+                value.get_type().name()?
+            )))
+        }
+    }
+}
+
+fn py_err(e: Error) -> PyErr {
+    match e {
+        e if e.is_not_found() => PyKeyError::new_err(e.to_string()),
+        e => PyRuntimeError::new_err(e.to_string()),
+    }
+}
+
+/// Calling a method of a service, releasing the interpreter while the service runs.
+#[pyfunction]
+#[pyo3(signature = (alias, method, *args))]
+fn call(
+    py: Python<'_>,
+    alias: &str,
+    method: &str,
+    args: &Bound<'_, PyTuple>,
+) -> PyResult<ScriptValue> {
+    let args: Vec<ScriptValue> = args.extract()?;
+    py.allow_threads(|| sm().call_script(alias, method, &args))
+        .map_err(py_err)
+}
+
+/// The aliases of the services that can be called.
+#[pyfunction]
+fn services() -> Vec<String> {
+    sm().scriptable()
+}
+
+/// The `singleton_manager` Python module.
+#[pymodule]
+#[pyo3(name = "singleton_manager")]
+pub fn py_singleton_manager(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_function(wrap_pyfunction!(call, module)?)?;
+    module.add_function(wrap_pyfunction!(services, module)?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::py_singleton_manager;
+    use crate::{sm, ScriptValue};
+    use pyo3::prelude::*;
+    use pyo3::types::PyDict;
+
+    #[test]
+    fn test_python_calls_services_through_adapters() {
+        sm().set("python_greeter", "hello".to_string()).unwrap();
+        sm().set_script_adapter::<String, _>("python_greeter", |greeting, _, args| match args {
+            [ScriptValue::Str(name)] => Ok(ScriptValue::Str(format!("{} {}", greeting, name))),
+            _ => Ok(ScriptValue::List(args.to_vec())),
+        })
+        .unwrap();
+
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let module = PyModule::new_bound(py, "singleton_manager").unwrap();
+            py_singleton_manager(&module).unwrap();
+            let globals = PyDict::new_bound(py);
+            globals.set_item("sm", module).unwrap();
+            py.run_bound(
+                r#"
+assert "python_greeter" in sm.services()
+assert sm.call("python_greeter", "greet", "bob") == "hello bob"
+assert sm.call("python_greeter", "echo", 1, 2.5, True, None) == [1, 2.5, True, None]
+try:
+    sm.call("python_missing", "greet")
+    raise AssertionError("Expected a KeyError")
+except KeyError:
+    pass
+"#,
+                Some(&globals),
+                None,
+            )
+            .unwrap();
+        });
+    }
+}
@@ -0,0 +1,238 @@
+//! # Readiness
+//! Waiting for the services of a group to report they are ready.
+//!
+//! Services report their readiness with `set_ready`, e.g. once the database pool is connected
+//! or the cache is warmed up. `ready_barrier` returns a future resolving once every service of a
+//! group is ready, and `wait_ready` blocks the thread until then, so application layers can gate
+//! accepting requests on the readiness of their infrastructure. Both are woken by the changes of
+//! readiness, instead of polling.
+//!
+//! A group is ready once all of its services are, so a group without services is ready right
+//! away, and the services must be added to the group before waiting on it.
+//! ```
+//! use singleton_manager::SingletonManager;
+//! use std::time::Duration;
+//!
+//! let mut manager = SingletonManager::new();
+//! manager.set_factory("db", || Box::new("postgres".to_string())).unwrap();
+//! manager.add_to_group("db", "infrastructure").unwrap();
+//!
+//! std::thread::scope(|s| {
+//!     s.spawn(|| manager.set_ready("db", true).unwrap());
+//!     assert!(manager.wait_ready("infrastructure", Some(Duration::from_secs(5))));
+//! });
+//! ```
+use crate::{Registry, Result, SingletonManager};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Condvar, Mutex, MutexGuard, PoisonError};
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, Instant};
+
+/// Notifying the threads and tasks waiting for the readiness of a group of its changes.
+#[derive(Debug, Default)]
+pub(crate) struct ReadySignal {
+    waiting: Mutex<ReadyWaiting>,
+    changed: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct ReadyWaiting {
+    /// Counting the changes of readiness, so blocked threads notice the changes they missed.
+    generation: u64,
+    wakers: Vec<Waker>,
+}
+
+impl ReadySignal {
+    fn waiting(&self) -> MutexGuard<'_, ReadyWaiting> {
+        self.waiting.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Waking all threads and tasks waiting, after unlocking.
+    pub(crate) fn notify(&self) {
+        let wakers = {
+            let mut waiting = self.waiting();
+            waiting.generation += 1;
+            std::mem::take(&mut waiting.wakers)
+        };
+        self.changed.notify_all();
+        wakers.into_iter().for_each(Waker::wake);
+    }
+}
+
+impl Registry {
+    fn is_group_ready(&self, group: &str) -> bool {
+        self.groups
+            .get(group)
+            .into_iter()
+            .flatten()
+            .all(|id| self.ready.contains(id))
+    }
+}
+
+impl SingletonManager {
+    /// Setting whether the service is ready, waking the barriers of its groups.
+    pub fn set_ready(&self, service_name: &str, ready: bool) -> Result<()> {
+        let id = self.service_id(service_name)?;
+        let mut registry = self.registry_mut()?;
+        let changed = match ready {
+            true => registry.ready.insert(id),
+            false => registry.ready.remove(&id),
+        };
+        if changed {
+            registry.ready_signal.notify();
+        }
+        Ok(())
+    }
+
+    /// True if the service reported it is ready.
+    pub fn is_ready(&self, service_name: &str) -> bool {
+        self.registry()
+            .map(|registry| {
+                registry
+                    .resolve(service_name)
+                    .is_some_and(|id| registry.ready.contains(&id))
+            })
+            .unwrap_or(false)
+    }
+
+    /// True if every service of the group reported it is ready.
+    pub fn is_group_ready(&self, group: &str) -> bool {
+        self.registry()
+            .map(|registry| registry.is_group_ready(group))
+            .unwrap_or(false)
+    }
+
+    /// Waiting until every service of the group is ready, see the module documentation.
+    pub fn ready_barrier<'a>(&'a self, group: &'a str) -> ReadyBarrier<'a> {
+        ReadyBarrier {
+            manager: self,
+            group,
+        }
+    }
+
+    /// Blocking until every service of the group is ready, or the timeout elapsed, returning
+    /// whether the group is ready. Without a timeout, the ready timeout of the configuration
+    /// applies, see `ManagerConfig`.
+    pub fn wait_ready(&self, group: &str, timeout: Option<Duration>) -> bool {
+        let timeout = timeout.or_else(|| self.registry().ok()?.config.ready_timeout);
+        let until = timeout.map(|timeout| Instant::now() + timeout);
+        loop {
+            let (signal, generation) = match self.registry() {
+                Ok(registry) if registry.is_group_ready(group) => return true,
+                Ok(registry) => {
+                    let generation = registry.ready_signal.waiting().generation;
+                    (registry.ready_signal.clone(), generation)
+                }
+                Err(_) => return false,
+            };
+            let mut waiting = signal.waiting();
+            while waiting.generation == generation {
+                waiting = match until {
+                    Some(until) => {
+                        let remaining = until.saturating_duration_since(Instant::now());
+                        if remaining.is_zero() {
+                            drop(waiting);
+                            return self.is_group_ready(group);
+                        }
+                        signal
+                            .changed
+                            .wait_timeout(waiting, remaining)
+                            .unwrap_or_else(PoisonError::into_inner)
+                            .0
+                    }
+                    None => signal
+                        .changed
+                        .wait(waiting)
+                        .unwrap_or_else(PoisonError::into_inner),
+                };
+            }
+        }
+    }
+}
+
+/// The future resolving once every service of a group is ready, see
+/// `SingletonManager::ready_barrier`.
+#[must_use = "futures do nothing unless polled"]
+pub struct ReadyBarrier<'a> {
+    manager: &'a SingletonManager,
+    group: &'a str,
+}
+
+impl Future for ReadyBarrier<'_> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let registry = match self.manager.registry() {
+            Ok(registry) => registry,
+            // The singleton manager is poisoned, no service will report its readiness again.
+            Err(_) => return Poll::Pending,
+        };
+        if registry.is_group_ready(self.group) {
+            return Poll::Ready(());
+        }
+        // Registering while holding the lock, so no change of readiness is missed.
+        let mut waiting = registry.ready_signal.waiting();
+        if !waiting
+            .wakers
+            .iter()
+            .any(|waker| waker.will_wake(cx.waker()))
+        {
+            waiting.wakers.push(cx.waker().clone());
+        }
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::SingletonManager;
+    use std::future::Future;
+    use std::pin::pin;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::task::{Context, Wake, Waker};
+    use std::time::Duration;
+
+    #[derive(Default)]
+    struct Flag(AtomicBool);
+
+    impl Wake for Flag {
+        fn wake(self: Arc<Self>) {
+            self.0.store(true, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn test_ready_barrier_waits_for_every_service() {
+        let mut manager = SingletonManager::new();
+        manager.set("db", 5432_u16).unwrap();
+        manager.set("cache", 6379_u16).unwrap();
+        manager.add_to_group("db", "infrastructure").unwrap();
+        manager.add_to_group("cache", "infrastructure").unwrap();
+        assert!(manager.is_group_ready("empty"));
+
+        let flag = Arc::new(Flag::default());
+        let waker = Waker::from(flag.clone());
+        let mut cx = Context::from_waker(&waker);
+        let mut barrier = pin!(manager.ready_barrier("infrastructure"));
+        assert!(barrier.as_mut().poll(&mut cx).is_pending());
+        manager.set_ready("db", true).unwrap();
+        assert!(flag.0.swap(false, Ordering::SeqCst));
+        assert!(barrier.as_mut().poll(&mut cx).is_pending());
+        assert!(!manager.wait_ready("infrastructure", Some(Duration::from_millis(10))));
+
+        std::thread::scope(|s| {
+            let waiting = s.spawn(|| manager.wait_ready("infrastructure", None));
+            std::thread::sleep(Duration::from_millis(10));
+            manager.set_ready("cache", true).unwrap();
+            assert!(waiting.join().unwrap());
+        });
+        assert!(flag.0.load(Ordering::SeqCst));
+        assert!(barrier.as_mut().poll(&mut cx).is_ready());
+
+        manager.set_ready("cache", false).unwrap();
+        assert!(!manager.is_ready("cache"));
+        assert!(!manager.is_group_ready("infrastructure"));
+    }
+}
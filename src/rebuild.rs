@@ -0,0 +1,187 @@
+//! # Warm restarts
+//! Replacing the instance of a service by a new instance from its factory, without downtime.
+//!
+//! Connection pools holding credentials that expire have to be rebuilt while requests keep
+//! using them. `rebuild` runs the factory while the old instance keeps being served, then swaps
+//! the new instance in once the old one is no longer borrowed, and finally drains the old
+//! instance, if the service is registered with a `Drain` implementation, or drops it.
+//!
+//! Calling `rebuild` from a background thread keeps the caller from waiting for the factory too.
+//! ```
+//! use singleton_manager::{Drain, SingletonManager};
+//! use std::sync::atomic::{AtomicU32, Ordering};
+//!
+//! static GENERATION: AtomicU32 = AtomicU32::new(0);
+//!
+//! struct Pool {
+//!     generation: u32,
+//! }
+//!
+//! impl Drain for Pool {
+//!     fn drain(self) {
+//!         // Waiting for the connections in use, then closing them.
+//!     }
+//! }
+//!
+//! let mut manager = SingletonManager::new();
+//! manager
+//!     .set_factory("pool", || {
+//!         let generation = GENERATION.fetch_add(1, Ordering::SeqCst) + 1;
+//!         Box::new(Pool { generation })
+//!     })
+//!     .unwrap();
+//! manager.set_drain::<Pool>("pool").unwrap();
+//!
+//! assert_eq!(1, manager.get_ref::<Pool>("pool").unwrap().generation);
+//! manager.rebuild("pool").unwrap();
+//! assert_eq!(2, manager.get_ref::<Pool>("pool").unwrap().generation);
+//! ```
+use crate::unsync::ThreadBound;
+use crate::{Error, Operation, Result, Service, SingletonManager, TimedOut};
+use std::any::Any;
+use std::panic::Location;
+
+/// A service draining its work before it is dropped, once it was replaced by `rebuild`.
+pub trait Drain {
+    /// Finishing, or handing over, the work of the replaced instance.
+    fn drain(self);
+}
+
+/// Draining a type erased instance of a service.
+pub(crate) type DrainFn = fn(Box<dyn Any>);
+
+impl SingletonManager {
+    /// Registering the service as drained when replaced by `rebuild`, where the service is a `T`.
+    pub fn set_drain<T: Drain + 'static>(&self, service_name: &str) -> Result<()> {
+        let id = self.service_id(service_name)?;
+        let drain: DrainFn = |service| {
+            if let Ok(service) = service.downcast::<T>() {
+                service.drain();
+            }
+        };
+        self.registry_mut()?.drains.insert(id, drain);
+        Ok(())
+    }
+
+    /// Replacing the instance of the service by a new instance from its factory.
+    /// The old instance is served while the factory runs, and swapped out once it is no longer
+    /// borrowed, blocking until then. The old instance is drained after the swap, unless
+    /// references handed out by `get` defer dropping it until `gc`.
+    ///
+    /// Fails with `Error::NoFactoryFunctionAvailable` if the service has no factory, with
+    /// `Error::ServiceFrozen` if the service is frozen, and with `Error::WrongThread` if the
+    /// instance is bound to another thread, as only its owner may drain and drop it.
+    #[track_caller]
+    pub fn rebuild(&self, service_name: &str) -> Result<()> {
+        let location = Location::caller();
+        let (id, factory) = {
+            let registry = self.registry()?;
+            let id = registry.resolve(service_name).ok_or_else(|| {
+                Error::ServiceDoesNotExist(service_name.into(), Default::default())
+            })?;
+            registry.check_unfrozen(&id, service_name)?;
+            registry.check_instance_thread(&id, service_name)?;
+            let factory = registry
+                .singleton_factories
+                .get(&id)
+                .cloned()
+                .ok_or_else(|| Error::NoFactoryFunctionAvailable(service_name.into()))?;
+            (id, factory)
+        };
+        let service: Service = Self::execute_factory(factory.as_ref())?;
+        TimedOut::check(service.as_ref(), service_name)?;
+        self.registry()?
+            .validate(service_name, &id, service.as_ref())?;
+
+        let state = self.borrow_state(&id, service_name)?;
+        state.wait_borrow_mut(location);
+        let swapped = self.registry_mut().and_then(|mut registry| {
+            if registry.resolve(service_name) != Some(id) {
+                return Err(Error::ServiceDoesNotExist(
+                    service_name.into(),
+                    Default::default(),
+                ));
+            }
+            registry.check_instance_thread(&id, service_name)?;
+            let old = registry.drop_instance(&id);
+            registry.insert_instance(id, service);
+            registry.record(Operation::Rebuild, service_name, Some(location));
+            Ok((old, registry.drains.get(&id).copied()))
+        });
+        state.release_mut();
+        if let (Some(old), Some(drain)) = swapped? {
+            drain(ThreadBound::into_inner(old));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Drain, Error, SingletonManager};
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    struct Pool {
+        generation: u32,
+        drained: Arc<AtomicU32>,
+    }
+
+    impl Drain for Pool {
+        fn drain(self) {
+            self.drained.store(self.generation, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn test_rebuild_swaps_and_drains() {
+        let generation = Arc::new(AtomicU32::new(0));
+        let drained = Arc::new(AtomicU32::new(0));
+        let mut manager = SingletonManager::new();
+        let (next, pool_drained) = (generation.clone(), drained.clone());
+        manager
+            .set_factory("pool", move || {
+                Box::new(Pool {
+                    generation: next.fetch_add(1, Ordering::SeqCst) + 1,
+                    drained: pool_drained.clone(),
+                })
+            })
+            .unwrap();
+        manager.set_drain::<Pool>("pool").unwrap();
+
+        let old = manager.get_ref::<Pool>("pool").unwrap();
+        std::thread::scope(|s| {
+            let rebuild = s.spawn(|| manager.rebuild("pool"));
+            std::thread::sleep(Duration::from_millis(50));
+            assert_eq!(0, drained.load(Ordering::SeqCst));
+            assert_eq!(1, old.generation);
+            drop(old);
+            rebuild.join().unwrap().unwrap();
+        });
+        assert_eq!(1, drained.load(Ordering::SeqCst));
+        assert_eq!(2, manager.get_ref::<Pool>("pool").unwrap().generation);
+
+        manager.freeze("pool").unwrap();
+        assert!(manager.rebuild("pool").unwrap_err().is_frozen());
+    }
+
+    #[test]
+    fn test_rebuild_only_on_the_owner_thread() {
+        let manager = SingletonManager::new();
+        manager
+            .set_unsync_factory("handle", || std::rc::Rc::new(7_u32))
+            .unwrap();
+        assert_eq!(7, **manager.get_ref::<std::rc::Rc<u32>>("handle").unwrap());
+
+        std::thread::scope(|s| {
+            let rebuild = s.spawn(|| manager.rebuild("handle"));
+            assert!(matches!(
+                rebuild.join().unwrap(),
+                Err(Error::WrongThread(alias, _, _)) if alias == "handle"
+            ));
+        });
+        manager.rebuild("handle").unwrap();
+        assert_eq!(7, **manager.get_ref::<std::rc::Rc<u32>>("handle").unwrap());
+    }
+}
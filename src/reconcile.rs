@@ -0,0 +1,240 @@
+//! # Reconciliation
+//! Accepting the registrations of an alias by equivalent values, instead of failing.
+//!
+//! With diamond dependencies two crates often both register the same shared value, e.g. the
+//! configuration they were both handed, and the second registration fails with
+//! `Error::ServiceAlreadyExists` although nothing conflicts. A reconciler set for the type of
+//! the value decides when two values are equivalent, and how they are reconciled: keeping the
+//! value already registered, or merging the new value into it. Values that are not equivalent,
+//! or of a type without reconciler, are left to the collision policy.
+//!
+//! A merged value is validated as a value set is, and its registration removed if it fails, as
+//! the merge can not be undone. For the same reason values are never merged within a
+//! transaction, where they are left to the collision policy so a rollback restores everything.
+//! ```
+//! use singleton_manager::{Reconcile, SingletonManager};
+//!
+//! #[derive(Debug, PartialEq)]
+//! struct Config {
+//!     region: String,
+//!     features: Vec<String>,
+//! }
+//!
+//! let mut manager = SingletonManager::new();
+//! manager
+//!     .set_reconciler::<Config, _>(
+//!         |existing, new| existing.region == new.region,
+//!         Reconcile::merge(|existing: &mut Config, new: Config| {
+//!             existing.features.extend(new.features)
+//!         }),
+//!     )
+//!     .unwrap();
+//!
+//! let config = |feature: &str| Config {
+//!     region: "eu-west-1".to_string(),
+//!     features: vec![feature.to_string()],
+//! };
+//! manager.set("config", config("billing")).unwrap();
+//! manager.set("config", config("search")).unwrap();
+//! assert_eq!(
+//!     vec!["billing", "search"],
+//!     manager.get_ref::<Config>("config").unwrap().features
+//! );
+//! ```
+use crate::{Registry, Result, Service, SingletonManager, Uuid};
+use std::any::{Any, TypeId};
+use std::fmt::{Debug, Formatter};
+use std::sync::Arc;
+
+/// Reconciling a new value with the value already registered, merging only if allowed, and
+/// telling whether it merged, or giving the new value back if it is not reconciled.
+pub(crate) type Reconciler = Arc<
+    dyn Fn(&mut (dyn Any + Send + Sync), Service, bool) -> std::result::Result<bool, Service>
+        + Send
+        + Sync,
+>;
+
+/// Merging a new value into the value already registered.
+type Merge<T> = Box<dyn Fn(&mut T, T) + Send + Sync>;
+
+/// How equivalent values of a `T` are reconciled, see `SingletonManager::set_reconciler`.
+pub enum Reconcile<T> {
+    /// Keeping the value already registered, dropping the new one.
+    KeepExisting,
+    /// Merging the new value into the value already registered.
+    Merge(Merge<T>),
+}
+
+impl<T> Reconcile<T> {
+    /// Merging the new value into the value already registered with the function.
+    pub fn merge<F: Fn(&mut T, T) + Send + Sync + 'static>(f: F) -> Self {
+        Self::Merge(Box::new(f))
+    }
+}
+
+impl<T> Debug for Reconcile<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::KeepExisting => write!(f, "KeepExisting"),
+            Self::Merge(_) => write!(f, "Merge"),
+        }
+    }
+}
+
+impl Registry {
+    /// Reconciling the new value with the value already registered under the alias, returning
+    /// the new value if it is left to the collision policy.
+    /// Values that are borrowed or frozen are not reconciled, and values are not merged within a
+    /// transaction. Fails with the error of a validator rejecting the merged value, removing it.
+    pub(crate) fn reconcile(&mut self, alias: &str, service: Service) -> Result<Option<Service>> {
+        let reconciler = match self.reconcilers.get(&(*service).type_id()) {
+            Some(reconciler) => reconciler.clone(),
+            None => return Ok(Some(service)),
+        };
+        let id = match self.alias.get(alias) {
+            Some(id) => *id,
+            None => return Ok(Some(service)),
+        };
+        let may_merge = self.replacing.is_none();
+        let reconcilable = !self.frozen.contains(&id)
+            && self
+                .borrows
+                .get(&id)
+                .is_none_or(|state| !state.is_borrowed());
+        let merged = match self.singletons.get_mut(&id) {
+            Some(existing) if reconcilable => {
+                match reconciler(existing.as_mut(), service, may_merge) {
+                    Ok(merged) => merged,
+                    Err(service) => return Ok(Some(service)),
+                }
+            }
+            _ => return Ok(Some(service)),
+        };
+        if merged {
+            self.validate_merged(alias, &id)?;
+        }
+        log::debug!("Service `{}` is already registered, reconciled it", alias);
+        self.invalidate_facades();
+        Ok(None)
+    }
+
+    /// Validating the value merged into the registration, removing it if it is rejected.
+    fn validate_merged(&mut self, alias: &str, id: &Uuid) -> Result<()> {
+        let validated = match self.singletons.get(id) {
+            Some(merged) => self.validate(alias, id, merged.as_ref()),
+            None => Ok(()),
+        };
+        if validated.is_err() {
+            log::warn!(
+                "Service `{}` was merged into an invalid value, removed it",
+                alias
+            );
+            self.remove_alias(alias);
+        }
+        validated
+    }
+}
+
+impl SingletonManager {
+    /// Setting how the values of a `T` registered under an alias already holding an equivalent
+    /// value are reconciled, replacing the reconciler set before.
+    pub fn set_reconciler<T, F>(&self, equivalent: F, reconcile: Reconcile<T>) -> Result<()>
+    where
+        T: Send + Sync + 'static,
+        F: Fn(&T, &T) -> bool + Send + Sync + 'static,
+    {
+        let reconciler: Reconciler = Arc::new(move |existing, service, may_merge| {
+            let existing = match existing.downcast_mut::<T>() {
+                Some(existing) => existing,
+                None => return Err(service),
+            };
+            let service = match service.downcast::<T>() {
+                Ok(service) => service,
+                Err(service) => return Err(service),
+            };
+            if !equivalent(existing, &service) {
+                return Err(service);
+            }
+            match &reconcile {
+                Reconcile::KeepExisting => Ok(false),
+                Reconcile::Merge(_) if !may_merge => Err(service),
+                Reconcile::Merge(merge) => {
+                    merge(existing, *service);
+                    Ok(true)
+                }
+            }
+        });
+        self.registry_mut()?
+            .reconcilers
+            .insert(TypeId::of::<T>(), reconciler);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Error, Reconcile, SetError, SingletonManager};
+
+    #[test]
+    fn test_equivalent_values_are_reconciled() {
+        let mut manager = SingletonManager::new();
+        manager
+            .set_reconciler::<String, _>(|existing, new| existing == new, Reconcile::KeepExisting)
+            .unwrap();
+        manager.set("region", "eu-west-1".to_string()).unwrap();
+        assert_eq!(
+            "eu-west-1",
+            manager.set("region", "eu-west-1".to_string()).unwrap()
+        );
+        assert!(matches!(
+            manager.set("region", "us-east-1".to_string()),
+            Err(SetError::ServiceAlreadyExists(_))
+        ));
+        manager.set("port", 80_u16).unwrap();
+        assert!(matches!(
+            manager.set("port", 80_u16),
+            Err(SetError::ServiceAlreadyExists(_))
+        ));
+
+        manager.freeze("region").unwrap();
+        assert!(matches!(
+            manager.set("region", "eu-west-1".to_string()),
+            Err(SetError::ServiceAlreadyExists(_))
+        ));
+
+        manager
+            .set_reconciler::<Vec<u32>, _>(
+                |_, _| true,
+                Reconcile::merge(|existing: &mut Vec<u32>, new| existing.extend(new)),
+            )
+            .unwrap();
+        manager.set("ports", vec![80_u32]).unwrap();
+        manager.set("ports", vec![443_u32]).unwrap();
+        assert_eq!(
+            vec![80, 443],
+            *manager.get_ref::<Vec<u32>>("ports").unwrap()
+        );
+
+        let merged = manager.transaction(|tx| tx.set("ports", vec![8080_u32]));
+        assert!(matches!(merged, Err(Error::ServiceAlreadyExists(alias)) if alias == "ports"));
+        assert_eq!(
+            vec![80, 443],
+            *manager.get_ref::<Vec<u32>>("ports").unwrap()
+        );
+
+        manager
+            .add_validator(|_, service| match service.downcast_ref::<Vec<u32>>() {
+                Some(ports) if ports.len() > 2 => Err(Error::ValidationFailed(
+                    "ports".into(),
+                    "too many ports".to_string(),
+                )),
+                _ => Ok(()),
+            })
+            .unwrap();
+        assert!(manager
+            .set("ports", vec![8080_u32])
+            .unwrap_err()
+            .is_validation_failed());
+        assert!(!manager.has("ports"));
+    }
+}
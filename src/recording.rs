@@ -0,0 +1,185 @@
+//! # Recording
+//! Recording the gets and sets of services, to assert on the wiring in tests.
+//!
+//! A recording scope records every service set and every service resolved, with the type and
+//! the location of the calling code, from its creation until it is dropped. Tests can then
+//! assert which services the code under test resolved, so wiring changes, e.g. a code path
+//! still using a legacy service, show up as failing tests.
+//! ```
+//! use singleton_manager::sm;
+//!
+//! sm().set("recorded_db", "postgres".to_string()).unwrap();
+//! sm().set("recorded_legacy_cache", "memcached".to_string()).unwrap();
+//!
+//! let recording = sm().recording_scope();
+//! sm().get::<String>("recorded_db").unwrap();
+//!
+//! recording.assert_resolved("recorded_db", 1..);
+//! recording.assert_never_resolved("recorded_legacy_cache");
+//! ```
+use crate::{Registry, SingletonManager};
+use std::ops::RangeBounds;
+use std::panic::Location;
+use std::sync::{Arc, Mutex, PoisonError, Weak};
+
+/// The calls recorded by a recording scope.
+type Recorder = Mutex<Vec<RecordedCall>>;
+
+/// The recorders of the live recording scopes.
+pub(crate) type Recorders = Vec<Weak<Recorder>>;
+
+/// What a recorded call did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CallKind {
+    /// Setting a service or a factory.
+    Set,
+    /// Getting a service.
+    Resolve,
+}
+
+/// A get or set recorded by a `RecordingScope`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordedCall {
+    pub kind: CallKind,
+    pub alias: String,
+    /// The type name of the service, unknown when setting a factory.
+    pub type_name: Option<&'static str>,
+    /// The location of the calling code.
+    pub location: &'static Location<'static>,
+}
+
+/// Recording the gets and sets of services until dropped, see
+/// `SingletonManager::recording_scope`.
+pub struct RecordingScope {
+    calls: Arc<Recorder>,
+}
+
+impl RecordingScope {
+    /// The calls recorded so far, in the order they were made.
+    pub fn calls(&self) -> Vec<RecordedCall> {
+        self.calls
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// The number of times the service was resolved.
+    pub fn resolutions(&self, service_name: &str) -> usize {
+        self.calls
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .filter(|call| call.kind == CallKind::Resolve && call.alias == service_name)
+            .count()
+    }
+
+    /// Panicking unless the number of times the service was resolved is within the range, e.g.
+    /// `1..` for at least once.
+    #[track_caller]
+    pub fn assert_resolved<R: RangeBounds<usize>>(&self, service_name: &str, times: R) {
+        let resolutions = self.resolutions(service_name);
+        assert!(
+            times.contains(&resolutions),
+            "Service `{}` was resolved {} times, outside of {:?}..{:?}",
+            service_name,
+            resolutions,
+            times.start_bound(),
+            times.end_bound()
+        );
+    }
+
+    /// Panicking if the service was resolved.
+    #[track_caller]
+    pub fn assert_never_resolved(&self, service_name: &str) {
+        let resolved_by: Vec<_> = self
+            .calls()
+            .into_iter()
+            .filter(|call| call.kind == CallKind::Resolve && call.alias == service_name)
+            .map(|call| call.location.to_string())
+            .collect();
+        assert!(
+            resolved_by.is_empty(),
+            "Service `{}` was resolved by: [{}]",
+            service_name,
+            resolved_by.join(", ")
+        );
+    }
+}
+
+impl Registry {
+    /// True if a recording scope may be recording.
+    pub(crate) fn is_recording(&self) -> bool {
+        !self.recorders.is_empty()
+    }
+
+    /// Recording the call in all live recording scopes, forgetting the scopes dropped.
+    pub(crate) fn record_call(
+        &mut self,
+        kind: CallKind,
+        alias: &str,
+        type_name: Option<&'static str>,
+        location: &'static Location<'static>,
+    ) {
+        self.recorders.retain(|recorder| {
+            let recorder = match recorder.upgrade() {
+                Some(recorder) => recorder,
+                None => return false,
+            };
+            recorder
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .push(RecordedCall {
+                    kind,
+                    alias: alias.to_string(),
+                    type_name,
+                    location,
+                });
+            true
+        });
+    }
+}
+
+impl SingletonManager {
+    /// Starting to record the gets and sets of services, until the scope is dropped.
+    pub fn recording_scope(&self) -> RecordingScope {
+        let calls = Arc::new(Recorder::default());
+        if let Ok(mut registry) = self.registry_mut() {
+            registry.recorders.push(Arc::downgrade(&calls));
+        }
+        RecordingScope { calls }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{CallKind, SingletonManager};
+
+    #[test]
+    fn test_recording_scope_records_gets_and_sets() {
+        let mut manager = SingletonManager::new();
+        manager.set("config", 1_u8).unwrap();
+        let recording = manager.recording_scope();
+        manager.set_factory("db", || Box::new(1_u32)).unwrap();
+        manager.get::<u32>("db").unwrap();
+        drop(manager.get_ref::<u32>("db").unwrap());
+
+        let calls = recording.calls();
+        assert_eq!(
+            vec![
+                (CallKind::Set, "db", None),
+                (CallKind::Resolve, "db", Some("u32")),
+                (CallKind::Resolve, "db", Some("u32")),
+            ],
+            calls
+                .iter()
+                .map(|call| (call.kind, call.alias.as_str(), call.type_name))
+                .collect::<Vec<_>>()
+        );
+        recording.assert_resolved("db", 2..=2);
+        recording.assert_never_resolved("config");
+
+        drop(recording);
+        manager.get::<u8>("config").unwrap();
+        assert!(manager.registry().unwrap().recorders.is_empty());
+    }
+}
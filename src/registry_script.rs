@@ -0,0 +1,266 @@
+//! # Registry scripts
+//! Generating sequences of registry operations for property-based tests, behind the `proptest`
+//! feature.
+//!
+//! A `RegistryScript` is a sequence of operations setting, getting, removing and replacing
+//! services under a handful of aliases, so the operations of a script collide. Running it
+//! executes the operations against a fresh singleton manager while keeping a model of what the
+//! registry must hold, and fails with the first step breaking an invariant: an entry lost or
+//! appearing, a service with the wrong value, or a factory run more than once.
+//!
+//! `RegistryScript` implements `proptest::arbitrary::Arbitrary`, so downstream crates can fuzz
+//! the registry, or their own invariants on top of it, with `any::<RegistryScript>()`.
+//! ```
+//! use singleton_manager::{RegistryScript, ScriptOp};
+//!
+//! let script = RegistryScript::new(vec![
+//!     ScriptOp::SetFactory(0, 7),
+//!     ScriptOp::Get(0),
+//!     ScriptOp::Get(0),
+//!     ScriptOp::Replace(0, 8),
+//!     ScriptOp::Remove(0),
+//!     ScriptOp::Set(0, 9),
+//! ]);
+//! script.run().unwrap();
+//! ```
+use crate::{CollisionPolicy, SingletonManager};
+use proptest::arbitrary::{any, Arbitrary};
+use proptest::strategy::{BoxedStrategy, Strategy};
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// The number of aliases the operations of a script use.
+pub const SCRIPT_ALIASES: u8 = 4;
+
+/// The longest script generated.
+const MAX_SCRIPT_LEN: usize = 64;
+
+/// An operation of a `RegistryScript`, on the alias with the index, taken modulo
+/// `SCRIPT_ALIASES`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScriptOp {
+    /// Setting the value, as `SingletonManager::set` does.
+    Set(u8, u64),
+    /// Setting a factory creating the value, as `SingletonManager::set_factory` does.
+    SetFactory(u8, u64),
+    /// Getting the value, as `SingletonManager::get_ref` does.
+    Get(u8),
+    /// Taking the value out, as `SingletonManager::take` does.
+    Remove(u8),
+    /// Setting the value, replacing the registration already there.
+    Replace(u8, u64),
+}
+
+impl ScriptOp {
+    /// The alias the operation is on.
+    pub fn alias(&self) -> String {
+        let index = match *self {
+            Self::Set(index, _)
+            | Self::SetFactory(index, _)
+            | Self::Get(index)
+            | Self::Remove(index)
+            | Self::Replace(index, _) => index,
+        };
+        format!("script_{}", index % SCRIPT_ALIASES)
+    }
+}
+
+impl Arbitrary for ScriptOp {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        let index = 0..SCRIPT_ALIASES;
+        proptest::prop_oneof![
+            (index.clone(), any::<u64>()).prop_map(|(index, value)| Self::Set(index, value)),
+            (index.clone(), any::<u64>()).prop_map(|(index, value)| Self::SetFactory(index, value)),
+            index.clone().prop_map(Self::Get),
+            index.clone().prop_map(Self::Remove),
+            (index, any::<u64>()).prop_map(|(index, value)| Self::Replace(index, value)),
+        ]
+        .boxed()
+    }
+}
+
+/// A sequence of registry operations, see `RegistryScript::run`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RegistryScript {
+    pub ops: Vec<ScriptOp>,
+}
+
+impl Arbitrary for RegistryScript {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        proptest::collection::vec(any::<ScriptOp>(), 0..=MAX_SCRIPT_LEN)
+            .prop_map(RegistryScript::new)
+            .boxed()
+    }
+}
+
+/// The first step of a script breaking an invariant of the registry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScriptViolation {
+    /// The index of the operation in the script.
+    pub step: usize,
+    pub op: ScriptOp,
+    pub message: String,
+}
+
+impl Display for ScriptViolation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Step {} `{:?}`: {}", self.step, self.op, self.message)
+    }
+}
+
+impl std::error::Error for ScriptViolation {}
+
+/// What the model expects to be registered under an alias.
+enum Entry {
+    Value(u64),
+    Factory { value: u64, runs: Arc<AtomicUsize> },
+}
+
+impl Entry {
+    fn runs(&self) -> usize {
+        match self {
+            Self::Value(_) => 0,
+            Self::Factory { runs, .. } => runs.load(Ordering::SeqCst),
+        }
+    }
+}
+
+impl RegistryScript {
+    pub fn new(ops: Vec<ScriptOp>) -> Self {
+        Self { ops }
+    }
+
+    /// Running the operations against a fresh singleton manager, checking the registry against
+    /// the model after every operation.
+    pub fn run(&self) -> Result<(), ScriptViolation> {
+        let mut manager = SingletonManager::new();
+        let mut model = HashMap::new();
+        for (step, op) in self.ops.iter().enumerate() {
+            let violation = |message: String| ScriptViolation {
+                step,
+                op: *op,
+                message,
+            };
+            run_op(&mut manager, &mut model, op).map_err(violation)?;
+            check_model(&manager, &model).map_err(violation)?;
+        }
+        Ok(())
+    }
+}
+
+/// Running the operation, and updating the model with what it must have done.
+fn run_op(
+    manager: &mut SingletonManager,
+    model: &mut HashMap<String, Entry>,
+    op: &ScriptOp,
+) -> Result<(), String> {
+    let alias = op.alias();
+    let registered = model.contains_key(&alias);
+    match *op {
+        ScriptOp::Set(_, value) => match (manager.set(&alias, value), registered) {
+            (Ok(_), false) => {
+                model.insert(alias, Entry::Value(value));
+            }
+            (Err(_), true) => {}
+            (result, _) => return Err(format!("Unexpected result {:?}", result.map(|_| ()))),
+        },
+        ScriptOp::SetFactory(_, value) => {
+            let runs = Arc::new(AtomicUsize::new(0));
+            let counted = runs.clone();
+            let factory = move || {
+                counted.fetch_add(1, Ordering::SeqCst);
+                Box::new(value) as _
+            };
+            match (manager.set_factory(&alias, factory), registered) {
+                (Ok(_), false) => {
+                    model.insert(alias, Entry::Factory { value, runs });
+                }
+                (Err(_), true) => {}
+                (result, _) => return Err(format!("Unexpected result {:?}", result.map(|_| ()))),
+            }
+        }
+        ScriptOp::Get(_) => {
+            let got = manager
+                .get_ref_by_alias::<u64>(&alias)
+                .map(|service| *service);
+            match (got, model.get(&alias)) {
+                (Err(e), None) if e.is_not_found() => {}
+                (Ok(got), Some(Entry::Value(value) | Entry::Factory { value, .. }))
+                    if got == *value => {}
+                (got, _) => return Err(format!("Unexpected result {:?}", got)),
+            }
+        }
+        ScriptOp::Remove(_) => {
+            let instantiated = model.get(&alias).map(|entry| match entry {
+                Entry::Value(_) => true,
+                Entry::Factory { .. } => entry.runs() > 0,
+            });
+            match (manager.take::<u64>(&alias), model.get(&alias)) {
+                (Err(e), None) if e.is_not_found() => {}
+                (Err(e), Some(_)) if instantiated == Some(false) && e.is_not_instantiated() => {}
+                (Ok(got), Some(Entry::Value(value) | Entry::Factory { value, .. }))
+                    if got == *value =>
+                {
+                    model.remove(&alias);
+                }
+                (got, _) => return Err(format!("Unexpected result {:?}", got)),
+            }
+        }
+        ScriptOp::Replace(_, value) => {
+            manager
+                .set_collision_policy(CollisionPolicy::LastWins)
+                .map_err(|e| e.to_string())?;
+            let replaced = manager.set(&alias, value).map(|_| ());
+            manager
+                .set_collision_policy(CollisionPolicy::Error)
+                .map_err(|e| e.to_string())?;
+            replaced.map_err(|e| format!("Unexpected result {:?}", e))?;
+            model.insert(alias, Entry::Value(value));
+        }
+    }
+    Ok(())
+}
+
+/// Checking that exactly the entries of the model are registered, and that no factory ran twice.
+fn check_model(manager: &SingletonManager, model: &HashMap<String, Entry>) -> Result<(), String> {
+    for index in 0..SCRIPT_ALIASES {
+        let alias = ScriptOp::Get(index).alias();
+        match (manager.has(&alias), model.get(&alias)) {
+            (true, None) => return Err(format!("Service `{}` appeared", alias)),
+            (false, Some(_)) => return Err(format!("Service `{}` was lost", alias)),
+            (_, Some(entry)) if entry.runs() > 1 => {
+                return Err(format!(
+                    "The factory of `{}` ran {} times",
+                    alias,
+                    entry.runs()
+                ))
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::RegistryScript;
+    use proptest::arbitrary::any;
+    use proptest::proptest;
+
+    proptest! {
+        #[test]
+        fn test_arbitrary_scripts_keep_the_invariants(script in any::<RegistryScript>()) {
+            if let Err(violation) = script.run() {
+                panic!("{}", violation);
+            }
+        }
+    }
+}
@@ -0,0 +1,287 @@
+//! # Remote-backed services
+//! Caching a service fetched from a remote source, and fetching it again once stale.
+//!
+//! Feature flags and configuration snapshots are often fetched from a remote service, and have to
+//! be fetched again every so often. A `CachedRemote` serves the value fetched last while it is
+//! fresh, and fetches it again with its async fetcher once it is older than its time to live.
+//!
+//! Only one fetch runs at a time: while the value is fetched again, the other callers are served
+//! the stale value, and before the first value is fetched, they wait for it. If fetching fails,
+//! the stale value is served until a later fetch succeeds.
+//! ```
+//! use singleton_manager::SingletonManager;
+//! use std::time::Duration;
+//!
+//! async fn fetch_flags() -> singleton_manager::Result<Vec<String>> {
+//!     Ok(vec!["dark_mode".to_string()])
+//! }
+//!
+//! async fn handle(manager: &SingletonManager) -> bool {
+//!     let flags = manager.get_remote::<Vec<String>>("flags").await.unwrap();
+//!     flags.contains(&"dark_mode".to_string())
+//! }
+//!
+//! let manager = SingletonManager::new();
+//! manager
+//!     .set_cached_remote("flags", Duration::from_secs(30), fetch_flags)
+//!     .unwrap();
+//! ```
+use crate::{GetError, Result, SetError, SingletonManager};
+use std::future::Future;
+use std::panic::Location;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, Instant};
+
+/// The async fetcher of a remote-backed service.
+type Fetch<T> = Arc<dyn Fn() -> Pin<Box<dyn Future<Output = Result<T>> + Send>> + Send + Sync>;
+
+/// The value fetched last, with the time it was fetched.
+struct Cache<T> {
+    value: Option<(Arc<T>, Instant)>,
+    /// True if the value was invalidated since it was fetched.
+    invalidated: bool,
+    /// True while a fetch runs.
+    fetching: bool,
+    /// The callers waiting for the first value.
+    waiting: Vec<Waker>,
+}
+
+/// A service fetched from a remote source by an async fetcher, cached for its time to live.
+pub struct CachedRemote<T> {
+    fetch: Fetch<T>,
+    ttl: Duration,
+    cache: Mutex<Cache<T>>,
+}
+
+/// What a caller of `CachedRemote::get` does.
+enum Step<T> {
+    Serve(Arc<T>),
+    Wait,
+    Fetch,
+}
+
+impl<T: Send + Sync + 'static> CachedRemote<T> {
+    /// Creating the service, fetching the value on the first `get`.
+    pub fn new<F, Fut>(ttl: Duration, fetch: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<T>> + Send + 'static,
+    {
+        Self::with_fetch(ttl, Arc::new(move || Box::pin(fetch())))
+    }
+
+    fn with_fetch(ttl: Duration, fetch: Fetch<T>) -> Self {
+        Self {
+            fetch,
+            ttl,
+            cache: Mutex::new(Cache {
+                value: None,
+                invalidated: false,
+                fetching: false,
+                waiting: Vec::new(),
+            }),
+        }
+    }
+
+    /// Getting the value, fetching it if there is none yet or it is stale.
+    /// Fails with the error of the fetcher if no value was fetched yet.
+    pub async fn get(&self) -> Result<Arc<T>> {
+        loop {
+            let step = {
+                let mut cache = self.cache();
+                match &cache.value {
+                    Some((value, fetched))
+                        if !cache.invalidated && fetched.elapsed() < self.ttl =>
+                    {
+                        Step::Serve(value.clone())
+                    }
+                    Some((value, _)) if cache.fetching => Step::Serve(value.clone()),
+                    None if cache.fetching => Step::Wait,
+                    _ => {
+                        cache.fetching = true;
+                        Step::Fetch
+                    }
+                }
+            };
+            match step {
+                Step::Serve(value) => return Ok(value),
+                Step::Wait => Fetched(self).await,
+                Step::Fetch => return self.refresh().await,
+            }
+        }
+    }
+
+    /// Marking the value as stale, so the next `get` fetches it again.
+    pub fn invalidate(&self) {
+        self.cache().invalidated = true;
+    }
+
+    async fn refresh(&self) -> Result<Arc<T>> {
+        let _fetching = Fetching(self);
+        let fetched = (self.fetch)().await;
+        let mut cache = self.cache();
+        match (fetched, &cache.value) {
+            (Ok(value), _) => {
+                let value = Arc::new(value);
+                cache.value = Some((value.clone(), Instant::now()));
+                cache.invalidated = false;
+                Ok(value)
+            }
+            (Err(e), Some((stale, _))) => {
+                log::warn!("Serving a stale value, fetching failed: {}", e);
+                Ok(stale.clone())
+            }
+            (Err(e), None) => Err(e),
+        }
+    }
+
+    fn cache(&self) -> MutexGuard<'_, Cache<T>> {
+        self.cache.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Ending the fetch when dropped, also when the fetch fails or is cancelled, and waking the
+/// callers waiting for it.
+struct Fetching<'a, T>(&'a CachedRemote<T>);
+
+impl<T> Drop for Fetching<'_, T> {
+    fn drop(&mut self) {
+        let waiting = {
+            let mut cache = self.0.cache.lock().unwrap_or_else(PoisonError::into_inner);
+            cache.fetching = false;
+            std::mem::take(&mut cache.waiting)
+        };
+        waiting.into_iter().for_each(Waker::wake);
+    }
+}
+
+/// Waiting for the fetch running to end.
+struct Fetched<'a, T>(&'a CachedRemote<T>);
+
+impl<T> Future for Fetched<'_, T> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let mut cache = self.0.cache.lock().unwrap_or_else(PoisonError::into_inner);
+        if !cache.fetching {
+            return Poll::Ready(());
+        }
+        if !cache
+            .waiting
+            .iter()
+            .any(|waker| waker.will_wake(cx.waker()))
+        {
+            cache.waiting.push(cx.waker().clone());
+        }
+        Poll::Pending
+    }
+}
+
+impl SingletonManager {
+    /// Setting a service fetched by the async fetcher, and cached for the time to live, see
+    /// `CachedRemote`.
+    #[track_caller]
+    pub fn set_cached_remote<T, F, Fut>(
+        &self,
+        service_name: &str,
+        ttl: Duration,
+        fetch: F,
+    ) -> std::result::Result<(), SetError>
+    where
+        T: Send + Sync + 'static,
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<T>> + Send + 'static,
+    {
+        let fetch: Fetch<T> = Arc::new(move || Box::pin(fetch()));
+        self.store_factory_with_default_timeout(
+            service_name,
+            move || Box::new(CachedRemote::with_fetch(ttl, fetch.clone())),
+            Location::caller(),
+        )
+        .map(|_| ())
+        .map_err(|e| SetError::from_error(e, service_name))
+    }
+
+    /// Getting the value of a service set by `set_cached_remote`, fetching it if stale.
+    #[track_caller]
+    pub fn get_remote<'a, T: Send + Sync + 'static>(
+        &'a self,
+        service_name: &'a str,
+    ) -> impl Future<Output = std::result::Result<Arc<T>, GetError>> + 'a {
+        let remote = self.get_ref_by_alias::<CachedRemote<T>>(service_name);
+        async move {
+            remote?
+                .get()
+                .await
+                .map_err(|e| GetError::from_error(e, service_name))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{CachedRemote, Error, SingletonManager};
+    use std::future::Future;
+    use std::pin::pin;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::task::{Context, Poll, Waker};
+    use std::time::Duration;
+
+    /// A future pending once before it is ready, as a fetch waiting for the network.
+    async fn network() {
+        let mut pending = true;
+        std::future::poll_fn(|cx| match std::mem::take(&mut pending) {
+            true => {
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+            false => Poll::Ready(()),
+        })
+        .await
+    }
+
+    #[test]
+    fn test_one_fetch_serves_concurrent_gets() {
+        let fetches = Arc::new(AtomicUsize::new(0));
+        let manager = SingletonManager::new();
+        let counted = fetches.clone();
+        manager
+            .set_cached_remote("flags", Duration::from_secs(60), move || {
+                let fetch = counted.fetch_add(1, Ordering::SeqCst) + 1;
+                async move {
+                    network().await;
+                    match fetch {
+                        3 => Err(Error::UnknownError("unreachable".to_string())),
+                        _ => Ok(fetch),
+                    }
+                }
+            })
+            .unwrap();
+        let mut cx = Context::from_waker(Waker::noop());
+
+        let mut first = pin!(manager.get_remote::<usize>("flags"));
+        let mut second = pin!(manager.get_remote::<usize>("flags"));
+        assert!(first.as_mut().poll(&mut cx).is_pending());
+        assert!(second.as_mut().poll(&mut cx).is_pending());
+        assert!(matches!(first.poll(&mut cx), Poll::Ready(Ok(v)) if *v == 1));
+        assert!(matches!(second.poll(&mut cx), Poll::Ready(Ok(v)) if *v == 1));
+        assert_eq!(1, fetches.load(Ordering::SeqCst));
+
+        let remote = manager.get_ref::<CachedRemote<usize>>("flags").unwrap();
+        remote.invalidate();
+        let mut refresh = pin!(remote.get());
+        assert!(refresh.as_mut().poll(&mut cx).is_pending());
+        let stale = pin!(remote.get());
+        assert!(matches!(stale.poll(&mut cx), Poll::Ready(Ok(v)) if *v == 1));
+        assert!(matches!(refresh.poll(&mut cx), Poll::Ready(Ok(v)) if *v == 2));
+
+        remote.invalidate();
+        let mut failed = pin!(remote.get());
+        assert!(failed.as_mut().poll(&mut cx).is_pending());
+        assert!(matches!(failed.poll(&mut cx), Poll::Ready(Ok(v)) if *v == 2));
+        assert_eq!(3, fetches.load(Ordering::SeqCst));
+    }
+}
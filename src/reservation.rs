@@ -0,0 +1,269 @@
+//! # Reservations
+//! Two-phase registration of singletons.
+//!
+//! An alias can be reserved before the service exists, declaring that it will be provided,
+//! while other components can already wait for it. This breaks initialization order knots
+//! between crates, where the component declaring a dependency is not the one providing it.
+//! ```
+//! use singleton_manager::sm;
+//! use std::thread;
+//!
+//! let reservation = sm().reserve::<String>("reserved_db").unwrap();
+//!
+//! let waiter = thread::spawn(|| {
+//!     sm().wait_for::<String>("reserved_db").unwrap().clone()
+//! });
+//!
+//! reservation.fulfill("postgres".to_string()).unwrap();
+//! assert_eq!("postgres", waiter.join().unwrap());
+//! ```
+use crate::{Alias, Error, Operation, Result, Service, ServiceRef, SingletonManager, Uuid};
+use std::marker::PhantomData;
+use std::panic::Location;
+use std::sync::{Arc, Condvar, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SlotState {
+    Pending,
+    Fulfilled,
+    Cancelled,
+}
+
+/// The state of a reservation, shared between the reservation and the waiters.
+#[derive(Debug)]
+pub(crate) struct ReservationSlot {
+    state: Mutex<SlotState>,
+    changed: Condvar,
+}
+
+impl Default for ReservationSlot {
+    fn default() -> Self {
+        Self {
+            state: Mutex::new(SlotState::Pending),
+            changed: Condvar::new(),
+        }
+    }
+}
+
+impl ReservationSlot {
+    fn finish(&self, state: SlotState) {
+        *self.state.lock().unwrap_or_else(PoisonError::into_inner) = state;
+        self.changed.notify_all();
+    }
+
+    fn wait(&self, timeout: Option<Duration>) -> SlotState {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        while *state == SlotState::Pending {
+            state = match deadline {
+                None => self
+                    .changed
+                    .wait(state)
+                    .unwrap_or_else(PoisonError::into_inner),
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        break;
+                    }
+                    self.changed
+                        .wait_timeout(state, deadline - now)
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .0
+                }
+            };
+        }
+        *state
+    }
+}
+
+/// A reserved alias, waiting for its service to be provided.
+/// Dropping the reservation without fulfilling it cancels it, removing the alias again.
+pub struct Reservation<'a, T> {
+    manager: &'a SingletonManager,
+    id: Uuid,
+    /// The alias reserved, reported if the reservation got removed meanwhile.
+    alias: Alias,
+    slot: Arc<ReservationSlot>,
+    fulfilled: bool,
+    _service: PhantomData<fn(T)>,
+}
+
+impl<T: Send + Sync + 'static> Reservation<'_, T> {
+    /// Providing the service for the reservation, waking up everyone waiting for it.
+    /// The validators of the manager are run against the service, and if they fail the
+    /// reservation is cancelled.
+    #[track_caller]
+    pub fn fulfill(mut self, service: T) -> Result<()> {
+        let location = Location::caller();
+        let mut registry = self.manager.registry_mut()?;
+        let alias = registry
+            .alias_of(&self.id)
+            .filter(|_| registry.reservations.contains_key(&self.id))
+            .map(str::to_string)
+            .ok_or_else(|| Error::ServiceDoesNotExist(self.alias.clone(), Default::default()))?;
+        let service: Service = Box::new(service);
+        registry.validate(&alias, &self.id, service.as_ref())?;
+        registry.insert_instance(self.id, service);
+        registry.reservations.remove(&self.id);
+        registry.record(Operation::Set, &alias, Some(location));
+        drop(registry);
+
+        self.fulfilled = true;
+        self.slot.finish(SlotState::Fulfilled);
+        Ok(())
+    }
+}
+
+impl<T> Drop for Reservation<'_, T> {
+    fn drop(&mut self) {
+        if self.fulfilled {
+            return;
+        }
+        if let Ok(mut registry) = self.manager.registry_mut() {
+            // The registration may have been removed, or replaced, in the meantime.
+            if registry.reservations.remove(&self.id).is_some() {
+                if let Some(alias) = registry.alias_of(&self.id).map(str::to_string) {
+                    registry.remove_alias(&alias);
+                }
+            }
+        }
+        self.slot.finish(SlotState::Cancelled);
+    }
+}
+
+impl SingletonManager {
+    /// Reserving an alias for a service that will be provided later through
+    /// `Reservation::fulfill`.
+    /// Until then getting the service fails with `GetError::ServiceNotInstantiated`, but
+    /// `wait_for` can be used to block until it is provided.
+    #[track_caller]
+    pub fn reserve<T: 'static>(&self, service_name: &str) -> Result<Reservation<'_, T>> {
+        let location = Location::caller();
+        let mut registry = self.registry_mut()?;
+        let id = registry.store_alias(service_name)?;
+        let slot = Arc::new(ReservationSlot::default());
+        registry.reservations.insert(id, slot.clone());
+        registry.record(Operation::Reserve, service_name, Some(location));
+        Ok(Reservation {
+            manager: self,
+            id,
+            alias: registry.intern(service_name),
+            slot,
+            fulfilled: false,
+            _service: PhantomData,
+        })
+    }
+
+    /// Waiting for a reserved service to be provided, then getting a shared reference to it as
+    /// `get_ref` does. Services that are not reserved are returned right away.
+    /// Fails with `Error::ReservationCancelled` if the reservation is dropped without being
+    /// fulfilled, and with `Error::ReservationTimeout` if the wait timeout of the configuration
+    /// elapsed, see `ManagerConfig`.
+    #[track_caller]
+    pub fn wait_for<T: 'static>(&self, service_name: &str) -> Result<ServiceRef<'_, T>> {
+        self.wait_for_reservation(service_name, None, Location::caller())
+    }
+
+    /// Waiting for a reserved service like `wait_for`, but failing with
+    /// `Error::ReservationTimeout` if it is not provided within the timeout.
+    #[track_caller]
+    pub fn wait_for_timeout<T: 'static>(
+        &self,
+        service_name: &str,
+        timeout: Duration,
+    ) -> Result<ServiceRef<'_, T>> {
+        self.wait_for_reservation(service_name, Some(timeout), Location::caller())
+    }
+
+    fn wait_for_reservation<T: 'static>(
+        &self,
+        service_name: &str,
+        timeout: Option<Duration>,
+        location: &'static Location<'static>,
+    ) -> Result<ServiceRef<'_, T>> {
+        let id = self.service_id(service_name)?;
+        let (slot, timeout) = {
+            let registry = self.registry()?;
+            let slot = registry.reservations.get(&id).cloned();
+            (slot, timeout.or(registry.config.wait_timeout))
+        };
+        if let Some(slot) = slot {
+            match slot.wait(timeout) {
+                SlotState::Pending => return Err(Error::ReservationTimeout(service_name.into())),
+                SlotState::Cancelled => {
+                    return Err(Error::ReservationCancelled(service_name.into()))
+                }
+                SlotState::Fulfilled => {}
+            }
+        }
+        self.shared_borrow(service_name, location, |state| {
+            state.wait_borrow(location);
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Error, GetError, SingletonManager};
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_reservation_fulfilled_wakes_waiters() {
+        let alias = "reservation_test_fulfilled";
+        let reservation = crate::sm().reserve::<u32>(alias).unwrap();
+        assert!(matches!(
+            crate::sm().get::<u32>(alias),
+            Err(GetError::ServiceNotInstantiated(_))
+        ));
+
+        let waiters = (0..3)
+            .map(|_| thread::spawn(move || *crate::sm().wait_for::<u32>(alias).unwrap()))
+            .collect::<Vec<_>>();
+        thread::sleep(Duration::from_millis(20));
+        reservation.fulfill(42).unwrap();
+
+        for waiter in waiters {
+            assert_eq!(42, waiter.join().unwrap());
+        }
+    }
+
+    #[test]
+    fn test_reservation_cancelled_on_drop() {
+        let manager = SingletonManager::new();
+        let reservation = manager.reserve::<u32>("db").unwrap();
+        assert!(manager.has("db"));
+        assert!(matches!(
+            manager.reserve::<u32>("db"),
+            Err(Error::ServiceAlreadyExists(_))
+        ));
+        assert!(matches!(
+            manager.wait_for_timeout::<u32>("db", Duration::from_millis(10)),
+            Err(Error::ReservationTimeout(_))
+        ));
+
+        drop(reservation);
+        assert!(!manager.has("db"));
+    }
+
+    #[test]
+    fn test_wait_for_unreserved_service() {
+        let mut manager = SingletonManager::new();
+        manager.set("db", 1_u32).unwrap();
+        assert_eq!(1, *manager.wait_for::<u32>("db").unwrap());
+        assert!(manager.wait_for::<u32>("unknown").is_err());
+    }
+
+    #[test]
+    fn test_reservation_of_a_removed_service_reports_its_alias() {
+        let manager = SingletonManager::new();
+        let reservation = manager.reserve::<u32>("db").unwrap();
+        manager.remove_prefix("db").unwrap();
+        assert!(matches!(
+            reservation.fulfill(1),
+            Err(Error::ServiceDoesNotExist(alias, _)) if alias == "db"
+        ));
+    }
+}
@@ -0,0 +1,498 @@
+//! # Runnable services
+//! Starting, stopping and restarting services in dependency order.
+//!
+//! Services like worker queues and connection pools implement `Runnable`, and once registered as
+//! runnable they can be operated through the singleton manager. Starting a service starts the
+//! services it depends on first, and stopping a service stops the services depending on it first.
+//! ```
+//! use singleton_manager::{Runnable, SingletonManager};
+//!
+//! #[derive(Default)]
+//! struct Worker {
+//!     running: bool,
+//! }
+//!
+//! impl Runnable for Worker {
+//!     fn start(&mut self) -> Result<(), String> {
+//!         self.running = true;
+//!         Ok(())
+//!     }
+//!
+//!     fn stop(&mut self) -> Result<(), String> {
+//!         self.running = false;
+//!         Ok(())
+//!     }
+//!
+//!     fn is_running(&self) -> bool {
+//!         self.running
+//!     }
+//! }
+//!
+//! let mut manager = SingletonManager::new();
+//! manager.set_factory("worker", || Box::new(Worker::default())).unwrap();
+//! manager.set_runnable::<Worker>("worker").unwrap();
+//!
+//! manager.start("worker").unwrap();
+//! assert!(manager.is_running("worker"));
+//! manager.stop("worker").unwrap();
+//! assert!(!manager.is_running("worker"));
+//! ```
+use crate::{Error, Registry, Result, ServiceRef, ServiceRefMut, SingletonManager, Uuid};
+use std::any::Any;
+use std::collections::HashSet;
+use std::panic::Location;
+
+/// A service that can be started and stopped while registered in the singleton manager.
+pub trait Runnable {
+    /// Starting the service, failing with the reason it could not be started.
+    fn start(&mut self) -> std::result::Result<(), String>;
+
+    /// Stopping the service, failing with the reason it could not be stopped.
+    fn stop(&mut self) -> std::result::Result<(), String>;
+
+    /// True if the service is running.
+    fn is_running(&self) -> bool;
+}
+
+/// Getting the `Runnable` implementation of a type erased service, mutably or shared.
+#[derive(Clone, Copy)]
+pub(crate) struct AsRunnable {
+    mutable: fn(&mut dyn Any) -> Option<&mut dyn Runnable>,
+    shared: fn(&dyn Any) -> Option<&dyn Runnable>,
+}
+
+impl Registry {
+    /// The services the service depends on, in the order the dependencies were added.
+    fn dependencies_of(&self, id: &Uuid) -> Vec<Uuid> {
+        self.dependencies.get(id).cloned().unwrap_or_default()
+    }
+
+    /// The services depending on the service.
+    fn dependents_of(&self, id: &Uuid) -> Vec<Uuid> {
+        let mut dependents: Vec<Uuid> = self
+            .dependencies
+            .iter()
+            .filter(|(_, dependencies)| dependencies.contains(id))
+            .map(|(dependent, _)| *dependent)
+            .collect();
+        dependents.sort_by_key(|id| self.alias_of(id).map(str::to_string));
+        dependents
+    }
+
+    /// Recording that the service depends on the other service directly, once.
+    pub(crate) fn add_dependency(&mut self, id: Uuid, dependency: Uuid) {
+        let dependencies = self.dependencies.entry(id).or_default();
+        if !dependencies.contains(&dependency) {
+            dependencies.push(dependency);
+        }
+    }
+
+    /// True if the service depends on the other service, directly or through other services.
+    pub(crate) fn depends_on(&self, id: &Uuid, other: &Uuid) -> bool {
+        let mut visited = HashSet::new();
+        let mut pending = vec![*id];
+        while let Some(id) = pending.pop() {
+            if !visited.insert(id) {
+                continue;
+            }
+            let dependencies = self.dependencies_of(&id);
+            if dependencies.contains(other) {
+                return true;
+            }
+            pending.extend(dependencies);
+        }
+        false
+    }
+}
+
+impl SingletonManager {
+    /// Registering the service as runnable, where the service is a `T`.
+    pub fn set_runnable<T: Runnable + 'static>(&self, service_name: &str) -> Result<()> {
+        let id = self.service_id(service_name)?;
+        let as_runnable = AsRunnable {
+            mutable: |service| {
+                service
+                    .downcast_mut::<T>()
+                    .map(|service| service as &mut dyn Runnable)
+            },
+            shared: |service| {
+                service
+                    .downcast_ref::<T>()
+                    .map(|service| service as &dyn Runnable)
+            },
+        };
+        self.registry_mut()?.runnables.insert(id, as_runnable);
+        Ok(())
+    }
+
+    /// Declaring that the service depends on another service.
+    /// Fails with `Error::DependencyCycle` if the other service depends on the service.
+    pub fn depends_on(&self, service_name: &str, dependency: &str) -> Result<()> {
+        let id = self.service_id(service_name)?;
+        let dependency_id = self.service_id(dependency)?;
+        let mut registry = self.registry_mut()?;
+        if id == dependency_id || registry.depends_on(&dependency_id, &id) {
+            return Err(Error::DependencyCycle(
+                service_name.into(),
+                dependency.to_string(),
+            ));
+        }
+        registry.add_dependency(id, dependency_id);
+        Ok(())
+    }
+
+    /// The aliases of the services the service depends on directly.
+    pub fn dependencies(&self, service_name: &str) -> Vec<String> {
+        self.registry()
+            .ok()
+            .and_then(|registry| {
+                let id = registry.resolve(service_name)?;
+                Some(
+                    registry
+                        .dependencies_of(&id)
+                        .iter()
+                        .filter_map(|id| registry.alias_of(id))
+                        .map(str::to_string)
+                        .collect(),
+                )
+            })
+            .unwrap_or_default()
+    }
+
+    /// True if the service is runnable, instantiated and running.
+    #[track_caller]
+    pub fn is_running(&self, service_name: &str) -> bool {
+        let location = Location::caller();
+        self.service_id(service_name)
+            .and_then(|id| {
+                if !self.registry()?.singletons.contains_key(&id) {
+                    return Ok(false);
+                }
+                self.with_shared_runnable(&id, service_name, location, |service| {
+                    service.is_running()
+                })
+            })
+            .unwrap_or(false)
+    }
+
+    /// Starting the service, after starting the runnable services it depends on, returning the
+    /// aliases of the services started in the order they were started.
+    /// Services that are already running are not started again, and services that are not
+    /// runnable are instantiated.
+    ///
+    /// Fails with `Error::NotRunnable` if the service itself is not runnable, and with
+    /// `Error::StartFailed` if any of the services fails to start.
+    #[track_caller]
+    pub fn start(&self, service_name: &str) -> Result<Vec<String>> {
+        let location = Location::caller();
+        let id = self.service_id(service_name)?;
+        self.check_runnable(&id, service_name)?;
+        let mut started = Vec::new();
+        self.start_with_dependencies(&id, location, &mut HashSet::new(), &mut started)?;
+        Ok(started)
+    }
+
+    /// Stopping the service, after stopping the running services depending on it, returning the
+    /// aliases of the services stopped in the order they were stopped.
+    ///
+    /// Fails with `Error::NotRunnable` if the service itself is not runnable, and with
+    /// `Error::StopFailed` if any of the services fails to stop.
+    #[track_caller]
+    pub fn stop(&self, service_name: &str) -> Result<Vec<String>> {
+        let location = Location::caller();
+        let id = self.service_id(service_name)?;
+        self.check_runnable(&id, service_name)?;
+        let mut stopped = Vec::new();
+        self.stop_with_dependents(&id, location, &mut HashSet::new(), &mut stopped)?;
+        Ok(stopped)
+    }
+
+    /// Restarting the service, stopping the running services depending on it first, and starting
+    /// them again after the service, returning the aliases of the services started.
+    #[track_caller]
+    pub fn restart(&self, service_name: &str) -> Result<Vec<String>> {
+        let location = Location::caller();
+        let id = self.service_id(service_name)?;
+        self.check_runnable(&id, service_name)?;
+        let mut stopped = Vec::new();
+        self.stop_with_dependents(&id, location, &mut HashSet::new(), &mut stopped)?;
+        let mut started = Vec::new();
+        let mut visited = HashSet::new();
+        self.start_with_dependencies(&id, location, &mut visited, &mut started)?;
+        for alias in stopped.iter().rev() {
+            let id = self.service_id(alias)?;
+            self.start_with_dependencies(&id, location, &mut visited, &mut started)?;
+        }
+        Ok(started)
+    }
+
+    fn check_runnable(&self, id: &Uuid, service_name: &str) -> Result<()> {
+        if self.registry()?.runnables.contains_key(id) {
+            Ok(())
+        } else {
+            Err(Error::NotRunnable(service_name.into()))
+        }
+    }
+
+    fn start_with_dependencies(
+        &self,
+        id: &Uuid,
+        location: &'static Location<'static>,
+        visited: &mut HashSet<Uuid>,
+        started: &mut Vec<String>,
+    ) -> Result<()> {
+        if !visited.insert(*id) {
+            return Ok(());
+        }
+        let (alias, dependencies, runnable) = {
+            let registry = self.registry()?;
+            (
+                registry.alias_of(id).unwrap_or_default().to_string(),
+                registry.dependencies_of(id),
+                registry.runnables.contains_key(id),
+            )
+        };
+        for dependency in &dependencies {
+            self.start_with_dependencies(dependency, location, visited, started)?;
+        }
+        self.singleton_get(id, &alias)?;
+        if !runnable {
+            return Ok(());
+        }
+        let result = self.with_runnable(id, &alias, location, |service| {
+            if service.is_running() {
+                Ok(false)
+            } else {
+                service.start().map(|_| true)
+            }
+        })?;
+        match result {
+            Ok(true) => started.push(alias),
+            Ok(false) => {}
+            Err(reason) => return Err(Error::StartFailed(alias.into(), reason)),
+        }
+        Ok(())
+    }
+
+    fn stop_with_dependents(
+        &self,
+        id: &Uuid,
+        location: &'static Location<'static>,
+        visited: &mut HashSet<Uuid>,
+        stopped: &mut Vec<String>,
+    ) -> Result<()> {
+        if !visited.insert(*id) {
+            return Ok(());
+        }
+        let (alias, dependents, stoppable) = {
+            let registry = self.registry()?;
+            (
+                registry.alias_of(id).unwrap_or_default().to_string(),
+                registry.dependents_of(id),
+                registry.runnables.contains_key(id) && registry.singletons.contains_key(id),
+            )
+        };
+        for dependent in &dependents {
+            self.stop_with_dependents(dependent, location, visited, stopped)?;
+        }
+        if !stoppable {
+            return Ok(());
+        }
+        let result = self.with_runnable(id, &alias, location, |service| {
+            if service.is_running() {
+                service.stop().map(|_| true)
+            } else {
+                Ok(false)
+            }
+        })?;
+        match result {
+            Ok(true) => stopped.push(alias),
+            Ok(false) => {}
+            Err(reason) => return Err(Error::StopFailed(alias.into(), reason)),
+        }
+        Ok(())
+    }
+
+    fn as_runnable(&self, id: &Uuid, service_name: &str) -> Result<AsRunnable> {
+        self.registry()?
+            .runnables
+            .get(id)
+            .copied()
+            .ok_or_else(|| Error::NotRunnable(service_name.into()))
+    }
+
+    /// Calling the closure with the `Runnable` implementation of the service, while the service
+    /// is mutably borrowed as `borrow_mut` does.
+    /// The borrow is held by a guard, so it is released even if the closure panics.
+    fn with_runnable<R>(
+        &self,
+        id: &Uuid,
+        service_name: &str,
+        location: &'static Location<'static>,
+        f: impl FnOnce(&mut dyn Runnable) -> R,
+    ) -> Result<R> {
+        let as_runnable = self.as_runnable(id, service_name)?;
+        self.check_caller(id, service_name, location)?;
+        self.registry()?.check_unfrozen(id, service_name)?;
+        // Instantiating before borrowing, as the factory may borrow other services.
+        self.singleton_get(id, service_name)?;
+        let state = self.borrow_state(id, service_name)?;
+        state.try_borrow_mut(service_name, location)?;
+        // Getting the instance again once borrowed, as it may have been replaced meanwhile.
+        let service = self
+            .singleton_get(id, service_name)
+            .inspect_err(|_| state.release_mut())?;
+        let mut service = ServiceRefMut::new(unsafe { &mut *service }, state);
+        (as_runnable.mutable)(&mut *service)
+            .map(f)
+            .ok_or_else(|| Error::FailedToDowncastRefOfService(service_name.into()))
+    }
+
+    /// Calling the closure with the `Runnable` implementation of the service, while the service
+    /// is borrowed as `get_ref` does.
+    fn with_shared_runnable<R>(
+        &self,
+        id: &Uuid,
+        service_name: &str,
+        location: &'static Location<'static>,
+        f: impl FnOnce(&dyn Runnable) -> R,
+    ) -> Result<R> {
+        let as_runnable = self.as_runnable(id, service_name)?;
+        self.check_caller(id, service_name, location)?;
+        self.singleton_get(id, service_name)?;
+        let state = self.borrow_state(id, service_name)?;
+        state.try_borrow(service_name, location)?;
+        let service = self
+            .singleton_get(id, service_name)
+            .inspect_err(|_| state.release(location))?;
+        let service = ServiceRef::new(unsafe { &*service }, state, location);
+        (as_runnable.shared)(&*service)
+            .map(f)
+            .ok_or_else(|| Error::FailedToDowncastRefOfService(service_name.into()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Error, Runnable, SingletonManager};
+    use std::sync::{Arc, Mutex};
+
+    struct Worker {
+        name: &'static str,
+        running: bool,
+        log: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl Runnable for Worker {
+        fn start(&mut self) -> Result<(), String> {
+            self.running = true;
+            self.log
+                .lock()
+                .unwrap()
+                .push(format!("start {}", self.name));
+            Ok(())
+        }
+
+        fn stop(&mut self) -> Result<(), String> {
+            self.running = false;
+            self.log.lock().unwrap().push(format!("stop {}", self.name));
+            Ok(())
+        }
+
+        fn is_running(&self) -> bool {
+            self.running
+        }
+    }
+
+    #[test]
+    fn test_start_and_stop_in_dependency_order() {
+        let mut manager = SingletonManager::new();
+        let log = Arc::new(Mutex::new(Vec::new()));
+        for name in ["pool", "queue", "consumer"] {
+            let log = log.clone();
+            manager
+                .set_factory(name, move || {
+                    Box::new(Worker {
+                        name,
+                        running: false,
+                        log: log.clone(),
+                    })
+                })
+                .unwrap();
+            manager.set_runnable::<Worker>(name).unwrap();
+        }
+        manager.set_factory("config", || Box::new(1_u32)).unwrap();
+        manager.depends_on("queue", "pool").unwrap();
+        manager.depends_on("queue", "config").unwrap();
+        manager.depends_on("consumer", "queue").unwrap();
+        assert!(matches!(
+            manager.depends_on("pool", "consumer"),
+            Err(Error::DependencyCycle(_, _))
+        ));
+        assert!(matches!(
+            manager.start("config"),
+            Err(Error::NotRunnable(_))
+        ));
+
+        assert_eq!(
+            vec!["pool", "queue", "consumer"],
+            manager.start("consumer").unwrap()
+        );
+        assert!(manager.is_instantiated("config"));
+        assert!(manager.start("consumer").unwrap().is_empty());
+
+        assert_eq!(vec!["consumer", "queue"], manager.stop("queue").unwrap());
+        assert!(manager.is_running("pool"));
+        assert_eq!(
+            vec!["queue", "consumer"],
+            manager.start("consumer").unwrap()
+        );
+        assert_eq!(vec!["queue", "consumer"], manager.restart("queue").unwrap());
+        assert_eq!(
+            vec![
+                "stop consumer",
+                "stop queue",
+                "start queue",
+                "start consumer"
+            ],
+            log.lock().unwrap()[7..]
+        );
+        assert!(manager.is_running("consumer"));
+    }
+
+    struct Flaky;
+
+    impl Runnable for Flaky {
+        fn start(&mut self) -> Result<(), String> {
+            panic!("The flaky service failed to start")
+        }
+
+        fn stop(&mut self) -> Result<(), String> {
+            Ok(())
+        }
+
+        fn is_running(&self) -> bool {
+            false
+        }
+    }
+
+    #[test]
+    fn test_runnables_are_borrowed_through_guards() {
+        let mut manager = SingletonManager::new();
+        manager.set_factory("flaky", || Box::new(Flaky)).unwrap();
+        manager.set_runnable::<Flaky>("flaky").unwrap();
+
+        let started =
+            std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| manager.start("flaky")));
+        assert!(started.is_err());
+        let flaky = manager.get_ref::<Flaky>("flaky").unwrap();
+        assert!(!manager.is_running("flaky"));
+        drop(flaky);
+
+        manager.freeze("flaky").unwrap();
+        assert!(matches!(
+            manager.start("flaky"),
+            Err(Error::ServiceFrozen(alias)) if alias == "flaky"
+        ));
+    }
+}
@@ -0,0 +1,172 @@
+//! # Safety audit
+//! Quantifying the references handed out without tracking.
+//!
+//! `get`, `set`, `set_with_meta` and `set_versioned` return plain mutable references into the
+//! singleton storage, which the singleton manager can not track. They are counted per service,
+//! until the next of these calls: it needs exclusive access to the manager, which the
+//! borrow checker only grants once the references handed out before are no longer used. When
+//! the instance of a service is dropped while references to it are outstanding, it is deferred
+//! until `gc` drops it, at which point the references are counted as possibly dangling, and
+//! debug builds log a warning naming the service.
+//!
+//! Debug builds also check that the instance borrowed by a guard is not replaced while the guard
+//! is held.
+//! `unsafe_stats` reports the counters, e.g. to follow the migration to the guards of `borrow`,
+//! `borrow_mut`, `get_ref` and `get_mut`, which are tracked.
+//! ```
+//! use singleton_manager::SingletonManager;
+//!
+//! let mut manager = SingletonManager::new();
+//! manager.set("db", "postgres".to_string()).unwrap();
+//! manager.get::<String>("db").unwrap();
+//! drop(manager.get_ref::<String>("db").unwrap());
+//!
+//! let stats = manager.unsafe_stats();
+//! assert_eq!(1, stats.outstanding);
+//! assert_eq!(1, stats.services);
+//! ```
+use crate::{Registry, Service, SingletonManager, Uuid};
+
+/// The counters of the references handed out without tracking, see
+/// `SingletonManager::unsafe_stats`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct UnsafeStats {
+    /// The number of references handed out to the instances currently stored, since the last
+    /// exclusive access of the manager.
+    pub outstanding: usize,
+    /// The number of services with references handed out.
+    pub services: usize,
+    /// The number of references handed out to instances dropped since.
+    pub dangling: usize,
+    /// The number of removed instances waiting in the deferral queue to be dropped.
+    pub deferred: usize,
+}
+
+impl Registry {
+    /// Removing the instance of the service from the storage, returning it together with the
+    /// number of references handed out to it.
+    pub(crate) fn remove_instance(&mut self, id: &Uuid) -> Option<(Service, usize)> {
+        let instance = self.singletons.remove(id)?;
+        self.instances.remove(id);
+        self.forget_downcasts(id);
+        self.invalidate_facades();
+        let references = self.borrows.get(id).map_or(0, |state| {
+            state.replace_instance();
+            state.take_raw_references(self.raw_epoch)
+        });
+        Some((instance, references))
+    }
+
+    /// Removing the instance of the service from the storage, to hand over its ownership,
+    /// counting the references handed out to it as dangling.
+    pub(crate) fn take_instance(&mut self, id: &Uuid) -> Option<Service> {
+        let (instance, references) = self.remove_instance(id)?;
+        let alias = self.alias_of(id).unwrap_or_default().to_string();
+        self.note_dangling(&alias, references);
+        Some(instance)
+    }
+
+    /// Counting the references handed out to an instance dropped, or moved, as dangling.
+    pub(crate) fn note_dangling(&mut self, alias: &str, references: usize) {
+        if cfg!(debug_assertions) && references > 0 {
+            log::warn!(
+                "Service `{}` is dropped while {} references to it may still be used",
+                alias,
+                references
+            );
+        }
+        self.dangling_references += references;
+    }
+}
+
+impl SingletonManager {
+    /// Ending the references handed out without tracking, on an exclusive access of the
+    /// manager, which the borrow checker only allows once they are no longer used.
+    pub(crate) fn end_raw_references(&mut self) {
+        if let Ok(registry) = self.registry.get_mut() {
+            registry.raw_epoch += 1;
+        }
+    }
+
+    /// Counting a reference to the service handed out without tracking.
+    pub(crate) fn note_raw_reference(&self, service_name: &str) {
+        if let Ok(registry) = self.registry() {
+            if let Some(state) = registry
+                .resolve(service_name)
+                .and_then(|id| registry.borrows.get(&id))
+            {
+                state.note_raw_reference(registry.raw_epoch);
+            }
+        }
+    }
+
+    /// The counters of the references handed out without tracking.
+    pub fn unsafe_stats(&self) -> UnsafeStats {
+        let registry = match self.registry() {
+            Ok(registry) => registry,
+            Err(_) => return UnsafeStats::default(),
+        };
+        let mut stats = UnsafeStats {
+            dangling: registry.dangling_references,
+            deferred: registry.deferred.len(),
+            ..UnsafeStats::default()
+        };
+        for state in registry.borrows.values() {
+            let references = state.raw_references(registry.raw_epoch);
+            if references > 0 {
+                stats.outstanding += references;
+                stats.services += 1;
+            }
+        }
+        stats
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Phase, SingletonManager, UnsafeStats};
+
+    #[test]
+    fn test_unsafe_stats_count_dangling_references() {
+        let mut manager = SingletonManager::new();
+        manager.set_factory("pool", || Box::new(4_u32)).unwrap();
+        manager.set("config", 1_u8).unwrap();
+        manager.set_phase("config", Phase::Infrastructure).unwrap();
+        assert_eq!(1, manager.unsafe_stats().outstanding);
+        manager.get::<u32>("pool").unwrap();
+        manager.get::<u32>("pool").unwrap();
+        drop(manager.get_mut::<u32>("pool").unwrap());
+
+        assert_eq!(
+            UnsafeStats {
+                outstanding: 1,
+                services: 1,
+                dangling: 0,
+                deferred: 0
+            },
+            manager.unsafe_stats()
+        );
+
+        manager.shutdown().unwrap();
+        assert_eq!(
+            UnsafeStats {
+                outstanding: 0,
+                services: 0,
+                dangling: 0,
+                deferred: 1
+            },
+            manager.unsafe_stats()
+        );
+        manager.gc();
+        manager.gc();
+        assert_eq!(
+            UnsafeStats {
+                outstanding: 0,
+                services: 0,
+                dangling: 1,
+                deferred: 0
+            },
+            manager.unsafe_stats()
+        );
+    }
+}
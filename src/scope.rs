@@ -0,0 +1,166 @@
+//! # Scopes
+//! Services living only as long as a closure runs.
+//!
+//! Per-request or per-job services, e.g. the context of a request, are registered through the
+//! scope handed to the closure of `SingletonManager::scope`. When the closure returns, or
+//! panics, the services registered through the scope are removed in the reverse order of their
+//! registration, and dropped after the singleton manager is unlocked.
+//! ```
+//! use singleton_manager::sm;
+//!
+//! let user = sm().scope(|scope| {
+//!     scope.set("scope_request_user", "alice".to_string()).unwrap();
+//!     sm().get_cloned::<String>("scope_request_user").unwrap()
+//! });
+//! assert_eq!("alice", user);
+//! assert!(!sm().has("scope_request_user"));
+//! ```
+use crate::{Registry, SetError, SingletonManager, Uuid};
+use std::any::Any;
+use std::panic::Location;
+use std::sync::Arc;
+
+/// The registrations of a scope, removed when it ends, see `SingletonManager::scope`.
+pub struct Scope<'a> {
+    manager: &'a SingletonManager,
+    registered: Vec<(Uuid, String)>,
+    /// Where the scope was opened, recorded as the location of the removals.
+    location: &'static Location<'static>,
+}
+
+impl<'a> Scope<'a> {
+    /// The singleton manager the services are registered in.
+    pub fn manager(&self) -> &'a SingletonManager {
+        self.manager
+    }
+
+    /// Setting a service for the rest of the scope, as `SingletonManager::set` does.
+    #[track_caller]
+    pub fn set<T: Send + Sync + 'static>(
+        &mut self,
+        service_name: &str,
+        service: T,
+    ) -> std::result::Result<(), SetError> {
+        let location = Location::caller();
+        self.register(service_name, |registry| {
+            registry
+                .store_service(
+                    service_name,
+                    Box::new(service),
+                    std::any::type_name::<T>(),
+                    &[],
+                    location,
+                )
+                .map(|stored| stored.is_some())
+        })
+    }
+
+    /// Setting a factory for the rest of the scope, as `SingletonManager::set_factory` does.
+    #[track_caller]
+    pub fn set_factory<F: 'static + Fn() -> Box<dyn Any + Send + Sync> + Send + Sync>(
+        &mut self,
+        service_name: &str,
+        factory: F,
+    ) -> std::result::Result<(), SetError> {
+        let location = Location::caller();
+        self.register(service_name, |registry| {
+            registry.store_factory(service_name, Arc::new(factory), location)
+        })
+    }
+
+    /// Storing a registration, and removing it when the scope ends unless the collision policy
+    /// kept the registration already there.
+    fn register(
+        &mut self,
+        service_name: &str,
+        store: impl FnOnce(&mut Registry) -> crate::Result<bool>,
+    ) -> std::result::Result<(), SetError> {
+        let mut registry = self
+            .manager
+            .registry_mut()
+            .map_err(|e| SetError::from_error(e, service_name))?;
+        let stored = store(&mut registry).map_err(|e| SetError::from_error(e, service_name))?;
+        if let Some(id) = registry.resolve(service_name).filter(|_| stored) {
+            self.registered.push((id, service_name.to_string()));
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Scope<'_> {
+    /// Removing the services of the scope, keeping the services still borrowed or frozen.
+    fn drop(&mut self) {
+        let mut torn_down = Vec::new();
+        if let Ok(mut registry) = self.manager.registry_mut() {
+            for service in self.registered.iter().rev() {
+                if registry.resolve(&service.1) != Some(service.0) {
+                    continue;
+                }
+                match registry.teardown(std::slice::from_ref(service), self.location) {
+                    Ok(services) => torn_down.extend(services),
+                    Err(e) => log::warn!("Service `{}` outlives its scope: {}", service.1, e),
+                }
+            }
+        }
+        torn_down.into_iter().for_each(drop);
+    }
+}
+
+impl SingletonManager {
+    /// Running the closure with a scope, removing the services registered through the scope
+    /// when the closure returns or panics.
+    /// Services still borrowed, or frozen, when the scope ends are kept.
+    #[track_caller]
+    pub fn scope<R>(&self, f: impl FnOnce(&mut Scope<'_>) -> R) -> R {
+        let mut scope = Scope {
+            manager: self,
+            registered: Vec::new(),
+            location: Location::caller(),
+        };
+        f(&mut scope)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::SingletonManager;
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    struct Job(Arc<AtomicUsize>);
+
+    impl Drop for Job {
+        fn drop(&mut self) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn test_scope_removes_its_services_even_on_panic() {
+        let dropped = Arc::new(AtomicUsize::new(0));
+        let mut manager = SingletonManager::new();
+        manager.set_factory("db", || Box::new(1_u32)).unwrap();
+
+        manager.scope(|scope| {
+            scope.set("job", Job(dropped.clone())).unwrap();
+            scope.set_factory("attempt", || Box::new(1_u32)).unwrap();
+            assert!(scope.set("db", 2_u32).is_err());
+            assert!(scope.manager().has("job"));
+        });
+        assert!(!manager.has("job"));
+        assert!(!manager.has("attempt"));
+        assert!(manager.has("db"));
+        assert_eq!(1, dropped.load(Ordering::SeqCst));
+
+        let panicked = catch_unwind(AssertUnwindSafe(|| {
+            manager.scope(|scope| {
+                scope.set("job", Job(dropped.clone())).unwrap();
+                panic!("The job failed");
+            })
+        }));
+        assert!(panicked.is_err());
+        assert!(!manager.has("job"));
+        assert_eq!(2, dropped.load(Ordering::SeqCst));
+    }
+}
@@ -0,0 +1,153 @@
+//! # Scripting adapters
+//! Calling services from embedded scripting languages through registered shims.
+//!
+//! Scripting layers can not call Rust methods directly. A service is made callable by
+//! registering an adapter, a shim dispatching a method name and dynamically typed arguments to
+//! the service. The Python and Node bindings, behind the `pyo3` and `napi` features, call the
+//! services of the global instance through their adapters.
+//! ```
+//! use singleton_manager::{ScriptValue, SingletonManager};
+//!
+//! let mut manager = SingletonManager::new();
+//! manager.set("visits", 0_i64).unwrap();
+//! manager
+//!     .set_script_adapter::<i64, _>("visits", |visits, method, args| match (method, args) {
+//!         ("add", [ScriptValue::Int(n)]) => {
+//!             *visits += n;
+//!             Ok(ScriptValue::Int(*visits))
+//!         }
+//!         _ => Err(format!("Unknown method `{}`", method).into()),
+//!     })
+//!     .unwrap();
+//!
+//! let visits = manager.call_script("visits", "add", &[ScriptValue::Int(2)]);
+//! assert_eq!(ScriptValue::Int(2), visits.unwrap());
+//! ```
+use crate::{Error, Result, SingletonManager};
+use std::panic::Location;
+use std::sync::Arc;
+
+/// A dynamically typed value passed between scripts and services.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScriptValue {
+    None,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Str(String),
+    List(Vec<ScriptValue>),
+}
+
+/// Calling a method of a type erased service, with the manager and alias of the service.
+/// The location is the caller of `call_script`, restricted as the caller of `get_mut` is.
+pub(crate) type ScriptShim = Arc<
+    dyn Fn(
+            &SingletonManager,
+            &str,
+            &str,
+            &[ScriptValue],
+            &'static Location<'static>,
+        ) -> Result<ScriptValue>
+        + Send
+        + Sync,
+>;
+
+impl SingletonManager {
+    /// Registering the adapter calling the methods of the service, where the service is a `T`.
+    /// The adapter gets exclusive access to the service for the duration of the call.
+    pub fn set_script_adapter<T, F>(&self, service_name: &str, adapter: F) -> Result<()>
+    where
+        T: 'static,
+        F: Fn(&mut T, &str, &[ScriptValue]) -> Result<ScriptValue> + Send + Sync + 'static,
+    {
+        let id = self.service_id(service_name)?;
+        let shim: ScriptShim = Arc::new(move |manager, alias, method, args, location| {
+            let mut service = manager.exclusive_borrow::<T>(alias, location, |state| {
+                state.wait_borrow_mut(location);
+                Ok(())
+            })?;
+            adapter(&mut *service, method, args)
+        });
+        self.registry_mut()?.script_adapters.insert(id, shim);
+        Ok(())
+    }
+
+    /// Calling a method of the service through its adapter.
+    /// Fails with `Error::NotScriptable` if the service has no adapter.
+    #[track_caller]
+    pub fn call_script(
+        &self,
+        service_name: &str,
+        method: &str,
+        args: &[ScriptValue],
+    ) -> Result<ScriptValue> {
+        let location = Location::caller();
+        let shim = {
+            let registry = self.registry()?;
+            let id = registry.resolve(service_name).ok_or_else(|| {
+                Error::ServiceDoesNotExist(service_name.into(), Default::default())
+            })?;
+            registry
+                .script_adapters
+                .get(&id)
+                .cloned()
+                .ok_or_else(|| Error::NotScriptable(service_name.into()))?
+        };
+        shim(self, service_name, method, args, location)
+    }
+
+    /// The aliases of the services with an adapter, in alias order.
+    pub fn scriptable(&self) -> Vec<String> {
+        let mut aliases: Vec<String> = self
+            .registry()
+            .map(|registry| {
+                registry
+                    .alias
+                    .iter()
+                    .filter(|(_, id)| registry.script_adapters.contains_key(id))
+                    .map(|(alias, _)| alias.to_string())
+                    .collect()
+            })
+            .unwrap_or_default();
+        aliases.sort();
+        aliases
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Error, ScriptValue, SingletonManager};
+
+    #[test]
+    fn test_scripts_call_services_through_adapters() {
+        let mut manager = SingletonManager::new();
+        manager.set("names", Vec::<String>::new()).unwrap();
+        manager.set("plain", 1_u8).unwrap();
+        manager
+            .set_script_adapter::<Vec<String>, _>("names", |names, method, args| {
+                match (method, args) {
+                    ("push", [ScriptValue::Str(name)]) => names.push(name.clone()),
+                    ("len", []) => return Ok(ScriptValue::Int(names.len() as i64)),
+                    _ => return Err(Error::UnknownError(method.to_string())),
+                }
+                Ok(ScriptValue::None)
+            })
+            .unwrap();
+
+        let alice = [ScriptValue::Str("alice".to_string())];
+        assert_eq!(
+            ScriptValue::None,
+            manager.call_script("names", "push", &alice).unwrap()
+        );
+        assert_eq!(
+            ScriptValue::Int(1),
+            manager.call_script("names", "len", &[]).unwrap()
+        );
+        assert!(manager.call_script("names", "pop", &[]).is_err());
+        assert!(matches!(
+            manager.call_script("plain", "len", &[]),
+            Err(Error::NotScriptable(_))
+        ));
+        assert_eq!(vec!["names".to_string()], manager.scriptable());
+    }
+}
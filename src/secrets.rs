@@ -0,0 +1,191 @@
+//! # Secrets
+//! Routing credentials through the registry without leaking them.
+//!
+//! A `SecretStore` bound as `dyn SecretStore`, see `SingletonManager::bind`, looks secrets up by
+//! key, from the environment with `EnvSecrets`, from a directory of files with `FileSecrets`,
+//! e.g. the secrets mounted into a container, or from any other backend implementing the trait,
+//! including closures. `SingletonManager::secret` gets a secret from the store bound.
+//!
+//! The value of a secret is wrapped in a `Secret`, which is redacted when formatted, so secrets
+//! set as services, or kept in services, do not show up in logs and the introspection of the
+//! registry. The value is only reachable by calling `expose`.
+//! ```
+//! use singleton_manager::{Secret, SecretStore, SingletonManager};
+//!
+//! let manager = SingletonManager::new();
+//! let vault = |key: &str| match key {
+//!     "db_password" => Some("hunter2".to_string()),
+//!     _ => None,
+//! };
+//! manager.bind::<dyn SecretStore>(Box::new(vault)).unwrap();
+//!
+//! let password = manager.secret("db_password").unwrap();
+//! assert_eq!("Secret(<redacted>)", format!("{:?}", password));
+//! assert_eq!("hunter2", password.expose());
+//! assert!(manager.secret("api_key").unwrap_err().is_not_found());
+//! ```
+use crate::{Error, Result, SingletonManager};
+use std::fmt::{Debug, Display, Formatter};
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+/// A value redacted when formatted, only reachable by calling `expose`.
+#[derive(Clone, Default)]
+pub struct Secret<T>(T);
+
+impl<T> Secret<T> {
+    pub fn new(value: T) -> Self {
+        Self(value)
+    }
+
+    /// The value of the secret.
+    pub fn expose(&self) -> &T {
+        &self.0
+    }
+
+    /// Unwrapping the value of the secret.
+    pub fn into_exposed(self) -> T {
+        self.0
+    }
+}
+
+impl<T> From<T> for Secret<T> {
+    fn from(value: T) -> Self {
+        Self(value)
+    }
+}
+
+impl<T> Debug for Secret<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Secret(<redacted>)")
+    }
+}
+
+impl<T> Display for Secret<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<redacted>")
+    }
+}
+
+/// A backend looking secrets up by key, bound as `dyn SecretStore`.
+pub trait SecretStore: Send + Sync {
+    /// Getting the secret, failing with `Error::SecretNotFound` if the store has none under the
+    /// key.
+    fn secret(&self, key: &str) -> Result<Secret<String>>;
+}
+
+impl<F> SecretStore for F
+where
+    F: Fn(&str) -> Option<String> + Send + Sync,
+{
+    fn secret(&self, key: &str) -> Result<Secret<String>> {
+        self(key)
+            .map(Secret)
+            .ok_or_else(|| Error::SecretNotFound(key.to_string()))
+    }
+}
+
+/// The secrets in the environment variables, named by the key with a prefix.
+#[derive(Debug, Clone, Default)]
+pub struct EnvSecrets {
+    prefix: String,
+}
+
+impl EnvSecrets {
+    /// The secrets in the environment variables named by their key.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The secrets in the environment variables named by the prefix followed by their key.
+    pub fn with_prefix(prefix: &str) -> Self {
+        Self {
+            prefix: prefix.to_string(),
+        }
+    }
+}
+
+impl SecretStore for EnvSecrets {
+    fn secret(&self, key: &str) -> Result<Secret<String>> {
+        std::env::var(format!("{}{}", self.prefix, key))
+            .map(Secret)
+            .map_err(|_| Error::SecretNotFound(key.to_string()))
+    }
+}
+
+/// The secrets in the files of a directory, named by their key, without the trailing line break.
+#[derive(Debug, Clone)]
+pub struct FileSecrets {
+    directory: PathBuf,
+}
+
+impl FileSecrets {
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        Self {
+            directory: directory.into(),
+        }
+    }
+}
+
+impl SecretStore for FileSecrets {
+    fn secret(&self, key: &str) -> Result<Secret<String>> {
+        // Keys are file names, not paths leaving the directory.
+        if key.is_empty() || key.contains(['/', '\\']) || key == "." || key == ".." {
+            return Err(Error::SecretNotFound(key.to_string()));
+        }
+        match std::fs::read_to_string(self.directory.join(key)) {
+            Ok(mut value) => {
+                let trimmed = value.trim_end_matches(['\r', '\n']).len();
+                value.truncate(trimmed);
+                Ok(Secret(value))
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                Err(Error::SecretNotFound(key.to_string()))
+            }
+            Err(e) => Err(Error::UnknownError(format!(
+                "Failed to read secret `{}`: {}",
+                key, e
+            ))),
+        }
+    }
+}
+
+impl SingletonManager {
+    /// Getting a secret from the store bound as `dyn SecretStore`.
+    /// Fails with `Error::SecretNotFound` if the store has no secret under the key, and with
+    /// `Error::ServiceDoesNotExist` if no store is bound.
+    #[track_caller]
+    pub fn secret(&self, key: &str) -> Result<Secret<String>> {
+        self.get_bound::<dyn SecretStore>()?.secret(key)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Error, FileSecrets, Secret, SecretStore, SingletonManager};
+
+    #[test]
+    fn test_secrets_from_files_are_redacted() {
+        let directory = std::env::temp_dir().join(format!("sm_secrets_{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(directory.join("token"), "s3cr3t\n").unwrap();
+
+        let mut manager = SingletonManager::new();
+        manager
+            .bind::<dyn SecretStore>(Box::new(FileSecrets::new(&directory)))
+            .unwrap();
+        let token = manager.secret("token").unwrap();
+        assert_eq!("s3cr3t", token.expose());
+        assert!(matches!(
+            manager.secret("../token"),
+            Err(Error::SecretNotFound(key)) if key == "../token"
+        ));
+
+        manager.set("api_token", token).unwrap();
+        let dump = manager.dump();
+        assert!(!format!("{:?}{}", dump, dump.to_json()).contains("s3cr3t"));
+        let token = manager.get_ref::<Secret<String>>("api_token").unwrap();
+        assert_eq!("<redacted>", token.to_string());
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
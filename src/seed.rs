@@ -0,0 +1,170 @@
+//! # Seed data
+//! Setting services deserialized from JSON, behind the `serde` feature.
+//!
+//! Tests and bootstrapping code often set up services from fixed data, e.g. the configuration
+//! of a test environment, deserializing every value by hand before setting it.
+//! `seed_from_json` does both in one step. For a whole document of seed data, the type of every
+//! alias is registered with `register_seed`, and `seed_all_from_json` sets the services from a
+//! JSON object of the seed data by alias.
+//!
+//! The values are set like any other service, going through the validators and the collision
+//! policy of the registry. The services of a document are set as a transaction, so either all
+//! of them are set or none are.
+//! ```
+//! use serde::Deserialize;
+//! use singleton_manager::SingletonManager;
+//!
+//! #[derive(Deserialize)]
+//! struct Config {
+//!     region: String,
+//!     replicas: u8,
+//! }
+//!
+//! let manager = SingletonManager::new();
+//! manager
+//!     .seed_from_json::<Config>("config", r#"{"region": "eu-west-1", "replicas": 3}"#)
+//!     .unwrap();
+//! assert_eq!(3, manager.get_ref::<Config>("config").unwrap().replicas);
+//!
+//! manager.register_seed::<u16>("port").unwrap();
+//! manager.register_seed::<Vec<String>>("admins").unwrap();
+//! manager
+//!     .seed_all_from_json(r#"{"port": 8080, "admins": ["root"]}"#)
+//!     .unwrap();
+//! assert_eq!(8080, *manager.get_ref::<u16>("port").unwrap());
+//! ```
+use crate::{Error, Result, Service, SingletonManager, Transaction};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::panic::Location;
+use std::sync::Arc;
+
+/// Deserializing the seed data of a service, together with the name of its type.
+pub(crate) type Seeder =
+    Arc<dyn Fn(Value) -> serde_json::Result<(Service, &'static str)> + Send + Sync>;
+
+impl SingletonManager {
+    /// Setting the service deserialized from the JSON.
+    /// Fails with `Error::InvalidSeed` if the JSON can not be deserialized as a `T`.
+    #[track_caller]
+    pub fn seed_from_json<T>(&self, service_name: &str, json: &str) -> Result<()>
+    where
+        T: DeserializeOwned + Send + Sync + 'static,
+    {
+        let location = Location::caller();
+        let service = serde_json::from_str::<T>(json)
+            .map_err(|e| Error::InvalidSeed(service_name.into(), e.to_string()))?;
+        self.store(service_name, service, &[], location).map(|_| ())
+    }
+
+    /// Registering `T` as the type of the seed data of the alias, used by `seed_all_from_json`.
+    pub fn register_seed<T>(&self, service_name: &str) -> Result<()>
+    where
+        T: DeserializeOwned + Send + Sync + 'static,
+    {
+        let seeder: Seeder = Arc::new(|value| {
+            let service: Service = Box::new(serde_json::from_value::<T>(value)?);
+            Ok((service, std::any::type_name::<T>()))
+        });
+        self.registry_mut()?
+            .seeders
+            .insert(service_name.to_string(), seeder);
+        Ok(())
+    }
+
+    /// Setting the services from a JSON object of their seed data by alias, returning the
+    /// aliases set in alias order.
+    ///
+    /// Fails with `Error::InvalidSeedData` if the JSON is not an object, and with
+    /// `Error::InvalidSeed` if an alias has no type registered by `register_seed`, or its seed
+    /// data can not be deserialized as that type. All seed data is deserialized before any
+    /// service is set, and the services are set as a transaction, so nothing is set if any of it
+    /// is invalid, or any service fails to be set, e.g. rejected by a validator.
+    #[track_caller]
+    pub fn seed_all_from_json(&self, json: &str) -> Result<Vec<String>> {
+        let location = Location::caller();
+        let seeds = match serde_json::from_str::<Value>(json) {
+            Ok(Value::Object(seeds)) => seeds,
+            Ok(_) => return Err(Error::InvalidSeedData("expected an object".to_string())),
+            Err(e) => return Err(Error::InvalidSeedData(e.to_string())),
+        };
+        let seeders = {
+            let registry = self.registry()?;
+            seeds
+                .keys()
+                .map(|alias| {
+                    registry.seeders.get(alias).cloned().ok_or_else(|| {
+                        Error::InvalidSeed(alias.as_str().into(), "no type registered".into())
+                    })
+                })
+                .collect::<Result<Vec<_>>>()?
+        };
+        let services = seeds
+            .into_iter()
+            .zip(seeders)
+            .map(|((alias, value), seeder)| match seeder(value) {
+                Ok((service, type_name)) => Ok((alias, service, type_name)),
+                Err(e) => Err(Error::InvalidSeed(alias.into(), e.to_string())),
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut transaction = Transaction::default();
+        let mut seeded = Vec::with_capacity(services.len());
+        for (alias, service, type_name) in services {
+            transaction.set_boxed(&alias, service, type_name, location)?;
+            seeded.push(alias);
+        }
+        self.registry_mut()?.apply(transaction)?;
+        Ok(seeded)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Error, SingletonManager};
+
+    #[test]
+    fn test_invalid_seed_data_sets_nothing() {
+        let mut manager = SingletonManager::new();
+        assert!(matches!(
+            manager.seed_from_json::<u8>("workers", "\"four\""),
+            Err(Error::InvalidSeed(alias, _)) if alias == "workers"
+        ));
+        assert!(!manager.has("workers"));
+
+        manager.register_seed::<u8>("workers").unwrap();
+        manager.register_seed::<String>("region").unwrap();
+        assert!(matches!(
+            manager.seed_all_from_json("[4]"),
+            Err(Error::InvalidSeedData(_))
+        ));
+        assert!(matches!(
+            manager.seed_all_from_json(r#"{"region": "eu", "workers": 400}"#),
+            Err(Error::InvalidSeed(alias, _)) if alias == "workers"
+        ));
+        assert!(matches!(
+            manager.seed_all_from_json(r#"{"region": "eu", "timeout": 30}"#),
+            Err(Error::InvalidSeed(alias, _)) if alias == "timeout"
+        ));
+        assert_eq!(0, manager.count());
+
+        manager.set("workers", 2_u8).unwrap();
+        assert!(matches!(
+            manager.seed_all_from_json(r#"{"region": "eu", "workers": 4}"#),
+            Err(Error::ServiceAlreadyExists(alias)) if alias == "workers"
+        ));
+        assert!(!manager.has("region"));
+        manager.take::<u8>("workers").unwrap();
+
+        assert_eq!(
+            vec!["region", "workers"],
+            manager
+                .seed_all_from_json(r#"{"workers": 4, "region": "eu"}"#)
+                .unwrap()
+        );
+        assert_eq!("eu", *manager.get_ref::<String>("region").unwrap());
+        let forked = manager.fork().unwrap();
+        forked.seed_all_from_json(r#"{"workers": 8}"#).unwrap();
+        assert_eq!(8, *forked.get_ref::<u8>("workers").unwrap());
+    }
+}
@@ -0,0 +1,225 @@
+//! # Shutdown
+//! Shutting down all services, reporting the services still in use.
+//!
+//! After a hot swap, a component holding on to a handle of the old service, e.g. the old
+//! connection pool, keeps it alive without anyone noticing. The singleton manager counts the
+//! handles it handed out per service, which are the borrows of `borrow`, `borrow_mut`,
+//! `get_ref` and `get_mut`, and the `Arc`s of the memoized instances. Shutting down never drops
+//! a service under a live handle, the service is reported as leaked instead.
+//!
+//! References returned by `get` are not tracked, and are not counted as handles.
+//! ```
+//! use singleton_manager::SingletonManager;
+//!
+//! let mut manager = SingletonManager::new();
+//! manager.set_factory("pool", || Box::new(vec![1_u32, 2])).unwrap();
+//! manager.set("config", "production".to_string()).unwrap();
+//!
+//! let pool = manager.get_ref::<Vec<u32>>("pool").unwrap();
+//! assert_eq!(1, manager.active_handles("pool"));
+//!
+//! let report = manager.shutdown().unwrap();
+//! assert_eq!(vec!["config".to_string()], report.shut_down);
+//! assert_eq!(vec![("pool".to_string(), 1)], report.leaked);
+//! # drop(pool);
+//! ```
+use crate::{Error, Operation, Registry, Result, Service, SingletonManager, Uuid};
+use std::cmp::Reverse;
+use std::panic::Location;
+
+/// The result of `SingletonManager::shutdown`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ShutdownReport {
+    /// The aliases of the services shut down, in the order they were shut down.
+    pub shut_down: Vec<String>,
+    /// The aliases of the services kept because of live handles, with the number of handles.
+    pub leaked: Vec<(String, usize)>,
+    /// The aliases of the frozen services, which are never shut down.
+    pub frozen: Vec<String>,
+    /// The aliases of the services whose stop hook failed, which are shut down regardless.
+    pub failed: Vec<(String, Error)>,
+    /// The background tasks, with their status when shut down, see `spawn_managed`.
+    #[cfg(feature = "tokio")]
+    pub tasks: Vec<(String, crate::TaskStatus)>,
+}
+
+impl ShutdownReport {
+    /// True if no service was kept because of live handles.
+    pub fn is_clean(&self) -> bool {
+        self.leaked.is_empty()
+    }
+}
+
+impl Registry {
+    /// The number of handles to the service held outside of the singleton manager.
+    fn active_handles(&self, id: &Uuid) -> usize {
+        self.borrows.get(id).map_or(0, |state| state.handles())
+            + self
+                .memoized
+                .get(id)
+                .map_or(0, |memoized| memoized.handles())
+    }
+
+    /// Shutting down the service, returning its instance so it can be dropped after releasing
+    /// the lock.
+    /// Services with a factory stay registered, services set directly are removed.
+    pub(crate) fn shut_down(
+        &mut self,
+        service: &(Uuid, String),
+        location: &'static Location<'static>,
+    ) -> Result<Vec<Service>> {
+        let (id, alias) = service;
+        if self.singleton_factories.contains_key(id) {
+            let instance = self.drop_instance(id);
+            self.record(Operation::Shutdown, alias, Some(location));
+            Ok(instance.into_iter().collect())
+        } else {
+            self.teardown(std::slice::from_ref(service), location)
+        }
+    }
+}
+
+impl SingletonManager {
+    /// The number of handles to the service currently held, 0 if the service does not exist.
+    pub fn active_handles(&self, service_name: &str) -> usize {
+        self.registry()
+            .ok()
+            .and_then(|registry| {
+                let id = registry.resolve(service_name)?;
+                Some(registry.active_handles(&id))
+            })
+            .unwrap_or(0)
+    }
+
+    /// Shutting down all instantiated services, in reverse startup phase order, and within a
+    /// phase in reverse alias order.
+    /// Services with a factory stay registered and are instantiated again when needed, services
+    /// set directly are removed, and the instances of memoized services are evicted. The
+    /// instances are dropped after the singleton manager is unlocked.
+    ///
+    /// Services with live handles and frozen services are kept, and listed in the report.
+    /// Started services are stopped by their lifecycle hooks before their instances are dropped,
+    /// one at a time in shutdown order. With the `tokio` feature, the background tasks still
+    /// running are aborted.
+    #[track_caller]
+    pub fn shutdown(&self) -> Result<ShutdownReport> {
+        let location = Location::caller();
+        let mut registry = self.registry_mut()?;
+        let mut services: Vec<(Uuid, String)> = registry
+            .alias
+            .iter()
+            .filter(|(_, id)| {
+                registry.singletons.contains_key(id) || registry.memoized.contains_key(id)
+            })
+            .map(|(alias, id)| (*id, alias.to_string()))
+            .collect();
+        services.sort_by_key(|(id, alias)| {
+            Reverse((
+                registry.phases.get(id).copied().unwrap_or_default(),
+                alias.clone(),
+            ))
+        });
+
+        let mut report = ShutdownReport::default();
+        #[cfg(feature = "tokio")]
+        {
+            report.tasks = registry.abort_tasks();
+        }
+        let mut shut_down = Vec::new();
+        let mut stopping = Vec::new();
+        for service in &services {
+            let (id, alias) = service;
+            if registry.frozen.contains(id) {
+                report.frozen.push(alias.clone());
+                continue;
+            }
+            let handles = registry.active_handles(id);
+            if handles > 0 {
+                log::warn!(
+                    "Service `{}` is not shut down, {} handles are still held",
+                    alias,
+                    handles
+                );
+                report.leaked.push((alias.clone(), handles));
+                continue;
+            }
+            match registry.memoized.get_mut(id) {
+                Some(memoized) => {
+                    memoized.clear();
+                    registry.record(Operation::Shutdown, alias, Some(location));
+                }
+                None => {
+                    let hooks = match registry.started.remove(id) {
+                        true => registry.lifecycles.get(id).copied(),
+                        false => None,
+                    };
+                    let instances = registry.shut_down(service, location)?;
+                    match hooks {
+                        Some(hooks) => stopping.extend(
+                            instances
+                                .into_iter()
+                                .map(|instance| (alias.clone(), hooks, instance)),
+                        ),
+                        None => shut_down.extend(instances),
+                    }
+                }
+            }
+            report.shut_down.push(alias.clone());
+        }
+        drop(registry);
+        for (alias, hooks, instance) in stopping {
+            if let Err(e) = hooks.stop(&alias, instance) {
+                report.failed.push((alias, e));
+            }
+        }
+        shut_down.into_iter().for_each(drop);
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Phase, SingletonManager};
+
+    #[test]
+    fn test_shutdown_reports_live_handles() {
+        let mut manager = SingletonManager::new();
+        manager.set_factory("pool", || Box::new(4_u32)).unwrap();
+        manager.set("config", "production".to_string()).unwrap();
+        manager.set_phase("config", Phase::Infrastructure).unwrap();
+        manager
+            .set_memoized("client", |region: &String| region.clone())
+            .unwrap();
+        manager.set("routes", vec!["/"]).unwrap();
+        manager.freeze("routes").unwrap();
+
+        let client = manager
+            .get_with::<_, String>("client", &"eu".to_string())
+            .unwrap();
+        let pool = manager.get_ref::<u32>("pool").unwrap();
+        let again = manager.get_ref::<u32>("pool").unwrap();
+        assert_eq!(2, manager.active_handles("pool"));
+        assert_eq!(1, manager.active_handles("client"));
+        assert_eq!(0, manager.active_handles("unknown"));
+
+        let report = manager.shutdown().unwrap();
+        assert!(!report.is_clean());
+        assert_eq!(vec!["config".to_string()], report.shut_down);
+        assert_eq!(
+            vec![("pool".to_string(), 2), ("client".to_string(), 1)],
+            report.leaked
+        );
+        assert_eq!(vec!["routes".to_string()], report.frozen);
+        assert!(!manager.has("config"));
+
+        drop((pool, again, client));
+        let report = manager.shutdown().unwrap();
+        assert!(report.is_clean());
+        assert_eq!(
+            vec!["pool".to_string(), "client".to_string()],
+            report.shut_down
+        );
+        assert!(manager.has("pool"));
+        assert!(!manager.is_instantiated("pool"));
+    }
+}
@@ -0,0 +1,150 @@
+//! # Signal-driven shutdown
+//! Shutting down all services when the process receives a signal, behind the `signals` feature,
+//! on Unix.
+//!
+//! Daemons are stopped by SIGTERM, or by ctrl-c while developing, and need their services torn
+//! down in order, e.g. flushing queues before closing the connection pool. `shutdown_on_signals`
+//! installs the signal handlers and runs `shutdown` on a background thread once a signal arrives.
+//! The main thread waits for the shutdown to finish before exiting the process.
+//! ```no_run
+//! use singleton_manager::{sm, Signal};
+//!
+//! let shutdown = sm().shutdown_on_signals(&[Signal::Term, Signal::Int]).unwrap();
+//! // Serving requests on other threads...
+//! let report = shutdown.wait().unwrap();
+//! assert!(report.map_or(true, |report| report.is_clean()));
+//! ```
+use crate::{Error, Result, ShutdownReport, SingletonManager};
+use signal_hook::consts::{SIGHUP, SIGINT, SIGQUIT, SIGTERM};
+use signal_hook::iterator::{Handle, Signals};
+use std::os::raw::c_int;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// How long to wait between the attempts to shut down the services still in use.
+const DRAIN_INTERVAL: Duration = Duration::from_millis(10);
+
+/// A signal triggering the shutdown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Signal {
+    /// SIGTERM, sent by service managers and container runtimes.
+    Term,
+    /// SIGINT, sent by ctrl-c.
+    Int,
+    /// SIGHUP, sent when the controlling terminal is closed.
+    Hup,
+    /// SIGQUIT, sent by ctrl-\.
+    Quit,
+}
+
+impl Signal {
+    fn number(self) -> c_int {
+        match self {
+            Self::Term => SIGTERM,
+            Self::Int => SIGINT,
+            Self::Hup => SIGHUP,
+            Self::Quit => SIGQUIT,
+        }
+    }
+}
+
+/// The shutdown waiting for a signal, see `SingletonManager::shutdown_on_signals`.
+pub struct SignalShutdown {
+    handle: Handle,
+    thread: JoinHandle<Result<Option<ShutdownReport>>>,
+}
+
+impl SignalShutdown {
+    /// Waiting for a signal and the shutdown it triggered, returning the report of the shutdown,
+    /// or nothing if the shutdown was cancelled.
+    pub fn wait(self) -> Result<Option<ShutdownReport>> {
+        self.thread
+            .join()
+            .unwrap_or_else(|_| Err(Error::UnknownError("The shutdown panicked".to_string())))
+    }
+
+    /// Uninstalling the signal handlers without shutting down.
+    pub fn cancel(self) {
+        self.handle.close();
+        let _ = self.thread.join();
+    }
+}
+
+impl SingletonManager {
+    /// Shutting down all services, once the process receives one of the signals.
+    /// The signals no longer terminate the process, the caller of `SignalShutdown::wait` exits
+    /// once the shutdown finished.
+    pub fn shutdown_on_signals(&'static self, signals: &[Signal]) -> Result<SignalShutdown> {
+        self.install_shutdown(signals, None)
+    }
+
+    /// Shutting down all services, once the process receives one of the signals, and retrying
+    /// the services still in use until they are released or the deadline passed, letting
+    /// requests in flight drain first.
+    pub fn shutdown_on_signals_with_deadline(
+        &'static self,
+        signals: &[Signal],
+        deadline: Duration,
+    ) -> Result<SignalShutdown> {
+        self.install_shutdown(signals, Some(deadline))
+    }
+
+    fn install_shutdown(
+        &'static self,
+        signals: &[Signal],
+        deadline: Option<Duration>,
+    ) -> Result<SignalShutdown> {
+        let mut signals = Signals::new(signals.iter().map(|signal| signal.number()))
+            .map_err(|e| Error::SignalHandler(e.to_string()))?;
+        let handle = signals.handle();
+        let thread = std::thread::spawn(move || match signals.forever().next() {
+            Some(signal) => {
+                log::info!("Shutting down on signal {}", signal);
+                self.drain(deadline).map(Some)
+            }
+            None => Ok(None),
+        });
+        Ok(SignalShutdown { handle, thread })
+    }
+
+    /// Shutting down, and shutting down the services kept because of live handles again until
+    /// none is kept or the deadline passed.
+    fn drain(&self, deadline: Option<Duration>) -> Result<ShutdownReport> {
+        let started = Instant::now();
+        let mut report = self.shutdown()?;
+        while !report.is_clean() && deadline.is_some_and(|deadline| started.elapsed() < deadline) {
+            std::thread::sleep(DRAIN_INTERVAL);
+            let retry = self.shutdown()?;
+            report.shut_down.extend(retry.shut_down);
+            report.failed.extend(retry.failed);
+            report.leaked = retry.leaked;
+        }
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::SingletonManager;
+    use std::time::Duration;
+
+    #[test]
+    fn test_drain_retries_services_in_use() {
+        let manager = SingletonManager::new();
+        manager.set_send_factory("pool", || 4_u32).unwrap();
+        let pool = manager.get_ref::<u32>("pool").unwrap();
+
+        let report = manager.drain(None).unwrap();
+        assert_eq!(vec![("pool".to_string(), 1)], report.leaked);
+        assert!(manager.is_instantiated("pool"));
+
+        let report = manager.drain(Some(Duration::from_millis(30))).unwrap();
+        assert!(!report.is_clean());
+        drop(pool);
+
+        let report = manager.drain(Some(Duration::from_secs(5))).unwrap();
+        assert!(report.is_clean());
+        assert_eq!(vec!["pool".to_string()], report.shut_down);
+        assert!(!manager.is_instantiated("pool"));
+    }
+}
@@ -0,0 +1,437 @@
+//! # Single-flight initialization
+//! Running the factory of a service only once, when many threads need it at the same time.
+//!
+//! The first thread getting a service that is not instantiated yet runs its factory, any other
+//! thread getting the service meanwhile blocks until the factory finished, and then gets the
+//! same instance, instead of racing to construct instances of its own. If the factory fails, or
+//! panics, a waiting thread runs the factory again.
+//!
+//! This is an invariant of the singleton manager: a factory is run at most once per
+//! instantiation of its service, i.e. only again after the instance was removed or the factory
+//! failed. A factory needing its own service, directly or through factories running on other
+//! threads, would wait for itself, and fails with `Error::RecursiveFactory` instead.
+//!
+//! Once instantiated, getting the service only takes the shared lock of the registry, the
+//! exclusive lock is only taken, and the instance checked again under it, while it is not.
+//! A `Lazy` goes without any lock once initialized: it registers and instantiates its service
+//! once, freezes it, so it is never removed or replaced, and then caches the reference to it as
+//! a `Key` does.
+//! ```
+//! use singleton_manager::{Lazy, SingletonManager};
+//! use std::sync::atomic::{AtomicUsize, Ordering};
+//!
+//! static CONNECTS: AtomicUsize = AtomicUsize::new(0);
+//!
+//! let manager = SingletonManager::new();
+//! std::thread::scope(|s| {
+//!     for _ in 0..4 {
+//!         s.spawn(|| {
+//!             let pool = manager.get_or_init_blocking("pool", || {
+//!                 CONNECTS.fetch_add(1, Ordering::SeqCst);
+//!                 "postgres".to_string()
+//!             });
+//!             assert_eq!("postgres", *pool.unwrap());
+//!         });
+//!     }
+//! });
+//! assert_eq!(1, CONNECTS.load(Ordering::SeqCst));
+//!
+//! static REGION: Lazy<String> = Lazy::new("region", || "eu-west-1".to_string());
+//! assert_eq!("eu-west-1", manager.get_lazy(&REGION).unwrap());
+//! assert!(manager.is_frozen("region"));
+//! ```
+use crate::{
+    Error, GetError, Key, NewAlias, Registry, Result, Service, ServiceRef, SingletonManager, Uuid,
+};
+use std::any::Any;
+use std::collections::hash_map::Entry;
+use std::panic::Location;
+use std::sync::{Arc, Condvar, Mutex, PoisonError};
+use std::thread::{self, ThreadId};
+
+/// A pointer to an instance in the storage, valid for as long as the instance is stored.
+#[derive(Clone, Copy)]
+pub(crate) struct InstancePtr(*mut dyn Any);
+
+// The instances are shareable across threads, or bound to a thread by `ThreadBound`.
+unsafe impl Send for InstancePtr {}
+unsafe impl Sync for InstancePtr {}
+
+impl Registry {
+    /// Storing the instance of the service, replacing the instance stored if any.
+    pub(crate) fn insert_instance(&mut self, id: Uuid, service: Service) -> *mut dyn Any {
+        let stored = match self.singletons.entry(id) {
+            Entry::Occupied(mut entry) => {
+                entry.insert(service);
+                entry.into_mut()
+            }
+            Entry::Vacant(entry) => entry.insert(service),
+        };
+        let instance = stored.as_mut() as *mut dyn Any;
+        self.instances.insert(id, InstancePtr(instance));
+        if let Some(state) = self.borrows.get(&id) {
+            state.replace_instance();
+        }
+        self.invalidate_facades();
+        instance
+    }
+
+    /// The pointer to the stored instance of the service.
+    pub(crate) fn instance_ptr(&self, id: &Uuid) -> Option<*mut dyn Any> {
+        self.instances.get(id).map(|instance| instance.0)
+    }
+
+    /// True if the thread runs a factory waiting, through the factories running on other
+    /// threads, for a factory run by the current thread.
+    fn waits_for_current(&self, mut owner: ThreadId, current: ThreadId) -> bool {
+        for _ in 0..=self.awaiting.len() {
+            if owner == current {
+                return true;
+            }
+            match self
+                .awaiting
+                .get(&owner)
+                .and_then(|id| self.initializing.get(id))
+            {
+                Some(latch) => owner = latch.owner,
+                None => return false,
+            }
+        }
+        false
+    }
+}
+
+/// Latch of a factory running, opened when it finished.
+pub(crate) struct InitLatch {
+    /// The thread running the factory.
+    owner: ThreadId,
+    done: Mutex<bool>,
+    changed: Condvar,
+}
+
+impl InitLatch {
+    fn wait(&self) {
+        let mut done = self.done.lock().unwrap_or_else(PoisonError::into_inner);
+        while !*done {
+            done = self
+                .changed
+                .wait(done)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+
+    fn open(&self) {
+        *self.done.lock().unwrap_or_else(PoisonError::into_inner) = true;
+        self.changed.notify_all();
+    }
+}
+
+/// A service initialized exactly once, on first use, and frozen, so getting it afterwards takes
+/// no lock, see `SingletonManager::get_lazy`.
+/// Like a `Key`, the reference is cached for the first manager the lazy is used with.
+pub struct Lazy<T> {
+    key: Key<T>,
+    init: fn() -> T,
+}
+
+impl<T> Lazy<T> {
+    /// A lazy registering the factory under the alias on first use, unless the alias is taken.
+    pub const fn new(alias: &'static str, init: fn() -> T) -> Self {
+        Self {
+            key: Key::new(alias),
+            init,
+        }
+    }
+
+    /// The alias of the lazy.
+    pub fn alias(&self) -> &'static str {
+        self.key.alias()
+    }
+}
+
+/// Whether the current thread has to run the factory of a service.
+pub(crate) enum Flight<'a> {
+    /// The service was instantiated by another thread while waiting.
+    Instantiated,
+    /// The current thread runs the factory, with the latch to open when done.
+    Run(InitGuard<'a>),
+}
+
+/// Opening the latch of a factory when dropped, also when the factory fails or panics.
+pub(crate) struct InitGuard<'a> {
+    manager: &'a SingletonManager,
+    id: Uuid,
+    latch: Arc<InitLatch>,
+}
+
+impl Drop for InitGuard<'_> {
+    fn drop(&mut self) {
+        if let Ok(mut registry) = self.manager.registry_mut() {
+            if let Some(latch) = registry.initializing.get(&self.id) {
+                if Arc::ptr_eq(latch, &self.latch) {
+                    registry.initializing.remove(&self.id);
+                }
+            }
+        }
+        self.latch.open();
+    }
+}
+
+impl SingletonManager {
+    /// Waiting for another thread running the factory of the service, or taking over the running
+    /// of the factory.
+    /// Fails with `Error::RecursiveFactory` if the factory would wait for itself.
+    pub(crate) fn begin_flight(&self, id: &Uuid) -> Result<Flight<'_>> {
+        let current = thread::current().id();
+        loop {
+            let latch = {
+                let mut registry = self.registry_mut()?;
+                if registry.singletons.contains_key(id) {
+                    return Ok(Flight::Instantiated);
+                }
+                match registry.initializing.get(id).cloned() {
+                    Some(latch) if registry.waits_for_current(latch.owner, current) => {
+                        return Err(Error::RecursiveFactory(
+                            registry.alias_of(id).unwrap_or_default().into(),
+                        ))
+                    }
+                    Some(latch) => {
+                        registry.awaiting.insert(current, *id);
+                        latch
+                    }
+                    None => {
+                        let latch = Arc::new(InitLatch {
+                            owner: current,
+                            done: Mutex::new(false),
+                            changed: Condvar::new(),
+                        });
+                        registry.initializing.insert(*id, latch.clone());
+                        return Ok(Flight::Run(InitGuard {
+                            manager: self,
+                            id: *id,
+                            latch,
+                        }));
+                    }
+                }
+            };
+            latch.wait();
+            self.registry_mut()?.awaiting.remove(&current);
+        }
+    }
+
+    /// Taking the instance out of the storage, with a latch making the threads getting the
+    /// service wait until the returned guard is dropped, as they wait for a factory.
+    pub(crate) fn lift_instance(&self, id: &Uuid) -> Result<Option<(Service, InitGuard<'_>)>> {
+        let mut registry = self.registry_mut()?;
+        let service = match registry.singletons.remove(id) {
+            Some(service) => service,
+            None => return Ok(None),
+        };
+        registry.instances.remove(id);
+        registry.invalidate_facades();
+        if let Some(state) = registry.borrows.get(id) {
+            state.replace_instance();
+        }
+        let latch = Arc::new(InitLatch {
+            owner: thread::current().id(),
+            done: Mutex::new(false),
+            changed: Condvar::new(),
+        });
+        registry.initializing.insert(*id, latch.clone());
+        Ok(Some((
+            service,
+            InitGuard {
+                manager: self,
+                id: *id,
+                latch,
+            },
+        )))
+    }
+
+    /// Getting a shared reference to the service, as `get_ref` does, setting the factory first
+    /// if no service is registered under the alias.
+    /// Threads getting the service while the factory runs block until it finished.
+    #[track_caller]
+    pub fn get_or_init_blocking<T, F>(
+        &self,
+        service_name: &str,
+        factory: F,
+    ) -> std::result::Result<ServiceRef<'_, T>, GetError>
+    where
+        T: Send + Sync + 'static,
+        F: Fn() -> T + Send + Sync + 'static,
+    {
+        let location = Location::caller();
+        self.register_missing(service_name, factory, location)
+            .map_err(|e| GetError::from_error(e, service_name))?;
+        self.shared_borrow(service_name, location, |state| {
+            state.wait_borrow(location);
+            Ok(())
+        })
+        .map_err(|e| GetError::from_error(e, service_name))
+    }
+
+    /// Getting the service of the lazy, registering its factory first if no service is
+    /// registered under its alias, and instantiating and freezing the service on first use.
+    /// Once cached in the lazy this is a single atomic load and a pointer cast.
+    /// Fails with `Error::AlreadyBorrowed` if the service is not frozen yet and borrowed.
+    #[inline]
+    #[track_caller]
+    pub fn get_lazy<T: Send + Sync + 'static>(&self, lazy: &Lazy<T>) -> Result<&T> {
+        match self.cached_key(&lazy.key) {
+            Some(service) => Ok(service),
+            None => self.init_lazy(lazy, Location::caller()),
+        }
+    }
+
+    #[cold]
+    fn init_lazy<T: Send + Sync + 'static>(
+        &self,
+        lazy: &Lazy<T>,
+        location: &'static Location<'static>,
+    ) -> Result<&T> {
+        let alias = lazy.alias();
+        self.register_missing(alias, lazy.init, location)?;
+        if !self.is_frozen(alias) {
+            self.freeze(alias)?;
+        }
+        self.get_key_slow(&lazy.key, location)
+    }
+
+    /// Setting the factory, unless a service is registered under the alias.
+    pub(crate) fn register_missing<'a, T, F>(
+        &self,
+        service_name: impl Into<NewAlias<'a>>,
+        factory: F,
+        location: &'static Location<'static>,
+    ) -> Result<()>
+    where
+        T: Send + Sync + 'static,
+        F: Fn() -> T + Send + Sync + 'static,
+    {
+        let service_name = service_name.into();
+        let mut registry = self.registry_mut()?;
+        if registry.resolve(service_name.as_str()).is_some() {
+            return Ok(());
+        }
+        match registry.store_factory(
+            service_name,
+            Arc::new(move || Box::new(factory()) as Service),
+            location,
+        ) {
+            Ok(_) | Err(Error::ServiceAlreadyExists(_)) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Lazy, SingletonManager};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Barrier, OnceLock};
+    use std::time::Duration;
+
+    const CHAIN: usize = 16;
+    static MANAGER: OnceLock<SingletonManager> = OnceLock::new();
+    static RUNS: [AtomicUsize; CHAIN] = [const { AtomicUsize::new(0) }; CHAIN];
+
+    #[test]
+    fn test_factory_runs_once_for_concurrent_gets() {
+        let runs = Arc::new(AtomicUsize::new(0));
+        let mut manager = SingletonManager::new();
+        let counted = runs.clone();
+        manager
+            .set_factory("index", move || {
+                counted.fetch_add(1, Ordering::SeqCst);
+                std::thread::sleep(Duration::from_millis(50));
+                Box::new(vec![1_u32, 2, 3])
+            })
+            .unwrap();
+
+        let barrier = Barrier::new(8);
+        std::thread::scope(|s| {
+            for _ in 0..8 {
+                s.spawn(|| {
+                    barrier.wait();
+                    assert_eq!(3, manager.get_ref::<Vec<u32>>("index").unwrap().len());
+                });
+            }
+        });
+        assert_eq!(1, runs.load(Ordering::SeqCst));
+        assert!(manager.registry().unwrap().initializing.is_empty());
+    }
+
+    #[test]
+    fn test_factories_run_exactly_once_under_contention() {
+        let manager: &SingletonManager = MANAGER.get_or_init(SingletonManager::new);
+        // Every factory gets the service before it, so the factories contend across threads.
+        for (i, runs) in RUNS.iter().enumerate() {
+            manager
+                .set_send_factory(&format!("chain_{}", i), move || {
+                    runs.fetch_add(1, Ordering::SeqCst);
+                    let previous = match i {
+                        0 => 0,
+                        _ => *MANAGER
+                            .get()
+                            .unwrap()
+                            .get_ref::<usize>(&format!("chain_{}", i - 1))
+                            .unwrap(),
+                    };
+                    std::thread::yield_now();
+                    previous + 1
+                })
+                .unwrap();
+        }
+        manager
+            .set_send_factory("ouroboros", || {
+                let itself = MANAGER.get().unwrap().get_ref::<bool>("ouroboros");
+                itself.unwrap_err().is_recursive_factory()
+            })
+            .unwrap();
+
+        let barrier = Barrier::new(CHAIN);
+        std::thread::scope(|s| {
+            for thread in 0..CHAIN {
+                let barrier = &barrier;
+                s.spawn(move || {
+                    barrier.wait();
+                    for i in (0..CHAIN).map(|i| (i + thread) % CHAIN) {
+                        let alias = format!("chain_{}", i);
+                        assert_eq!(i + 1, *manager.get_ref::<usize>(&alias).unwrap());
+                    }
+                });
+            }
+        });
+        assert!(RUNS.iter().all(|runs| runs.load(Ordering::SeqCst) == 1));
+        assert!(*manager.get_ref::<bool>("ouroboros").unwrap());
+    }
+
+    #[test]
+    fn test_lazy_initialized_once_and_cached() {
+        static INITS: AtomicUsize = AtomicUsize::new(0);
+        static WORKERS: Lazy<usize> = Lazy::new("workers", || {
+            std::thread::sleep(Duration::from_millis(20));
+            INITS.fetch_add(1, Ordering::SeqCst) + 4
+        });
+        let manager = SingletonManager::new();
+
+        let barrier = Barrier::new(8);
+        let services: Vec<usize> = std::thread::scope(|s| {
+            let spawned: Vec<_> = (0..8)
+                .map(|_| {
+                    s.spawn(|| {
+                        barrier.wait();
+                        manager.get_lazy(&WORKERS).unwrap() as *const usize as usize
+                    })
+                })
+                .collect();
+            spawned.into_iter().map(|t| t.join().unwrap()).collect()
+        });
+        assert!(services.iter().all(|service| *service == services[0]));
+        assert_eq!(1, INITS.load(Ordering::SeqCst));
+        assert!(manager.is_frozen("workers"));
+        assert_eq!(4, *manager.get_lazy(&WORKERS).unwrap());
+        assert_eq!(1, INITS.load(Ordering::SeqCst));
+    }
+}
@@ -0,0 +1,390 @@
+//! # Startup
+//! Ordered instantiation of the registered singletons.
+//!
+//! Registrations can be tagged with a startup `Phase`, and `SingletonManager::init_all` will
+//! instantiate all the singletons phase by phase, where a phase is only started when every
+//! singleton of the previous phase has been instantiated.
+//! ```
+//! use singleton_manager::{Phase, SingletonManager};
+//!
+//! let mut manager = SingletonManager::new();
+//! manager.set_factory("db", || Box::new("db".to_string())).unwrap();
+//! manager.set_factory("http", || Box::new("http".to_string())).unwrap();
+//! manager.set_phase("db", Phase::Infrastructure).unwrap();
+//! manager.set_phase("http", Phase::Interface).unwrap();
+//!
+//! let report = manager.init_all();
+//! assert!(report.is_success());
+//! assert_eq!(vec!["db".to_string()], report.phases()[0].initialized);
+//! ```
+//!
+//! `init_all_with_progress` reports the progress of the startup to a callback, e.g. to show a
+//! progress bar in a CLI or to answer a readiness probe, and returns a `StartupReport` with how
+//! long each singleton took to start, pointing out the slow factories.
+//! ```
+//! use singleton_manager::SingletonManager;
+//!
+//! let mut manager = SingletonManager::new();
+//! manager.set_factory("db", || Box::new("db".to_string())).unwrap();
+//! manager.set_factory("http", || Box::new("http".to_string())).unwrap();
+//!
+//! let report = manager.init_all_with_progress(|done, total, current| {
+//!     println!("[{}/{}] starting {}", done, total, current);
+//! });
+//! assert!(report.is_success());
+//! assert_eq!("db", report.services()[0].alias);
+//! ```
+use crate::{Error, Result, SingletonManager, Uuid};
+use std::cmp::{Ordering, Reverse};
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::hash::{Hash, Hasher};
+use std::time::{Duration, Instant};
+
+/// The startup phase of a singleton.
+/// Phases are started in the order of their numeric value, where the named phases are
+/// `Infrastructure` (100), `Domain` (200) and `Interface` (300).
+/// Singletons without a phase are started in the `Domain` phase.
+#[derive(Debug, Clone, Copy, Default)]
+pub enum Phase {
+    /// Services everything else depends on, like configuration, logging and database pools.
+    Infrastructure,
+    /// The services holding the domain logic of the application.
+    #[default]
+    Domain,
+    /// Services exposing the application, like HTTP servers or queue consumers.
+    Interface,
+    /// Any custom phase, ordered by its value.
+    Numeric(u32),
+}
+
+impl Phase {
+    /// The numeric value the phase is ordered by.
+    pub fn order(&self) -> u32 {
+        match self {
+            Self::Infrastructure => 100,
+            Self::Domain => 200,
+            Self::Interface => 300,
+            Self::Numeric(n) => *n,
+        }
+    }
+}
+
+impl PartialEq for Phase {
+    fn eq(&self, other: &Self) -> bool {
+        self.order() == other.order()
+    }
+}
+
+impl Eq for Phase {}
+
+impl PartialOrd for Phase {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Phase {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.order().cmp(&other.order())
+    }
+}
+
+impl Hash for Phase {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.order().hash(state)
+    }
+}
+
+impl Display for Phase {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Infrastructure => write!(f, "infrastructure"),
+            Self::Domain => write!(f, "domain"),
+            Self::Interface => write!(f, "interface"),
+            Self::Numeric(n) => write!(f, "phase {}", n),
+        }
+    }
+}
+
+/// The result of a single phase of `SingletonManager::init_all`.
+#[derive(Debug, Clone)]
+pub struct PhaseReport {
+    pub phase: Phase,
+    /// The aliases of the singletons that was instantiated, or already was.
+    pub initialized: Vec<String>,
+    /// The aliases of the singletons that could not be instantiated, or started.
+    pub failed: Vec<(String, Error)>,
+}
+
+/// The result of `SingletonManager::init_all`.
+/// If a phase fails the following phases will not be started, and will not be in the report.
+#[derive(Debug, Clone, Default)]
+pub struct InitReport {
+    phases: Vec<PhaseReport>,
+}
+
+impl InitReport {
+    /// The reports of the phases that was started, in the order they were started.
+    pub fn phases(&self) -> &[PhaseReport] {
+        &self.phases
+    }
+
+    /// True if all singletons was instantiated.
+    pub fn is_success(&self) -> bool {
+        self.phases.iter().all(|phase| phase.failed.is_empty())
+    }
+}
+
+/// How a singleton was started by `SingletonManager::init_all_with_progress`.
+#[derive(Debug, Clone)]
+pub struct ServiceStartup {
+    pub alias: String,
+    pub phase: Phase,
+    /// The time taken to instantiate and start the singleton.
+    pub duration: Duration,
+    /// The reason the singleton could not be instantiated, or started, if it failed.
+    pub error: Option<Error>,
+}
+
+/// The result of `SingletonManager::init_all_with_progress`.
+#[derive(Debug, Clone, Default)]
+pub struct StartupReport {
+    services: Vec<ServiceStartup>,
+    /// The singletons not started, because an earlier phase failed.
+    skipped: usize,
+    duration: Duration,
+    init: InitReport,
+}
+
+impl StartupReport {
+    /// The singletons started, in the order they were started.
+    pub fn services(&self) -> &[ServiceStartup] {
+        &self.services
+    }
+
+    /// The number of singletons not started, because an earlier phase failed.
+    pub fn skipped(&self) -> usize {
+        self.skipped
+    }
+
+    /// The time taken by the whole startup.
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    /// The singletons that took the longest to start, slowest first.
+    pub fn slowest(&self, n: usize) -> Vec<&ServiceStartup> {
+        let mut services = self.services.iter().collect::<Vec<_>>();
+        services.sort_by_key(|service| Reverse(service.duration));
+        services.truncate(n);
+        services
+    }
+
+    /// The results by phase, as returned by `init_all`.
+    pub fn init_report(&self) -> &InitReport {
+        &self.init
+    }
+
+    /// True if all singletons was instantiated.
+    pub fn is_success(&self) -> bool {
+        self.init.is_success()
+    }
+}
+
+impl From<StartupReport> for InitReport {
+    fn from(report: StartupReport) -> Self {
+        report.init
+    }
+}
+
+impl SingletonManager {
+    /// Tagging a registration with the startup phase it should be instantiated in by `init_all`.
+    pub fn set_phase(&self, service_name: &str, phase: Phase) -> Result<()> {
+        let id = self.service_id(service_name)?;
+        self.registry_mut()?.phases.insert(id, phase);
+        Ok(())
+    }
+
+    /// Getting the startup phase of a registration.
+    pub fn phase(&self, service_name: &str) -> Result<Phase> {
+        let id = self.service_id(service_name)?;
+        Ok(self
+            .registry()?
+            .phases
+            .get(&id)
+            .copied()
+            .unwrap_or_default())
+    }
+
+    /// Instantiating all registered singletons, phase by phase.
+    /// Within a phase the singletons are instantiated in alias order. If any singleton of a phase
+    /// fails, the rest of the phase is still instantiated, but the following phases are not.
+    /// Singletons with lifecycle hooks are started once instantiated, see `set_lifecycle`.
+    pub fn init_all(&self) -> InitReport {
+        self.init_all_with_progress(|_, _, _| {}).into()
+    }
+
+    /// Instantiating all registered singletons as `init_all` does, calling the callback with
+    /// the number of singletons started, the number of singletons to start, and the alias of the
+    /// singleton being started, before starting each singleton.
+    pub fn init_all_with_progress<F>(&self, mut progress: F) -> StartupReport
+    where
+        F: FnMut(usize, usize, &str),
+    {
+        let started = Instant::now();
+        let phases = self.services_by_phase();
+        let total = phases.values().map(Vec::len).sum();
+        let mut report = StartupReport::default();
+        for (phase, services) in phases {
+            if !report.is_success() {
+                report.skipped += services.len();
+                continue;
+            }
+            let mut phase_report = PhaseReport {
+                phase,
+                initialized: Vec::new(),
+                failed: Vec::new(),
+            };
+            for (alias, id) in services {
+                progress(report.services.len(), total, &alias);
+                let starting = Instant::now();
+                let started = self
+                    .singleton_get(&id, &alias)
+                    .and_then(|_| self.run_on_start(&alias, &id));
+                report.services.push(ServiceStartup {
+                    alias: alias.clone(),
+                    phase,
+                    duration: starting.elapsed(),
+                    error: started.as_ref().err().cloned(),
+                });
+                match started {
+                    Ok(_) => phase_report.initialized.push(alias),
+                    Err(e) => phase_report.failed.push((alias, e)),
+                }
+            }
+            report.init.phases.push(phase_report);
+        }
+        report.duration = started.elapsed();
+        report
+    }
+
+    fn services_by_phase(&self) -> BTreeMap<Phase, Vec<(String, Uuid)>> {
+        let mut phases: BTreeMap<Phase, Vec<(String, Uuid)>> = BTreeMap::new();
+        if let Ok(registry) = self.registry() {
+            for (alias, id) in registry.alias.iter() {
+                let phase = registry.phases.get(id).copied().unwrap_or_default();
+                phases
+                    .entry(phase)
+                    .or_default()
+                    .push((alias.to_string(), *id));
+            }
+        }
+        for services in phases.values_mut() {
+            services.sort();
+        }
+        phases
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_phase_ordering() {
+        assert!(Phase::Infrastructure < Phase::Domain);
+        assert!(Phase::Domain < Phase::Numeric(250));
+        assert!(Phase::Numeric(250) < Phase::Interface);
+        assert_eq!(Phase::Domain, Phase::Numeric(200));
+    }
+
+    #[test]
+    fn test_init_all_instantiates_in_phase_order() {
+        let mut manager = SingletonManager::new();
+        let started = Arc::new(Mutex::new(Vec::new()));
+        for (alias, phase) in [
+            ("http", Some(Phase::Interface)),
+            ("cache", Some(Phase::Numeric(250))),
+            ("users", None),
+            ("db", Some(Phase::Infrastructure)),
+        ] {
+            let started = started.clone();
+            manager
+                .set_factory(alias, move || {
+                    started.lock().unwrap().push(alias);
+                    Box::new(alias)
+                })
+                .unwrap();
+            if let Some(phase) = phase {
+                manager.set_phase(alias, phase).unwrap();
+            }
+        }
+
+        let report = manager.init_all();
+
+        assert!(report.is_success());
+        assert_eq!(
+            vec!["db", "users", "cache", "http"],
+            *started.lock().unwrap()
+        );
+        assert_eq!(
+            vec![
+                Phase::Infrastructure,
+                Phase::Domain,
+                Phase::Numeric(250),
+                Phase::Interface
+            ],
+            report.phases().iter().map(|p| p.phase).collect::<Vec<_>>()
+        );
+        assert_eq!(Phase::Domain, manager.phase("users").unwrap());
+    }
+
+    #[test]
+    fn test_startup_progress_and_durations() {
+        let mut manager = SingletonManager::new();
+        manager
+            .set_factory("db", || {
+                std::thread::sleep(Duration::from_millis(20));
+                Box::new(1_u32)
+            })
+            .unwrap();
+        manager.set_factory("users", || Box::new(2_u32)).unwrap();
+        manager.set_factory("http", || Box::new(3_u32)).unwrap();
+        manager.set_phase("db", Phase::Infrastructure).unwrap();
+        manager.set_phase("http", Phase::Interface).unwrap();
+
+        let mut progress = Vec::new();
+        let report = manager.init_all_with_progress(|done, total, current| {
+            progress.push((done, total, current.to_string()));
+        });
+        assert_eq!(
+            vec![
+                (0, 3, "db".to_string()),
+                (1, 3, "users".to_string()),
+                (2, 3, "http".to_string())
+            ],
+            progress
+        );
+        assert!(report.is_success());
+        assert_eq!(3, report.init_report().phases().len());
+        assert_eq!("db", report.slowest(1)[0].alias);
+        assert!(report.duration() >= report.services()[0].duration);
+
+        let mut manager = SingletonManager::new();
+        manager.set_factory("db", || Box::new(0_u32)).unwrap();
+        manager
+            .add_validator(|info, _| match info.alias() {
+                "db" => Err(Error::UnknownError("no connection".to_string())),
+                _ => Ok(()),
+            })
+            .unwrap();
+        manager.set_factory("http", || Box::new(3_u32)).unwrap();
+        manager.set_phase("db", Phase::Infrastructure).unwrap();
+        let report = manager.init_all_with_progress(|_, _, _| {});
+        assert!(!report.is_success());
+        assert!(report.services()[0].error.is_some());
+        assert_eq!(1, report.skipped());
+    }
+}
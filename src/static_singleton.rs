@@ -0,0 +1,116 @@
+//! # Static singletons
+//! Declaring services as statics, resolving their alias only once.
+//!
+//! A `StaticSingleton` names a service of the global singleton manager, see `sm`, and registers
+//! its factory on first use if it has one. The alias is resolved on the first `get`, later gets
+//! use the id it resolved to, so they do not hash the alias again.
+//! ```
+//! use singleton_manager::StaticSingleton;
+//!
+//! struct DbPool {
+//!     url: String,
+//! }
+//!
+//! static DB: StaticSingleton<DbPool> = StaticSingleton::with_factory("static_db", || DbPool {
+//!     url: "postgres://localhost".to_string(),
+//! });
+//!
+//! assert_eq!("postgres://localhost", DB.get().unwrap().url);
+//! ```
+use crate::{sm, GetError, NewAlias, ServiceRef, SingletonManager, Uuid};
+use std::marker::PhantomData;
+use std::panic::Location;
+use std::sync::OnceLock;
+
+/// A service of the global singleton manager declared as a static.
+/// The id the alias resolved to is kept, so feature flags, default versions or renames changing
+/// the service served under the alias afterwards are not followed. If the service is removed,
+/// the alias is resolved on every get again.
+pub struct StaticSingleton<T> {
+    alias: &'static str,
+    factory: Option<fn() -> T>,
+    id: OnceLock<Uuid>,
+    _service: PhantomData<fn() -> T>,
+}
+
+impl<T: Send + Sync + 'static> StaticSingleton<T> {
+    /// Declaring the service registered under the alias.
+    pub const fn new(alias: &'static str) -> Self {
+        Self {
+            alias,
+            factory: None,
+            id: OnceLock::new(),
+            _service: PhantomData,
+        }
+    }
+
+    /// Declaring the service registered under the alias, registering the factory on first use
+    /// unless a service is registered under the alias already.
+    pub const fn with_factory(alias: &'static str, factory: fn() -> T) -> Self {
+        Self {
+            alias,
+            factory: Some(factory),
+            id: OnceLock::new(),
+            _service: PhantomData,
+        }
+    }
+
+    /// The alias of the service.
+    pub fn alias(&self) -> &'static str {
+        self.alias
+    }
+
+    /// Getting a shared reference to the service, as `get_ref` does.
+    #[track_caller]
+    pub fn get(&self) -> std::result::Result<ServiceRef<'static, T>, GetError> {
+        let manager: &'static SingletonManager = sm();
+        let location = Location::caller();
+        let acquire = |state: &crate::BorrowState| {
+            state.wait_borrow(location);
+            Ok(())
+        };
+        if let Some(id) = self.id.get() {
+            if let Ok(service) = manager.shared_borrow_id(id, self.alias, location, acquire) {
+                return Ok(service);
+            }
+        }
+        self.resolve(manager, location)
+            .and_then(|id| manager.shared_borrow_id(&id, self.alias, location, acquire))
+            .map_err(|e| GetError::from_error(e, self.alias))
+    }
+
+    /// Registering the factory if needed, and resolving the alias.
+    fn resolve(
+        &self,
+        manager: &SingletonManager,
+        location: &'static Location<'static>,
+    ) -> crate::Result<Uuid> {
+        if let Some(factory) = self.factory {
+            manager.register_missing(NewAlias::Static(self.alias), factory, location)?;
+        }
+        let id = manager.serving_id(self.alias, location)?;
+        // A service removed and registered again is not cached again.
+        let _ = self.id.set(id);
+        Ok(id)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{sm, StaticSingleton};
+
+    static COUNTER: StaticSingleton<u64> = StaticSingleton::with_factory("static_counter", || 7);
+    static MISSING: StaticSingleton<u64> = StaticSingleton::new("static_missing");
+
+    #[test]
+    fn test_static_singleton_resolves_once() {
+        assert_eq!(7, *COUNTER.get().unwrap());
+        let id = *COUNTER.id.get().unwrap();
+        assert_eq!(7, *COUNTER.get().unwrap());
+        assert_eq!(id, *COUNTER.id.get().unwrap());
+        assert!(sm().is_instantiated("static_counter"));
+
+        assert!(MISSING.get().unwrap_err().is_not_found());
+        assert!(MISSING.id.get().is_none());
+    }
+}
@@ -0,0 +1,326 @@
+//! # Stress tests
+//! Hammering a singleton manager from many threads, checking it stays consistent.
+//!
+//! `StressTest` spins up threads performing randomized operations on a shared singleton
+//! manager: racing to register and instantiate factories, incrementing counters through
+//! exclusive borrows, and registering and removing scoped services, next to an optional
+//! workload of the application. Once all threads are done it checks the invariants of the
+//! registry: no thread panicked, no factory ran more than once, and no increment was lost.
+//!
+//! The operations are picked by a generator seeded per thread, so a failing run can be repeated
+//! with the same seed, e.g. in CI. Only built with the `stress` feature.
+//! ```
+//! use singleton_manager::StressTest;
+//!
+//! let report = StressTest::new()
+//!     .threads(4)
+//!     .operations(200)
+//!     .workload(|manager, n| {
+//!         let _ = manager.has(&format!("app_{}", n % 8));
+//!     })
+//!     .run()
+//!     .unwrap();
+//! assert_eq!(800, report.operations);
+//! ```
+use crate::{GetError, SetError, SingletonManager};
+use std::fmt::{Debug, Display, Formatter};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// An operation of the application, run with the manager and a random number.
+type Workload = Arc<dyn Fn(&SingletonManager, u64) + Send + Sync>;
+
+/// A stress test of a singleton manager, see `StressTest::run`.
+#[derive(Clone)]
+pub struct StressTest {
+    threads: usize,
+    operations: usize,
+    aliases: usize,
+    seed: u64,
+    workload: Option<Workload>,
+}
+
+impl Default for StressTest {
+    fn default() -> Self {
+        Self {
+            threads: 8,
+            operations: 1000,
+            aliases: 4,
+            seed: 0x5eed,
+            workload: None,
+        }
+    }
+}
+
+impl Debug for StressTest {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StressTest")
+            .field("threads", &self.threads)
+            .field("operations", &self.operations)
+            .field("aliases", &self.aliases)
+            .field("seed", &self.seed)
+            .field("workload", &self.workload.is_some())
+            .finish()
+    }
+}
+
+/// The operations performed by a stress test that kept the invariants.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StressReport {
+    /// The number of operations performed over all threads.
+    pub operations: usize,
+    /// The number of factories run.
+    pub factory_runs: usize,
+    /// The number of increments of the counters.
+    pub increments: u64,
+}
+
+/// The invariant a stress test found broken.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StressViolation {
+    /// The thread breaking the invariant, `None` for the checks once all threads are done.
+    pub thread: Option<usize>,
+    pub message: String,
+}
+
+impl Display for StressViolation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.thread {
+            Some(thread) => write!(f, "Thread {}: {}", thread, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl std::error::Error for StressViolation {}
+
+/// A xorshift generator, enough to pick operations without a dependency.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64, thread: usize) -> Self {
+        Self((seed ^ (thread as u64 + 1).wrapping_mul(0x9e37_79b9_7f4a_7c15)) | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+}
+
+impl StressTest {
+    /// A stress test with 8 threads performing 1000 operations each.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads;
+        self
+    }
+
+    /// The number of operations performed by every thread.
+    pub fn operations(mut self, operations: usize) -> Self {
+        self.operations = operations;
+        self
+    }
+
+    /// The number of factories and counters the threads contend on.
+    pub fn aliases(mut self, aliases: usize) -> Self {
+        self.aliases = aliases.max(1);
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Performing the operation of the application as one of the randomized operations, with
+    /// the manager and a random number.
+    pub fn workload<F: Fn(&SingletonManager, u64) + Send + Sync + 'static>(mut self, f: F) -> Self {
+        self.workload = Some(Arc::new(f));
+        self
+    }
+
+    /// Running the threads against a fresh singleton manager, and checking the invariants.
+    pub fn run(&self) -> Result<StressReport, StressViolation> {
+        let manager = SingletonManager::new();
+        let runs = (0..self.aliases)
+            .map(|_| Arc::new(AtomicUsize::new(0)))
+            .collect::<Vec<_>>();
+        for i in 0..self.aliases {
+            manager
+                .set_send_factory(&format!("stress_counter_{}", i), || 0_u64)
+                .map_err(|e| violation(None, e))?;
+        }
+
+        let results = std::thread::scope(|s| {
+            let threads = (0..self.threads)
+                .map(|thread| {
+                    let (manager, runs) = (&manager, &runs);
+                    s.spawn(move || self.run_thread(thread, manager, runs))
+                })
+                .collect::<Vec<_>>();
+            threads
+                .into_iter()
+                .enumerate()
+                .map(|(thread, handle)| {
+                    handle
+                        .join()
+                        .unwrap_or_else(|_| Err(violation(Some(thread), "panicked")))
+                })
+                .collect::<Vec<_>>()
+        });
+        let mut increments = vec![0_u64; self.aliases];
+        for result in results {
+            for (total, counted) in increments.iter_mut().zip(result?) {
+                *total += counted;
+            }
+        }
+
+        let mut factory_runs = 0;
+        for (i, runs) in runs.iter().enumerate() {
+            let runs = runs.load(Ordering::SeqCst);
+            let alias = format!("stress_factory_{}", i);
+            let expected = usize::from(manager.is_instantiated(&alias));
+            if runs != expected {
+                return Err(violation(
+                    None,
+                    format!("The factory of `{}` ran {} times", alias, runs),
+                ));
+            }
+            factory_runs += runs;
+        }
+        for (i, expected) in increments.iter().enumerate() {
+            let alias = format!("stress_counter_{}", i);
+            let counted = *manager
+                .get_ref_by_alias::<u64>(&alias)
+                .map_err(|e| violation(None, e))?;
+            if counted != *expected {
+                return Err(violation(
+                    None,
+                    format!(
+                        "`{}` was incremented {} times, but counts {}",
+                        alias, expected, counted
+                    ),
+                ));
+            }
+        }
+        Ok(StressReport {
+            operations: self.threads * self.operations,
+            factory_runs,
+            increments: increments.iter().sum(),
+        })
+    }
+
+    /// Performing the operations of a thread, returning the increments of every counter.
+    fn run_thread(
+        &self,
+        thread: usize,
+        manager: &SingletonManager,
+        runs: &[Arc<AtomicUsize>],
+    ) -> Result<Vec<u64>, StressViolation> {
+        let mut rng = Rng::new(self.seed, thread);
+        let mut increments = vec![0_u64; self.aliases];
+        for _ in 0..self.operations {
+            let n = rng.next();
+            let i = (n >> 8) as usize % self.aliases;
+            match n % 5 {
+                0 => {
+                    let runs = runs[i].clone();
+                    let registered =
+                        manager.set_send_factory(&format!("stress_factory_{}", i), move || {
+                            runs.fetch_add(1, Ordering::SeqCst);
+                            i as u64
+                        });
+                    match registered {
+                        Ok(()) | Err(SetError::ServiceAlreadyExists(_)) => {}
+                        Err(e) => return Err(violation(Some(thread), e)),
+                    }
+                }
+                1 => match manager.get_ref_by_alias::<u64>(&format!("stress_factory_{}", i)) {
+                    Ok(value) if *value == i as u64 => {}
+                    Ok(value) => {
+                        return Err(violation(
+                            Some(thread),
+                            format!("`stress_factory_{}` holds {}", i, *value),
+                        ))
+                    }
+                    Err(GetError::ServiceDoesNotExist(_, _)) => {}
+                    Err(e) => return Err(violation(Some(thread), e)),
+                },
+                2 => {
+                    *manager
+                        .get_mut_by_alias::<u64>(&format!("stress_counter_{}", i))
+                        .map_err(|e| violation(Some(thread), e))? += 1;
+                    increments[i] += 1;
+                }
+                3 => {
+                    let alias = format!("stress_scoped_{}", thread);
+                    let value = manager.scope(|scope| {
+                        scope.set(&alias, n).map_err(|e| e.to_string())?;
+                        scope
+                            .manager()
+                            .get_cloned::<u64>(&alias)
+                            .map_err(|e| e.to_string())
+                    });
+                    match value {
+                        Ok(value) if value == n && !manager.has(&alias) => {}
+                        Ok(_) => {
+                            return Err(violation(
+                                Some(thread),
+                                format!("`{}` was not scoped", alias),
+                            ))
+                        }
+                        Err(e) => return Err(violation(Some(thread), e)),
+                    }
+                }
+                _ => match &self.workload {
+                    Some(workload) => workload(manager, n),
+                    None => {
+                        manager
+                            .get_ref_by_alias::<u64>(&format!("stress_counter_{}", i))
+                            .map_err(|e| violation(Some(thread), e))?;
+                    }
+                },
+            }
+        }
+        Ok(increments)
+    }
+}
+
+fn violation(thread: Option<usize>, message: impl Display) -> StressViolation {
+    StressViolation {
+        thread,
+        message: message.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::StressTest;
+
+    #[test]
+    fn test_stress_keeps_the_invariants() {
+        let report = StressTest::new()
+            .threads(8)
+            .operations(500)
+            .seed(7)
+            .run()
+            .unwrap();
+        assert_eq!(4000, report.operations);
+        assert!(report.factory_runs <= 4);
+        assert!(report.increments > 0);
+
+        let failed = StressTest::new()
+            .threads(2)
+            .operations(50)
+            .workload(|_, _| panic!("workload failed"))
+            .run()
+            .unwrap_err();
+        assert_eq!("panicked", failed.message);
+    }
+}
@@ -0,0 +1,189 @@
+//! # Suggestions
+//! Suggesting the aliases registered when looking up an alias that is not.
+//!
+//! Services are looked up by strings, so a typo only shows up as a failed lookup at runtime.
+//! When getting a service fails because nothing is registered under the alias, the error holds
+//! the registered aliases closest to it, by the number of characters inserted, removed, replaced
+//! or swapped, and its message suggests them.
+//! The suggestions are only computed when they are looked at, so lookups that are expected to
+//! fail cost no more than the failed hash lookup.
+//! ```
+//! use singleton_manager::{GetError, SingletonManager};
+//!
+//! let mut manager = SingletonManager::new();
+//! manager.set_factory("database", || Box::new("postgres".to_string())).unwrap();
+//!
+//! let e = manager.get_ref::<String>("databse").unwrap_err();
+//! match &e {
+//!     GetError::ServiceDoesNotExist(_, suggestions) => assert_eq!(&["database"], &suggestions[..]),
+//!     _ => panic!("Expected the service to not exist"),
+//! }
+//! assert_eq!(
+//!     "Service `databse` does not exist, did you mean `database`?",
+//!     e.to_string()
+//! );
+//! ```
+use crate::{Alias, Registry};
+use std::collections::BTreeSet;
+use std::fmt::{Debug, Formatter};
+use std::ops::Deref;
+use std::sync::{Arc, OnceLock, PoisonError, RwLock, Weak};
+
+/// The number of aliases suggested at most.
+const MAX_SUGGESTIONS: usize = 3;
+
+/// The length of the longest alias compared, longer aliases are never suggested.
+const MAX_COMPARED: usize = 64;
+
+/// The number of edits between the strings, counting swapped neighbours as one edit, or `None`
+/// if it is above the limit or a string is too long to compare.
+/// Computed on the stack, so failing lookups do not allocate unless something is suggested.
+fn distance(a: &str, b: &str, limit: usize) -> Option<usize> {
+    let mut a_chars = ['\0'; MAX_COMPARED];
+    let mut b_chars = ['\0'; MAX_COMPARED];
+    let (mut m, mut n) = (0, 0);
+    for c in a.chars() {
+        *a_chars.get_mut(m)? = c;
+        m += 1;
+    }
+    for c in b.chars() {
+        *b_chars.get_mut(n)? = c;
+        n += 1;
+    }
+    if m.abs_diff(n) > limit {
+        return None;
+    }
+
+    // The rows of the edit distances of the prefixes of `a` with the prefixes of `b`.
+    let mut before = [0; MAX_COMPARED + 1];
+    let mut previous: [usize; MAX_COMPARED + 1] = std::array::from_fn(|j| j);
+    let mut current = [0; MAX_COMPARED + 1];
+    for i in 1..=m {
+        current[0] = i;
+        for j in 1..=n {
+            let replaced = previous[j - 1] + usize::from(a_chars[i - 1] != b_chars[j - 1]);
+            let mut best = replaced.min(previous[j] + 1).min(current[j - 1] + 1);
+            if i > 1
+                && j > 1
+                && a_chars[i - 1] == b_chars[j - 2]
+                && a_chars[i - 2] == b_chars[j - 1]
+            {
+                best = best.min(before[j - 2] + 1);
+            }
+            current[j] = best;
+        }
+        before = previous;
+        previous = current;
+    }
+    Some(previous[n]).filter(|distance| *distance <= limit)
+}
+
+/// The registered aliases closest to an alias that is not registered, closest first.
+///
+/// Computed from the aliases registered when they are first looked at, under the read lock of the
+/// alias index only, and empty if the manager is gone by then.
+#[derive(Default)]
+pub struct Suggestions {
+    alias: Alias,
+    index: Weak<RwLock<BTreeSet<Alias>>>,
+    computed: OnceLock<Vec<String>>,
+}
+
+impl Suggestions {
+    fn compute(&self) -> Vec<String> {
+        let index = match self.index.upgrade() {
+            Some(index) => index,
+            None => return Vec::new(),
+        };
+        let aliases = index.read().unwrap_or_else(PoisonError::into_inner);
+        let limit = (self.alias.chars().count() / 3).max(1);
+        let mut close = aliases
+            .iter()
+            .filter_map(|candidate| Some((distance(&self.alias, candidate, limit)?, candidate)))
+            .collect::<Vec<_>>();
+        close.sort_by(|(a, a_alias), (b, b_alias)| a.cmp(b).then_with(|| a_alias.cmp(b_alias)));
+        close
+            .into_iter()
+            .take(MAX_SUGGESTIONS)
+            .map(|(_, candidate)| candidate.to_string())
+            .collect()
+    }
+}
+
+impl Deref for Suggestions {
+    type Target = [String];
+
+    fn deref(&self) -> &[String] {
+        self.computed.get_or_init(|| self.compute())
+    }
+}
+
+impl Clone for Suggestions {
+    fn clone(&self) -> Self {
+        Self {
+            alias: self.alias.clone(),
+            index: self.index.clone(),
+            computed: self.computed.clone(),
+        }
+    }
+}
+
+impl Debug for Suggestions {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl PartialEq for Suggestions {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl Eq for Suggestions {}
+
+impl Registry {
+    /// The registered aliases closest to the alias, computed when they are first looked at.
+    pub(crate) fn suggestions(&self, alias: Alias) -> Suggestions {
+        Suggestions {
+            alias,
+            index: Arc::downgrade(&self.prefix_index),
+            computed: OnceLock::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::distance;
+    use crate::{Error, SingletonManager};
+
+    #[test]
+    fn test_suggestions_for_typos() {
+        assert_eq!(Some(1), distance("cahce", "cache", 1));
+        assert_eq!(Some(1), distance("db", "dbs", 1));
+        assert_eq!(None, distance("queue", "cache", 3));
+        assert_eq!(Some(0), distance("", "", 1));
+
+        let mut manager = SingletonManager::new();
+        manager.set("cache", 1_u32).unwrap();
+        manager.set("caches", 2_u32).unwrap();
+        manager.set("queue", 3_u32).unwrap();
+        match Error::from(manager.get::<u32>("cahce").unwrap_err()) {
+            Error::ServiceDoesNotExist(alias, suggestions) => {
+                assert_eq!("cahce", alias);
+                assert_eq!(&["cache"], &suggestions[..]);
+            }
+            e => panic!("Expected the service to not exist, got {}", e),
+        }
+        // Computed from the aliases registered when looked at, not when the lookup failed.
+        let e = manager.get::<u32>("queeu").unwrap_err();
+        manager.set("queen", 4_u32).unwrap();
+        assert_eq!(
+            "Service `queeu` does not exist, did you mean one of `queen`, `queue`?",
+            e.to_string()
+        );
+        let e = manager.get::<u32>("mailer").unwrap_err();
+        assert_eq!("Service `mailer` does not exist", e.to_string());
+    }
+}
@@ -0,0 +1,258 @@
+//! # Managed tasks
+//! Tracking the background tasks of the services, behind the `tokio` feature.
+//!
+//! Services often come with background tasks, e.g. flushing metrics or draining a worker queue,
+//! which are spawned and forgotten, and still running, or silently dead, when the application
+//! shuts down. Tasks spawned through `SingletonManager::spawn_managed` are tracked by name
+//! alongside the singletons, their status can be queried at any time, and shutting down the
+//! singleton manager stops them: `shutdown` aborts the tasks still running, and
+//! `shutdown_with_deadline` first waits for them to finish until the deadline.
+//! ```
+//! use singleton_manager::{SingletonManager, TaskStatus};
+//! use std::time::Duration;
+//!
+//! let runtime = tokio::runtime::Builder::new_current_thread()
+//!     .enable_time()
+//!     .build()
+//!     .unwrap();
+//! let manager = SingletonManager::new();
+//! runtime.block_on(async {
+//!     manager
+//!         .spawn_managed("metrics_flusher", async {
+//!             loop {
+//!                 tokio::time::sleep(Duration::from_secs(10)).await;
+//!             }
+//!         })
+//!         .unwrap();
+//!     assert_eq!(Some(TaskStatus::Running), manager.task_status("metrics_flusher"));
+//!
+//!     let report = manager
+//!         .shutdown_with_deadline(Duration::from_millis(10))
+//!         .await
+//!         .unwrap();
+//!     assert_eq!(
+//!         vec![("metrics_flusher".to_string(), TaskStatus::Aborted)],
+//!         report.tasks
+//!     );
+//! });
+//! ```
+use crate::{Error, Registry, Result, ShutdownReport, SingletonManager};
+use std::fmt::{Display, Formatter};
+use std::future::Future;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+/// The status of a task spawned by `SingletonManager::spawn_managed`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TaskStatus {
+    Running,
+    /// The future of the task completed.
+    Finished,
+    /// The future of the task panicked.
+    Panicked,
+    /// The task was aborted before its future completed.
+    Aborted,
+}
+
+impl Display for TaskStatus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Running => write!(f, "running"),
+            Self::Finished => write!(f, "finished"),
+            Self::Panicked => write!(f, "panicked"),
+            Self::Aborted => write!(f, "aborted"),
+        }
+    }
+}
+
+type SharedStatus = Arc<Mutex<TaskStatus>>;
+
+/// Settling the status of a running task.
+fn settle(status: &SharedStatus, settled: TaskStatus) -> TaskStatus {
+    let mut status = status.lock().unwrap_or_else(PoisonError::into_inner);
+    if *status == TaskStatus::Running {
+        *status = settled;
+    }
+    *status
+}
+
+/// Settling the status of a task whose future is dropped before completing.
+struct Completion(SharedStatus);
+
+impl Drop for Completion {
+    fn drop(&mut self) {
+        let dropped = match std::thread::panicking() {
+            true => TaskStatus::Panicked,
+            false => TaskStatus::Aborted,
+        };
+        settle(&self.0, dropped);
+    }
+}
+
+/// A task spawned by `SingletonManager::spawn_managed`.
+pub(crate) struct ManagedTask {
+    handle: JoinHandle<()>,
+    status: SharedStatus,
+}
+
+impl ManagedTask {
+    fn status(&self) -> TaskStatus {
+        *self.status.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Aborting the task if it is still running, returning its status.
+    fn abort(&self) -> TaskStatus {
+        let status = settle(&self.status, TaskStatus::Aborted);
+        self.handle.abort();
+        status
+    }
+}
+
+impl Registry {
+    /// Aborting the tasks still running and forgetting all tasks, returning their status.
+    pub(crate) fn abort_tasks(&mut self) -> Vec<(String, TaskStatus)> {
+        std::mem::take(&mut self.tasks)
+            .into_iter()
+            .map(|(name, task)| (name, task.abort()))
+            .collect()
+    }
+}
+
+impl SingletonManager {
+    /// Spawning a background task on the tokio runtime of the caller, tracked under the name.
+    /// Fails with `Error::ServiceAlreadyExists` if a task of the name is still running, and with
+    /// `Error::UnknownError` if not called from within a tokio runtime.
+    pub fn spawn_managed<F>(&self, name: &str, future: F) -> Result<()>
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let runtime = tokio::runtime::Handle::try_current()
+            .map_err(|e| Error::UnknownError(format!("Failed to spawn task `{}`: {}", name, e)))?;
+        let mut registry = self.registry_mut()?;
+        if registry
+            .tasks
+            .get(name)
+            .is_some_and(|task| task.status() == TaskStatus::Running)
+        {
+            return Err(Error::ServiceAlreadyExists(name.into()));
+        }
+        let status = Arc::new(Mutex::new(TaskStatus::Running));
+        let completion = Completion(status.clone());
+        let handle = runtime.spawn(async move {
+            future.await;
+            settle(&completion.0, TaskStatus::Finished);
+        });
+        registry
+            .tasks
+            .insert(name.to_string(), ManagedTask { handle, status });
+        Ok(())
+    }
+
+    /// The status of the task spawned under the name, if any.
+    pub fn task_status(&self, name: &str) -> Option<TaskStatus> {
+        self.registry()
+            .ok()?
+            .tasks
+            .get(name)
+            .map(ManagedTask::status)
+    }
+
+    /// The tasks spawned, with their status, sorted by name.
+    pub fn tasks(&self) -> Vec<(String, TaskStatus)> {
+        self.registry()
+            .map(|registry| {
+                registry
+                    .tasks
+                    .iter()
+                    .map(|(name, task)| (name.clone(), task.status()))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Waiting until the deadline for the tasks to finish, aborting the tasks still running
+    /// then, and shutting down all services, see `shutdown`.
+    pub async fn shutdown_with_deadline(&self, deadline: Duration) -> Result<ShutdownReport> {
+        let until = tokio::time::Instant::now() + deadline;
+        let tasks = std::mem::take(&mut self.registry_mut()?.tasks);
+        let mut stopped = Vec::with_capacity(tasks.len());
+        for (name, mut task) in tasks {
+            if tokio::time::timeout_at(until, &mut task.handle)
+                .await
+                .is_err()
+            {
+                task.abort();
+                let _ = (&mut task.handle).await;
+            }
+            stopped.push((name, task.status()));
+        }
+        let mut report = self.shutdown()?;
+        // Tasks spawned while waiting were aborted by the shutdown.
+        stopped.append(&mut report.tasks);
+        report.tasks = stopped;
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Error, SingletonManager, TaskStatus};
+    use std::future::pending;
+    use std::time::Duration;
+
+    #[test]
+    fn test_managed_tasks_are_stopped_on_shutdown() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap();
+        let manager = SingletonManager::new();
+        assert!(matches!(
+            manager.spawn_managed("outside", async {}),
+            Err(Error::UnknownError(_))
+        ));
+
+        runtime.block_on(async {
+            manager.spawn_managed("done", async {}).unwrap();
+            manager
+                .spawn_managed("flusher", async {
+                    tokio::time::sleep(Duration::from_millis(5)).await;
+                })
+                .unwrap();
+            manager.spawn_managed("stuck", pending()).unwrap();
+            manager
+                .spawn_managed("crashed", async { panic!("flush failed") })
+                .unwrap();
+            assert!(matches!(
+                manager.spawn_managed("stuck", async {}),
+                Err(Error::ServiceAlreadyExists(name)) if name == "stuck"
+            ));
+            tokio::task::yield_now().await;
+            assert_eq!(Some(TaskStatus::Finished), manager.task_status("done"));
+            assert_eq!(Some(TaskStatus::Panicked), manager.task_status("crashed"));
+
+            let report = manager
+                .shutdown_with_deadline(Duration::from_millis(100))
+                .await
+                .unwrap();
+            assert_eq!(
+                vec![
+                    ("crashed".to_string(), TaskStatus::Panicked),
+                    ("done".to_string(), TaskStatus::Finished),
+                    ("flusher".to_string(), TaskStatus::Finished),
+                    ("stuck".to_string(), TaskStatus::Aborted),
+                ],
+                report.tasks
+            );
+            assert!(manager.tasks().is_empty());
+
+            manager.spawn_managed("stuck", pending()).unwrap();
+            let report = manager.shutdown().unwrap();
+            assert_eq!(
+                vec![("stuck".to_string(), TaskStatus::Aborted)],
+                report.tasks
+            );
+        });
+    }
+}
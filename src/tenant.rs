@@ -0,0 +1,329 @@
+//! # Tenants
+//! Per-tenant singletons, grouped and disposable as a unit.
+//!
+//! A tenant is a namespace of the singleton manager, where the services of the tenant are
+//! registered under the alias `<tenant>/<alias>`. Services are set and retrieved through the
+//! `Tenant` view, and dropping the tenant tears down all of its services at once, e.g. the
+//! connection pools and caches of a customer leaving a SaaS backend.
+//! ```
+//! use singleton_manager::SingletonManager;
+//!
+//! let manager = SingletonManager::new();
+//! let acme = manager.create_tenant("acme").unwrap();
+//! acme.set("db", "postgres://acme".to_string()).unwrap();
+//!
+//! assert_eq!("postgres://acme", *acme.get_ref::<String>("db").unwrap());
+//! assert_eq!("postgres://acme", *manager.get_ref::<String>("acme/db").unwrap());
+//! assert_eq!(vec!["acme".to_string()], manager.tenants());
+//!
+//! manager.drop_tenant("acme").unwrap();
+//! assert!(!manager.has("acme/db"));
+//! ```
+use crate::{
+    Error, GetError, Registry, Result, ServiceRef, ServiceRefMut, SetError, SingletonManager, Uuid,
+};
+use std::any::Any;
+use std::cmp::Reverse;
+use std::panic::Location;
+use std::sync::Arc;
+
+/// Getting the alias a service of a tenant is registered under.
+pub fn tenant_alias(tenant: &str, service_name: &str) -> String {
+    format!("{}/{}", tenant, service_name)
+}
+
+/// A view of the singleton manager scoped to the services of a tenant.
+pub struct Tenant<'a> {
+    manager: &'a SingletonManager,
+    name: String,
+}
+
+impl<'a> Tenant<'a> {
+    /// The name of the tenant.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The alias the service of the tenant is registered under in the singleton manager.
+    pub fn alias(&self, service_name: &str) -> String {
+        tenant_alias(&self.name, service_name)
+    }
+
+    /// Setting a service of the tenant, as `SingletonManager::set` does.
+    #[track_caller]
+    pub fn set<T: Send + Sync + 'static>(
+        &self,
+        service_name: &str,
+        service: T,
+    ) -> std::result::Result<(), SetError> {
+        let alias = self.alias(service_name);
+        let location = Location::caller();
+        self.manager
+            .registry_mut()
+            .and_then(|mut registry| {
+                registry.store_in_tenant(&self.name, &alias, |registry| {
+                    let service = Box::new(service);
+                    let type_name = std::any::type_name::<T>();
+                    registry
+                        .store_service(&alias, service, type_name, &[], location)
+                        .map(|_| ())
+                })
+            })
+            .map_err(|e| SetError::from_error(e, &alias))
+    }
+
+    /// Setting the factory of a service of the tenant, as `SingletonManager::set_factory` does.
+    #[track_caller]
+    pub fn set_factory<F: 'static + Fn() -> Box<dyn Any + Send + Sync> + Send + Sync>(
+        &self,
+        service_name: &str,
+        factory: F,
+    ) -> std::result::Result<(), SetError> {
+        let alias = self.alias(service_name);
+        let location = Location::caller();
+        self.manager
+            .registry_mut()
+            .and_then(|mut registry| {
+                registry.store_in_tenant(&self.name, &alias, |registry| {
+                    let factory = registry.with_default_timeout(Arc::new(factory));
+                    registry
+                        .store_factory(&alias, factory, location)
+                        .map(|_| ())
+                })
+            })
+            .map_err(|e| SetError::from_error(e, &alias))
+    }
+
+    /// True if the tenant has the service.
+    pub fn has(&self, service_name: &str) -> bool {
+        self.manager.has(&self.alias(service_name))
+    }
+
+    /// Getting a shared reference to a service of the tenant, as `SingletonManager::get_ref`
+    /// does.
+    #[track_caller]
+    pub fn get_ref<T: 'static>(
+        &self,
+        service_name: &str,
+    ) -> std::result::Result<ServiceRef<'a, T>, GetError> {
+        self.manager
+            .get_ref_by_alias::<T>(&self.alias(service_name))
+    }
+
+    /// Getting an exclusive reference to a service of the tenant, as
+    /// `SingletonManager::get_mut` does.
+    #[track_caller]
+    pub fn get_mut<T: 'static>(
+        &self,
+        service_name: &str,
+    ) -> std::result::Result<ServiceRefMut<'a, T>, GetError> {
+        self.manager
+            .get_mut_by_alias::<T>(&self.alias(service_name))
+    }
+
+    /// The aliases of the services of the tenant, without the tenant prefix, in the order they
+    /// were registered.
+    pub fn services(&self) -> Vec<String> {
+        let prefix = tenant_alias(&self.name, "");
+        self.manager
+            .registry()
+            .map(|registry| {
+                registry
+                    .tenants
+                    .get(&self.name)
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|id| registry.alias_of(id))
+                    .map(|alias| alias.strip_prefix(&prefix).unwrap_or(alias).to_string())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+impl SingletonManager {
+    /// Creating a tenant.
+    /// Fails with `Error::TenantAlreadyExists` if the tenant exists.
+    pub fn create_tenant(&self, tenant: &str) -> Result<Tenant<'_>> {
+        let mut registry = self.registry_mut()?;
+        if registry.tenants.contains_key(tenant) {
+            return Err(Error::TenantAlreadyExists(tenant.to_string()));
+        }
+        registry.tenants.insert(tenant.to_string(), Vec::new());
+        Ok(Tenant {
+            manager: self,
+            name: tenant.to_string(),
+        })
+    }
+
+    /// Getting the view of a tenant.
+    /// Fails with `Error::TenantDoesNotExist` if the tenant is not created.
+    pub fn tenant(&self, tenant: &str) -> Result<Tenant<'_>> {
+        if !self.registry()?.tenants.contains_key(tenant) {
+            return Err(Error::TenantDoesNotExist(tenant.to_string()));
+        }
+        Ok(Tenant {
+            manager: self,
+            name: tenant.to_string(),
+        })
+    }
+
+    /// Getting the view of the namespace of a crate, creating it when first used, see
+    /// `crate_scope!`.
+    /// The namespace of a crate is a tenant, so its services are reachable from the application
+    /// by their fully qualified alias `<crate>/<alias>`.
+    pub fn crate_scope(&self, crate_name: &str) -> Result<Tenant<'_>> {
+        self.registry_mut()?
+            .tenants
+            .entry(crate_name.to_string())
+            .or_default();
+        Ok(Tenant {
+            manager: self,
+            name: crate_name.to_string(),
+        })
+    }
+
+    /// The names of the tenants, in alphabetical order.
+    pub fn tenants(&self) -> Vec<String> {
+        self.registry()
+            .map(|registry| registry.tenants.keys().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Dropping a tenant and tearing down all of its services, returning the aliases of the
+    /// services in the order they were torn down.
+    ///
+    /// Services are torn down in dependency order, by startup phase starting with the
+    /// `Interface` phase, and within a phase in the reverse order of their registration. The
+    /// services are dropped after the singleton manager is unlocked, so their `Drop` may use the
+    /// singleton manager.
+    ///
+    /// Nothing is torn down if any of the services is borrowed or frozen.
+    #[track_caller]
+    pub fn drop_tenant(&self, tenant: &str) -> Result<Vec<String>> {
+        let location = Location::caller();
+        let mut registry = self.registry_mut()?;
+        let mut services: Vec<(Uuid, String)> = registry
+            .tenants
+            .get(tenant)
+            .ok_or_else(|| Error::TenantDoesNotExist(tenant.to_string()))?
+            .iter()
+            .rev()
+            .filter_map(|id| Some((*id, registry.alias_of(id)?.to_string())))
+            .collect();
+        services
+            .sort_by_key(|(id, _)| Reverse(registry.phases.get(id).copied().unwrap_or_default()));
+        let torn_down = registry.teardown(&services, location)?;
+        registry.tenants.remove(tenant);
+        drop(registry);
+        torn_down.into_iter().for_each(drop);
+        Ok(services.into_iter().map(|(_, alias)| alias).collect())
+    }
+}
+
+impl Registry {
+    /// Registering a service of the tenant with the closure, and adding it to the tenant.
+    /// Nothing is registered if the tenant does not exist.
+    fn store_in_tenant<F>(&mut self, tenant: &str, alias: &str, store: F) -> Result<()>
+    where
+        F: FnOnce(&mut Registry) -> Result<()>,
+    {
+        if !self.tenants.contains_key(tenant) {
+            return Err(Error::TenantDoesNotExist(tenant.to_string()));
+        }
+        store(self)?;
+        let id = self
+            .resolve(alias)
+            .ok_or_else(|| Error::ServiceDoesNotExist(alias.into(), Default::default()))?;
+        if let Some(services) = self.tenants.get_mut(tenant) {
+            services.push(id);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Error, Phase, SingletonManager};
+    use std::sync::{Arc, Mutex};
+
+    struct Service {
+        alias: &'static str,
+        dropped: Arc<Mutex<Vec<&'static str>>>,
+    }
+
+    impl Drop for Service {
+        fn drop(&mut self) {
+            self.dropped.lock().unwrap().push(self.alias);
+        }
+    }
+
+    #[test]
+    fn test_drop_tenant_in_dependency_order() {
+        let manager = SingletonManager::new();
+        let dropped = Arc::new(Mutex::new(Vec::new()));
+        let acme = manager.create_tenant("acme").unwrap();
+        for alias in ["db", "cache", "http"] {
+            let dropped = dropped.clone();
+            acme.set_factory(alias, move || {
+                Box::new(Service {
+                    alias,
+                    dropped: dropped.clone(),
+                })
+            })
+            .unwrap();
+        }
+        manager.set_phase("acme/db", Phase::Infrastructure).unwrap();
+        manager.set_phase("acme/http", Phase::Interface).unwrap();
+        manager.create_tenant("globex").unwrap();
+        assert!(matches!(
+            manager.create_tenant("acme"),
+            Err(Error::TenantAlreadyExists(_))
+        ));
+        assert_eq!(vec!["acme", "globex"], manager.tenants());
+        assert_eq!(vec!["db", "cache", "http"], acme.services());
+
+        let http = acme.get_ref::<Service>("http").unwrap();
+        acme.get_ref::<Service>("cache").unwrap();
+        acme.get_ref::<Service>("db").unwrap();
+        assert!(matches!(
+            manager.drop_tenant("acme"),
+            Err(Error::AlreadyBorrowed(_, _))
+        ));
+        drop(http);
+
+        assert_eq!(
+            vec!["acme/http", "acme/cache", "acme/db"],
+            manager.drop_tenant("acme").unwrap()
+        );
+        assert_eq!(vec!["http", "cache", "db"], *dropped.lock().unwrap());
+        assert!(!manager.has("acme/db"));
+        assert!(acme.set("late", 1_u32).is_err());
+        assert!(!manager.has("acme/late"));
+        assert_eq!(vec!["globex"], manager.tenants());
+        assert!(matches!(
+            manager.tenant("acme"),
+            Err(Error::TenantDoesNotExist(_))
+        ));
+    }
+
+    #[test]
+    fn test_crate_scopes_are_created_when_first_used() {
+        let manager = SingletonManager::new();
+        let scope = crate_scope!(manager).unwrap();
+        assert_eq!("singleton-manager", scope.name());
+        scope.set("config", 1_u32).unwrap();
+        assert_eq!(
+            1,
+            *crate_scope!(manager)
+                .unwrap()
+                .get_ref::<u32>("config")
+                .unwrap()
+        );
+        assert!(manager.has("singleton-manager/config"));
+        assert_eq!(
+            vec!["config"],
+            manager.crate_scope("singleton-manager").unwrap().services()
+        );
+    }
+}
@@ -0,0 +1,168 @@
+//! # Factory timeouts
+//! Bounding the time a factory may take to construct its service.
+//!
+//! A slow factory, e.g. one connecting to an unreachable database, stalls every `get` of its
+//! service without any visibility. A factory with a construction budget is run on a helper
+//! thread, and when it exceeds the budget the `get` fails with `Error::FactoryTimeout` instead.
+//! The construction keeps running, and the next `get` waits for it again rather than starting
+//! another one, so a factory never runs on more than one helper thread at the time.
+//! ```
+//! use singleton_manager::{Error, SingletonManager};
+//! use std::time::Duration;
+//!
+//! let mut manager = SingletonManager::new();
+//! manager
+//!     .set_factory_with_timeout("db", Duration::from_millis(10), || {
+//!         std::thread::sleep(Duration::from_secs(1));
+//!         "postgres".to_string()
+//!     })
+//!     .unwrap();
+//!
+//! let e = Error::from(manager.get::<String>("db").unwrap_err());
+//! assert!(matches!(e, Error::FactoryTimeout(alias, _) if alias == "db"));
+//! ```
+use crate::{Error, Factory, Registry, Result, Service, SetError, SingletonManager};
+use std::any::Any;
+use std::panic::Location;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+/// The output of a factory that exceeded its construction budget, after waiting for the time
+/// contained.
+pub(crate) struct TimedOut(pub(crate) Duration);
+
+impl Registry {
+    /// The factory, bounded by the factory timeout of the configuration if any.
+    pub(crate) fn with_default_timeout(&self, factory: Factory) -> Factory {
+        match self.config.factory_timeout {
+            Some(budget) => Arc::new(timed(factory, budget)),
+            None => factory,
+        }
+    }
+}
+
+impl SingletonManager {
+    /// Setting a factory that must construct the service within the budget.
+    /// The factory is run on a helper thread, and if it does not finish within the budget,
+    /// getting the service fails with `Error::FactoryTimeout`, holding the time waited.
+    ///
+    /// The helper thread is not interrupted. The next `get` waits for the same construction
+    /// again, for another budget, and gets its service if it finished meanwhile.
+    #[track_caller]
+    pub fn set_factory_with_timeout<T, F>(
+        &self,
+        service_name: &str,
+        budget: Duration,
+        factory: F,
+    ) -> std::result::Result<(), SetError>
+    where
+        T: Send + Sync + 'static,
+        F: Fn() -> T + Send + Sync + 'static,
+    {
+        let factory = timed(Arc::new(move || Box::new(factory()) as Service), budget);
+        self.store_factory(service_name, factory, Location::caller())
+            .map(|_| ())
+            .map_err(|e| SetError::from_error(e, service_name))
+    }
+
+    /// Storing the factory, bounded by the factory timeout of the configuration if any.
+    pub(crate) fn store_factory_with_default_timeout<F>(
+        &self,
+        service_name: &str,
+        factory: F,
+        location: &'static Location<'static>,
+    ) -> Result<bool>
+    where
+        F: Fn() -> Service + Send + Sync + 'static,
+    {
+        let mut registry = self.registry_mut()?;
+        let factory = registry.with_default_timeout(Arc::new(factory));
+        registry.store_factory(service_name, factory, location)
+    }
+}
+
+/// Running the factory on a helper thread, giving a `TimedOut` if it does not finish within
+/// the budget. A construction exceeding the budget is kept in flight, and waited for by the
+/// next call instead of starting another helper thread.
+fn timed(factory: Factory, budget: Duration) -> impl Fn() -> Service + Send + Sync + 'static {
+    let in_flight: Mutex<Option<Receiver<Service>>> = Mutex::new(None);
+    move || {
+        let mut in_flight = in_flight.lock().unwrap_or_else(PoisonError::into_inner);
+        let receiver = in_flight.take().unwrap_or_else(|| {
+            let factory = factory.clone();
+            let (sender, receiver) = mpsc::sync_channel(1);
+            std::thread::spawn(move || {
+                let _ = sender.send(factory());
+            });
+            receiver
+        });
+        let started = Instant::now();
+        match receiver.recv_timeout(budget) {
+            Ok(service) => service,
+            Err(RecvTimeoutError::Timeout) => {
+                *in_flight = Some(receiver);
+                Box::new(TimedOut(started.elapsed()))
+            }
+            Err(RecvTimeoutError::Disconnected) => Box::new(TimedOut(started.elapsed())),
+        }
+    }
+}
+
+impl TimedOut {
+    /// Failing with `Error::FactoryTimeout` if the output of the factory is a timeout.
+    pub(crate) fn check(service: &dyn Any, service_name: &str) -> Result<()> {
+        match service.downcast_ref::<TimedOut>() {
+            Some(TimedOut(elapsed)) => Err(Error::FactoryTimeout(service_name.into(), *elapsed)),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::SingletonManager;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[test]
+    fn test_factory_within_budget() {
+        let mut manager = SingletonManager::new();
+        manager
+            .set_factory_with_timeout("port", Duration::from_secs(5), || 8080_u16)
+            .unwrap();
+        manager
+            .set_factory_with_timeout("slow", Duration::from_millis(10), || {
+                std::thread::sleep(Duration::from_millis(500));
+                1_u32
+            })
+            .unwrap();
+
+        assert_eq!(8080, *manager.get::<u16>("port").unwrap());
+        let e = manager.get::<u32>("slow").unwrap_err();
+        assert!(e.is_timeout());
+        assert_eq!(Some("slow"), e.alias());
+        assert!(!manager.is_instantiated("slow"));
+    }
+
+    #[test]
+    fn test_timed_out_construction_is_shared() {
+        let mut manager = SingletonManager::new();
+        let runs = Arc::new(AtomicUsize::new(0));
+        let counted = runs.clone();
+        manager
+            .set_factory_with_timeout("slow", Duration::from_millis(10), move || {
+                counted.fetch_add(1, Ordering::SeqCst);
+                std::thread::sleep(Duration::from_millis(200));
+                1_u32
+            })
+            .unwrap();
+
+        assert!(manager.get::<u32>("slow").unwrap_err().is_timeout());
+        assert!(manager.get::<u32>("slow").unwrap_err().is_timeout());
+        std::thread::sleep(Duration::from_millis(400));
+        assert_eq!(1, *manager.get::<u32>("slow").unwrap());
+        assert_eq!(1, runs.load(Ordering::SeqCst));
+    }
+}
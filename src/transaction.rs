@@ -0,0 +1,247 @@
+//! # Transactions
+//! Registering a set of services atomically.
+//!
+//! The registrations of a transaction are staged, and only applied to the singleton manager when
+//! the transaction closure succeeds. They are applied while the manager is locked, where either
+//! all registrations are applied or none are, so the wiring of a module is never half installed
+//! when one of its aliases conflicts.
+//! ```
+//! use singleton_manager::SingletonManager;
+//!
+//! let mut manager = SingletonManager::new();
+//! manager.set_factory("cache", || Box::new(0_u32)).unwrap();
+//!
+//! let result = manager.transaction(|tx| {
+//!     tx.set("db", "postgres".to_string())?;
+//!     tx.set_factory("cache", || Box::new(1_u32))?;
+//!     Ok(())
+//! });
+//! assert!(result.is_err());
+//! assert!(!manager.has("db"));
+//! ```
+use crate::{Error, Factory, Registry, Result, Service, SingletonManager, Uuid};
+use std::any::Any;
+use std::panic::Location;
+use std::sync::Arc;
+
+/// A registration staged in a transaction.
+enum Staged {
+    Service {
+        alias: String,
+        service: Service,
+        type_name: &'static str,
+        location: &'static Location<'static>,
+    },
+    Factory {
+        alias: String,
+        factory: Factory,
+        location: &'static Location<'static>,
+    },
+}
+
+impl Staged {
+    fn alias(&self) -> &str {
+        match self {
+            Self::Service { alias, .. } | Self::Factory { alias, .. } => alias,
+        }
+    }
+}
+
+/// The registrations staged by a transaction, see `SingletonManager::transaction`.
+#[derive(Default)]
+pub struct Transaction {
+    staged: Vec<Staged>,
+}
+
+impl Transaction {
+    /// Staging a service, as `SingletonManager::set` does.
+    /// Fails with `Error::ServiceAlreadyExists` if the alias is already staged.
+    #[track_caller]
+    pub fn set<T: Send + Sync + 'static>(&mut self, service_name: &str, service: T) -> Result<()> {
+        self.stage(Staged::Service {
+            alias: service_name.to_string(),
+            service: Box::new(service),
+            type_name: std::any::type_name::<T>(),
+            location: Location::caller(),
+        })
+    }
+
+    /// Staging a service already boxed, together with the name of its type.
+    #[cfg(feature = "serde")]
+    pub(crate) fn set_boxed(
+        &mut self,
+        service_name: &str,
+        service: Service,
+        type_name: &'static str,
+        location: &'static Location<'static>,
+    ) -> Result<()> {
+        self.stage(Staged::Service {
+            alias: service_name.to_string(),
+            service,
+            type_name,
+            location,
+        })
+    }
+
+    /// Staging a factory, as `SingletonManager::set_factory` does.
+    /// Fails with `Error::ServiceAlreadyExists` if the alias is already staged.
+    #[track_caller]
+    pub fn set_factory<F: 'static + Fn() -> Box<dyn Any + Send + Sync> + Send + Sync>(
+        &mut self,
+        service_name: &str,
+        factory: F,
+    ) -> Result<()> {
+        self.stage(Staged::Factory {
+            alias: service_name.to_string(),
+            factory: Arc::new(factory),
+            location: Location::caller(),
+        })
+    }
+
+    /// The aliases staged, in the order they were staged.
+    pub fn aliases(&self) -> Vec<&str> {
+        self.staged.iter().map(Staged::alias).collect()
+    }
+
+    fn stage(&mut self, staged: Staged) -> Result<()> {
+        if self.staged.iter().any(|s| s.alias() == staged.alias()) {
+            return Err(Error::ServiceAlreadyExists(staged.alias().into()));
+        }
+        self.staged.push(staged);
+        Ok(())
+    }
+}
+
+impl SingletonManager {
+    /// Running the closure staging registrations, and applying them all if it succeeds.
+    /// If the closure fails nothing is applied, and if any registration fails, e.g. with
+    /// `Error::ServiceAlreadyExists` or `Error::ValidationFailed`, the registrations already
+    /// applied are rolled back and the error is returned.
+    ///
+    /// The closure is run without holding the lock, the registrations are applied while holding
+    /// it, so other threads never see a part of the registrations.
+    pub fn transaction<R, F>(&self, f: F) -> Result<R>
+    where
+        F: FnOnce(&mut Transaction) -> Result<R>,
+    {
+        let mut transaction = Transaction::default();
+        let result = f(&mut transaction)?;
+        self.registry_mut()?.apply(transaction)?;
+        Ok(result)
+    }
+}
+
+impl Registry {
+    /// Applying the registrations of the transaction, rolling back if any fails.
+    /// Returns the ids of the registrations, in the order they were staged.
+    ///
+    /// A rollback restores the registrations and deprecated aliases replaced by the
+    /// registrations, and leaves nothing of the transaction in the history.
+    pub(crate) fn apply(&mut self, transaction: Transaction) -> Result<Vec<Uuid>> {
+        let forwarding = self.forwarding.clone();
+        let bindings = self.bindings.clone();
+        let history = self.history.set_aside();
+        self.begin_replacing();
+        let mut applied: Vec<String> = Vec::with_capacity(transaction.staged.len());
+        for staged in transaction.staged {
+            let alias = staged.alias().to_string();
+            let stored = match staged {
+                Staged::Service {
+                    alias,
+                    service,
+                    type_name,
+                    location,
+                } => self
+                    .store_service(&alias, service, type_name, &[], location)
+                    .map(|stored| stored.is_some()),
+                Staged::Factory {
+                    alias,
+                    factory,
+                    location,
+                } => self.store_factory(&alias, factory, location),
+            };
+            match stored {
+                Ok(true) => applied.push(alias),
+                Ok(false) => {}
+                Err(e) => {
+                    for alias in applied.iter().rev() {
+                        self.remove_alias(alias);
+                    }
+                    self.finish_replacing(false);
+                    self.forwarding = forwarding;
+                    self.bindings = bindings;
+                    self.history.put_back(history, false);
+                    return Err(e);
+                }
+            }
+        }
+        self.finish_replacing(true);
+        self.history.put_back(history, true);
+        Ok(applied
+            .iter()
+            .filter_map(|alias| self.alias.get(alias.as_str()).copied())
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Error, SingletonManager};
+
+    #[test]
+    fn test_transaction_applies_all_or_nothing() {
+        let mut manager = SingletonManager::new();
+        manager.set_factory("cache", || Box::new(0_u32)).unwrap();
+
+        assert!(matches!(
+            manager.transaction(|tx| {
+                tx.set("db", 1_u32)?;
+                tx.set_factory("queue", || Box::new(2_u32))?;
+                tx.set_factory("cache", || Box::new(3_u32))
+            }),
+            Err(Error::ServiceAlreadyExists(alias)) if alias == "cache"
+        ));
+        assert!(!manager.has("db"));
+        assert!(!manager.has("queue"));
+        assert_eq!(0, *manager.get_ref::<u32>("cache").unwrap());
+
+        assert!(matches!(
+            manager.transaction(|tx| {
+                tx.set("db", 1_u32)?;
+                tx.set("db", 2_u32)
+            }),
+            Err(Error::ServiceAlreadyExists(_))
+        ));
+        assert!(manager
+            .transaction(|_| Err::<(), _>(Error::UnknownError("aborted".to_string())))
+            .is_err());
+
+        let staged = manager
+            .transaction(|tx| {
+                tx.set("db", 1_u32)?;
+                tx.set_factory("queue", || Box::new(2_u32))?;
+                Ok(tx.aliases().len())
+            })
+            .unwrap();
+        assert_eq!(2, staged);
+        assert_eq!(1, *manager.get_ref::<u32>("db").unwrap());
+        assert_eq!(2, *manager.get_ref::<u32>("queue").unwrap());
+    }
+
+    #[test]
+    fn test_rollback_restores_forwarding_and_history() {
+        let mut manager = SingletonManager::new();
+        manager.set("database", 1_u32).unwrap();
+        manager.rename_forwarding("database", "db").unwrap();
+        let history = manager.history().len();
+
+        assert!(manager
+            .transaction(|tx| {
+                tx.set("database", 2_u32)?;
+                tx.set("db", 3_u32)
+            })
+            .is_err());
+        assert_eq!(1, *manager.get_ref::<u32>("database").unwrap());
+        assert_eq!(history, manager.history().len());
+    }
+}
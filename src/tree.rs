@@ -0,0 +1,134 @@
+//! # Wiring tree
+//! Rendering the effective wiring of the singleton manager.
+//!
+//! `SingletonManager::render_tree` renders the registrations as an indented tree, meant to be
+//! logged at startup so the logs of every deployed build document its wiring. The services are
+//! grouped by namespace, where the services of a tenant are in the namespace of the tenant and
+//! all other services are in the root namespace, and every service lists its state, its type and
+//! the services it depends on.
+//! ```
+//! use singleton_manager::SingletonManager;
+//!
+//! let mut manager = SingletonManager::new();
+//! manager.set("config", "production".to_string()).unwrap();
+//! manager.set_factory("db", || Box::new(5_u32)).unwrap();
+//! manager.depends_on("db", "config").unwrap();
+//!
+//! println!("{}", manager.render_tree());
+//! assert!(manager.render_tree().contains("db [factory] <unknown type> -> config"));
+//! ```
+use crate::diagnostics::UNKNOWN_TYPE;
+use crate::{Registry, SingletonManager, Uuid};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// The name of the namespace of the services not belonging to a tenant.
+const ROOT_NAMESPACE: &str = "<root>";
+
+impl Registry {
+    /// The state of the registration, as rendered in the tree.
+    fn state_of(&self, id: &Uuid) -> &'static str {
+        if self.frozen.contains(id) {
+            "frozen"
+        } else if self.has_instance(id) {
+            "instantiated"
+        } else if self.reservations.contains_key(id) {
+            "reserved"
+        } else if self.memoized.contains_key(id) {
+            "memoized"
+        } else {
+            "factory"
+        }
+    }
+
+    /// The namespace of the registration, and the name of the service within it.
+    fn namespace_of<'a>(&self, id: &Uuid, alias: &'a str) -> (&'a str, &'a str) {
+        for (tenant, ids) in &self.tenants {
+            if ids.contains(id) {
+                if let Some(name) = alias
+                    .strip_prefix(tenant.as_str())
+                    .and_then(|name| name.strip_prefix('/'))
+                {
+                    return (&alias[..tenant.len()], name);
+                }
+            }
+        }
+        (ROOT_NAMESPACE, alias)
+    }
+}
+
+impl SingletonManager {
+    /// Rendering the registrations as an indented tree of namespaces and services, with the
+    /// state, the type and the dependencies of every service.
+    /// The namespaces are in alphabetical order, with the root namespace first, and so are the
+    /// services within a namespace.
+    pub fn render_tree(&self) -> String {
+        let registry = match self.registry() {
+            Ok(registry) => registry,
+            Err(_) => return String::new(),
+        };
+        let mut namespaces: BTreeMap<(bool, &str), Vec<(&str, &Uuid)>> = BTreeMap::new();
+        for (alias, id) in registry.alias.iter() {
+            let (namespace, name) = registry.namespace_of(id, alias);
+            namespaces
+                .entry((namespace != ROOT_NAMESPACE, namespace))
+                .or_default()
+                .push((name, id));
+        }
+
+        let mut tree = String::new();
+        for ((_, namespace), mut services) in namespaces {
+            services.sort();
+            let _ = writeln!(tree, "{}", namespace);
+            for (name, id) in services {
+                let _ = write!(
+                    tree,
+                    "  {} [{}] {}",
+                    name,
+                    registry.state_of(id),
+                    registry.type_names.get(id).copied().unwrap_or(UNKNOWN_TYPE)
+                );
+                let dependencies = registry
+                    .dependencies
+                    .get(id)
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|dependency| registry.alias_of(dependency))
+                    .collect::<Vec<_>>();
+                if !dependencies.is_empty() {
+                    let _ = write!(tree, " -> {}", dependencies.join(", "));
+                }
+                tree.push('\n');
+            }
+        }
+        tree
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::SingletonManager;
+
+    #[test]
+    fn test_render_tree() {
+        let mut manager = SingletonManager::new();
+        manager.set("config", "production".to_string()).unwrap();
+        manager.set_factory("db", || Box::new(5_u32)).unwrap();
+        manager.depends_on("db", "config").unwrap();
+        manager
+            .create_tenant("acme")
+            .unwrap()
+            .set("cache", 1_u8)
+            .unwrap();
+        manager.freeze("config").unwrap();
+
+        assert_eq!(
+            "<root>\n\
+             \x20 config [frozen] alloc::string::String\n\
+             \x20 db [factory] <unknown type> -> config\n\
+             acme\n\
+             \x20 cache [instantiated] u8\n",
+            manager.render_tree()
+        );
+    }
+}
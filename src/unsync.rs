@@ -0,0 +1,276 @@
+//! # Thread-bound services
+//! Storing services that can not be shared across threads.
+//!
+//! The storage of the singleton manager only holds `Send + Sync` services, so the manager itself
+//! can be shared across threads. Legacy services that are not, e.g. some FFI handles, can still
+//! be stored with `set_unsync`, which binds them to the thread storing them, or created by a
+//! factory set with `set_unsync_factory`, which binds them to the thread creating them. Getting a
+//! thread-bound service on any other thread fails with `Error::WrongThread`, naming both threads,
+//! instead of handing out a reference that would corrupt it.
+//! ```
+//! use singleton_manager::SingletonManager;
+//! use std::rc::Rc;
+//!
+//! let manager = SingletonManager::new();
+//! manager.set_unsync("handle", Rc::new(42_u32)).unwrap();
+//! assert_eq!(42, **manager.get_ref::<Rc<u32>>("handle").unwrap());
+//!
+//! std::thread::scope(|scope| {
+//!     scope.spawn(|| {
+//!         assert!(manager.get_ref::<Rc<u32>>("handle").unwrap_err().is_wrong_thread());
+//!     });
+//! });
+//! ```
+use crate::{Error, Factory, Registry, Result, Service, SetError, SingletonManager, Uuid};
+use std::any::Any;
+use std::mem::ManuallyDrop;
+use std::panic::Location;
+use std::sync::Arc;
+use std::thread::ThreadId;
+
+/// A service bound to the thread that stored it.
+pub(crate) struct ThreadBound {
+    owner: ThreadId,
+    service: ManuallyDrop<Box<dyn Any>>,
+}
+
+// The service is only accessed, and dropped, on the thread owning it.
+unsafe impl Send for ThreadBound {}
+unsafe impl Sync for ThreadBound {}
+
+impl Drop for ThreadBound {
+    fn drop(&mut self) {
+        if std::thread::current().id() == self.owner {
+            unsafe { ManuallyDrop::drop(&mut self.service) };
+        } else {
+            log::warn!("A thread-bound service is dropped on another thread and is leaked");
+        }
+    }
+}
+
+impl ThreadBound {
+    /// Binding the service to the current thread.
+    fn new<T: 'static>(service: T) -> Self {
+        ThreadBound {
+            owner: std::thread::current().id(),
+            service: ManuallyDrop::new(Box::new(service)),
+        }
+    }
+
+    /// The service itself if it is thread-bound, to check its type. The service must not be
+    /// otherwise used without `check_thread`.
+    pub(crate) fn inner(service: &dyn Any) -> &dyn Any {
+        match service.downcast_ref::<ThreadBound>() {
+            Some(bound) => bound.service.as_ref(),
+            None => service,
+        }
+    }
+
+    /// Failing with `Error::WrongThread` if the service is bound to another thread.
+    pub(crate) fn check_thread(service: &dyn Any, service_name: &str) -> Result<()> {
+        match service.downcast_ref::<ThreadBound>() {
+            Some(bound) if bound.owner != std::thread::current().id() => Err(Error::WrongThread(
+                service_name.into(),
+                bound.owner,
+                std::thread::current().id(),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// A pointer to the service itself, if it may be used on the current thread.
+    /// The pointer to the stored service must be valid.
+    pub(crate) unsafe fn checked(
+        service: *mut dyn Any,
+        service_name: &str,
+    ) -> Result<*mut dyn Any> {
+        Self::check_thread(&*service, service_name)?;
+        Ok(match (*service).downcast_mut::<ThreadBound>() {
+            Some(bound) => bound.service.as_mut() as *mut dyn Any,
+            None => service,
+        })
+    }
+
+    /// Unwrapping the service if it is thread-bound, on the thread owning it.
+    pub(crate) fn into_inner(service: Box<dyn Any>) -> Box<dyn Any> {
+        match service.downcast::<ThreadBound>() {
+            Ok(bound) => {
+                let mut bound = ManuallyDrop::new(*bound);
+                unsafe { ManuallyDrop::take(&mut bound.service) }
+            }
+            Err(service) => service,
+        }
+    }
+}
+
+impl Registry {
+    /// Failing with `Error::NotSendSync` if the configuration rejects services that are not
+    /// `Send + Sync`.
+    fn check_send_sync(&self, service_name: &str) -> Result<()> {
+        match self.config.strict_send_sync {
+            true => Err(Error::NotSendSync(service_name.into())),
+            false => Ok(()),
+        }
+    }
+
+    /// Storing a factory creating a service that can not be shared across threads, marking it
+    /// as such under the same lock, so it is never preheated on a background thread.
+    fn store_unsync_factory(
+        &mut self,
+        service_name: &str,
+        factory: Factory,
+        location: &'static Location<'static>,
+    ) -> Result<()> {
+        self.check_send_sync(service_name)?;
+        if self.store_factory(service_name, factory, location)? {
+            if let Some(id) = self.alias.get(service_name).copied() {
+                self.unsync_factories.insert(id);
+            }
+        }
+        Ok(())
+    }
+
+    /// Failing with `Error::WrongThread` if the instance of the service is bound to another
+    /// thread.
+    pub(crate) fn check_instance_thread(&self, id: &Uuid, service_name: &str) -> Result<()> {
+        match self.singletons.get(id) {
+            Some(service) => ThreadBound::check_thread(service.as_ref(), service_name),
+            None => Ok(()),
+        }
+    }
+}
+
+impl SingletonManager {
+    /// Setting a service that can not be shared across threads, binding it to the current
+    /// thread. Getting it on any other thread fails with `Error::WrongThread`.
+    ///
+    /// The service is leaked if it is dropped on another thread, e.g. when the manager is.
+    #[track_caller]
+    pub fn set_unsync<T: 'static>(
+        &self,
+        service_name: &str,
+        service: T,
+    ) -> std::result::Result<(), SetError> {
+        self.registry_mut()
+            .and_then(|mut registry| {
+                registry.check_send_sync(service_name)?;
+                registry.store_service(
+                    service_name,
+                    Box::new(ThreadBound::new(service)),
+                    std::any::type_name::<T>(),
+                    &[],
+                    Location::caller(),
+                )
+            })
+            .map(|_| ())
+            .map_err(|e| SetError::from_error(e, service_name))
+    }
+
+    /// Setting a factory creating a service that can not be shared across threads, binding it
+    /// to the thread creating it, i.e. the thread getting it first. Getting it on any other
+    /// thread fails with `Error::WrongThread`.
+    ///
+    /// The factory can not be preheated, as the service would be bound to a background thread.
+    #[track_caller]
+    pub fn set_unsync_factory<T, F>(
+        &self,
+        service_name: &str,
+        factory: F,
+    ) -> std::result::Result<(), SetError>
+    where
+        T: 'static,
+        F: Fn() -> T + Send + Sync + 'static,
+    {
+        let location = Location::caller();
+        self.registry_mut()
+            .and_then(|mut registry| {
+                registry.store_unsync_factory(
+                    service_name,
+                    Arc::new(move || Box::new(ThreadBound::new(factory())) as Service),
+                    location,
+                )
+            })
+            .map_err(|e| SetError::from_error(e, service_name))
+    }
+
+    /// The thread the service is bound to, if it is a thread-bound service already created.
+    pub fn owner_thread(&self, service_name: &str) -> Option<ThreadId> {
+        let registry = self.registry().ok()?;
+        let id = registry.resolve(service_name)?;
+        registry
+            .singletons
+            .get(&id)?
+            .downcast_ref::<ThreadBound>()
+            .map(|bound| bound.owner)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Error, GetError, SingletonManager};
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    #[test]
+    fn test_unsync_service_bound_to_thread() {
+        let mut manager = SingletonManager::new();
+        manager
+            .set_unsync("counter", Rc::new(Cell::new(1_u32)))
+            .unwrap();
+        manager.get::<Rc<Cell<u32>>>("counter").unwrap().set(2);
+        assert!(manager.contains::<Rc<Cell<u32>>>("counter"));
+
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                assert!(manager.get_ref::<Rc<Cell<u32>>>("counter").is_err());
+            });
+        });
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                assert!(matches!(
+                    manager.take::<Rc<Cell<u32>>>("counter"),
+                    Err(GetError::WrongThread(_, _, _))
+                ));
+            });
+        });
+        assert_eq!(2, manager.take::<Rc<Cell<u32>>>("counter").unwrap().get());
+    }
+
+    #[test]
+    fn test_unsync_factory_bound_to_creating_thread() {
+        let manager = SingletonManager::new();
+        manager
+            .set_unsync_factory("handle", || Rc::new(7_u32))
+            .unwrap();
+        assert!(matches!(
+            manager.preheat(["handle"]),
+            Err(Error::FactoryNotSend(_))
+        ));
+        assert_eq!(None, manager.owner_thread("handle"));
+
+        let creator = std::thread::scope(|scope| {
+            scope
+                .spawn(|| {
+                    assert_eq!(7, **manager.get_ref::<Rc<u32>>("handle").unwrap());
+                    std::thread::current().id()
+                })
+                .join()
+                .unwrap()
+        });
+        assert_eq!(Some(creator), manager.owner_thread("handle"));
+        match manager.get_ref::<Rc<u32>>("handle") {
+            Err(GetError::WrongThread(alias, owner, caller)) => {
+                assert_eq!("handle", alias);
+                assert_eq!(creator, owner);
+                assert_eq!(std::thread::current().id(), caller);
+            }
+            _ => panic!("expected the service to be bound to the creating thread"),
+        };
+    }
+
+    #[test]
+    fn test_manager_is_send_and_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<SingletonManager>();
+    }
+}
@@ -0,0 +1,148 @@
+//! # Usage report
+//! Finding dead wiring and mistyped aliases from a run of the application.
+//!
+//! The singleton manager counts the lookups of aliases no service is registered under.
+//! `SingletonManager::usage_report`, e.g. logged at shutdown, lists them together with the
+//! registrations never retrieved, so dead registrations can be pruned and typos in aliases are
+//! spotted from production runs.
+//! ```
+//! use singleton_manager::SingletonManager;
+//!
+//! let mut manager = SingletonManager::new();
+//! manager.set_factory("database", || Box::new("postgres".to_string())).unwrap();
+//! manager.set_factory("mailer", || Box::new(25_u16)).unwrap();
+//! manager.get_ref::<String>("database").unwrap();
+//! assert!(manager.get_ref::<String>("databse").is_err());
+//!
+//! let report = manager.usage_report();
+//! assert_eq!(vec!["mailer".to_string()], report.unused);
+//! assert_eq!(vec![("databse".to_string(), 1)], report.failed_lookups);
+//! println!("{}", report);
+//! ```
+use crate::{Alias, Registry, SingletonManager};
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{PoisonError, RwLockReadGuard};
+
+/// The number of distinct aliases counted as failed lookups, lookups of further aliases are not
+/// counted.
+const MAX_FAILED_LOOKUPS: usize = 1024;
+
+/// The usage of the registrations, see `SingletonManager::usage_report`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UsageReport {
+    /// The aliases of the registrations never retrieved, sorted.
+    pub unused: Vec<String>,
+    /// The aliases looked up while no service was registered under them, with the number of
+    /// lookups, most frequent first.
+    pub failed_lookups: Vec<(String, u64)>,
+}
+
+impl Display for UsageReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.unused.is_empty() && self.failed_lookups.is_empty() {
+            return writeln!(f, "Every registration was used and every lookup succeeded");
+        }
+        if !self.unused.is_empty() {
+            writeln!(f, "Registrations never retrieved:")?;
+            for alias in &self.unused {
+                writeln!(f, "  - {}", alias)?;
+            }
+        }
+        if !self.failed_lookups.is_empty() {
+            writeln!(f, "Lookups of aliases not registered:")?;
+            for (alias, count) in &self.failed_lookups {
+                writeln!(f, "  - {}: {} times", alias, count)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Registry {
+    /// Counting a lookup of an alias no service is registered under, returning the alias for
+    /// the error, which is only allocated on the first lookup.
+    /// Only the first lookup of an alias takes the lock of the counts exclusively.
+    pub(crate) fn note_failed_lookup(&self, alias: &str) -> Alias {
+        if !self.config.metrics {
+            return Alias::from(alias);
+        }
+        if let Some((counted, count)) = self.counted_lookups().get_key_value(alias) {
+            count.fetch_add(1, Ordering::Relaxed);
+            return counted.clone();
+        }
+        let alias = Alias::from(alias);
+        let mut counted = self
+            .failed_lookups
+            .write()
+            .unwrap_or_else(PoisonError::into_inner);
+        if counted.len() < MAX_FAILED_LOOKUPS || counted.contains_key(&alias) {
+            counted
+                .entry(alias.clone())
+                .or_default()
+                .fetch_add(1, Ordering::Relaxed);
+        }
+        alias
+    }
+
+    fn counted_lookups(&self) -> RwLockReadGuard<'_, HashMap<Alias, AtomicU64>> {
+        self.failed_lookups
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl SingletonManager {
+    /// Reporting the registrations never retrieved, and the lookups of aliases not registered.
+    pub fn usage_report(&self) -> UsageReport {
+        let registry = match self.registry() {
+            Ok(registry) => registry,
+            Err(_) => return UsageReport::default(),
+        };
+        let mut unused = registry
+            .alias
+            .iter()
+            .filter(|(_, id)| !registry.retrieved.contains(id))
+            .map(|(alias, _)| alias.to_string())
+            .collect::<Vec<_>>();
+        unused.sort();
+        let mut failed_lookups = registry
+            .counted_lookups()
+            .iter()
+            .map(|(alias, count)| (alias.to_string(), count.load(Ordering::Relaxed)))
+            .collect::<Vec<_>>();
+        failed_lookups.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        UsageReport {
+            unused,
+            failed_lookups,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::SingletonManager;
+
+    #[test]
+    fn test_usage_report_counts_failed_lookups() {
+        let mut manager = SingletonManager::new();
+        manager.set("cache", 1_u32).unwrap();
+        manager.set("queue", 2_u32).unwrap();
+        manager.get::<u32>("cache").unwrap();
+        for _ in 0..2 {
+            assert!(manager.get::<u32>("cahce").is_err());
+        }
+        assert!(manager.borrow::<u32>("qeueu").is_err());
+        assert!(!manager.has("probe"));
+        assert_eq!(None, manager.get_optional::<u32>("tracing").unwrap());
+
+        let report = manager.usage_report();
+        assert_eq!(vec!["queue".to_string()], report.unused);
+        assert_eq!(
+            vec![("cahce".to_string(), 2), ("qeueu".to_string(), 1)],
+            report.failed_lookups
+        );
+        assert!(report.to_string().contains("  - cahce: 2 times\n"));
+    }
+}
@@ -0,0 +1,131 @@
+//! # Validation
+//! Validation hooks run before a service is stored in the singleton manager.
+//!
+//! This allows enforcing invariants centrally, like naming conventions or banned types, and
+//! rejecting invalid registrations with a descriptive error.
+//! ```
+//! use singleton_manager::{Error, SingletonManager};
+//!
+//! let mut manager = SingletonManager::new();
+//! manager
+//!     .add_validator(|info, _service| {
+//!         if info.alias().starts_with("app.") {
+//!             Ok(())
+//!         } else {
+//!             Err(Error::ValidationFailed(
+//!                 info.alias().into(),
+//!                 "aliases must start with `app.`".to_string(),
+//!             ))
+//!         }
+//!     })
+//!     .unwrap();
+//!
+//! assert!(manager.set("app.db", 1).is_ok());
+//! assert!(manager.set("db", 1).is_err());
+//! assert!(!manager.has("db"));
+//! ```
+use crate::{Registry, Result, ServiceInfo, SingletonManager, ThreadBound, Uuid};
+use std::any::Any;
+use std::sync::Arc;
+
+/// A validation hook, getting the information about the registration and the service itself.
+pub type Validator = Arc<dyn Fn(&ServiceInfo, &dyn Any) -> Result<()> + Send + Sync>;
+
+impl Registry {
+    /// Running all validators against the service about to be stored under the alias.
+    /// The validators are run while the registry is locked, so they must not use the manager.
+    pub(crate) fn validate(&self, alias: &str, id: &Uuid, service: &dyn Any) -> Result<()> {
+        if self.validators.is_empty() {
+            return Ok(());
+        }
+        let info = self.info(alias, id);
+        self.validators
+            .iter()
+            .try_for_each(|validator| validator(&info, ThreadBound::inner(service)))
+    }
+}
+
+impl SingletonManager {
+    /// Adding a validation hook, run every time a service is stored in the singleton manager,
+    /// both when set directly and when created by a factory.
+    /// If any validator fails the service is not stored and the error of the validator is
+    /// returned.
+    ///
+    /// The validators are run while the singleton manager is locked, so they must not use the
+    /// singleton manager themselves.
+    pub fn add_validator<V>(&self, validator: V) -> Result<()>
+    where
+        V: Fn(&ServiceInfo, &dyn Any) -> Result<()> + Send + Sync + 'static,
+    {
+        self.registry_mut()?.validators.push(Arc::new(validator));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Error, FactoryError, GetError, SetError, SingletonManager};
+
+    fn reject_strings(manager: &SingletonManager) {
+        manager
+            .add_validator(|info, service| {
+                if service.is::<String>() {
+                    Err(Error::ValidationFailed(
+                        info.alias().into(),
+                        "strings are not services".to_string(),
+                    ))
+                } else {
+                    Ok(())
+                }
+            })
+            .unwrap();
+    }
+
+    #[test]
+    fn test_validator_rejects_set() {
+        let mut manager = SingletonManager::new();
+        reject_strings(&manager);
+
+        assert!(manager.set("number", 1_u32).is_ok());
+        match manager.set("text", "hello".to_string()) {
+            Err(SetError::ValidationFailed(alias, _)) => assert_eq!("text", alias),
+            _ => panic!("Expected the validation to fail"),
+        }
+        assert!(!manager.has("text"));
+        assert!(manager.set("text", 2_u32).is_ok());
+    }
+
+    #[test]
+    fn test_validator_rejects_factory_output() {
+        let mut manager = SingletonManager::new();
+        reject_strings(&manager);
+
+        manager
+            .set_factory("text", || Box::new("hello".to_string()))
+            .unwrap();
+        assert!(matches!(
+            manager.get::<String>("text"),
+            Err(GetError::Factory(FactoryError::ValidationFailed(_, _)))
+        ));
+        assert!(!manager.info("text").unwrap().is_instantiated());
+    }
+
+    #[test]
+    fn test_validator_sees_tags() {
+        let mut manager = SingletonManager::new();
+        manager
+            .add_validator(|info, _| match info.tag("owner") {
+                Some(_) => Ok(()),
+                None => Err(Error::ValidationFailed(
+                    info.alias().into(),
+                    "missing owner".to_string(),
+                )),
+            })
+            .unwrap();
+
+        assert!(manager.set("db", 1_u32).is_err());
+        assert!(manager
+            .set_with_meta("db", 1_u32, &[("owner", "platform")])
+            .is_ok());
+    }
+}
@@ -0,0 +1,178 @@
+//! # Versions
+//! Side-by-side versions of a singleton registered under one alias.
+//!
+//! Every version is a registration of its own, under the alias `<alias>@<version>`, while the
+//! alias itself resolves to the default version. This allows a gradual migration to run the old
+//! and new implementation of a singleton at the same time, and to switch the default atomically.
+//! ```
+//! use singleton_manager::SingletonManager;
+//!
+//! let mut manager = SingletonManager::new();
+//! manager.set_versioned("api_client", "v1", "http://v1".to_string()).unwrap();
+//! manager.set_versioned("api_client", "v2", "http://v2".to_string()).unwrap();
+//!
+//! assert_eq!("http://v1", manager.get::<String>("api_client").unwrap());
+//! assert_eq!("http://v2", manager.get_version::<String>("api_client", "v2").unwrap());
+//!
+//! manager.set_default_version("api_client", "v2").unwrap();
+//! assert_eq!("http://v2", manager.get::<String>("api_client").unwrap());
+//! assert_eq!("http://v1", manager.get::<String>("api_client@v1").unwrap());
+//! ```
+use crate::{Error, GetError, Result, SetError, SingletonManager};
+use std::panic::Location;
+
+/// Getting the alias a version of a singleton is registered under.
+pub fn versioned_alias(service_name: &str, version: &str) -> String {
+    format!("{}@{}", service_name, version)
+}
+
+impl SingletonManager {
+    /// Setting a version of a singleton.
+    /// The first version set becomes the default version, served when getting the alias itself.
+    /// Fails with `SetError::ServiceAlreadyExists` if the alias is registered without versions, or
+    /// the version is already set.
+    #[track_caller]
+    pub fn set_versioned<T: Send + Sync + 'static>(
+        &mut self,
+        service_name: &str,
+        version: &str,
+        service: T,
+    ) -> std::result::Result<&mut T, SetError> {
+        let alias = versioned_alias(service_name, version);
+        self.end_raw_references();
+        let service = self
+            .store_versioned(service_name, version, &alias, service)
+            .map_err(|e| SetError::from_error(e, &alias))?;
+        self.note_raw_reference(&alias);
+        Ok(unsafe { &mut *service })
+    }
+
+    #[track_caller]
+    fn store_versioned<T: Send + Sync + 'static>(
+        &self,
+        service_name: &str,
+        version: &str,
+        alias: &str,
+        service: T,
+    ) -> Result<*mut T> {
+        let stored = {
+            let mut registry = self.registry_mut()?;
+            if registry.alias.contains_key(service_name) {
+                return Err(Error::ServiceAlreadyExists(service_name.into()));
+            }
+            let stored = registry.store_service(
+                alias,
+                Box::new(service),
+                std::any::type_name::<T>(),
+                &[],
+                Location::caller(),
+            )?;
+            registry
+                .default_versions
+                .entry(service_name.to_string())
+                .or_insert_with(|| version.to_string());
+            stored
+        };
+        self.stored::<T>(alias, stored)
+    }
+
+    /// Switching the default version of a singleton.
+    /// Fails with `Error::ServiceDoesNotExist` if the version is not set.
+    pub fn set_default_version(&self, service_name: &str, version: &str) -> Result<()> {
+        let mut registry = self.registry_mut()?;
+        let alias = versioned_alias(service_name, version);
+        if !registry.alias.contains_key(alias.as_str()) {
+            return Err(Error::ServiceDoesNotExist(alias.into(), Default::default()));
+        }
+        registry
+            .default_versions
+            .insert(service_name.to_string(), version.to_string());
+        registry.invalidate_facades();
+        Ok(())
+    }
+
+    /// Getting the default version of a singleton, if it has versions.
+    pub fn default_version(&self, service_name: &str) -> Option<String> {
+        self.registry()
+            .ok()?
+            .default_versions
+            .get(service_name)
+            .cloned()
+    }
+
+    /// Getting all versions set of a singleton, in version order.
+    pub fn versions(&self, service_name: &str) -> Vec<String> {
+        let prefix = versioned_alias(service_name, "");
+        let mut versions = self
+            .registry()
+            .map(|registry| {
+                registry
+                    .alias
+                    .keys()
+                    .filter_map(|alias| alias.strip_prefix(&prefix))
+                    .map(str::to_string)
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        versions.sort();
+        versions
+    }
+
+    /// Getting a specific version of a singleton, as `get` does.
+    #[track_caller]
+    pub fn get_version<T: 'static>(
+        &mut self,
+        service_name: &str,
+        version: &str,
+    ) -> std::result::Result<&mut T, GetError> {
+        self.get_by_alias::<T>(&versioned_alias(service_name, version))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Error, SetError, SingletonManager};
+
+    #[test]
+    fn test_versions_side_by_side() {
+        let mut manager = SingletonManager::new();
+        manager.set_versioned("client", "v1", 1_u32).unwrap();
+        manager.set_versioned("client", "v2", 2_u32).unwrap();
+
+        assert_eq!(
+            vec!["v1".to_string(), "v2".to_string()],
+            manager.versions("client")
+        );
+        assert_eq!(Some("v1".to_string()), manager.default_version("client"));
+        assert!(manager.has("client"));
+        assert_eq!(1, *manager.get_ref::<u32>("client").unwrap());
+
+        manager.set_default_version("client", "v2").unwrap();
+        assert_eq!(2, *manager.get_ref::<u32>("client").unwrap());
+        assert_eq!(1, *manager.get_version::<u32>("client", "v1").unwrap());
+        assert!(matches!(
+            manager.set_default_version("client", "v3"),
+            Err(Error::ServiceDoesNotExist(_, _))
+        ));
+    }
+
+    #[test]
+    fn test_versions_conflicts() {
+        let mut manager = SingletonManager::new();
+        manager.set("plain", 1_u32).unwrap();
+        manager.set_versioned("client", "v1", 1_u32).unwrap();
+
+        assert!(matches!(
+            manager.set_versioned("plain", "v1", 1_u32),
+            Err(SetError::ServiceAlreadyExists(_))
+        ));
+        assert!(matches!(
+            manager.set_versioned("client", "v1", 1_u32),
+            Err(SetError::ServiceAlreadyExists(_))
+        ));
+        assert!(matches!(
+            manager.set("client", 1_u32),
+            Err(SetError::ServiceAlreadyExists(_))
+        ));
+    }
+}
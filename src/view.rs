@@ -0,0 +1,106 @@
+//! # Read-only views
+//! Handing out the singleton manager without the ability to change its wiring.
+//!
+//! A `RegistryView` only exposes the reading and introspection of the services, so subsystems
+//! that must not register, replace or remove services, e.g. request handlers or plugins, can be
+//! given a view instead of the singleton manager itself. Views are `Copy`, and as cheap to pass
+//! around as a reference.
+//! ```
+//! use singleton_manager::{sm, RegistryView};
+//!
+//! fn handle_request(services: RegistryView<'_>) -> usize {
+//!     services.get_ref::<Vec<String>>("view_routes").unwrap().len()
+//! }
+//!
+//! sm().set("view_routes", vec!["/".to_string()]).unwrap();
+//! assert_eq!(1, handle_request(sm().view()));
+//! assert!(sm().view().has("view_routes"));
+//! ```
+use crate::{GetError, GroupMetrics, ServiceInfo, ServiceRef, SingletonManager};
+
+/// A read-only view of the singleton manager, see `SingletonManager::view`.
+#[derive(Clone, Copy)]
+pub struct RegistryView<'a> {
+    manager: &'a SingletonManager,
+}
+
+impl<'a> RegistryView<'a> {
+    /// Getting a shared reference to the service, as `SingletonManager::get_ref` does.
+    /// Services registered by a factory are still created on first use.
+    #[track_caller]
+    pub fn get_ref<T: 'static>(
+        &self,
+        service_name: &str,
+    ) -> std::result::Result<ServiceRef<'a, T>, GetError> {
+        self.manager.get_ref_by_alias(service_name)
+    }
+
+    /// True if a service is registered under the alias.
+    pub fn has(&self, service_name: &str) -> bool {
+        self.manager.has(service_name)
+    }
+
+    /// The information about all registrations, in alias order.
+    pub fn entries(&self) -> Vec<ServiceInfo> {
+        let mut entries = self
+            .manager
+            .registry()
+            .map(|registry| {
+                registry
+                    .alias
+                    .iter()
+                    .map(|(alias, id)| registry.info(alias, id))
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        entries.sort_by(|a, b| a.alias().cmp(b.alias()));
+        entries
+    }
+
+    /// Counting all services by their state, as `SingletonManager::metrics_of_group` does for
+    /// a group.
+    pub fn metrics(&self) -> GroupMetrics {
+        self.manager
+            .registry()
+            .map(|registry| registry.metrics_of(registry.alias.values()))
+            .unwrap_or_default()
+    }
+}
+
+impl SingletonManager {
+    /// A read-only view of the singleton manager, for subsystems that must not change its
+    /// wiring.
+    pub fn view(&self) -> RegistryView<'_> {
+        RegistryView { manager: self }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::SingletonManager;
+
+    #[test]
+    fn test_view_reads_services() {
+        let mut manager = SingletonManager::new();
+        manager.set_factory("port", || Box::new(80_u16)).unwrap();
+        manager
+            .set_factory("host", || Box::new("localhost"))
+            .unwrap();
+        let view = manager.view();
+        let copy = view;
+
+        assert_eq!(
+            vec!["host", "port"],
+            view.entries()
+                .iter()
+                .map(|info| info.alias())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(0, copy.metrics().instantiated);
+        let port = copy.get_ref::<u16>("port").unwrap();
+        assert_eq!(80, *port);
+        assert_eq!(1, view.metrics().borrowed);
+        assert_eq!(1, view.metrics().instantiated);
+        assert!(!view.has("unknown"));
+    }
+}
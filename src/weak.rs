@@ -0,0 +1,114 @@
+//! # Weak handles
+//! Referring to a service without keeping it alive.
+//!
+//! Background tasks holding a `ServiceRef` keep the service from being shut down, and holding a
+//! clone of an `Arc` keeps the instance alive after it was removed. Services stored as an
+//! `Arc<T>` can instead be downgraded to a `WeakHandle<T>`, which does not count as a handle,
+//! and only gives access to the service for as long as the manager keeps it.
+//! ```
+//! use singleton_manager::SingletonManager;
+//! use std::sync::Arc;
+//!
+//! let mut manager = SingletonManager::new();
+//! manager.set_factory("pool", || Box::new(Arc::new("postgres".to_string()))).unwrap();
+//!
+//! let pool = manager.downgrade::<String>("pool").unwrap();
+//! assert_eq!("postgres", *pool.upgrade().unwrap());
+//!
+//! manager.shutdown().unwrap();
+//! assert!(pool.upgrade().unwrap_err().is_not_instantiated());
+//! ```
+use crate::{GetError, SingletonManager};
+use std::fmt::{Debug, Formatter};
+use std::sync::{Arc, Weak};
+
+/// A reference to a service stored as an `Arc<T>`, not keeping it alive, see
+/// `SingletonManager::downgrade`.
+pub struct WeakHandle<T> {
+    alias: String,
+    service: Weak<T>,
+}
+
+impl<T> WeakHandle<T> {
+    /// The alias the service was downgraded from.
+    pub fn alias(&self) -> &str {
+        &self.alias
+    }
+
+    /// Getting the service, failing with `GetError::ServiceNotInstantiated` once the instance
+    /// downgraded from was removed, or shut down, and is no longer used elsewhere.
+    /// The instance is also kept alive by the references handed out by `get`, until `gc`.
+    pub fn upgrade(&self) -> std::result::Result<Arc<T>, GetError> {
+        self.service
+            .upgrade()
+            .ok_or_else(|| GetError::ServiceNotInstantiated(self.alias.clone().into()))
+    }
+
+    /// True if the instance downgraded from is still alive.
+    pub fn is_alive(&self) -> bool {
+        self.service.strong_count() > 0
+    }
+}
+
+impl<T> Clone for WeakHandle<T> {
+    fn clone(&self) -> Self {
+        Self {
+            alias: self.alias.clone(),
+            service: self.service.clone(),
+        }
+    }
+}
+
+impl<T> Debug for WeakHandle<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WeakHandle")
+            .field("alias", &self.alias)
+            .field("alive", &self.is_alive())
+            .finish()
+    }
+}
+
+impl SingletonManager {
+    /// Getting a weak handle to the service stored as an `Arc<T>`, as `get_ref` does.
+    /// The handle refers to the current instance, an instance created again after a shutdown
+    /// needs to be downgraded again.
+    #[track_caller]
+    pub fn downgrade<T: Send + Sync + 'static>(
+        &self,
+        service_name: &str,
+    ) -> std::result::Result<WeakHandle<T>, GetError> {
+        let service = self.get_ref_by_alias::<Arc<T>>(service_name)?;
+        Ok(WeakHandle {
+            alias: service_name.to_string(),
+            service: Arc::downgrade(&service),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::SingletonManager;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_weak_handle_does_not_keep_service_alive() {
+        let mut manager = SingletonManager::new();
+        manager
+            .set_factory("cache", || Box::new(Arc::new(1_u32)))
+            .unwrap();
+        let cache = manager.downgrade::<u32>("cache").unwrap();
+        let copy = cache.clone();
+
+        let upgraded = cache.upgrade().unwrap();
+        assert_eq!(1, *upgraded);
+        drop(upgraded);
+        assert_eq!(0, manager.shutdown().unwrap().leaked.len());
+        assert!(!copy.is_alive());
+        assert_eq!(Some("cache"), cache.upgrade().unwrap_err().alias());
+
+        manager.get_ref::<Arc<u32>>("cache").unwrap();
+        assert!(!cache.is_alive());
+        assert!(manager.downgrade::<u32>("cache").unwrap().is_alive());
+        assert!(manager.downgrade::<String>("cache").is_err());
+    }
+}